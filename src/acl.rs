@@ -0,0 +1,62 @@
+//! Per-guild command access control lists
+//!
+//! Bot owners and server administrators allow or deny capabilities to roles and users with the
+//! `perm` command.  A capability is a command name, optionally followed by a subcommand, e.g.
+//! `rivals` or `rivals.delete`.  Plugins call `check()` or `permitted()` with the capability and
+//! the default for when no rule applies, which is usually whatever the command required before,
+//! such as being a bot owner or having a Discord permission.  Bot owners are always permitted.
+//!
+//! Bot owners may also `grant` capabilities to individual users, in every guild and in DMs.  A
+//! grant covers the capability's subcommands and takes precedence over guild rules.
+
+use crate::context::Context;
+use crate::error::{PluginError, Result};
+use crate::helper::MessageHelper;
+use serenity::all::{Message, RoleId};
+
+/// Whether the author of `msg` may use `capability`, falling back on `default` if no rule applies
+pub async fn permitted(ctx: &Context<'_>, msg: &Message, capability: &str, default: bool) -> bool {
+    if msg.is_from_owner(ctx).await {
+        return true;
+    }
+    if ctx
+        .pstate
+        .read()
+        .await
+        .acl
+        .is_granted(msg.author.id, capability)
+    {
+        return true;
+    }
+    let Some(guild_id) = msg.guild_id else {
+        return default;
+    };
+    let roles: Vec<RoleId> = match &msg.member {
+        Some(member) => member.roles.clone(),
+        None => guild_id
+            .member(ctx.cache_http, msg.author.id)
+            .await
+            .map(|member| member.roles.clone())
+            .unwrap_or_default(),
+    };
+    ctx.pstate
+        .read()
+        .await
+        .acl
+        .decide(guild_id, capability, msg.author.id, &roles)
+        .unwrap_or(default)
+}
+
+/// Like `permitted()`, but as a `PermissionDenied` error for plugins to propagate
+pub async fn check(
+    ctx: &Context<'_>,
+    msg: &Message,
+    capability: &str,
+    default: bool,
+) -> Result<()> {
+    if permitted(ctx, msg, capability, default).await {
+        Ok(())
+    } else {
+        Err(PluginError::PermissionDenied)
+    }
+}
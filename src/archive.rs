@@ -0,0 +1,85 @@
+//! Local copies of message attachments
+//!
+//! Discord attachment URLs eventually expire.  When configured, attachments are downloaded to
+//! `~/.config/digmbot/attachments/<channel id>/` so features such as vision can still use them.
+
+use crate::{config::AttachmentArchive, log_internal};
+use anyhow::{anyhow, Result};
+use serenity::all::Message;
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+
+const ARCHIVE_PATH_REL_HOME: &str = ".config/digmbot/attachments";
+
+fn archive_dir() -> Result<PathBuf> {
+    dirs::home_dir()
+        .map(|p| p.join(ARCHIVE_PATH_REL_HOME))
+        .ok_or(anyhow!("Could not find home directory"))
+}
+
+/// Download a message's attachments which pass the configured filters.  Returns the local copy of
+/// each, keyed by attachment URL.
+pub async fn save(cfg: &AttachmentArchive, msg: &Message) -> Result<HashMap<String, PathBuf>> {
+    let mut archived = HashMap::new();
+
+    let wanted = msg.attachments.iter().filter(|attachment| {
+        attachment.size as u64 <= cfg.max_bytes
+            && attachment
+                .content_type
+                .as_deref()
+                .is_some_and(|content_type| {
+                    cfg.content_types
+                        .iter()
+                        .any(|prefix| content_type.starts_with(prefix))
+                })
+    });
+
+    for attachment in wanted {
+        let dir = archive_dir()?.join(msg.channel_id.to_string());
+        tokio::fs::create_dir_all(&dir).await?;
+
+        // Discord filenames are already sanitized, but be sure they can't escape the directory.
+        let filename: String = attachment
+            .filename
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' => '_',
+                c => c,
+            })
+            .collect();
+        let path = dir.join(format!("{}_{}_{}", msg.id, attachment.id, filename));
+
+        let bytes = attachment.download().await?;
+        tokio::fs::write(&path, bytes).await?;
+        archived.insert(attachment.url.clone(), path);
+    }
+
+    Ok(archived)
+}
+
+/// Delete archived attachments last modified before `cutoff`.  Returns the number removed.
+pub async fn remove_older_than(cutoff: SystemTime) -> Result<usize> {
+    let root = archive_dir()?;
+    if !tokio::fs::try_exists(&root).await? {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    let mut channels = tokio::fs::read_dir(&root).await?;
+    while let Some(channel) = channels.next_entry().await? {
+        let mut files = tokio::fs::read_dir(channel.path()).await?;
+        while let Some(file) = files.next_entry().await? {
+            let modified = file.metadata().await?.modified()?;
+            if modified < cutoff {
+                match tokio::fs::remove_file(file.path()).await {
+                    Ok(()) => removed += 1,
+                    Err(err) => log_internal!(
+                        "Could not remove archived attachment {}: {}",
+                        file.path().to_string_lossy(),
+                        err
+                    ),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
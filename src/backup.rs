@@ -0,0 +1,272 @@
+//! Backups of persistent state
+//!
+//! Run on the `[backup]` schedule by `scheduler.rs`.  Each backup is a snapshot named by its UTC
+//! time containing `state.toml`.  `config.toml` is left out, as it holds the Discord token and
+//! other credentials which must not end up in a bucket or a DM.
+
+use crate::{config::S3Backup, context::Context, log_internal, persistent_state::PersistentState};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serenity::all::{CreateAttachment, CreateMessage};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Snapshot names, which sort chronologically
+const SNAPSHOT_NAME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Snapshot the state file to every configured destination.
+pub async fn run(ctx: &Context<'_>) -> Result<()> {
+    // Don't hold the config lock during uploads.
+    let Some(backup) = ctx.cfg.read().await.backup.clone() else {
+        return Ok(());
+    };
+
+    let name = Utc::now().format(SNAPSHOT_NAME_FORMAT).to_string();
+    let files = [(
+        "state.toml",
+        tokio::fs::read(PersistentState::config_path()?).await?,
+    )];
+
+    // Try every destination even if one fails.
+    let mut failures = Vec::new();
+    if let Some(path) = &backup.local_path {
+        if let Err(err) = to_local(path, &name, &files, backup.keep).await {
+            failures.push(format!("local: {}", err));
+        }
+    }
+    if let Some(s3) = &backup.s3 {
+        if let Err(err) = to_s3(ctx, s3, &name, &files, backup.keep).await {
+            failures.push(format!("S3: {}", err));
+        }
+    }
+    if let Some(user_id) = backup.dm_user {
+        let mut message = CreateMessage::new().content(format!("Backup {}", name));
+        for (filename, contents) in &files {
+            message = message.add_file(CreateAttachment::bytes(contents.clone(), *filename));
+        }
+        let sent = async {
+            user_id
+                .to_user(ctx.http)
+                .await?
+                .direct_message(ctx.cache_http, message)
+                .await
+        };
+        if let Err(err) = sent.await {
+            failures.push(format!("DM: {}", err));
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow!("Backup {} failed: {}", name, failures.join("; ")));
+    }
+    log_internal!("Backup {} complete", name);
+    Ok(())
+}
+
+async fn to_local(root: &Path, name: &str, files: &[(&str, Vec<u8>)], keep: usize) -> Result<()> {
+    let dir = root.join(name);
+    tokio::fs::create_dir_all(&dir).await?;
+    for (filename, contents) in files {
+        tokio::fs::write(dir.join(filename), contents).await?;
+    }
+
+    let mut snapshots = Vec::new();
+    let mut entries = tokio::fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if chrono::NaiveDateTime::parse_from_str(&name, SNAPSHOT_NAME_FORMAT).is_ok() {
+            snapshots.push(name);
+        }
+    }
+    snapshots.sort_unstable();
+    let excess = snapshots.len().saturating_sub(keep);
+    for old in &snapshots[..excess] {
+        tokio::fs::remove_dir_all(root.join(old)).await?;
+    }
+    Ok(())
+}
+
+async fn to_s3(
+    ctx: &Context<'_>,
+    s3: &S3Backup,
+    name: &str,
+    files: &[(&str, Vec<u8>)],
+    keep: usize,
+) -> Result<()> {
+    // Snapshots are tracked in the state, so they couldn't be expired if uploaded now
+    ctx.pstate.read().await.ensure_writable()?;
+    for (filename, contents) in files {
+        let key = format!("{}{}/{}", s3.prefix, name, filename);
+        s3_request(s3, reqwest::Method::PUT, &key, contents.clone()).await?;
+    }
+
+    let expired: Vec<String> = {
+        let mut pstate = ctx.pstate.write().await;
+        let snapshots = &mut pstate.backups.s3_snapshots;
+        snapshots.push_back(name.to_string());
+        let excess = snapshots.len().saturating_sub(keep);
+        let expired = snapshots.drain(..excess).collect();
+        pstate.save().await?;
+        expired
+    };
+    for old in expired {
+        for (filename, _) in files {
+            let key = format!("{}{}/{}", s3.prefix, old, filename);
+            s3_request(s3, reqwest::Method::DELETE, &key, Vec::new()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Send a path-style S3 request signed with AWS Signature Version 4.  Keys are expected to be
+/// URL-safe.
+async fn s3_request(
+    s3: &S3Backup,
+    method: reqwest::Method,
+    key: &str,
+    body: Vec<u8>,
+) -> Result<()> {
+    let url = reqwest::Url::parse(&format!(
+        "{}/{}/{}",
+        s3.endpoint.trim_end_matches('/'),
+        s3.bucket,
+        key
+    ))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(anyhow!("S3 endpoint has no host")),
+    };
+
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    let scope = format!("{}/{}/s3/aws4_request", date, s3.region);
+
+    let canonical_request = canonical_request(
+        method.as_str(),
+        url.path(),
+        "",
+        &host,
+        &payload_hash,
+        &amz_date,
+    );
+    let string_to_sign = string_to_sign(&amz_date, &scope, &canonical_request);
+    let signature = signature(&s3.secret_key, &date, &s3.region, &string_to_sign)?;
+
+    reqwest::Client::new()
+        .request(method, url)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", amz_date)
+        .header(
+            "Authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                s3.access_key, scope, SIGNED_HEADERS, signature
+            ),
+        )
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// The only headers signed, which every request sends
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Signature Version 4 canonical request.  `query` is the canonical query string, if any.
+fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    host: &str,
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    format!(
+        "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method, path, query, host, payload_hash, amz_date, SIGNED_HEADERS, payload_hash
+    )
+}
+
+fn string_to_sign(amz_date: &str, scope: &str, canonical_request: &str) -> String {
+    format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    )
+}
+
+/// Hex signature of `string_to_sign`, with a key derived for S3 in `region` on `date`
+fn signature(secret_key: &str, date: &str, region: &str, string_to_sign: &str) -> Result<String> {
+    type HmacSha256 = Hmac<Sha256>;
+    let hmac = |key: &[u8], data: &str| -> Result<Vec<u8>> {
+        let mut mac = HmacSha256::new_from_slice(key)?;
+        mac.update(data.as_bytes());
+        Ok(mac.finalize().into_bytes().to_vec())
+    };
+
+    let signing_key = [date, region, "s3", "aws4_request"]
+        .iter()
+        .try_fold(format!("AWS4{}", secret_key).into_bytes(), |key, part| {
+            hmac(&key, part)
+        })?;
+    Ok(hex::encode(hmac(&signing_key, string_to_sign)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "GET Bucket Lifecycle" example from AWS's Signature Version 4 documentation for S3
+    #[test]
+    fn signs_aws_example() {
+        let empty_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let amz_date = "20130524T000000Z";
+
+        let canonical_request = canonical_request(
+            "GET",
+            "/",
+            "lifecycle=",
+            "examplebucket.s3.amazonaws.com",
+            empty_hash,
+            amz_date,
+        );
+        assert_eq!(
+            canonical_request,
+            format!(
+                "GET\n/\nlifecycle=\nhost:examplebucket.s3.amazonaws.com\n\
+                 x-amz-content-sha256:{}\nx-amz-date:20130524T000000Z\n\n\
+                 host;x-amz-content-sha256;x-amz-date\n{}",
+                empty_hash, empty_hash
+            )
+        );
+
+        let string_to_sign = string_to_sign(
+            amz_date,
+            "20130524/us-east-1/s3/aws4_request",
+            &canonical_request,
+        );
+        assert_eq!(
+            string_to_sign,
+            "AWS4-HMAC-SHA256\n20130524T000000Z\n20130524/us-east-1/s3/aws4_request\n\
+             9766c798316ff2757b517bc739a67f6213b4ab36dd5da2f94eaebf79c77395ca"
+        );
+
+        let signature = signature(
+            "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+            "20130524",
+            "us-east-1",
+            &string_to_sign,
+        )
+        .unwrap();
+        assert_eq!(
+            signature,
+            "fea454ca298b7da1c68078a5d1bdbfbbe0d65c699e0f91ac7a200a0136783543"
+        );
+    }
+}
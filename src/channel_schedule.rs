@@ -0,0 +1,72 @@
+//! Channels opened and closed on a schedule, e.g. an off-topic channel only open on weekends
+//!
+//! Run on each `[[channel_schedules]]` entry's `open` and `close` schedules by `scheduler.rs`, or
+//! manually with the `channel` command.  Closing denies `@everyone` either sending messages or
+//! seeing the channel, per the entry's `mode`; opening lifts just that denial, leaving any other
+//! permissions as they were.  A manual change lasts until the next scheduled one.
+
+use crate::{config::ChannelCloseMode, context::Context, helper::post_mod_log};
+use anyhow::{anyhow, Result};
+use serenity::all::{ChannelId, PermissionOverwrite, PermissionOverwriteType, Permissions, RoleId};
+
+/// Open or close `channel_id`, recording why in the moderation log
+pub async fn set_open(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    mode: ChannelCloseMode,
+    open: bool,
+    reason: &str,
+) -> Result<()> {
+    let channel = channel_id
+        .to_channel(ctx.cache_http)
+        .await?
+        .guild()
+        .ok_or(anyhow!("Channel {} is not in a server", channel_id))?;
+    // The @everyone role shares the guild's ID
+    let everyone = PermissionOverwriteType::Role(RoleId::new(channel.guild_id.get()));
+    let (allow, deny) = channel
+        .permission_overwrites
+        .iter()
+        .find(|overwrite| overwrite.kind == everyone)
+        .map(|overwrite| (overwrite.allow, overwrite.deny))
+        .unwrap_or((Permissions::empty(), Permissions::empty()));
+
+    let denied = match mode {
+        ChannelCloseMode::Lock => Permissions::SEND_MESSAGES
+            .union(Permissions::SEND_MESSAGES_IN_THREADS)
+            .union(Permissions::ADD_REACTIONS),
+        ChannelCloseMode::Hide => Permissions::VIEW_CHANNEL,
+    };
+    let (allow, deny) = if open {
+        (allow, deny.difference(denied))
+    } else {
+        (allow.difference(denied), deny.union(denied))
+    };
+    channel_id
+        .create_permission(
+            ctx.http,
+            PermissionOverwrite {
+                allow,
+                deny,
+                kind: everyone,
+            },
+        )
+        .await?;
+
+    post_mod_log(
+        ctx,
+        channel.guild_id,
+        &format!(
+            "<#{}> {} ({})",
+            channel_id,
+            match (open, mode) {
+                (true, ChannelCloseMode::Lock) => "unlocked",
+                (true, ChannelCloseMode::Hide) => "shown",
+                (false, ChannelCloseMode::Lock) => "locked",
+                (false, ChannelCloseMode::Hide) => "hidden",
+            },
+            reason
+        ),
+    )
+    .await
+}
@@ -1,6 +1,13 @@
 use crate::llm::LlmSettings;
+use crate::persistent_state::ModAction;
 use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use serenity::all::{ChannelId, GuildId, RoleId, UserId};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 use tokio::io::AsyncReadExt;
 
 const CONFIG_PATH_REL_HOME: &str = ".config/digmbot/config.toml";
@@ -13,6 +20,72 @@ pub struct Config {
     pub llm_general: LlmGeneral,
     pub llm_reply: LlmReply,
     pub llm_permission_denied: LlmPermissionDenied,
+    pub llm_summary: Option<LlmSummary>,
+    pub llm_translate: Option<LlmTranslate>,
+    pub llm_trivia: Option<LlmTrivia>,
+    pub llm_rules_qa: Option<LlmRulesQa>,
+    pub llm_audit: Option<LlmAudit>,
+    pub llm_cache: Option<LlmCache>,
+    pub llm_memory: Option<LlmMemory>,
+    pub llm_keep_alive: Option<LlmKeepAlive>,
+    pub stream_notify: Option<StreamNotify>,
+    pub retention: Option<Retention>,
+    pub moderation: Option<Moderation>,
+    pub welcome: Option<Welcome>,
+    pub scheduler: Option<Scheduler>,
+    pub webhooks: Option<Webhooks>,
+    pub sent_log: Option<SentLog>,
+    pub redaction: Option<Redaction>,
+    pub attachment_archive: Option<AttachmentArchive>,
+    pub error_reports: Option<ErrorReports>,
+    pub backup: Option<Backup>,
+    pub timeouts: Option<Timeouts>,
+    pub reactions: Option<Reactions>,
+    pub rivals: Option<Rivals>,
+    pub rivals_seasons: Option<RivalsSeasons>,
+    pub rivals_confirmation: Option<RivalsConfirmation>,
+    pub self_test: Option<SelfTest>,
+    pub quickpoll: Option<QuickPoll>,
+    pub topic_summaries: Option<TopicSummaries>,
+    pub vc_role: Option<VcRole>,
+    pub config_watch: Option<ConfigWatch>,
+    pub stats_snapshot: Option<StatsSnapshot>,
+    pub state_watch: Option<StateWatch>,
+    pub conversation: Option<Conversation>,
+    pub wake_words: Option<WakeWords>,
+    pub typing_pace: Option<TypingPace>,
+    pub response_budget: Option<ResponseBudget>,
+    pub faq: Option<Faq>,
+    pub blocklist: Option<Blocklist>,
+    pub thread_titles: Option<ThreadTitles>,
+    pub weather: Option<Weather>,
+    pub define: Option<Define>,
+    pub impersonate: Option<Impersonate>,
+    pub imagine: Option<Imagine>,
+    pub word_puzzle: Option<WordPuzzle>,
+    pub word_filter: Option<WordFilter>,
+    pub auto_mod: Option<AutoMod>,
+    pub locale: Option<Locale>,
+    pub middleware: Option<Middleware>,
+    /// Channels in which every new message gets its own thread
+    #[serde(default)]
+    pub auto_threads: HashMap<ChannelId, AutoThread>,
+    /// Named sets of channels `clonechannel template` recreates under a new category
+    #[serde(default)]
+    pub channel_templates: HashMap<String, ChannelTemplate>,
+    /// Channels opened and closed on a schedule
+    #[serde(default)]
+    pub channel_schedules: Vec<ChannelSchedule>,
+    /// Channels which hold photo contests on a schedule
+    #[serde(default)]
+    pub photo_contests: Vec<PhotoContest>,
+    /// Named alternatives to the `[llm_reply]` settings, which bot owners may switch a channel's
+    /// replies to with `;llm profile`
+    #[serde(default)]
+    pub llm_profiles: HashMap<String, LlmProfile>,
+    /// Where channel history, cooldowns, and voice queues are kept besides memory
+    #[serde(default)]
+    pub state_backend: StateBackend,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -33,6 +106,8 @@ pub struct History {
 pub struct LlmGeneral {
     pub chat_url: String,
     pub completion_url: String,
+    /// Larger image attachments are not downloaded for vision models
+    pub max_image_bytes: Option<u64>,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -41,23 +116,707 @@ pub struct LlmReply {
     pub system: String,
     pub context_size: usize,
     pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+    /// Whether the model accepts images
+    #[serde(default)]
+    pub vision: bool,
+    /// Replied instead when the LLM can't be reached.  If unset, the user is told the LLM is
+    /// unavailable.
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Channels in which replies go in a thread on the message replied to, rather than inline
+    #[serde(default)]
+    pub thread_channels: Vec<ChannelId>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct LlmPermissionDenied {
     pub model_name: String,
     pub system: String,
     pub context_size: usize,
     pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+    /// Replied instead when the LLM can't be reached.  If unset, a plain, translatable refusal.
+    #[serde(default)]
+    pub fallback: Option<String>,
 }
 
+#[allow(dead_code)] // Not read by `vc_notify`, which keeps its names in the persistent state
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct VcNotify {
     pub global_names: Vec<String>,
 }
 
+/// Reply settings a channel may use instead of `[llm_reply]`.  See `Config::llm_profiles`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LlmProfile {
+    pub model_name: String,
+    pub system: String,
+    pub context_size: usize,
+    pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+    /// Whether the model accepts images
+    #[serde(default)]
+    pub vision: bool,
+    /// Replied instead when the LLM can't be reached
+    #[serde(default)]
+    pub fallback: Option<String>,
+}
+
+/// Optional generation parameters, alongside `temperature` in any `[llm_*]` section.  Those left
+/// unset use the model's defaults.
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LlmOptions {
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    /// Generation stops at any of these
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+    /// Fixed for reproducible output
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// Maximum tokens in the response
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+/// Settings for summarizing channel history which no longer fits in the LLM context.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LlmSummary {
+    pub model_name: String,
+    pub system: String,
+    pub context_size: usize,
+    pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+}
+
+/// Settings for `;translate`.  A low temperature keeps translations faithful.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmTranslate {
+    pub model_name: String,
+    pub system: String,
+    pub context_size: usize,
+    pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+}
+
+/// Generates `;trivia` questions for categories OpenTDB doesn't have
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmTrivia {
+    pub model_name: String,
+    pub system: String,
+    pub context_size: usize,
+    pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+}
+
+/// Answers questions from the guild's rules and FAQ channels.  See `plugin/rules_qa.rs`.
+/// Passages are embedded with the `[faq]` model.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmRulesQa {
+    pub model_name: String,
+    pub system: String,
+    pub context_size: usize,
+    pub temperature: f32,
+    #[serde(flatten)]
+    pub options: LlmOptions,
+    /// Minimum cosine similarity, from 0 to 1, for a passage to count as relevant to a question
+    pub threshold: f32,
+    pub guilds: HashMap<GuildId, RulesQaGuild>,
+}
+
+/// Log of every LLM request and response.  See `prompt_audit.rs`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LlmAudit {
+    /// The log is rotated once it would exceed this size
+    pub max_bytes: u64,
+    /// Number of rotated logs to keep
+    pub keep_files: usize,
+}
+
+/// Reuse of responses to identical LLM requests
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmCache {
+    /// How long a response may be reused
+    pub ttl_seconds: u64,
+    /// Maximum responses cached; the oldest is evicted first
+    pub capacity: usize,
+}
+
+/// Long-term memory of each channel for LLM replies.  See `plugin/memory.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmMemory {
+    /// Embedding endpoint, e.g. Ollama's `/api/embed`
+    pub embed_url: String,
+    pub model_name: String,
+    /// Most past messages to include in a reply's context
+    pub top_k: usize,
+    /// Minimum cosine similarity, from 0 to 1, for a past message to count as relevant
+    pub threshold: f32,
+    /// Oldest messages are forgotten beyond this many per channel
+    pub max_entries_per_channel: usize,
+}
+
+/// Keeps the `[llm_reply]` model loaded, so replies don't wait on it loading.  See
+/// `llm::keep_alive()`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LlmKeepAlive {
+    /// Cron expression on which to ping the model, in the `[scheduler]` default timezone.  See
+    /// `scheduler.rs`.
+    pub schedule: String,
+    /// How long the backend keeps a model loaded after each request, e.g. `30m`.  Sent with every
+    /// chat request.  Defaults to the backend's own setting.
+    #[serde(default)]
+    pub keep_alive: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct RulesQaGuild {
+    /// Channel whose questions are answered
+    pub questions_channel: ChannelId,
+    /// Channels whose messages answers are drawn from, e.g. #rules and #faq
+    pub source_channels: Vec<ChannelId>,
+    /// Role pinged when no passage is relevant enough to answer from
+    pub helper_role: Option<RoleId>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct StreamNotify {
+    /// Per-guild text channel in which to announce streams
+    pub announce_channels: HashMap<GuildId, ChannelId>,
+}
+
+/// Maximum age, in days, of stored data.  Unset fields are kept indefinitely.
+///
+/// Moderation cases are always kept, as escalation counts a member's past warnings and the cases
+/// are the record of what moderators did.  Server facts are kept too, as members add and `forget`
+/// them deliberately.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Retention {
+    /// Also applies to archived attachments, `[llm_memory]` entries, topic summaries, replaced
+    /// names, the sent log, and the `[llm_audit]` log
+    pub history_days: Option<u64>,
+    pub stats_days: Option<u64>,
+    /// `[rivals]` match logs, current and archived.  Seasons' final ratings are kept.
+    pub matches_days: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Moderation {
+    /// Per-guild role which prevents members from speaking
+    #[serde(default)]
+    pub mute_roles: HashMap<GuildId, RoleId>,
+    /// Per-guild channel to which moderation cases are posted
+    #[serde(default)]
+    pub log_channels: HashMap<GuildId, ChannelId>,
+    /// Per-guild actions taken automatically as members accumulate warnings
+    #[serde(default)]
+    pub escalation: HashMap<GuildId, EscalationPolicy>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct EscalationPolicy {
+    /// Warnings older than this no longer count.  If unset, warnings never expire.
+    pub warning_expiry_days: Option<u64>,
+    pub steps: Vec<EscalationStep>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct EscalationStep {
+    /// Number of unexpired warnings at which to take `action`
+    pub warnings: usize,
+    /// `mute`, `kick`, or `ban`
+    pub action: ModAction,
+    /// For `mute`, how long until it expires.  If unset, the mute is indefinite.
+    pub duration_minutes: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Welcome {
+    pub guilds: HashMap<GuildId, WelcomeGuild>,
+}
+
+/// Welcome/farewell templates.  See `plugin/welcome.rs` for placeholders.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WelcomeGuild {
+    pub channel: ChannelId,
+    pub welcome: Option<String>,
+    pub farewell: Option<String>,
+    /// DM'd to new members
+    pub rules_dm: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Scheduler {
+    /// IANA timezone name used when a schedule doesn't specify one
+    pub default_timezone: String,
+}
+
+/// Backend for parts of the volatile state.  See `redis_state.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum StateBackend {
+    /// Kept in memory only, and lost on restart
+    #[default]
+    Memory,
+    /// Written through to Redis.  Requires the `redis` feature.
+    Redis {
+        /// e.g. `redis://127.0.0.1/`.  Separate deployments should use separate databases, e.g.
+        /// `redis://127.0.0.1/1`.
+        url: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Webhooks {
+    /// Address on which to listen for webhooks, e.g. `0.0.0.0:8080`
+    pub listen: String,
+    pub github: Option<GithubWebhooks>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct GithubWebhooks {
+    /// Secret shared with GitHub, used to verify payloads
+    pub secret: String,
+    /// Repository full name (`owner/repo`) to the channel its events are posted in
+    pub channels: HashMap<String, ChannelId>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SentLog {
+    /// Number of sent messages to remember
+    pub capacity: usize,
+    /// Whether to save the log in `state.toml`
+    pub persist: bool,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Redaction {
+    /// Regular expressions matching text which should never be sent
+    pub patterns: Vec<String>,
+    /// Text to send instead of each match
+    pub replacement: String,
+    /// `patterns`, compiled on load
+    #[serde(skip)]
+    regexes: Vec<regex::Regex>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct AttachmentArchive {
+    /// Larger attachments are not archived
+    pub max_bytes: u64,
+    /// MIME type prefixes to archive, e.g. `image/`
+    pub content_types: Vec<String>,
+}
+
+/// Where to report plugin errors.  Set either or both destinations.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ErrorReports {
+    pub channel: Option<ChannelId>,
+    /// User to DM
+    pub user: Option<UserId>,
+    /// Report a given error from a given plugin at most once per this many minutes
+    pub dedup_minutes: u64,
+}
+
+/// Periodic backups of `state.toml`.  Set any combination of destinations.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Backup {
+    /// Cron expression, evaluated in the `[scheduler]` default timezone.  See `scheduler.rs`.
+    pub schedule: String,
+    /// Number of backups to keep at each destination which supports deleting old ones.  Must be
+    /// positive.
+    pub keep: usize,
+    /// Local directory
+    pub local_path: Option<PathBuf>,
+    pub s3: Option<S3Backup>,
+    /// User to DM backups to as attachments
+    pub dm_user: Option<UserId>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct S3Backup {
+    /// e.g. `https://s3.us-east-1.amazonaws.com`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Prepended to object keys, e.g. `digmbot/`
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// How long plugins may take to handle an event before being abandoned
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Timeouts {
+    pub default_seconds: u64,
+    /// Per-plugin overrides, by plugin name
+    #[serde(default)]
+    pub plugins: HashMap<String, u64>,
+    /// After this long, the watchdog gives up on any plugin and tells the user
+    pub watchdog_seconds: Option<u64>,
+}
+
+/// Weekly post of the most reacted messages
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Reactions {
+    pub highlight_channel: ChannelId,
+    /// Cron expression, evaluated in the `[scheduler]` default timezone.  See `scheduler.rs`.
+    pub highlight_schedule: String,
+    /// Number of messages to highlight
+    pub highlight_count: usize,
+}
+
+/// Rivals rating tuning.  See `plugin/rivals_rating.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Rivals {
+    /// Rating difference (in percent) equating to one stock of handicap
+    pub stock_value: Option<usize>,
+    /// Maximum rating difference (in percent) for which a match may update ratings
+    pub max_delta: Option<usize>,
+    /// Total rating change in an even match
+    pub k_factor: Option<f64>,
+    /// Per-guild `stock_value` overrides.  Ratings are shared between guilds, so only the handicap
+    /// may vary.
+    #[serde(default)]
+    pub guild_stock_values: HashMap<GuildId, usize>,
+}
+
+/// Soft reset of ratings when a new rivals season starts
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct RivalsSeasons {
+    /// Rating everyone is pulled toward
+    pub baseline: usize,
+    /// Fraction of each player's distance from `baseline` kept, from 0.0 (full reset) to 1.0
+    pub carryover: f64,
+}
+
+/// Hold reported rivals matches until the winner's owner confirms them
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct RivalsConfirmation {
+    /// Unconfirmed matches are dropped after this long
+    pub timeout_minutes: u64,
+}
+
+/// Periodically writes a JSON stats snapshot to the state directory.  See
+/// `plugin/stats_snapshot.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct StatsSnapshot {
+    pub interval_seconds: u64,
+}
+
+/// Checks run once on startup.  See `plugin/self_test.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SelfTest {
+    /// Where to post results.  Defaults to the `[error_reports]` channel.
+    pub log_channel: Option<ChannelId>,
+    /// Channels which must exist and grant the bot every permission its plugins need
+    #[serde(default)]
+    pub channels: Vec<ChannelId>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct QuickPoll {
+    /// How long polls stay open before the tally is posted
+    pub window_minutes: u64,
+}
+
+/// Persistent per-channel summaries, for LLM context across restarts.  Requires `[llm_summary]`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TopicSummaries {
+    /// Update a channel's summary after this many new messages
+    pub every_messages: usize,
+}
+
+/// Summarize and retitle threads once they go quiet.  Requires `[llm_summary]`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ThreadTitles {
+    /// How long a thread must be inactive before it is summarized.  Keep this shorter than the
+    /// threads' auto-archive duration.
+    pub inactive_minutes: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct AutoThread {
+    /// How to title threads
+    #[serde(default)]
+    pub title: AutoThreadTitle,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoThreadTitle {
+    /// The message's first line
+    #[default]
+    FirstLine,
+    /// A short title written by the `[llm_summary]` model, falling back on the first line
+    Llm,
+}
+
+/// Translations of the bot's fixed text.  See `locale.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Locale {
+    /// Per-guild language, naming a table in `languages`
+    #[serde(default)]
+    pub guilds: HashMap<GuildId, String>,
+    /// Per-language translations, keyed by the English text or `usage.<command>`
+    #[serde(default)]
+    pub languages: HashMap<String, HashMap<String, String>>,
+}
+
+/// Settings for `;define`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Define {
+    /// Guilds in which `define urban` may look terms up on Urban Dictionary
+    #[serde(default)]
+    pub urban_guilds: Vec<GuildId>,
+}
+
+/// Settings for `;impersonate`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Impersonate {
+    /// Guilds in which `;impersonate` is enabled
+    pub guilds: Vec<GuildId>,
+}
+
+/// Image generation for `;imagine`.  See `plugin/imagine.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Imagine {
+    /// AUTOMATIC1111-compatible text-to-image endpoint, e.g.
+    /// `http://localhost:7860/sdapi/v1/txt2img`
+    pub url: String,
+    /// Sampling steps per image
+    pub steps: u32,
+    /// How long each user must wait between images
+    pub cooldown_seconds: u64,
+    /// Guilds with the NSFW filter off in channels marked age-restricted.  Everywhere else, NSFW
+    /// prompts are refused and steered away from.
+    #[serde(default)]
+    pub unfiltered_guilds: Vec<GuildId>,
+}
+
+/// The daily word puzzle.  See `word_puzzle.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WordPuzzle {
+    /// Cron expression on which a new puzzle is posted, in the `[scheduler]` default timezone
+    pub schedule: String,
+    /// Per-guild channel in which to post puzzles
+    pub channels: HashMap<GuildId, ChannelId>,
+    /// Five-letter answers to choose from.  If empty, a built-in list is used.
+    #[serde(default)]
+    pub words: Vec<String>,
+}
+
+/// Settings for `;weather`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Weather {
+    /// Per-guild units.  Defaults to metric.
+    #[serde(default)]
+    pub units: HashMap<GuildId, Units>,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+/// Role held by members while they are in a (non-AFK) voice channel
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct VcRole {
+    pub roles: HashMap<GuildId, RoleId>,
+}
+
+/// Tuning for reloading the configuration when `config.toml` is modified, which happens regardless
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ConfigWatch {
+    /// How often to check the file's modification time, if file notifications are unavailable
+    pub poll_seconds: u64,
+}
+
+/// Reload the state when `state.toml` is modified by another instance sharing it
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct StateWatch {
+    /// How often to check the file's modification time, if file notifications are unavailable
+    pub poll_seconds: u64,
+}
+
+/// Treat follow-ups to the bot's replies as addressed to it, without a new mention
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Conversation {
+    /// How long after the bot replies the same user's messages in that channel count as to it
+    pub window_seconds: u64,
+}
+
+/// Delays `llm_reply` answers as if typed, in channels enabled with `;typingpace`.  See
+/// `plugin/typing_pace.rs`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct TypingPace {
+    /// Typing speed each message's delay is based on
+    pub chars_per_second: f64,
+    /// Longest delay before any one message
+    pub max_delay_seconds: f64,
+    /// Replies longer than this, in bytes, are sent as a couple of messages
+    pub split_len: usize,
+}
+
+/// Names, besides the bot's own, which address it in channels with wake words enabled
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WakeWords {
+    pub aliases: Vec<String>,
+}
+
+/// Limit on the bot's own messages per channel, so a runaway trigger can't flood it
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ResponseBudget {
+    /// Once the bot has sent this many messages in a channel within a minute, it ignores events
+    /// there until the minute is up
+    pub messages_per_minute: usize,
+}
+
+/// Answer questions similar to stored ones with their canonical answer.  See `plugin/faq.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Faq {
+    /// Embedding endpoint, e.g. Ollama's `/api/embed`
+    pub embed_url: String,
+    pub model_name: String,
+    /// Minimum cosine similarity, from 0 to 1, for a message to match a stored question
+    pub threshold: f32,
+}
+
+/// Channels and users whose messages are ignored, besides those added with `;ignore`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Blocklist {
+    #[serde(default)]
+    pub channels: Vec<ChannelId>,
+    #[serde(default)]
+    pub users: Vec<UserId>,
+}
+
+/// The middleware chain events pass through before reaching plugins.  See `middleware.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Middleware {
+    /// Middlewares by name, in the order events pass through them.  If unset, all of them in the
+    /// default order.
+    pub order: Option<Vec<String>>,
+    /// Per-guild plugins, by name, which don't run there
+    #[serde(default)]
+    pub disabled_plugins: HashMap<GuildId, Vec<String>>,
+}
+
+/// A category of channels cloned from existing ones, e.g. for an event
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ChannelTemplate {
+    /// Category whose permission overwrites the new category copies
+    pub category: Option<ChannelId>,
+    pub channels: Vec<ChannelTemplateEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ChannelTemplateEntry {
+    /// Channel whose settings to copy
+    pub source: ChannelId,
+    pub name: String,
+}
+
+/// Opens and closes a channel on a schedule.  See `channel_schedule.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ChannelSchedule {
+    pub channel: ChannelId,
+    #[serde(default)]
+    pub mode: ChannelCloseMode,
+    /// Cron expressions.  See `scheduler.rs`.
+    pub open: String,
+    pub close: String,
+    /// IANA timezone name.  Defaults to the `[scheduler]` default timezone.
+    pub timezone: Option<String>,
+}
+
+/// Runs a photo contest in a channel on a schedule.  See `photo_contest.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PhotoContest {
+    pub channel: ChannelId,
+    /// Cron expressions on which submissions open, voting opens, and voting closes.  See
+    /// `scheduler.rs`.
+    pub open: String,
+    pub vote: String,
+    pub close: String,
+    /// IANA timezone name.  Defaults to the `[scheduler]` default timezone.
+    pub timezone: Option<String>,
+}
+
+/// Words removed from messages.  See `plugin/word_filter.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WordFilter {
+    /// Single words, matched whole and ignoring case
+    pub words: Vec<String>,
+    /// Per-guild handling of messages containing them.  Guilds not listed aren't filtered.
+    pub guilds: HashMap<GuildId, FilterMode>,
+}
+
+/// Classifies messages into prohibited categories, by rules and optionally an LLM, and acts on
+/// those in categories the guild has turned on.  See `middleware.rs`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct AutoMod {
+    /// `[llm_profiles]` entry which classifies messages the rules don't catch.  If unset, only the
+    /// rules apply.
+    pub profile: Option<String>,
+    /// Per-category words, matched whole and ignoring case
+    #[serde(default)]
+    pub rules: HashMap<String, Vec<String>>,
+    /// Per-guild action for each category it moderates.  Guilds not listed aren't moderated.
+    pub guilds: HashMap<GuildId, HashMap<String, AutoModAction>>,
+}
+
+/// What happens to a message in a prohibited category
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoModAction {
+    /// Deleted, and reported to the moderation log
+    Delete,
+    /// Deleted, and its author warned as with `;mod warn`
+    Warn,
+    /// Reported to the moderation log for a human to judge
+    Flag,
+}
+
+/// What happens to a message containing filtered words
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Deleted, and reported to the moderation log
+    #[default]
+    Delete,
+    /// Deleted and reposted as the author with the words masked
+    Repost,
+}
+
+/// What closing a channel means
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelCloseMode {
+    /// Members can read but not send messages
+    #[default]
+    Lock,
+    /// Members can't see the channel
+    Hide,
+}
+
 impl Config {
-    fn config_path() -> Result<PathBuf> {
+    pub fn config_path() -> Result<PathBuf> {
         dirs::home_dir()
             .map(|p| p.join(CONFIG_PATH_REL_HOME))
             .ok_or(anyhow!("Could not find home directory"))
@@ -83,7 +842,7 @@ impl Config {
             )
         })?;
 
-        let config: Config = toml::from_str(&contents).map_err(|e| {
+        let mut config: Config = toml::from_str(&contents).map_err(|e| {
             anyhow!(
                 "Could not parse configuration at `{}`: {}",
                 path.to_string_lossy(),
@@ -91,9 +850,159 @@ impl Config {
             )
         })?;
 
+        if let Some(redaction) = config.redaction.as_mut() {
+            redaction.compile()?;
+        }
+        if let Some(rivals) = &config.rivals {
+            rivals.validate()?;
+        }
+        if let Some(word_puzzle) = &config.word_puzzle {
+            word_puzzle.validate()?;
+        }
+        if let Some(typing_pace) = &config.typing_pace {
+            typing_pace.validate()?;
+        }
+        if config
+            .backup
+            .as_ref()
+            .is_some_and(|backup| backup.keep == 0)
+        {
+            return Err(anyhow!(
+                "`[backup]` keep must be positive, or the new backup would be deleted"
+            ));
+        }
+        if let Some(auto_mod) = &config.auto_mod {
+            auto_mod.validate(&config.llm_profiles)?;
+        }
+        if let Some(middleware) = &config.middleware {
+            middleware.validate()?;
+        }
+        if cfg!(not(feature = "redis"))
+            && matches!(config.state_backend, StateBackend::Redis { .. })
+        {
+            return Err(anyhow!(
+                "`[state_backend]` kind \"redis\" requires building with the `redis` feature"
+            ));
+        }
+
         Ok(config)
     }
 
+    /// How long `plugin` may take to handle an event
+    pub fn plugin_timeout(&self, plugin: &str) -> Duration {
+        const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+        match &self.timeouts {
+            Some(timeouts) => Duration::from_secs(
+                timeouts
+                    .plugins
+                    .get(plugin)
+                    .copied()
+                    .unwrap_or(timeouts.default_seconds),
+            ),
+            None => DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long any plugin may take before the watchdog cancels it
+    pub fn watchdog_timeout(&self) -> Duration {
+        const DEFAULT_WATCHDOG_TIMEOUT: Duration = Duration::from_secs(90);
+
+        self.timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.watchdog_seconds)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WATCHDOG_TIMEOUT)
+    }
+
+    /// Largest image attachment to download for vision models
+    pub fn max_image_bytes(&self) -> u64 {
+        const DEFAULT_MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+        self.llm_general
+            .max_image_bytes
+            .unwrap_or(DEFAULT_MAX_IMAGE_BYTES)
+    }
+
+    /// Rating difference (in percent) equating to one stock of handicap in `guild_id`
+    pub fn rivals_stock_value(&self, guild_id: Option<GuildId>) -> usize {
+        const DEFAULT_STOCK_VALUE: usize = 150;
+
+        let Some(rivals) = &self.rivals else {
+            return DEFAULT_STOCK_VALUE;
+        };
+        guild_id
+            .and_then(|guild_id| rivals.guild_stock_values.get(&guild_id).copied())
+            .or(rivals.stock_value)
+            .unwrap_or(DEFAULT_STOCK_VALUE)
+    }
+
+    /// Maximum rating difference (in percent) for which a match may update ratings
+    pub fn rivals_max_delta(&self) -> usize {
+        const DEFAULT_MAX_DELTA: usize = 300;
+
+        self.rivals
+            .as_ref()
+            .and_then(|rivals| rivals.max_delta)
+            .unwrap_or(DEFAULT_MAX_DELTA)
+    }
+
+    /// Total rating change in an even rivals match
+    pub fn rivals_k_factor(&self) -> f64 {
+        const DEFAULT_K_FACTOR: f64 = 10.0;
+
+        self.rivals
+            .as_ref()
+            .and_then(|rivals| rivals.k_factor)
+            .unwrap_or(DEFAULT_K_FACTOR)
+    }
+
+    /// Scrub any configured redaction patterns from text the bot is about to send somewhere
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match &self.redaction {
+            Some(redaction) => redaction.redact(text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Modification time of `config.toml`
+    pub async fn modified() -> Result<SystemTime> {
+        let path = Self::config_path()?;
+        tokio::fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| {
+                anyhow!(
+                    "Could not stat configuration at `{}`: {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            })
+    }
+
+    /// How closing `channel_id` works, per its `[[channel_schedules]]` entry if any
+    pub fn channel_close_mode(&self, channel_id: ChannelId) -> ChannelCloseMode {
+        self.channel_schedules
+            .iter()
+            .find(|schedule| schedule.channel == channel_id)
+            .map(|schedule| schedule.mode)
+            .unwrap_or_default()
+    }
+
+    /// Translation of `key` into the guild's language, if it has one and the key is translated
+    pub fn translation(&self, guild_id: Option<GuildId>, key: &str) -> Option<&str> {
+        let locale = self.locale.as_ref()?;
+        let language = locale.guilds.get(&guild_id?)?;
+        locale.languages.get(language)?.get(key).map(String::as_str)
+    }
+
+    /// Units in which `;weather` reports for a guild
+    pub fn weather_units(&self, guild_id: Option<GuildId>) -> Units {
+        guild_id
+            .and_then(|guild_id| self.weather.as_ref()?.units.get(&guild_id).copied())
+            .unwrap_or_default()
+    }
+
     pub async fn reload(&mut self) -> Result<()> {
         let new = Self::load().await?;
         *self = new;
@@ -108,6 +1017,21 @@ impl<'a> LlmReply {
             system: &self.system,
             context_size: self.context_size,
             temperature: self.temperature,
+            options: &self.options,
+            vision: self.vision,
+        }
+    }
+}
+
+impl<'a> LlmProfile {
+    pub fn as_llm_settings(&'a self) -> LlmSettings<'a> {
+        LlmSettings {
+            model_name: &self.model_name,
+            system: &self.system,
+            context_size: self.context_size,
+            temperature: self.temperature,
+            options: &self.options,
+            vision: self.vision,
         }
     }
 }
@@ -119,6 +1043,191 @@ impl<'a> LlmPermissionDenied {
             system: &self.system,
             context_size: self.context_size,
             temperature: self.temperature,
+            options: &self.options,
+            vision: false,
+        }
+    }
+}
+
+impl<'a> LlmSummary {
+    pub fn as_llm_settings(&'a self) -> LlmSettings<'a> {
+        LlmSettings {
+            model_name: &self.model_name,
+            system: &self.system,
+            context_size: self.context_size,
+            temperature: self.temperature,
+            options: &self.options,
+            vision: false,
         }
     }
 }
+
+impl<'a> LlmTranslate {
+    pub fn as_llm_settings(&'a self) -> LlmSettings<'a> {
+        LlmSettings {
+            model_name: &self.model_name,
+            system: &self.system,
+            context_size: self.context_size,
+            temperature: self.temperature,
+            options: &self.options,
+            vision: false,
+        }
+    }
+}
+
+impl<'a> LlmTrivia {
+    pub fn as_llm_settings(&'a self) -> LlmSettings<'a> {
+        LlmSettings {
+            model_name: &self.model_name,
+            system: &self.system,
+            context_size: self.context_size,
+            temperature: self.temperature,
+            options: &self.options,
+            vision: false,
+        }
+    }
+}
+
+impl<'a> LlmRulesQa {
+    pub fn as_llm_settings(&'a self) -> LlmSettings<'a> {
+        LlmSettings {
+            model_name: &self.model_name,
+            system: &self.system,
+            context_size: self.context_size,
+            temperature: self.temperature,
+            options: &self.options,
+            vision: false,
+        }
+    }
+}
+
+impl Rivals {
+    fn validate(&self) -> Result<()> {
+        let stock_values = self
+            .stock_value
+            .iter()
+            .chain(self.guild_stock_values.values());
+        if stock_values.into_iter().any(|&value| value == 0) {
+            return Err(anyhow!("`[rivals]` stock values must be positive"));
+        }
+        if self.k_factor.is_some_and(|k| !k.is_finite() || k <= 0.0) {
+            return Err(anyhow!("`[rivals]` k_factor must be positive"));
+        }
+        Ok(())
+    }
+}
+
+impl WordPuzzle {
+    fn validate(&self) -> Result<()> {
+        if let Some(word) = self
+            .words
+            .iter()
+            .find(|word| !crate::word_puzzle::is_word(word))
+        {
+            return Err(anyhow!(
+                "`[word_puzzle]` word `{}` is not five letters",
+                word
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl TypingPace {
+    fn validate(&self) -> Result<()> {
+        if self.chars_per_second <= 0.0 {
+            return Err(anyhow!("`[typing_pace]` chars_per_second must be positive"));
+        }
+        if self.max_delay_seconds < 0.0 {
+            return Err(anyhow!(
+                "`[typing_pace]` max_delay_seconds must not be negative"
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AutoMod {
+    fn validate(&self, profiles: &HashMap<String, LlmProfile>) -> Result<()> {
+        if let Some(profile) = &self.profile {
+            if !profiles.contains_key(profile) {
+                return Err(anyhow!(
+                    "`[auto_mod]` profile `{}` is not in `[llm_profiles]`",
+                    profile
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Middleware {
+    fn validate(&self) -> Result<()> {
+        let mut seen = Vec::new();
+        for name in self.order.iter().flatten() {
+            if !crate::middleware::NAMES.contains(&name.as_str()) {
+                return Err(anyhow!(
+                    "`[middleware]` order has unknown middleware `{}`; expected one of {}",
+                    name,
+                    crate::middleware::NAMES.join(", ")
+                ));
+            }
+            if seen.contains(&name) {
+                return Err(anyhow!(
+                    "`[middleware]` order lists `{}` more than once",
+                    name
+                ));
+            }
+            seen.push(name);
+        }
+        let plugins = crate::plugin::plugins();
+        if let Some(name) = self
+            .disabled_plugins
+            .values()
+            .flatten()
+            .find(|name| !plugins.iter().any(|plugin| plugin.name() == name.as_str()))
+        {
+            return Err(anyhow!(
+                "`[middleware]` disabled_plugins has unknown plugin `{}`",
+                name
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Redaction {
+    fn compile(&mut self) -> Result<()> {
+        self.regexes = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                regex::Regex::new(pattern)
+                    .map_err(|e| anyhow!("Invalid redaction pattern `{}`: {}", pattern, e))
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for regex in &self.regexes {
+            if let Cow::Owned(redacted) =
+                regex.replace_all(&text, regex::NoExpand(&self.replacement))
+            {
+                text = Cow::Owned(redacted);
+            }
+        }
+        text
+    }
+}
+
+impl EscalationPolicy {
+    /// The step with the highest threshold `warnings` has reached, if any
+    pub fn step_for(&self, warnings: usize) -> Option<&EscalationStep> {
+        self.steps
+            .iter()
+            .filter(|step| step.warnings <= warnings)
+            .max_by_key(|step| step.warnings)
+    }
+}
@@ -0,0 +1,55 @@
+//! Two-phase confirmation of destructive commands
+//!
+//! Rather than acting immediately, a plugin calls `request()` with the action to perform.  The
+//! user is given a short token and must reply `confirm <token>` within `TIMEOUT`, which runs the
+//! action via the `confirm` plugin.  This way typos don't destroy data.
+
+use crate::context::{Context, OwnedContext};
+use crate::error::Result;
+use crate::volatile_state::PendingConfirmation;
+use futures::future::BoxFuture;
+use serenity::all::Message;
+use std::hash::BuildHasher;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Performs a confirmed operation, returning the reply to send
+pub type Action = Box<dyn FnOnce(OwnedContext) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Ask the author of `msg` to confirm `action`, described as e.g. "delete player `foo`".
+pub async fn request(
+    ctx: &Context<'_>,
+    msg: &Message,
+    description: &str,
+    action: Action,
+) -> Result<()> {
+    // Unpredictable, so a mistyped or stale token is unlikely to match another pending action
+    let token = format!(
+        "{:06x}",
+        std::collections::hash_map::RandomState::new().hash_one(msg.id) & 0xff_ffff
+    );
+    ctx.vstate.write().await.confirmations.insert(
+        token.clone(),
+        PendingConfirmation {
+            user_id: msg.author.id,
+            expires: Instant::now() + TIMEOUT,
+            action,
+        },
+    );
+
+    let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+    msg.reply(
+        ctx.cache_http,
+        format!(
+            "This will {}.  Reply `{}confirm {}` within {} seconds to proceed.",
+            description,
+            prefix,
+            token,
+            TIMEOUT.as_secs()
+        ),
+    )
+    .await?;
+    Ok(())
+}
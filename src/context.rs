@@ -5,9 +5,9 @@ use tokio::sync::RwLock;
 /// Collection of data that is shared across events
 pub struct Context<'a> {
     // Digmbot's own context types
-    pub cfg: &'a RwLock<Config>,
-    pub pstate: &'a RwLock<PersistentState>,
-    pub vstate: &'a RwLock<VolatileState>,
+    pub cfg: &'a Arc<RwLock<Config>>,
+    pub pstate: &'a Arc<RwLock<PersistentState>>,
+    pub vstate: &'a Arc<RwLock<VolatileState>>,
     // Discord/Serenity context types
     pub cache: &'a Arc<serenity::all::Cache>,
     pub http: &'a Arc<serenity::all::Http>,
@@ -18,3 +18,36 @@ pub struct Context<'a> {
 /// is available and fall back to an http request otherwise.  The most readily available type that
 /// impl's this is named very differently in a way that could be confusing, and so we alias it.
 pub type CacheHttp = serenity::all::Context;
+
+/// Owned counterpart to `Context` which may be moved into long-lived background tasks.
+#[derive(Clone)]
+pub struct OwnedContext {
+    cfg: Arc<RwLock<Config>>,
+    pstate: Arc<RwLock<PersistentState>>,
+    vstate: Arc<RwLock<VolatileState>>,
+    cache_http: CacheHttp,
+}
+
+impl Context<'_> {
+    pub fn owned(&self) -> OwnedContext {
+        OwnedContext {
+            cfg: Arc::clone(self.cfg),
+            pstate: Arc::clone(self.pstate),
+            vstate: Arc::clone(self.vstate),
+            cache_http: self.cache_http.clone(),
+        }
+    }
+}
+
+impl OwnedContext {
+    pub fn ctx(&self) -> Context<'_> {
+        Context {
+            cfg: &self.cfg,
+            pstate: &self.pstate,
+            vstate: &self.vstate,
+            cache: &self.cache_http.cache,
+            http: &self.cache_http.http,
+            cache_http: &self.cache_http,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Errors returned by plugins
+//!
+//! These tell the dispatcher in `event.rs` how to respond to a failure: telling the user what they
+//! did wrong, explaining they lack permission, noting a backend is unavailable, or just logging.
+
+/// External services plugins depend upon
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Service {
+    /// LLM chat endpoint
+    Llm,
+    /// Discord API
+    Discord,
+    /// Other web APIs
+    Web,
+}
+
+impl Service {
+    /// Services whose health is tracked and reported by `status`.  Discord is excluded; its
+    /// errors are usually due to e.g. missing permissions rather than an outage.
+    pub const MONITORED: [Service; 2] = [Service::Llm, Service::Web];
+}
+
+pub enum PluginError {
+    /// The user did something wrong.  Reply with the given text.
+    UserError(String),
+    /// The user lacks permission for the requested operation.
+    PermissionDenied,
+    /// A service the plugin depends upon failed.
+    Backend(Service, anyhow::Error),
+    /// The bot is in read-only maintenance mode.  See `MaintenanceMode`.
+    Maintenance,
+    /// Bug or otherwise unexpected failure.
+    Internal(anyhow::Error),
+}
+
+/// Returned by operations refused during maintenance, namely saving state and LLM requests.
+/// Becomes `PluginError::Maintenance` when converted.
+#[derive(Debug)]
+pub struct MaintenanceMode;
+
+impl std::error::Error for MaintenanceMode {}
+
+impl std::fmt::Display for MaintenanceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "refused during maintenance")
+    }
+}
+
+pub type Result<T> = std::result::Result<T, PluginError>;
+
+impl PluginError {
+    pub fn llm(err: impl Into<anyhow::Error>) -> Self {
+        let err = err.into();
+        if err.is::<MaintenanceMode>() {
+            return PluginError::Maintenance;
+        }
+        PluginError::Backend(Service::Llm, err)
+    }
+}
+
+impl std::fmt::Display for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Service::Llm => "LLM",
+            Service::Discord => "Discord",
+            Service::Web => "web",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PluginError::UserError(reply) => write!(f, "user error: {}", reply),
+            PluginError::PermissionDenied => write!(f, "permission denied"),
+            PluginError::Backend(service, err) => write!(f, "{} backend error: {}", service, err),
+            PluginError::Maintenance => write!(f, "{}", MaintenanceMode),
+            PluginError::Internal(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<anyhow::Error> for PluginError {
+    fn from(err: anyhow::Error) -> Self {
+        if err.is::<MaintenanceMode>() {
+            return PluginError::Maintenance;
+        }
+        PluginError::Internal(err)
+    }
+}
+
+impl From<serenity::Error> for PluginError {
+    fn from(err: serenity::Error) -> Self {
+        PluginError::Backend(Service::Discord, err.into())
+    }
+}
+
+impl From<reqwest::Error> for PluginError {
+    fn from(err: reqwest::Error) -> Self {
+        PluginError::Backend(Service::Web, err.into())
+    }
+}
@@ -1,7 +1,25 @@
-use crate::context::Context;
-use serenity::all::{Message, Reaction, Ready, VoiceState};
+use crate::{
+    context::Context,
+    error::{PluginError, Service},
+    helper, locale, log_internal,
+    plugin::{DmPolicy, Plugin},
+    volatile_state::Operation,
+};
+use anyhow::anyhow;
+use futures::FutureExt;
+use serenity::all::{
+    ChannelId, CreateMessage, GuildId, GuildMemberUpdateEvent, Member, Message, Reaction, Ready,
+    User, VoiceState,
+};
+use std::{panic::AssertUnwindSafe, time::Duration};
+use tokio::time::Instant;
+
+/// Events per channel which may run through ordered plugins at once.  Later ones wait their turn,
+/// so a burst of slow commands in one channel can't crowd out the others.
+const SLOTS_PER_CHANNEL: usize = 2;
 
 /// A Discord event
+#[allow(clippy::large_enum_variant)] // Short-lived and passed by reference to plugins
 pub enum Event {
     Ready(Ready),
     Message(Message),
@@ -11,18 +29,360 @@ pub enum Event {
     },
     ReactionAdd(Reaction),
     ReactionRemove(Reaction),
+    GuildMemberAddition(Member),
+    GuildMemberRemoval {
+        guild_id: GuildId,
+        user: User,
+        member: Option<Member>,
+    },
+    GuildMemberUpdate(GuildMemberUpdateEvent),
 }
 
 impl Event {
     /// When an event occurs, iterate over all the plugins to see if any can/should handle it.
+    /// Middlewares first narrow down which plugins see the event, then observe each ordered
+    /// plugin's result.  See `middleware.rs`.
+    ///
+    /// Serenity already handles each event in its own task, so events proceed concurrently up to
+    /// contention on the shared state locks.  Within an event, passive plugins run concurrently
+    /// with all the others, while the remaining plugins are run in order until one handles it.
+    /// Ordered plugins are limited to `SLOTS_PER_CHANNEL` events per channel at a time.
     pub async fn handle(self, ctx: Context<'_>) {
-        for plugin in crate::plugin::plugins() {
-            match plugin.handle(&ctx, &self).await {
-                Ok(EventHandled::Yes) => return,
-                Ok(EventHandled::No) => continue,
-                Err(err) => eprintln!("Error in plugin `{}`: {}", plugin.name(), err),
+        let middlewares = crate::middleware::middlewares(&*ctx.cfg.read().await);
+        let mut plugins = crate::plugin::plugins();
+        for middleware in &middlewares {
+            middleware.before(&ctx, &self, &mut plugins).await;
+        }
+        let is_dm = self.is_dm();
+        let (passive, ordered): (Vec<_>, Vec<_>) =
+            plugins.into_iter().partition(|plugin| plugin.passive());
+        // Guild-only passive plugins have nothing to observe in DMs.  Ordered ones still get to
+        // explain that their command doesn't work here, below.
+        let passive: Vec<_> = passive
+            .into_iter()
+            .filter(|plugin| !is_dm || plugin.dm_policy() == DmPolicy::Allow)
+            .collect();
+
+        let passive = futures::future::join_all(passive.iter().map(|plugin| async {
+            if let Err(err) = self.handle_isolated(&ctx, plugin.as_ref()).await {
+                if passive_error_reported(&err) {
+                    self.handle_error(&ctx, plugin.name(), err).await;
+                }
+            }
+        }));
+        let ordered = async {
+            if ordered.is_empty() {
+                return;
+            }
+            let slots = match self.channel_id() {
+                Some(channel_id) => Some(
+                    ctx.vstate
+                        .write()
+                        .await
+                        .command_slots
+                        .get(channel_id, SLOTS_PER_CHANNEL),
+                ),
+                None => None,
+            };
+            let _slot = match &slots {
+                Some(slots) => slots.acquire().await.ok(),
+                None => None,
+            };
+            for plugin in ordered {
+                let result = if is_dm && plugin.dm_policy() == DmPolicy::GuildOnly {
+                    let command = match plugin.command() {
+                        Some(command) => self.is_bot_cmd(&ctx, command).await,
+                        None => None,
+                    };
+                    match command {
+                        Some(_) => Err(PluginError::UserError(
+                            "That command only works within a server.".to_string(),
+                        )),
+                        None => Ok(EventHandled::No),
+                    }
+                } else {
+                    self.handle_isolated(&ctx, plugin.as_ref()).await
+                };
+                for middleware in &middlewares {
+                    middleware
+                        .after(&ctx, &self, plugin.as_ref(), &result)
+                        .await;
+                }
+                match result {
+                    Ok(EventHandled::Yes) => return,
+                    Ok(EventHandled::No) => continue,
+                    Err(err) => {
+                        if self.handle_error(&ctx, plugin.name(), err).await {
+                            return;
+                        }
+                    }
+                }
+            }
+        };
+
+        tokio::join!(passive, ordered);
+    }
+
+    /// Respond to and report a plugin's error as appropriate.  Returns whether the error counts as
+    /// handling the event.
+    async fn handle_error(
+        &self,
+        ctx: &Context<'_>,
+        plugin_name: &'static str,
+        err: PluginError,
+    ) -> bool {
+        // The plugin took responsibility for user-facing errors; don't let another plugin also
+        // respond.
+        let handled = matches!(
+            err,
+            PluginError::UserError(_) | PluginError::PermissionDenied | PluginError::Maintenance
+        );
+        let report = (!handled).then(|| err.to_string());
+        if let Err(response_err) = self.respond_to_error(ctx, plugin_name, err).await {
+            eprintln!(
+                "Error in plugin `{}` while responding to its error: {}",
+                plugin_name, response_err
+            );
+        }
+        if let Some(report) = report {
+            if let Err(report_err) = self.report_error(ctx, plugin_name, &report).await {
+                eprintln!("Error reporting error in `{}`: {}", plugin_name, report_err);
             }
         }
+        handled
+    }
+
+    /// Run a plugin such that it hanging or panicking is just an error, rather than blocking or
+    /// aborting dispatch to the remaining plugins.
+    async fn handle_isolated(
+        &self,
+        ctx: &Context<'_>,
+        plugin: &dyn Plugin,
+    ) -> Result<EventHandled, PluginError> {
+        let timeout = ctx.cfg.read().await.plugin_timeout(plugin.name());
+        let handle = AssertUnwindSafe(plugin.handle(ctx, self)).catch_unwind();
+
+        let (cancel, cancelled) = tokio::sync::oneshot::channel();
+        let operation = ctx.vstate.write().await.in_flight.start(Operation {
+            plugin: plugin.name(),
+            message: match self {
+                Event::Message(msg) => Some((msg.channel_id, msg.id)),
+                _ => None,
+            },
+            started: Instant::now(),
+            cancel: Some(cancel),
+        });
+        // Cancelling drops the plugin's future, and with it e.g. any typing indicator.
+        let outcome = tokio::select! {
+            outcome = tokio::time::timeout(timeout, handle) => Some(outcome),
+            _ = cancelled => None,
+        };
+        ctx.vstate.write().await.in_flight.finish(operation);
+
+        let Some(outcome) = outcome else {
+            return Err(PluginError::Internal(anyhow!("cancelled by watchdog")));
+        };
+        match outcome {
+            Ok(Ok(result)) => result,
+            Ok(Err(panic)) => {
+                let panic = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "<unknown panic>".to_string());
+                Err(PluginError::Internal(anyhow!("panicked: {}", panic)))
+            }
+            Err(_) => Err(PluginError::Internal(anyhow!(
+                "timed out after {} seconds",
+                timeout.as_secs()
+            ))),
+        }
+    }
+
+    /// Let the user know what went wrong, if appropriate for the error.
+    async fn respond_to_error(
+        &self,
+        ctx: &Context<'_>,
+        plugin_name: &str,
+        err: PluginError,
+    ) -> anyhow::Result<()> {
+        let msg = match self {
+            Event::Message(msg) => Some(msg),
+            _ => None,
+        };
+
+        match (err, msg) {
+            (PluginError::UserError(reply), Some(msg)) => {
+                let reply = locale::text(ctx, msg.guild_id, &reply).await;
+                msg.reply(ctx.cache_http, reply).await?;
+            }
+            (PluginError::PermissionDenied, Some(msg)) => {
+                helper::reply_permission_denied(ctx, msg).await?;
+            }
+            // If Discord itself is failing, replying likely would too.
+            (PluginError::Backend(service, err), Some(msg)) if service != Service::Discord => {
+                log_internal!("{} backend error in `{}`: {}", service, plugin_name, err);
+                ctx.vstate.write().await.degraded.mark(service, &err);
+                let reply = locale::text(
+                    ctx,
+                    msg.guild_id,
+                    "Sorry, that feature is temporarily unavailable; I'm having trouble reaching \
+                     my {service} backend.  Try again later.",
+                )
+                .await
+                .replace("{service}", &service.to_string());
+                msg.reply(ctx.cache_http, reply).await?;
+            }
+            (PluginError::Maintenance, Some(msg)) => {
+                let reply = locale::text(
+                    ctx,
+                    msg.guild_id,
+                    "Sorry, I'm in read-only maintenance mode right now and can't do that.  Try \
+                     again later.",
+                )
+                .await;
+                msg.reply(ctx.cache_http, reply).await?;
+            }
+            (
+                PluginError::UserError(_)
+                | PluginError::PermissionDenied
+                | PluginError::Maintenance,
+                None,
+            ) => {}
+            (PluginError::Backend(service, err), _) => {
+                log_internal!("{} backend error in `{}`: {}", service, plugin_name, err);
+                if service != Service::Discord {
+                    ctx.vstate.write().await.degraded.mark(service, &err);
+                }
+            }
+            (PluginError::Internal(err), _) => {
+                eprintln!("Error in plugin `{}`: {}", plugin_name, err)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tell the bot operators about an unexpected error, unless it was reported recently.
+    async fn report_error(
+        &self,
+        ctx: &Context<'_>,
+        plugin_name: &'static str,
+        err: &str,
+    ) -> anyhow::Result<()> {
+        const MAX_ERROR_LEN: usize = 1500;
+
+        let (channel, user, window) = match &ctx.cfg.read().await.error_reports {
+            Some(cfg) => (
+                cfg.channel,
+                cfg.user,
+                Duration::from_secs(cfg.dedup_minutes * 60),
+            ),
+            None => return Ok(()),
+        };
+        let Some(suppressed) =
+            ctx.vstate
+                .write()
+                .await
+                .error_reports
+                .should_report(plugin_name, err, window)
+        else {
+            return Ok(());
+        };
+
+        let truncated = helper::truncate(err, MAX_ERROR_LEN);
+        let mut content = format!(
+            "Error in plugin `{}` handling {}:\n```\n{}\n```",
+            plugin_name,
+            self.summary(),
+            truncated.replace("```", "'''"),
+        );
+        if suppressed > 0 {
+            content.push_str(&format!(
+                "({} repeat(s) suppressed since the last report)",
+                suppressed
+            ));
+        }
+
+        if let Some(channel) = channel {
+            channel.say(ctx.cache_http, &content).await?;
+        }
+        if let Some(user) = user {
+            user.to_user(ctx.http)
+                .await?
+                .direct_message(ctx.cache_http, CreateMessage::new().content(&content))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Guild in which the event occurred, if any
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match self {
+            Event::Message(msg) => msg.guild_id,
+            Event::VoiceStateUpdate { new, .. } => new.guild_id,
+            Event::ReactionAdd(reaction) | Event::ReactionRemove(reaction) => reaction.guild_id,
+            Event::GuildMemberAddition(member) => Some(member.guild_id),
+            Event::GuildMemberRemoval { guild_id, .. } => Some(*guild_id),
+            Event::GuildMemberUpdate(update) => Some(update.guild_id),
+            Event::Ready(_) => None,
+        }
+    }
+
+    /// Channel in which the event occurred, if any
+    pub fn channel_id(&self) -> Option<ChannelId> {
+        match self {
+            Event::Message(msg) => Some(msg.channel_id),
+            Event::VoiceStateUpdate { new, .. } => new.channel_id,
+            Event::ReactionAdd(reaction) | Event::ReactionRemove(reaction) => {
+                Some(reaction.channel_id)
+            }
+            Event::Ready(_)
+            | Event::GuildMemberAddition(_)
+            | Event::GuildMemberRemoval { .. }
+            | Event::GuildMemberUpdate(_) => None,
+        }
+    }
+
+    /// Whether the event happened in a direct message rather than a server
+    pub fn is_dm(&self) -> bool {
+        match self {
+            Event::Message(msg) => msg.guild_id.is_none(),
+            Event::ReactionAdd(reaction) | Event::ReactionRemove(reaction) => {
+                reaction.guild_id.is_none()
+            }
+            Event::Ready(_)
+            | Event::VoiceStateUpdate { .. }
+            | Event::GuildMemberAddition(_)
+            | Event::GuildMemberRemoval { .. }
+            | Event::GuildMemberUpdate(_) => false,
+        }
+    }
+
+    /// Short description of the event for error reports
+    fn summary(&self) -> String {
+        match self {
+            Event::Ready(_) => "ready".to_string(),
+            Event::Message(msg) => format!("message {} from {}", msg.link(), msg.author.name),
+            Event::VoiceStateUpdate { new, .. } => {
+                format!("voice state update of user {}", new.user_id)
+            }
+            Event::ReactionAdd(reaction) => format!(
+                "reaction to {}",
+                reaction
+                    .message_id
+                    .link(reaction.channel_id, reaction.guild_id)
+            ),
+            Event::ReactionRemove(reaction) => format!(
+                "reaction removal from {}",
+                reaction
+                    .message_id
+                    .link(reaction.channel_id, reaction.guild_id)
+            ),
+            Event::GuildMemberAddition(member) => format!("{} joining", member.user.name),
+            Event::GuildMemberRemoval { user, .. } => format!("{} leaving", user.name),
+            Event::GuildMemberUpdate(update) => format!("update of member {}", update.user.name),
+        }
     }
 
     /// Check if a message should be interpreted as a special bot command.
@@ -45,7 +405,38 @@ impl Event {
     }
 }
 
+/// Whether to respond to and report a passive plugin's error.  Passive plugins keep recording
+/// during maintenance, e.g. stats, but don't notify users when they can't save.  Commands are
+/// refused up front instead.  See `PersistentState::ensure_writable()`.
+fn passive_error_reported(err: &PluginError) -> bool {
+    !matches!(err, PluginError::Maintenance)
+}
+
 pub enum EventHandled {
     Yes,
     No,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistent_state::PersistentState;
+
+    #[test]
+    fn maintenance_only_refuses_commands() {
+        let mut pstate: PersistentState = toml::from_str(
+            "rivals_ratings = {}\nrivals_ratings_owners = {}\n[vc_notify]\nfollowers = []",
+        )
+        .unwrap();
+        assert!(pstate.ensure_writable().is_ok());
+
+        pstate.read_only = true;
+        let err = PluginError::from(pstate.ensure_writable().unwrap_err());
+        // Commands are answered with the maintenance notice, passive recorders carry on quietly
+        assert!(matches!(err, PluginError::Maintenance));
+        assert!(!passive_error_reported(&err));
+        assert!(passive_error_reported(&PluginError::Internal(anyhow!(
+            "unrelated"
+        ))));
+    }
+}
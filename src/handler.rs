@@ -1,26 +1,34 @@
 use crate::{
-    config::Config, context::Context, event::Event, persistent_state::PersistentState,
+    config::Config, context::Context, event::Event, persistent_state::PersistentState, plugin,
     volatile_state::VolatileState,
 };
-use serenity::all::{Message, Reaction, Ready, VoiceState};
+use serenity::all::{
+    GuildId, GuildMemberUpdateEvent, Member, Message, Reaction, Ready, User, VoiceState,
+};
+use std::sync::Arc;
 use tokio::sync::RwLock;
 
 /// Discord event handler
 pub struct Handler {
-    cfg: RwLock<Config>,
-    pstate: RwLock<PersistentState>,
-    vstate: RwLock<VolatileState>,
+    cfg: Arc<RwLock<Config>>,
+    pstate: Arc<RwLock<PersistentState>>,
+    vstate: Arc<RwLock<VolatileState>>,
 }
 
 impl<'a> Handler {
     pub fn new(cfg: Config, pstate: PersistentState, vstate: VolatileState) -> Self {
         Self {
-            cfg: RwLock::new(cfg),
-            pstate: RwLock::new(pstate),
-            vstate: RwLock::new(vstate),
+            cfg: Arc::new(RwLock::new(cfg)),
+            pstate: Arc::new(RwLock::new(pstate)),
+            vstate: Arc::new(RwLock::new(vstate)),
         }
     }
 
+    /// Volatile state, kept by `main` to stop plugins on shutdown
+    pub fn vstate(&self) -> Arc<RwLock<VolatileState>> {
+        Arc::clone(&self.vstate)
+    }
+
     fn ctx(&'a self, discord_ctx: &'a serenity::all::Context) -> Context<'a> {
         Context {
             cfg: &self.cfg,
@@ -36,7 +44,9 @@ impl<'a> Handler {
 #[serenity::async_trait]
 impl serenity::all::EventHandler for Handler {
     async fn ready(&self, discord_ctx: serenity::all::Context, ready: Ready) {
-        Event::Ready(ready).handle(self.ctx(&discord_ctx)).await;
+        let ctx = self.ctx(&discord_ctx);
+        plugin::start_all(&ctx).await;
+        Event::Ready(ready).handle(ctx).await;
     }
 
     async fn message(&self, discord_ctx: serenity::all::Context, msg: Message) {
@@ -65,4 +75,38 @@ impl serenity::all::EventHandler for Handler {
             .handle(self.ctx(&discord_ctx))
             .await;
     }
+
+    async fn guild_member_addition(&self, discord_ctx: serenity::all::Context, new_member: Member) {
+        Event::GuildMemberAddition(new_member)
+            .handle(self.ctx(&discord_ctx))
+            .await;
+    }
+
+    async fn guild_member_removal(
+        &self,
+        discord_ctx: serenity::all::Context,
+        guild_id: GuildId,
+        user: User,
+        member: Option<Member>,
+    ) {
+        Event::GuildMemberRemoval {
+            guild_id,
+            user,
+            member,
+        }
+        .handle(self.ctx(&discord_ctx))
+        .await;
+    }
+
+    async fn guild_member_update(
+        &self,
+        discord_ctx: serenity::all::Context,
+        _old_if_available: Option<Member>,
+        _new: Option<Member>,
+        event: GuildMemberUpdateEvent,
+    ) {
+        Event::GuildMemberUpdate(event)
+            .handle(self.ctx(&discord_ctx))
+            .await;
+    }
 }
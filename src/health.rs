@@ -0,0 +1,98 @@
+//! Bot health, as reported by `status` and the optional `/healthz` endpoint
+
+use crate::{context::Context, error::Service};
+use serenity::all::ShardManager;
+use std::{sync::Arc, time::Duration};
+
+/// How long to wait on the LLM endpoint before considering it unreachable
+const LLM_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Key under which the shard manager is stored in Serenity's data map, to look up gateway latency
+pub struct ShardManagerKey;
+
+impl serenity::prelude::TypeMapKey for ShardManagerKey {
+    type Value = Arc<ShardManager>;
+}
+
+#[derive(serde::Serialize)]
+pub struct Health {
+    pub uptime_seconds: u64,
+    /// None if no heartbeat has been acknowledged yet
+    pub gateway_latency_ms: Option<u128>,
+    pub guilds: usize,
+    /// Round trip to the LLM chat endpoint, or the reason it couldn't be reached
+    pub llm_ping_ms: Result<u128, String>,
+    /// Resident memory, if known
+    pub memory_bytes: Option<u64>,
+    /// Backends which have recently failed, with their last error
+    pub degraded: Vec<(String, String)>,
+}
+
+impl Health {
+    pub async fn check(ctx: &Context<'_>) -> Self {
+        let (uptime_seconds, degraded) = {
+            let vstate = ctx.vstate.read().await;
+            let degraded = Service::MONITORED
+                .into_iter()
+                .filter_map(|service| {
+                    let degradation = vstate.degraded.get(service)?;
+                    Some((service.to_string(), degradation.last_error.clone()))
+                })
+                .collect();
+            (vstate.started.elapsed().as_secs(), degraded)
+        };
+
+        Self {
+            uptime_seconds,
+            gateway_latency_ms: gateway_latency(ctx).await.map(|l| l.as_millis()),
+            guilds: ctx.cache.guild_count(),
+            llm_ping_ms: ping_llm(ctx).await,
+            memory_bytes: memory_bytes().await,
+            degraded,
+        }
+    }
+
+    /// Whether the bot is connected to Discord
+    #[cfg(feature = "webhooks")]
+    pub fn is_healthy(&self) -> bool {
+        self.gateway_latency_ms.is_some()
+    }
+}
+
+async fn gateway_latency(ctx: &Context<'_>) -> Option<Duration> {
+    let shard_manager = ctx
+        .cache_http
+        .data
+        .read()
+        .await
+        .get::<ShardManagerKey>()
+        .cloned()?;
+    let runners = shard_manager.runners.lock().await;
+    runners.get(&ctx.cache_http.shard_id)?.latency
+}
+
+/// Any HTTP response at all, even an error status, shows the endpoint is reachable.
+async fn ping_llm(ctx: &Context<'_>) -> Result<u128, String> {
+    let url = ctx.cfg.read().await.llm_general.chat_url.clone();
+    let start = std::time::Instant::now();
+    reqwest::Client::new()
+        .head(url)
+        .timeout(LLM_PING_TIMEOUT)
+        .send()
+        .await
+        .map(|_| start.elapsed().as_millis())
+        .map_err(|err| err.to_string())
+}
+
+async fn memory_bytes() -> Option<u64> {
+    let status = tokio::fs::read_to_string("/proc/self/status").await.ok()?;
+    let kib: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
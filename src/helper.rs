@@ -1,9 +1,15 @@
 //! Miscellaneous convenience methods
 
 use crate::context::Context;
+use crate::error::Service;
+use crate::llm::LlmChatRequest;
+use crate::locale;
 use anyhow::Result;
-use serenity::all::GuildId;
-use std::collections::HashMap;
+use serenity::all::{ChannelId, GuildId, Message, UserId};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 #[serenity::async_trait]
 pub trait UserIdHelper {
@@ -48,6 +54,7 @@ pub trait MessageHelper {
     async fn human_format_content(&self, ctx: &Context) -> Result<String>;
     async fn is_to_me(&self, ctx: &Context) -> Result<bool>;
     async fn is_from_owner(&self, ctx: &Context) -> bool;
+    fn image_urls(&self) -> Vec<String>;
 }
 
 #[serenity::async_trait]
@@ -113,6 +120,11 @@ impl MessageHelper for serenity::all::Message {
     }
 
     async fn is_to_me(&self, ctx: &Context) -> Result<bool> {
+        // Everything in a DM is to me
+        if self.guild_id.is_none() {
+            return Ok(true);
+        }
+
         // mentions me, the bot, directly
         if self.mentions_me(ctx.cache_http).await? {
             return Ok(true);
@@ -135,6 +147,17 @@ impl MessageHelper for serenity::all::Message {
             }
         }
 
+        // Follows up on a recent reply to the same user
+        if let Some(window) = conversation_window(ctx).await {
+            if ctx.vstate.read().await.conversations.is_active(
+                self.channel_id,
+                self.author.id,
+                window,
+            ) {
+                return Ok(true);
+            }
+        }
+
         // mentions a role I'm in within the guild
         let message_roles = &self.mention_roles;
         if message_roles.is_empty() {
@@ -161,4 +184,658 @@ impl MessageHelper for serenity::all::Message {
 
         owners.contains(author_global_name)
     }
+
+    fn image_urls(&self) -> Vec<String> {
+        self.attachments
+            .iter()
+            .filter(|attachment| {
+                attachment
+                    .content_type
+                    .as_deref()
+                    .is_some_and(|content_type| content_type.starts_with("image/"))
+            })
+            .map(|attachment| attachment.url.clone())
+            .collect()
+    }
+}
+
+/// Parse a user mention such as `<@123>` or a raw user ID.
+pub fn parse_user(arg: &str) -> Option<UserId> {
+    serenity::utils::parse_user_mention(arg).or_else(|| {
+        arg.parse::<u64>()
+            .ok()
+            .filter(|id| *id != 0)
+            .map(UserId::new)
+    })
+}
+
+/// Parse a human-friendly duration such as `90s`, `10m`, `1h30m`, or `2d`.
+pub fn parse_duration(arg: &str) -> Option<Duration> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in arg.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let value: u64 = digits.parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+        digits.clear();
+    }
+
+    // Require a unit on every number, and something non-zero overall
+    if !digits.is_empty() || total == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+/// Command arguments, split on whitespace except within double quotes, so e.g. `"Big Jim"` is one
+/// argument.  Unquoted arguments such as `--mine` are flags rather than positional arguments.
+#[derive(Clone, Default)]
+pub struct Args {
+    positional: Vec<String>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    pub fn parse(input: &str) -> Self {
+        let mut args = Args::default();
+        let mut chars = input.chars();
+        let mut current = String::new();
+        // Whether there's an argument in progress, which may be an empty quoted string
+        let mut started = false;
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut push = |arg: &mut String, quoted: bool| match arg.strip_prefix("--") {
+            Some(flag) if !quoted && !flag.is_empty() => {
+                args.flags.insert(flag.to_lowercase());
+                arg.clear();
+            }
+            _ => args.positional.push(std::mem::take(arg)),
+        };
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    started = true;
+                    quoted = true;
+                }
+                '\\' if in_quotes => {
+                    if let Some(escaped) = chars.next() {
+                        current.push(escaped);
+                    }
+                }
+                c if c.is_whitespace() && !in_quotes => {
+                    if started {
+                        push(&mut current, quoted);
+                    }
+                    started = false;
+                    quoted = false;
+                }
+                c => {
+                    current.push(c);
+                    started = true;
+                }
+            }
+        }
+        // An unterminated quote runs to the end of the input
+        if started {
+            push(&mut current, quoted);
+        }
+        args
+    }
+
+    /// The arguments after the first `n` positional ones, with the same flags
+    pub fn skip(&self, n: usize) -> Self {
+        Args {
+            positional: self.positional.iter().skip(n).cloned().collect(),
+            flags: self.flags.clone(),
+        }
+    }
+
+    pub fn positional(&self) -> Vec<&str> {
+        self.positional.iter().map(String::as_str).collect()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.positional.get(i).map(String::as_str)
+    }
+
+    /// The `i`th positional argument, if present and valid as a `T`
+    pub fn get_as<T: FromArg>(&self, i: usize) -> Option<T> {
+        self.get(i).and_then(T::from_arg)
+    }
+
+    /// Whether `--name` was given, case-insensitively
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name.to_lowercase())
+    }
+}
+
+/// Types which `Args::get_as()` can extract
+pub trait FromArg: Sized {
+    fn from_arg(arg: &str) -> Option<Self>;
+}
+
+impl FromArg for UserId {
+    fn from_arg(arg: &str) -> Option<Self> {
+        parse_user(arg)
+    }
+}
+
+impl FromArg for ChannelId {
+    fn from_arg(arg: &str) -> Option<Self> {
+        serenity::utils::parse_channel_mention(arg).or_else(|| {
+            arg.parse::<u64>()
+                .ok()
+                .filter(|id| *id != 0)
+                .map(ChannelId::new)
+        })
+    }
+}
+
+impl FromArg for Duration {
+    fn from_arg(arg: &str) -> Option<Self> {
+        parse_duration(arg)
+    }
+}
+
+impl FromArg for usize {
+    fn from_arg(arg: &str) -> Option<Self> {
+        arg.parse().ok()
+    }
+}
+
+/// Shorten `text` to at most `max_len` bytes, on a character boundary, marking any cut with `...`.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    if text.len() <= max_len {
+        return text.to_string();
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+/// Discord's limit on the length of a message
+pub const MESSAGE_MAX_LEN: usize = 2000;
+
+/// Pause between the parts of a split message, so Discord doesn't take them for spam
+const CHUNK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest markup `Markup::closers` may append
+const MAX_CLOSERS_LEN: usize = "\n```||".len();
+
+/// Split `text` into messages of at most `max_len` bytes.  Splits fall between lines where
+/// possible, otherwise between words, and only within a word longer than a whole message.  Code
+/// blocks, spoilers, and quotes open at a split are closed at the end of one part and reopened at
+/// the start of the next.
+pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    let mut splitter = Splitter {
+        max_len,
+        chunks: Vec::new(),
+        current: String::new(),
+        markup: Markup::new(),
+        fresh: true,
+    };
+    for line in text.split_inclusive('\n') {
+        if !splitter.fits(line) && !splitter.fresh {
+            splitter.finish_chunk();
+        }
+        if splitter.fits(line) {
+            splitter.append(line);
+            continue;
+        }
+        for word in line.split_inclusive(' ') {
+            splitter.push_word(word);
+        }
+    }
+    // Leave whatever the text itself left unclosed
+    let last = splitter.current.trim_end();
+    if !splitter.fresh && !last.is_empty() {
+        splitter.chunks.push(last.to_string());
+    }
+    splitter.chunks
+}
+
+/// Markup open at some point in a message
+#[derive(Clone)]
+struct Markup {
+    /// Opening fence of the current code block, including its language, e.g. `` ```rust ``
+    fence: Option<String>,
+    spoiler: bool,
+    /// `>>> ` quotes the rest of the message
+    block_quote: bool,
+    /// `> ` quotes the rest of the line
+    line_quote: bool,
+    at_line_start: bool,
+}
+
+impl Markup {
+    fn new() -> Self {
+        Self {
+            fence: None,
+            spoiler: false,
+            block_quote: false,
+            line_quote: false,
+            at_line_start: true,
+        }
+    }
+
+    /// Follow the markup through `text`
+    fn scan(&mut self, text: &str) {
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("```") {
+                self.fence = match self.fence {
+                    Some(_) => None,
+                    None => {
+                        let language = after
+                            .split(|c: char| c.is_whitespace() || c == '`')
+                            .next()
+                            .unwrap_or_default();
+                        Some(format!("```{}", language))
+                    }
+                };
+                self.at_line_start = false;
+                rest = after;
+                continue;
+            }
+            // Nothing else is markup within a code block
+            if self.fence.is_none() {
+                let quote = if !self.at_line_start {
+                    None
+                } else if let Some(after) = rest.strip_prefix(">>> ") {
+                    self.block_quote = true;
+                    Some(after)
+                } else if let Some(after) = rest.strip_prefix("> ") {
+                    self.line_quote = true;
+                    Some(after)
+                } else {
+                    None
+                };
+                let spoiler = rest.strip_prefix("||");
+                if spoiler.is_some() {
+                    self.spoiler = !self.spoiler;
+                }
+                if let Some(after) = quote.or(spoiler) {
+                    self.at_line_start = false;
+                    rest = after;
+                    continue;
+                }
+            }
+            self.at_line_start = c == '\n';
+            if c == '\n' {
+                self.line_quote = false;
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    /// Markup to end a part of a split message with
+    fn closers(&self) -> String {
+        let mut closers = String::new();
+        if self.fence.is_some() {
+            closers.push_str("\n```");
+        }
+        if self.spoiler {
+            closers.push_str("||");
+        }
+        closers
+    }
+
+    /// Markup to start the next part with, reopening whatever `closers` closed
+    fn openers(&self) -> String {
+        let mut openers = String::new();
+        if self.block_quote {
+            openers.push_str(">>> ");
+        } else if self.line_quote {
+            openers.push_str("> ");
+        }
+        if self.spoiler {
+            openers.push_str("||");
+        }
+        if let Some(fence) = &self.fence {
+            openers.push_str(fence);
+            openers.push('\n');
+        }
+        openers
+    }
+}
+
+struct Splitter {
+    max_len: usize,
+    chunks: Vec<String>,
+    current: String,
+    markup: Markup,
+    /// Whether `current` has no content beyond its openers
+    fresh: bool,
+}
+
+impl Splitter {
+    /// Whether `text` can be appended to the current part, leaving room to close its markup
+    fn fits(&self, text: &str) -> bool {
+        let mut markup = self.markup.clone();
+        markup.scan(text);
+        self.current.len() + text.len() + markup.closers().len() <= self.max_len
+    }
+
+    fn append(&mut self, text: &str) {
+        // Don't start a part with blank lines
+        if self.fresh && self.markup.fence.is_none() && text.trim().is_empty() {
+            return;
+        }
+        self.current.push_str(text);
+        self.markup.scan(text);
+        self.fresh = false;
+    }
+
+    fn push_word(&mut self, mut word: &str) {
+        loop {
+            if self.fits(word) {
+                self.append(word);
+                return;
+            }
+            if !self.fresh {
+                self.finish_chunk();
+                continue;
+            }
+            // Longer than a whole message, so it has to be split somewhere
+            let room = self
+                .max_len
+                .saturating_sub(self.current.len() + MAX_CLOSERS_LEN);
+            let mut end = room.min(word.len());
+            while !word.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end == 0 {
+                end = word.chars().next().map_or(word.len(), char::len_utf8);
+            }
+            self.append(&word[..end]);
+            word = &word[end..];
+            if word.is_empty() {
+                return;
+            }
+            self.finish_chunk();
+        }
+    }
+
+    fn finish_chunk(&mut self) {
+        let mut chunk = self.current.trim_end().to_string();
+        chunk.push_str(&self.markup.closers());
+        self.chunks.push(chunk);
+        self.current = self.markup.openers();
+        self.markup.at_line_start = self.current.is_empty() || self.current.ends_with('\n');
+        self.fresh = true;
+    }
+}
+
+/// Styles of Discord timestamp markup, which each viewer sees in their own timezone and locale
+#[derive(Clone, Copy)]
+pub enum TimestampStyle {
+    /// e.g. `16:20:30`
+    LongTime,
+    /// e.g. `20/04/2021`
+    ShortDate,
+    /// e.g. `in 2 hours` or `3 days ago`
+    Relative,
+}
+
+/// Format unix seconds as a Discord timestamp, e.g. `<t:1618953630:R>`
+pub fn discord_timestamp(unix_seconds: i64, style: TimestampStyle) -> String {
+    let style = match style {
+        TimestampStyle::LongTime => 'T',
+        TimestampStyle::ShortDate => 'd',
+        TimestampStyle::Relative => 'R',
+    };
+    format!("<t:{}:{}>", unix_seconds, style)
+}
+
+/// Format a number with thousands separators, e.g. `1,234,567`.  Unlike timestamps, Discord can't
+/// localize numbers per viewer, so this uses the common English convention.
+pub fn format_number(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Post to the guild's `[moderation]` log channel, if it has one
+pub async fn post_mod_log(ctx: &Context<'_>, guild_id: GuildId, content: &str) -> Result<()> {
+    let log_channel = ctx
+        .cfg
+        .read()
+        .await
+        .moderation
+        .as_ref()
+        .and_then(|m| m.log_channels.get(&guild_id).cloned());
+    if let Some(log_channel) = log_channel {
+        log_channel.say(ctx.cache_http, content).await?;
+    }
+    Ok(())
+}
+
+/// How long follow-ups to the bot's replies count as addressed to it, if `[conversation]` is set
+pub async fn conversation_window(ctx: &Context<'_>) -> Option<Duration> {
+    ctx.cfg
+        .read()
+        .await
+        .conversation
+        .as_ref()
+        .map(|conversation| Duration::from_secs(conversation.window_seconds))
+}
+
+/// Reply with `text`, split into several messages if it's too long for one
+pub async fn reply_in_chunks(ctx: &Context<'_>, msg: &Message, text: &str) -> Result<()> {
+    let mut chunks = split_message(text, MESSAGE_MAX_LEN).into_iter();
+    let Some(first) = chunks.next() else {
+        return Ok(());
+    };
+    msg.reply(ctx.cache_http, first).await?;
+    for chunk in chunks {
+        tokio::time::sleep(CHUNK_INTERVAL).await;
+        msg.channel_id.say(ctx.cache_http, chunk).await?;
+    }
+    Ok(())
+}
+
+/// Tell the author of `msg` they lack permission, with sass from the `[llm_permission_denied]`
+/// LLM if it's up, or plainly if it's not
+pub async fn reply_permission_denied(ctx: &Context<'_>, msg: &Message) -> Result<()> {
+    let configured = ctx.cfg.read().await.llm_permission_denied.fallback.clone();
+    let fallback = match configured {
+        Some(fallback) => fallback,
+        None => locale::text(ctx, msg.guild_id, "You don't have permission to do that.").await,
+    };
+    let llm_degraded = ctx.vstate.read().await.degraded.get(Service::Llm).is_some();
+    let response = if llm_degraded {
+        fallback
+    } else {
+        let typing = msg.channel_id.start_typing(ctx.http);
+        // Copied out so the config lock isn't held while `from_recent_history` takes the vstate
+        // lock, which `History::push` takes first
+        let denied_cfg = ctx.cfg.read().await.llm_permission_denied.clone();
+        let request =
+            LlmChatRequest::from_recent_history(ctx, msg.channel_id, &denied_cfg.as_llm_settings())
+                .await;
+        let response = match request {
+            Ok(request) => request
+                .post_or(ctx, Some(fallback.clone()))
+                .await
+                .unwrap_or(fallback),
+            Err(_) => fallback,
+        };
+        typing.stop();
+        response
+    };
+    reply_in_chunks(ctx, msg, &response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_within_limit(chunks: &[String], max_len: usize) {
+        for chunk in chunks {
+            assert!(
+                chunk.len() <= max_len,
+                "chunk of {} bytes exceeds {}: {:?}",
+                chunk.len(),
+                max_len,
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn short_text_is_one_chunk() {
+        assert_eq!(split_message("hello world", 2000), vec!["hello world"]);
+    }
+
+    #[test]
+    fn splits_between_lines() {
+        let text = format!("{}\n{}", "a".repeat(1500), "b".repeat(1500));
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert_eq!(chunks, vec!["a".repeat(1500), "b".repeat(1500)]);
+    }
+
+    #[test]
+    fn code_fence_is_closed_and_reopened_with_language() {
+        let lines: Vec<String> = (0..200).map(|i| format!("let x{} = {};", i, i)).collect();
+        let text = format!("Here:\n```rust\n{}\n```\nDone.", lines.join("\n"));
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert!(chunks.len() > 1);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        assert!(chunks[0].ends_with("\n```"));
+        for chunk in &chunks[1..] {
+            assert!(chunk.starts_with("```rust\n"), "not reopened: {:?}", chunk);
+        }
+        for chunk in &chunks {
+            assert_eq!(
+                chunk.matches("```").count() % 2,
+                0,
+                "unbalanced: {:?}",
+                chunk
+            );
+        }
+        // Nothing is lost besides the added fences
+        let code: Vec<&str> = chunks
+            .iter()
+            .flat_map(|chunk| chunk.lines())
+            .filter(|line| line.starts_with("let "))
+            .collect();
+        assert_eq!(code, lines);
+    }
+
+    #[test]
+    fn spoiler_is_closed_and_reopened() {
+        let text = format!("||{}||", "secret ".repeat(500).trim_end());
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert!(chunks.len() > 1);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        for chunk in &chunks {
+            assert!(
+                chunk.starts_with("||") && chunk.ends_with("||"),
+                "{:?}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn block_quote_is_carried_over() {
+        let lines: Vec<String> = (0..300).map(|i| format!("quoted line {}", i)).collect();
+        let text = format!(">>> {}", lines.join("\n"));
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert!(chunks.len() > 1);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        for chunk in &chunks {
+            assert!(chunk.starts_with(">>> "), "not quoted: {:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn line_quote_is_carried_over() {
+        let text = format!("> {}\nafter", "word ".repeat(600).trim_end());
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert!(chunks.len() > 1);
+        assert!(
+            chunks[1].starts_with("> word"),
+            "not quoted: {:?}",
+            chunks[1]
+        );
+        assert!(chunks.last().unwrap().ends_with("\nafter"));
+    }
+
+    #[test]
+    fn word_longer_than_limit_is_split() {
+        let text = "x".repeat(4500);
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert_eq!(chunks.len(), 3);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn long_word_splits_on_char_boundaries() {
+        let text = "é".repeat(1500);
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn mentions_channels_and_urls_are_kept_whole() {
+        let tokens = [
+            "<@123456789012345678>",
+            "<#123456789012345678>",
+            "https://example.com/some/long/path?query=value&other=thing",
+        ];
+        let text = tokens
+            .iter()
+            .cycle()
+            .take(200)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chunks = split_message(&text, MESSAGE_MAX_LEN);
+        assert!(chunks.len() > 1);
+        assert_within_limit(&chunks, MESSAGE_MAX_LEN);
+        for chunk in &chunks {
+            for word in chunk.split_whitespace() {
+                assert!(tokens.contains(&word), "split token: {:?}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_markup_stays_within_limit() {
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!(
+                "> quote {i} ||spoiler {i}|| <@{i}> https://example.com/{i}\n```py\nprint({i})\n```\n"
+            ));
+        }
+        text.push_str(&"y".repeat(3000));
+        for max_len in [MESSAGE_MAX_LEN, 100, 20] {
+            let chunks = split_message(&text, max_len);
+            assert_within_limit(&chunks, max_len);
+            assert!(chunks.iter().all(|chunk| !chunk.trim().is_empty()));
+        }
+    }
 }
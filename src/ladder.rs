@@ -0,0 +1,95 @@
+//! Rating ladders for one-on-one games
+//!
+//! A `Ladder` pairs a `RatingFormula`, which updates ratings after a match, with a `Handicap`,
+//! which describes how the stronger player should be held back for an even match, so each game
+//! only needs to supply its own.  `plugin/rivals_rating.rs` is the only ladder so far; its
+//! players, matches and seasons are stored in `persistent_state.rs` as `rivals_*`.
+
+use crate::config::Config;
+use serenity::all::GuildId;
+use std::cmp::Ordering;
+
+/// How ratings change after a match
+pub trait RatingFormula: Send + Sync {
+    /// The winner's and loser's new ratings
+    fn rate(&self, winner: usize, loser: usize) -> (usize, usize);
+}
+
+/// How to even out a match between differently rated players
+pub trait Handicap: Send + Sync {
+    /// Describe the handicap for `higher`, rated `diff` above their opponent
+    fn describe(&self, higher: &str, diff: usize) -> String;
+}
+
+/// Elo ratings: the change is `k_factor` times how unexpected the result was, on a logistic curve
+/// where a rating difference of `scale` makes the higher rated player about 76% likely to win
+pub struct Elo {
+    pub k_factor: f64,
+    pub scale: f64,
+}
+
+impl RatingFormula for Elo {
+    fn rate(&self, winner: usize, loser: usize) -> (usize, usize) {
+        let expected_winner = 1.0 / (1.0 + 10f64.powf((loser as f64 - winner as f64) / self.scale));
+        let change = self.k_factor * (1.0 - expected_winner);
+        (
+            (winner as f64 + change).round() as usize,
+            (loser as f64 - change).round() as usize,
+        )
+    }
+}
+
+/// Ratings in damage percent, as in Super Smash Bros, where each `stock_value` of difference is
+/// one stock the stronger player gives up, and the remainder is damage they start with
+pub struct Stocks {
+    pub stock_value: usize,
+}
+
+impl Handicap for Stocks {
+    fn describe(&self, higher: &str, diff: usize) -> String {
+        format!(
+            "Handicap: `{}` should start with {} stock(s) and {}% extra damage.",
+            higher,
+            diff / self.stock_value,
+            diff % self.stock_value
+        )
+    }
+}
+
+pub struct Ladder {
+    pub formula: Box<dyn RatingFormula>,
+    pub handicap: Box<dyn Handicap>,
+    /// Largest rating difference for which a match may update ratings.  Beyond it, handicaps
+    /// stop making for even matches.
+    pub max_delta: usize,
+}
+
+impl Ladder {
+    /// The Rivals ladder, with handicaps as configured for `guild_id`
+    pub fn rivals(cfg: &Config, guild_id: Option<GuildId>) -> Self {
+        Ladder {
+            formula: Box::new(Elo {
+                k_factor: cfg.rivals_k_factor(),
+                scale: 200.0,
+            }),
+            handicap: Box::new(Stocks {
+                stock_value: cfg.rivals_stock_value(guild_id),
+            }),
+            max_delta: cfg.rivals_max_delta(),
+        }
+    }
+
+    /// Whether a match between players with these ratings may update them
+    pub fn can_rate(&self, rating1: usize, rating2: usize) -> bool {
+        rating1.abs_diff(rating2) <= self.max_delta
+    }
+
+    /// Describe the starting handicap for an even match between two players
+    pub fn handicap(&self, player1: &str, rating1: usize, player2: &str, rating2: usize) -> String {
+        match rating1.cmp(&rating2) {
+            Ordering::Greater => self.handicap.describe(player1, rating1 - rating2),
+            Ordering::Less => self.handicap.describe(player2, rating2 - rating1),
+            Ordering::Equal => "No handicap.".to_string(),
+        }
+    }
+}
@@ -1,6 +1,19 @@
-use crate::{context::Context, helper::UserHelper, log_internal};
+use crate::{
+    config::LlmOptions,
+    context::Context,
+    error::{MaintenanceMode, Service},
+    helper::UserHelper,
+    log_internal,
+    persistent_state::TopicSummary,
+    prompt_audit,
+    volatile_state::{History, Summary},
+};
 use anyhow::{anyhow, Result};
-use serenity::all::ChannelId;
+use serenity::all::{ChannelId, MessageId};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    time::Duration,
+};
 
 /// LLM generation settings
 pub struct LlmSettings<'a> {
@@ -8,8 +21,14 @@ pub struct LlmSettings<'a> {
     pub system: &'a str,
     pub context_size: usize,
     pub temperature: f32,
+    /// Whether to send image attachments along with messages
+    pub vision: bool,
+    pub options: &'a LlmOptions,
 }
 
+/// Maximum number of (most recent) images to include in a request
+const MAX_IMAGES: usize = 4;
+
 #[derive(serde::Serialize)]
 pub struct LlmChatRequest {
     /// LLM model name
@@ -18,16 +37,57 @@ pub struct LlmChatRequest {
     stream: bool,
     /// Chat conversation to continue.
     messages: Vec<ChatMessage>,
+    options: ChatOptions,
+    /// How long to keep the model loaded afterwards.  See `[llm_keep_alive]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+}
+
+/// Generation parameters.  Those which are `None` are left out, so the model's defaults apply.
+#[derive(serde::Serialize)]
+struct ChatOptions {
     /// Context size
     num_ctx: usize,
     /// LLM temperature
     temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    /// Maximum tokens in the response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+impl ChatOptions {
+    fn new(settings: &LlmSettings<'_>) -> Self {
+        let options = settings.options;
+        Self {
+            num_ctx: settings.context_size,
+            temperature: settings.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            repeat_penalty: options.repeat_penalty,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            num_predict: options.max_tokens,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct ChatMessage {
     role: ChatMessageRole,
     content: String,
+    /// Base64-encoded images
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
 }
 
 #[allow(non_camel_case_types)] // Serialized literally; case matters
@@ -65,6 +125,28 @@ struct LLmChatResponse {
 // }
 
 impl LlmChatRequest {
+    /// A one-off request: the system prompt from `settings` followed by `content` from the user
+    pub fn from_prompt(settings: &LlmSettings<'_>, content: String) -> Self {
+        Self {
+            model: settings.model_name.to_owned(),
+            stream: false,
+            messages: vec![
+                ChatMessage {
+                    role: ChatMessageRole::system,
+                    content: settings.system.to_owned(),
+                    images: Vec::new(),
+                },
+                ChatMessage {
+                    role: ChatMessageRole::user,
+                    content,
+                    images: Vec::new(),
+                },
+            ],
+            options: ChatOptions::new(settings),
+            keep_alive: None,
+        }
+    }
+
     pub async fn from_recent_history(
         ctx: &Context<'_>,
         channel_id: ChannelId,
@@ -76,8 +158,25 @@ impl LlmChatRequest {
             .guild()
             .map(|g| g.guild_id);
 
-        let mut vstate = ctx.vstate.write().await;
+        let (opted_out, topic_summary) = {
+            let pstate = ctx.pstate.read().await;
+            (
+                pstate.llm_optout.users.clone(),
+                pstate
+                    .topic_summaries
+                    .channels
+                    .get(&channel_id)
+                    .map(|s| s.content.clone()),
+            )
+        };
+
+        let max_image_bytes = ctx.cfg.read().await.max_image_bytes();
+
+        History::ensure_backfilled(ctx, channel_id).await?;
+        let mut vstate_guard = ctx.vstate.write().await;
+        let vstate = &mut *vstate_guard;
         let history = vstate.history.get(ctx, channel_id).await?;
+        let previous_summary = vstate.summaries.get(channel_id);
 
         let bot = ctx.cache.current_user().clone(); // clone to avoid async/send safety
         let bot_id = bot.id;
@@ -98,13 +197,24 @@ impl LlmChatRequest {
         // Build in reverse order so that we can stop adding if the accumulated content gets too
         // long.
         let mut total_bytes = system.len(); // include not yet added system message size
+        total_bytes += previous_summary
+            .map(|s| s.content.len())
+            .or(topic_summary.as_ref().map(|s| s.len()))
+            .unwrap_or(0);
         let mut messages = Vec::new();
+        // Number of (newest) history entries either included or deliberately skipped
+        let mut considered = 0;
+        let mut image_urls = Vec::new();
         for entry in history.iter().rev() {
+            if opted_out.contains(&entry.author_id) {
+                considered += 1;
+                continue;
+            }
             let (role, content) = if entry.author_id == bot_id {
                 let content = entry.human_format_content.clone();
                 (ChatMessageRole::assistant, content)
             } else {
-                let content = format!("{}: {}", entry.author_name, &entry.human_format_content);
+                let content = format!("{}: {}", entry.author_name, entry.llm_content());
                 (ChatMessageRole::user, content)
             };
             total_bytes += content.len();
@@ -112,7 +222,90 @@ impl LlmChatRequest {
             if total_bytes / 3 > settings.context_size {
                 break;
             }
-            messages.push(ChatMessage { role, content });
+            if settings.vision {
+                for url in &entry.image_urls {
+                    if image_urls.len() < MAX_IMAGES {
+                        let archived = entry.archived.get(url).cloned();
+                        image_urls.push((messages.len(), url.clone(), archived));
+                    }
+                }
+            }
+            messages.push(ChatMessage {
+                role,
+                content,
+                images: Vec::new(),
+            });
+            considered += 1;
+        }
+
+        // Messages which did not fit and have not yet been folded into the channel summary.
+        let dropped = &history[..history.len() - considered];
+        let unsummarized: Vec<String> = dropped
+            .iter()
+            .filter(|entry| previous_summary.is_none_or(|s| entry.message_id > s.through))
+            .filter(|entry| !opted_out.contains(&entry.author_id))
+            .map(|entry| format!("{}: {}", entry.author_name, entry.llm_content()))
+            .collect();
+        let through = dropped.last().map(|entry| entry.message_id);
+        let previous_summary = previous_summary.map(|s| s.content.clone());
+
+        // Don't hold the history lock while waiting on the LLM or image downloads.
+        drop(vstate_guard);
+
+        for (index, url, archived) in image_urls {
+            match load_image(&url, archived.as_deref(), max_image_bytes).await {
+                Ok(image) => messages[index].images.push(image),
+                Err(err) => log_internal!("Could not download image {}: {}", url, err),
+            }
+        }
+
+        // Copied out so the config lock isn't held while waiting on the LLM, nor while taking the
+        // vstate lock, which `History::push` takes first
+        let summary_cfg = ctx.cfg.read().await.llm_summary.clone();
+        let summary = match (through, summary_cfg) {
+            (Some(through), Some(summary_cfg)) if !unsummarized.is_empty() => {
+                let summarized = summarize(
+                    ctx,
+                    &summary_cfg.as_llm_settings(),
+                    previous_summary.as_deref(),
+                    &unsummarized,
+                )
+                .await;
+                match summarized {
+                    Ok(content) => {
+                        ctx.vstate.write().await.summaries.insert(
+                            channel_id,
+                            Summary {
+                                content: content.clone(),
+                                through,
+                            },
+                        );
+                        Some(content)
+                    }
+                    // The reply can go ahead with the older summary; the next one retries
+                    Err(err) => {
+                        log_internal!("Could not summarize {}: {}", channel_id, err);
+                        previous_summary
+                    }
+                }
+            }
+            _ => previous_summary,
+        };
+
+        // Summary of the conversation preceding the included history, if any.  Like the system
+        // message, push at the end so it lands at the start after reversal.
+        // Otherwise fall back to the persistent topic summary, e.g. just after a restart.
+        let summary = match (summary, topic_summary) {
+            (Some(summary), _) => Some(format!("Summary of the earlier conversation: {}", summary)),
+            (None, Some(topic)) => Some(format!("Summary of the recent conversation: {}", topic)),
+            (None, None) => None,
+        };
+        if let Some(summary) = summary {
+            messages.push(ChatMessage {
+                role: ChatMessageRole::system,
+                content: summary,
+                images: Vec::new(),
+            });
         }
 
         // Add system message at the end of about-to-be-reversed message history so it's at the
@@ -120,6 +313,7 @@ impl LlmChatRequest {
         messages.push(ChatMessage {
             role: ChatMessageRole::system,
             content: system,
+            images: Vec::new(),
         });
 
         // Reverse back to chronological order.
@@ -129,33 +323,430 @@ impl LlmChatRequest {
             model: settings.model_name.to_owned(),
             messages,
             stream: false,
-            temperature: settings.temperature,
-            num_ctx: settings.context_size,
+            options: ChatOptions::new(settings),
+            keep_alive: None,
         })
     }
 
-    pub async fn post(&self, ctx: &Context<'_>) -> Result<String> {
-        let cfg = ctx.cfg.read().await;
-        let url = cfg.llm_general.chat_url.as_str();
+    pub async fn post(mut self, ctx: &Context<'_>) -> Result<String> {
+        if ctx.vstate.read().await.maintenance.is_some() {
+            return Err(MaintenanceMode.into());
+        }
+        // Copied out so the config lock isn't held while waiting on the LLM, nor while taking the
+        // vstate lock, which `History::push` takes first
+        let (url, redaction, audit, cache) = {
+            let cfg = ctx.cfg.read().await;
+            self.keep_alive = cfg
+                .llm_keep_alive
+                .as_ref()
+                .and_then(|keep_alive| keep_alive.keep_alive.clone());
+            (
+                cfg.llm_general.chat_url.clone(),
+                cfg.redaction.clone(),
+                cfg.llm_audit.clone(),
+                cfg.llm_cache
+                    .as_ref()
+                    .map(|cache| (Duration::from_secs(cache.ttl_seconds), cache.capacity)),
+            )
+        };
+        let redact = |text: &str| match &redaction {
+            Some(redaction) => redaction.redact(text).into_owned(),
+            None => text.to_string(),
+        };
+
+        // History may contain sensitive strings; keep them from the LLM and thus its response.
+        for message in &mut self.messages {
+            message.content = redact(&message.content);
+        }
+
+        // Identical requests, e.g. the same question asked twice, get the same response
+        let cache = cache.map(|(ttl, capacity)| {
+            let mut hasher = DefaultHasher::new();
+            serde_json::to_string(&self)
+                .unwrap_or_default()
+                .hash(&mut hasher);
+            (hasher.finish(), ttl, capacity)
+        });
+        if let Some((hash, ttl, _)) = cache {
+            let mut vstate = ctx.vstate.write().await;
+            if let Some(response) = vstate.llm_cache.get(hash, ttl) {
+                vstate.metrics.llm_cache_hits += 1;
+                return Ok(response);
+            }
+        }
 
         log_internal!("Sending request to chat endpoint {}... ", url);
         let client = reqwest::Client::new();
-        let response = client
+        let start = std::time::Instant::now();
+        let response = async {
+            client
+                .post(&url)
+                .json(&self)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<LLmChatResponse>()
+                .await
+        }
+        .await;
+        if let Some(audit) = audit.as_ref() {
+            let outcome = match &response {
+                Ok(response) => Ok(redact(&response.message.content)),
+                Err(err) => Err(err.to_string()),
+            };
+            prompt_audit::record(ctx, audit, &self, outcome).await;
+        }
+        let response = match response {
+            Ok(response) => {
+                let mut vstate = ctx.vstate.write().await;
+                vstate.degraded.clear(Service::Llm);
+                vstate.metrics.record_llm_latency(start.elapsed());
+                response
+            }
+            Err(err) => {
+                ctx.vstate.write().await.degraded.mark(Service::Llm, &err);
+                return Err(err.into());
+            }
+        };
+        log_internal!("Sending request to chat endpoint {}... done", url);
+        // May be longer than a Discord message; see `helper::reply_in_chunks`
+        let response = redact(&response.message.content);
+        if let Some((hash, _, capacity)) = cache {
+            ctx.vstate
+                .write()
+                .await
+                .llm_cache
+                .insert(hash, response.clone(), capacity);
+        }
+        Ok(response)
+    }
+
+    /// Append `facts` about the server to the system prompt
+    pub fn with_facts(mut self, facts: Vec<String>) -> Self {
+        if facts.is_empty() {
+            return self;
+        }
+        if let Some(system) = self.messages.first_mut() {
+            system.content.push_str("\n\nFacts about this server:");
+            for fact in facts {
+                system.content.push_str("\n- ");
+                system.content.push_str(&fact);
+            }
+        }
+        self
+    }
+
+    /// Include `memories`, past messages relevant to the conversation, after the system prompt
+    pub fn with_memories(mut self, memories: Vec<String>) -> Self {
+        if memories.is_empty() {
+            return self;
+        }
+        let content = format!(
+            "Earlier messages which may be relevant:\n{}",
+            memories.join("\n")
+        );
+        let index = self.messages.len().min(1);
+        self.messages.insert(
+            index,
+            ChatMessage {
+                role: ChatMessageRole::system,
+                content,
+                images: Vec::new(),
+            },
+        );
+        self
+    }
+
+    /// Like `post()`, but if the LLM can't be reached, `fallback` instead, if there is one.
+    /// Fallbacks are counted for `status`.
+    pub async fn post_or(self, ctx: &Context<'_>, fallback: Option<String>) -> Result<String> {
+        match (self.post(ctx).await, fallback) {
+            (Ok(response), _) => Ok(response),
+            (Err(err), Some(fallback)) if !err.is::<MaintenanceMode>() => {
+                log_internal!("LLM unreachable, replying with fallback: {}", err);
+                ctx.vstate.write().await.metrics.llm_fallbacks += 1;
+                Ok(fallback)
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+}
+
+/// Load the `[llm_reply]` model if it isn't already, by asking it for a single token.  Skips
+/// `post()`'s cache, audit log and metrics, as this isn't a real request.
+pub async fn keep_alive(ctx: &Context<'_>) -> Result<()> {
+    if ctx.vstate.read().await.maintenance.is_some() {
+        return Ok(());
+    }
+    let (url, request) = {
+        let cfg = ctx.cfg.read().await;
+        let options = LlmOptions {
+            max_tokens: Some(1),
+            ..LlmOptions::default()
+        };
+        let settings = LlmSettings {
+            system: "",
+            vision: false,
+            options: &options,
+            ..cfg.llm_reply.as_llm_settings()
+        };
+        let mut request = LlmChatRequest::from_prompt(&settings, "Hi".to_string());
+        request.keep_alive = cfg
+            .llm_keep_alive
+            .as_ref()
+            .and_then(|keep_alive| keep_alive.keep_alive.clone());
+        (cfg.llm_general.chat_url.clone(), request)
+    };
+
+    let response = async {
+        reqwest::Client::new()
+            .post(&url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()
+    }
+    .await;
+    match response {
+        Ok(_) => {
+            ctx.vstate.write().await.degraded.clear(Service::Llm);
+            Ok(())
+        }
+        Err(err) => {
+            ctx.vstate.write().await.degraded.mark(Service::Llm, &err);
+            Err(err.into())
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Embed `input` with the `[faq]` model.  Fails if `[faq]` isn't configured.
+pub async fn embed(ctx: &Context<'_>, input: &str) -> Result<Vec<f32>> {
+    let (url, model) = {
+        let cfg = ctx.cfg.read().await;
+        let faq = cfg
+            .faq
+            .as_ref()
+            .ok_or_else(|| anyhow!("No [faq] embedding model is configured"))?;
+        (faq.embed_url.clone(), faq.model_name.clone())
+    };
+    embed_with(ctx, &url, &model, input).await
+}
+
+/// Embed `input` with `model` at the embedding endpoint `url`
+pub async fn embed_with(
+    ctx: &Context<'_>,
+    url: &str,
+    model: &str,
+    input: &str,
+) -> Result<Vec<f32>> {
+    let client = reqwest::Client::new();
+    let request = EmbedRequest { model, input };
+    let response = async {
+        client
             .post(url)
-            .json(self)
+            .json(&request)
             .send()
             .await?
-            .json::<LLmChatResponse>()
-            .await?;
-        log_internal!("Sending request to chat endpoint {}... done", url);
-        let response_content = response.message.content;
+            .error_for_status()?
+            .json::<EmbedResponse>()
+            .await
+    }
+    .await;
+    match response {
+        Ok(response) => {
+            ctx.vstate.write().await.degraded.clear(Service::Llm);
+            response
+                .embeddings
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("Embedding endpoint returned no embeddings"))
+        }
+        Err(err) => {
+            ctx.vstate.write().await.degraded.mark(Service::Llm, &err);
+            Err(err.into())
+        }
+    }
+}
 
-        // TODO: split messages longer than the discord max of 2000 characters into multiple
-        // messages.  Put some time between them to avoid Discord thinking of it as spam.
-        if response_content.len() >= 1900 {
-            return Ok("I blabbed too long and my message was longer than the discord post limit and paradigm didn't implement a solution to cut a post up into multiple messages".to_string());
+/// Cosine similarity of two embeddings, or 0 if they're from different models
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Summarize a channel's `count` most recent messages, other than `exclude`, e.g. to carry the
+/// conversation over to another channel.  Returns `None` if `[llm_summary]` isn't configured.
+pub async fn summarize_recent(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    count: usize,
+    exclude: MessageId,
+) -> Result<Option<String>> {
+    let opted_out = ctx.pstate.read().await.llm_optout.users.clone();
+    History::ensure_backfilled(ctx, channel_id).await?;
+    let mut entries: Vec<String> = {
+        let mut vstate = ctx.vstate.write().await;
+        let history = vstate.history.get(ctx, channel_id).await?;
+        history
+            .iter()
+            .rev()
+            .filter(|entry| entry.message_id != exclude && !opted_out.contains(&entry.author_id))
+            .take(count)
+            .map(|entry| format!("{}: {}", entry.author_name, entry.llm_content()))
+            .collect()
+    };
+    entries.reverse();
+
+    let Some(summary_cfg) = ctx.cfg.read().await.llm_summary.clone() else {
+        return Ok(None);
+    };
+    summarize(ctx, &summary_cfg.as_llm_settings(), None, &entries)
+        .await
+        .map(Some)
+}
+
+/// Fold the channel's messages since its last topic summary into that summary, and save it.  Does
+/// nothing if `[llm_summary]` isn't configured.
+pub async fn update_topic_summary(ctx: &Context<'_>, channel_id: ChannelId) -> Result<()> {
+    let (opted_out, previous) = {
+        let pstate = ctx.pstate.read().await;
+        (
+            pstate.llm_optout.users.clone(),
+            pstate.topic_summaries.channels.get(&channel_id).cloned(),
+        )
+    };
+
+    History::ensure_backfilled(ctx, channel_id).await?;
+    let (entries, through) = {
+        let mut vstate = ctx.vstate.write().await;
+        let history = vstate.history.get(ctx, channel_id).await?;
+        let new: Vec<_> = history
+            .iter()
+            .filter(|entry| {
+                previous
+                    .as_ref()
+                    .is_none_or(|s| entry.message_id > s.through)
+            })
+            .collect();
+        let entries: Vec<String> = new
+            .iter()
+            .filter(|entry| !opted_out.contains(&entry.author_id))
+            .map(|entry| format!("{}: {}", entry.author_name, entry.llm_content()))
+            .collect();
+        (entries, new.last().map(|entry| entry.message_id))
+    };
+    let Some(through) = through else {
+        return Ok(());
+    };
+
+    let content = {
+        let Some(summary_cfg) = ctx.cfg.read().await.llm_summary.clone() else {
+            return Ok(());
+        };
+        if entries.is_empty() {
+            // Only opted out users spoke; keep the summary as is, but don't revisit their messages.
+            match &previous {
+                Some(previous) => previous.content.clone(),
+                None => return Ok(()),
+            }
+        } else {
+            summarize(
+                ctx,
+                &summary_cfg.as_llm_settings(),
+                previous.as_ref().map(|s| s.content.as_str()),
+                &entries,
+            )
+            .await?
         }
+    };
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate
+        .topic_summaries
+        .channels
+        .insert(channel_id, TopicSummary { content, through });
+    pstate.save().await
+}
 
-        Ok(response_content)
+/// Fold `entries` into a rolling summary of the conversation, building on `previous` if the
+/// channel has already been summarized.
+async fn summarize(
+    ctx: &Context<'_>,
+    settings: &LlmSettings<'_>,
+    previous: Option<&str>,
+    entries: &[String],
+) -> Result<String> {
+    let mut content = String::new();
+    if let Some(previous) = previous {
+        content.push_str("Summary so far:\n");
+        content.push_str(previous);
+        content.push_str("\n\n");
+    }
+    content.push_str("New messages:\n");
+    for entry in entries {
+        content.push_str(entry);
+        content.push('\n');
     }
+
+    LlmChatRequest::from_prompt(settings, content)
+        .post(ctx)
+        .await
+}
+
+/// Read an image from its archived copy if available, otherwise download it, and base64-encode it
+/// for the chat API.  Images larger than `max_bytes` are refused without reading them in full.
+async fn load_image(
+    url: &str,
+    archived: Option<&std::path::Path>,
+    max_bytes: u64,
+) -> Result<String> {
+    use base64::Engine;
+
+    let too_large = |size: u64| anyhow!("{} bytes exceeds the limit of {}", size, max_bytes);
+
+    let bytes = match archived {
+        Some(path) => {
+            let size = tokio::fs::metadata(path).await?.len();
+            if size > max_bytes {
+                return Err(too_large(size));
+            }
+            tokio::fs::read(path).await?
+        }
+        None => {
+            let mut response = reqwest::get(url).await?.error_for_status()?;
+            if let Some(size) = response.content_length().filter(|size| *size > max_bytes) {
+                return Err(too_large(size));
+            }
+            // Content-Length may be missing or wrong, so also stop reading past the limit
+            let mut bytes = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                bytes.extend_from_slice(&chunk);
+                if bytes.len() as u64 > max_bytes {
+                    return Err(too_large(bytes.len() as u64));
+                }
+            }
+            bytes
+        }
+    };
+    Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
 }
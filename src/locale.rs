@@ -0,0 +1,64 @@
+//! Per-guild translation of the bot's fixed text, configured in `[locale]`.
+//!
+//! Text is looked up by its English wording, so untranslated text falls back to English.  Error
+//! replies are translated when their whole text matches; those which include details, such as a
+//! command name, are left as is.  Command usage is looked up as `usage.<command>`, or
+//! `usage.<command>.detailed` for `help <command>`, where `{prefix}` stands for the command
+//! prefix.
+
+use crate::context::Context;
+use crate::plugin::Plugin;
+use serenity::all::GuildId;
+
+/// `english`, translated into the guild's language if possible
+pub async fn text(ctx: &Context<'_>, guild_id: Option<GuildId>, english: &str) -> String {
+    ctx.cfg
+        .read()
+        .await
+        .translation(guild_id, english)
+        .unwrap_or(english)
+        .to_string()
+}
+
+/// The plugin's `usage()`, translated into the guild's language if possible
+pub async fn usage(
+    ctx: &Context<'_>,
+    guild_id: Option<GuildId>,
+    plugin: &dyn Plugin,
+) -> Option<String> {
+    let usage = plugin.usage(ctx).await?;
+    Some(
+        translated_usage(ctx, guild_id, &format!("usage.{}", plugin.name()))
+            .await
+            .unwrap_or(usage),
+    )
+}
+
+/// The plugin's `detailed_usage()`, translated into the guild's language if possible.  Falls back
+/// on a translated `usage()` before the English detail.
+pub async fn detailed_usage(
+    ctx: &Context<'_>,
+    guild_id: Option<GuildId>,
+    plugin: &dyn Plugin,
+) -> Option<String> {
+    let detail = plugin.detailed_usage(ctx).await?;
+    for key in [
+        format!("usage.{}.detailed", plugin.name()),
+        format!("usage.{}", plugin.name()),
+    ] {
+        if let Some(translated) = translated_usage(ctx, guild_id, &key).await {
+            return Some(translated);
+        }
+    }
+    Some(detail)
+}
+
+async fn translated_usage(
+    ctx: &Context<'_>,
+    guild_id: Option<GuildId>,
+    key: &str,
+) -> Option<String> {
+    let cfg = ctx.cfg.read().await;
+    let translated = cfg.translation(guild_id, key)?;
+    Some(translated.replace("{prefix}", &cfg.general.command_prefix))
+}
@@ -1,13 +1,33 @@
+mod acl;
+mod archive;
+mod backup;
+mod channel_schedule;
 mod config;
+mod confirm;
 mod context;
+mod error;
 mod event;
 mod handler;
+mod health;
 mod helper;
+mod ladder;
 mod llm;
+mod locale;
 mod logging;
+mod middleware;
+mod notification;
 mod persistent_state;
+mod photo_contest;
 mod plugin;
+mod prompt_audit;
+#[cfg(feature = "redis")]
+mod redis_state;
+mod scheduler;
+mod subcommand;
 mod volatile_state;
+#[cfg(feature = "webhooks")]
+mod webhook;
+mod word_puzzle;
 
 use serenity::{all::GatewayIntents, Client};
 
@@ -15,12 +35,15 @@ use serenity::{all::GatewayIntents, Client};
 async fn main() -> anyhow::Result<()> {
     let cfg = crate::config::Config::load().await?;
     let token = cfg.general.discord_token.clone();
+    // Privileged, so only requested when needed; connecting fails unless it's enabled for the bot
+    let presences = cfg.stream_notify.is_some();
     let pstate = crate::persistent_state::PersistentState::load().await?;
     let vstate = crate::volatile_state::VolatileState::new().await;
     let handler = handler::Handler::new(cfg, pstate, vstate);
+    let vstate = handler.vstate();
 
     // Things we want discord to tell us about.
-    let intents = GatewayIntents::DIRECT_MESSAGES
+    let mut intents = GatewayIntents::DIRECT_MESSAGES
         | GatewayIntents::DIRECT_MESSAGE_REACTIONS
         | GatewayIntents::GUILDS
         | GatewayIntents::GUILD_MEMBERS
@@ -28,11 +51,50 @@ async fn main() -> anyhow::Result<()> {
         | GatewayIntents::GUILD_MESSAGE_REACTIONS
         | GatewayIntents::GUILD_VOICE_STATES
         | GatewayIntents::MESSAGE_CONTENT;
+    if presences {
+        intents |= GatewayIntents::GUILD_PRESENCES;
+    }
 
-    Client::builder(&token, intents)
+    let mut client = Client::builder(&token, intents)
         .event_handler(handler)
-        .await?
-        .start()
+        .await?;
+    // Lets `status` report gateway latency
+    client
+        .data
+        .write()
         .await
-        .map_err(Into::into)
+        .insert::<health::ShardManagerKey>(client.shard_manager.clone());
+
+    // Shutting the shards down returns from `start()`, so plugins get to clean up
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shard_manager.shutdown_all().await;
+    });
+
+    let result = client.start().await;
+    plugin::stop_all(&vstate).await;
+    result.map_err(Into::into)
+}
+
+/// Wait for SIGINT or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => log_internal!("Could not listen for SIGTERM: {}", err),
+        }
+    }
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        log_internal!("Could not listen for SIGINT: {}", err);
+        std::future::pending::<()>().await;
+    }
 }
@@ -0,0 +1,512 @@
+//! Cross-cutting event processing which wraps plugin dispatch
+//!
+//! Before an event reaches any plugin, each middleware in `middlewares()` may narrow down which
+//! plugins see it, e.g. skipping everything but passive plugins in a throttled channel.  After
+//! each ordered plugin runs, each middleware may observe its result, e.g. for metrics.  Which
+//! middlewares run, and in what order, is configurable with `[middleware] order`.
+
+use crate::error::Result;
+use crate::{
+    config::{AutoModAction, Config},
+    context::Context,
+    event::{Event, EventHandled},
+    helper::{post_mod_log, truncate},
+    llm::LlmChatRequest,
+    locale, log_internal,
+    plugin::{moderation, DmPolicy, Plugin, QuietMode},
+};
+use serenity::all::{GuildId, Message, Permissions, UserId};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+#[serenity::async_trait]
+pub trait Middleware: Sync + Send {
+    /// Called once per event, before any plugin runs.  Remove any plugins which shouldn't see the
+    /// event.
+    async fn before(&self, _ctx: &Context, _event: &Event, _plugins: &mut Vec<Box<dyn Plugin>>) {}
+    /// Called after each ordered plugin runs for the event, with its result
+    async fn after(
+        &self,
+        _ctx: &Context,
+        _event: &Event,
+        _plugin: &dyn Plugin,
+        _result: &Result<EventHandled>,
+    ) {
+    }
+}
+
+/// Every middleware's name, in the default order.  Cooldowns come last, so a use only counts
+/// against them if it reaches the plugin.
+pub const NAMES: &[&str] = &[
+    "metrics",
+    "blocklist",
+    "disabled_plugins",
+    "quiet",
+    "response_budget",
+    "auto_mod",
+    "ignore_bots",
+    "cooldowns",
+];
+
+/// Ordered list of middlewares each event passes through, per `[middleware] order`
+pub fn middlewares(cfg: &Config) -> Vec<Box<dyn Middleware>> {
+    let names: Vec<&str> = match cfg.middleware.as_ref().and_then(|m| m.order.as_ref()) {
+        Some(order) => order.iter().map(String::as_str).collect(),
+        None => NAMES.to_vec(),
+    };
+    // Unknown names were rejected when the config was loaded
+    names.into_iter().filter_map(by_name).collect()
+}
+
+fn by_name(name: &str) -> Option<Box<dyn Middleware>> {
+    Some(match name {
+        "metrics" => Box::new(Metrics),
+        "blocklist" => Box::new(Blocklist),
+        "disabled_plugins" => Box::new(DisabledPlugins),
+        "quiet" => Box::new(Quiet),
+        "response_budget" => Box::new(ResponseBudget),
+        "auto_mod" => Box::new(AutoMod),
+        "ignore_bots" => Box::new(IgnoreBots),
+        "cooldowns" => Box::new(Cooldowns),
+        _ => return None,
+    })
+}
+
+/// Counts events, and which plugins handled them
+struct Metrics;
+
+#[serenity::async_trait]
+impl Middleware for Metrics {
+    async fn before(&self, ctx: &Context, _event: &Event, _plugins: &mut Vec<Box<dyn Plugin>>) {
+        ctx.vstate.write().await.metrics.events += 1;
+    }
+
+    async fn after(
+        &self,
+        ctx: &Context,
+        event: &Event,
+        plugin: &dyn Plugin,
+        result: &Result<EventHandled>,
+    ) {
+        if let Ok(EventHandled::No) = result {
+            return;
+        }
+        let mut vstate = ctx.vstate.write().await;
+        *vstate.metrics.handled.entry(plugin.name()).or_default() += 1;
+        // Remembered so `debug` can tell which plugin answered a message
+        if let Event::Message(msg) = event {
+            if !msg.author.bot {
+                vstate.handlers.insert(msg.id, plugin.name());
+            }
+        }
+    }
+}
+
+/// Messages in guilds with `[auto_mod]` categories are classified, first by its rules, then by its
+/// LLM profile if it has one, and acted on.  Messages deleted by a rule only reach passive plugins,
+/// so nothing responds to them.  The LLM is only asked if no rule matched, and in the background so
+/// a slow endpoint doesn't hold up plugins; they may have responded by the time it's acted on.
+/// Members who may manage messages aren't moderated.
+struct AutoMod;
+
+/// Longest excerpt of a deleted message in the moderation log, in bytes
+const AUTO_MOD_EXCERPT_LEN: usize = 200;
+
+#[serenity::async_trait]
+impl Middleware for AutoMod {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Event::Message(msg) = event else {
+            return;
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return;
+        };
+        if msg.author.bot
+            || msg.content.trim().is_empty()
+            || msg
+                .author_permissions(ctx.cache)
+                .is_some_and(|p| p.contains(Permissions::MANAGE_MESSAGES))
+        {
+            return;
+        }
+
+        let (categories, profile) = match classify_by_rules(ctx, guild_id, msg).await {
+            Classification::Rule(category, action) => {
+                match moderate(ctx, guild_id, msg, &category, action).await {
+                    Ok(()) if action != AutoModAction::Flag => {
+                        plugins.retain(|plugin| plugin.passive())
+                    }
+                    Ok(()) => {}
+                    Err(err) => log_internal!("Could not moderate message {}: {}", msg.id, err),
+                }
+                return;
+            }
+            Classification::Llm(categories, profile) => (categories, profile),
+            Classification::Unmoderated => return,
+        };
+
+        let ctx = ctx.owned();
+        let msg = msg.clone();
+        tokio::spawn(async move {
+            let ctx = ctx.ctx();
+            let (category, action) = match classify_by_llm(&ctx, &msg, &categories, profile).await {
+                Ok(Some(verdict)) => verdict,
+                Ok(None) => return,
+                Err(err) => {
+                    log_internal!("Could not classify message {}: {}", msg.id, err);
+                    return;
+                }
+            };
+            if let Err(err) = moderate(&ctx, guild_id, &msg, &category, action).await {
+                log_internal!("Could not moderate message {}: {}", msg.id, err);
+            }
+        });
+    }
+}
+
+/// What `[auto_mod]`'s rules made of a message
+enum Classification {
+    /// A rule matched this category, with this action
+    Rule(String, AutoModAction),
+    /// No rule matched; ask this LLM profile which of the guild's categories it falls in
+    Llm(HashMap<String, AutoModAction>, String),
+    /// No rule matched and there's no LLM to ask, or the guild isn't moderated
+    Unmoderated,
+}
+
+/// Check `msg` against the rules for the guild's moderated categories
+async fn classify_by_rules(ctx: &Context<'_>, guild_id: GuildId, msg: &Message) -> Classification {
+    let cfg = ctx.cfg.read().await;
+    let Some(auto_mod) = cfg.auto_mod.as_ref() else {
+        return Classification::Unmoderated;
+    };
+    let Some(categories) = auto_mod.guilds.get(&guild_id) else {
+        return Classification::Unmoderated;
+    };
+    let words: HashSet<String> = msg
+        .content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let rule_match = categories.iter().find(|(category, _)| {
+        auto_mod
+            .rules
+            .get(*category)
+            .is_some_and(|rule| rule.iter().any(|word| words.contains(&word.to_lowercase())))
+    });
+    match (rule_match, &auto_mod.profile) {
+        (Some((category, action)), _) => Classification::Rule(category.clone(), *action),
+        (None, Some(profile)) => Classification::Llm(categories.clone(), profile.clone()),
+        (None, None) => Classification::Unmoderated,
+    }
+}
+
+/// The first of `categories` the `profile` LLM says `msg` falls in, and the action for it
+async fn classify_by_llm(
+    ctx: &Context<'_>,
+    msg: &Message,
+    categories: &HashMap<String, AutoModAction>,
+    profile: String,
+) -> anyhow::Result<Option<(String, AutoModAction)>> {
+    // Users who opted out of LLM features don't have their messages classified by it either
+    if ctx
+        .pstate
+        .read()
+        .await
+        .llm_optout
+        .users
+        .contains(&msg.author.id)
+    {
+        return Ok(None);
+    }
+
+    let mut names: Vec<&str> = categories.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    let content = format!(
+        "Categories: {}\nReply with the category the message belongs to, or `none`.\n\nMessage: {}",
+        names.join(", "),
+        msg.content
+    );
+    let request = {
+        let cfg = ctx.cfg.read().await;
+        let Some(profile) = cfg.llm_profiles.get(&profile) else {
+            return Ok(None);
+        };
+        LlmChatRequest::from_prompt(&profile.as_llm_settings(), content)
+    };
+    let verdict = request.post(ctx).await?.to_lowercase();
+    let verdict: HashSet<&str> = verdict
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .collect();
+    Ok(find_category(categories, &verdict))
+}
+
+fn find_category(
+    categories: &HashMap<String, AutoModAction>,
+    verdict: &HashSet<&str>,
+) -> Option<(String, AutoModAction)> {
+    categories
+        .iter()
+        .find(|(category, _)| verdict.contains(category.to_lowercase().as_str()))
+        .map(|(category, action)| (category.clone(), *action))
+}
+
+async fn moderate(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    msg: &Message,
+    category: &str,
+    action: AutoModAction,
+) -> Result<()> {
+    match action {
+        AutoModAction::Delete => {
+            msg.delete(ctx.cache_http).await?;
+            post_mod_log(
+                ctx,
+                guild_id,
+                &format!(
+                    "Deleted a message from <@{}> in <#{}> as {}: {}",
+                    msg.author.id,
+                    msg.channel_id,
+                    category,
+                    truncate(&msg.content, AUTO_MOD_EXCERPT_LEN)
+                ),
+            )
+            .await?;
+        }
+        AutoModAction::Warn => {
+            msg.delete(ctx.cache_http).await?;
+            moderation::warn(
+                ctx,
+                guild_id,
+                msg.author.id,
+                format!("Automatic: message removed as {}", category),
+            )
+            .await?;
+        }
+        AutoModAction::Flag => {
+            post_mod_log(
+                ctx,
+                guild_id,
+                &format!(
+                    "Flagged a message from <@{}> as {}: {}",
+                    msg.author.id,
+                    category,
+                    msg.link()
+                ),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Messages from channels or users on the `[blocklist]` or `;ignore` list only reach plugins
+/// which run when ignored.  See `Plugin::runs_when_ignored()`.
+struct Blocklist;
+
+#[serenity::async_trait]
+impl Middleware for Blocklist {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Event::Message(msg) = event else {
+            return;
+        };
+        let configured = ctx.cfg.read().await.blocklist.as_ref().is_some_and(|b| {
+            b.channels.contains(&msg.channel_id) || b.users.contains(&msg.author.id)
+        });
+        let ignored = configured || {
+            let pstate = ctx.pstate.read().await;
+            pstate.blocklist.channels.contains(&msg.channel_id)
+                || pstate.blocklist.users.contains(&msg.author.id)
+        };
+        if ignored {
+            plugins.retain(|plugin| plugin.runs_when_ignored());
+        }
+    }
+}
+
+/// Events in quiet channels only reach plugins which may run there.  See `plugin/quiet.rs`.
+struct Quiet;
+
+#[serenity::async_trait]
+impl Middleware for Quiet {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Some(channel_id) = event.channel_id() else {
+            return;
+        };
+        let Some(quiet) = ctx.pstate.read().await.quiet.get(channel_id) else {
+            return;
+        };
+        plugins.retain(|plugin| match plugin.in_quiet_channels() {
+            QuietMode::Run => true,
+            QuietMode::Record => quiet.record_history,
+            QuietMode::Skip => false,
+        });
+    }
+}
+
+/// Counts the bot's own messages against `[response_budget]`.  Once a channel has exceeded it,
+/// its events only reach passive plugins, so nothing responds.
+struct ResponseBudget;
+
+#[serenity::async_trait]
+impl Middleware for ResponseBudget {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Some(channel_id) = event.channel_id() else {
+            return;
+        };
+        let Some(limit) = ctx
+            .cfg
+            .read()
+            .await
+            .response_budget
+            .as_ref()
+            .map(|budget| budget.messages_per_minute)
+        else {
+            return;
+        };
+
+        let mut vstate = ctx.vstate.write().await;
+        if let Event::Message(msg) = event {
+            if msg.author.id == ctx.cache.current_user().id {
+                vstate.response_budget.record(channel_id);
+            }
+        }
+        let (throttled, newly) = vstate.response_budget.check(channel_id, limit);
+        if newly {
+            log_internal!(
+                "Response budget exceeded in {}; ignoring events there for now",
+                channel_id
+            );
+        }
+        if throttled {
+            plugins.retain(|plugin| plugin.passive());
+        }
+    }
+}
+
+/// Messages from bots, including our own, only reach passive plugins and those which see bot
+/// messages, so two bots can't trigger each other into spam.  See
+/// `Plugin::sees_bot_messages()`.
+struct IgnoreBots;
+
+#[serenity::async_trait]
+impl Middleware for IgnoreBots {
+    async fn before(&self, _ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        if let Event::Message(msg) = event {
+            if msg.author.bot {
+                plugins.retain(|plugin| plugin.passive() || plugin.sees_bot_messages());
+            }
+        }
+    }
+}
+
+/// Events in a guild only reach plugins it hasn't turned off in `[middleware] disabled_plugins`
+struct DisabledPlugins;
+
+#[serenity::async_trait]
+impl Middleware for DisabledPlugins {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Some(guild_id) = event.guild_id() else {
+            return;
+        };
+        let cfg = ctx.cfg.read().await;
+        let Some(disabled) = cfg
+            .middleware
+            .as_ref()
+            .and_then(|middleware| middleware.disabled_plugins.get(&guild_id))
+        else {
+            return;
+        };
+        plugins.retain(|plugin| !disabled.iter().any(|name| name == plugin.name()));
+    }
+}
+
+/// Rate limits plugins with a `Plugin::cooldown()`.  Until a user's last use of such a command has
+/// cooled down, their next is answered with how long is left and only reaches passive plugins.
+/// Every use which reaches the plugin counts, even one it then refuses.
+struct Cooldowns;
+
+#[serenity::async_trait]
+impl Middleware for Cooldowns {
+    async fn before(&self, ctx: &Context, event: &Event, plugins: &mut Vec<Box<dyn Plugin>>) {
+        let Event::Message(msg) = event else {
+            return;
+        };
+        if msg.author.bot {
+            return;
+        }
+        let mut limited = None;
+        for plugin in plugins.iter() {
+            let Some(command) = plugin.command() else {
+                continue;
+            };
+            if event.is_dm() && plugin.dm_policy() == DmPolicy::GuildOnly
+                || event.is_bot_cmd(ctx, command).await.is_none()
+            {
+                continue;
+            }
+            let Some(cooldown) = plugin.cooldown(ctx).await else {
+                continue;
+            };
+            if let Err(remaining) =
+                start_cooldown(ctx, plugin.name(), msg.author.id, cooldown).await
+            {
+                limited = Some((command, remaining));
+                break;
+            }
+        }
+        let Some((command, remaining)) = limited else {
+            return;
+        };
+
+        plugins.retain(|plugin| plugin.passive());
+        let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+        let reply = format!(
+            "Please wait {} more seconds before using `{}{}` again",
+            remaining.as_secs() + 1,
+            prefix,
+            command
+        );
+        let reply = locale::text(ctx, msg.guild_id, &reply).await;
+        if let Err(err) = msg.reply(ctx.cache_http, reply).await {
+            log_internal!("Could not reply to rate limited {}: {}", msg.id, err);
+        }
+    }
+}
+
+/// Start `user_id`'s cooldown for `plugin` if it has elapsed, otherwise the time remaining.
+/// Shared between instances through Redis if it's the state backend.
+async fn start_cooldown(
+    ctx: &Context<'_>,
+    plugin: &'static str,
+    user_id: UserId,
+    cooldown: Duration,
+) -> std::result::Result<(), Duration> {
+    #[cfg(feature = "redis")]
+    if let Some(started) = crate::redis_state::start_cooldown(ctx, plugin, user_id, cooldown).await
+    {
+        return started;
+    }
+    ctx.vstate
+        .write()
+        .await
+        .cooldowns
+        .start(plugin, user_id, cooldown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_has_a_middleware() {
+        for name in NAMES {
+            assert!(by_name(name).is_some(), "{}", name);
+        }
+        assert!(by_name("nonexistent").is_none());
+    }
+}
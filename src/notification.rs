@@ -0,0 +1,88 @@
+//! Direct message notifications to users
+//!
+//! Users may opt in to receiving notifications as a periodic digest rather than one DM per
+//! notification.  Plugins should send DM notifications through `notify_user()` so the user's
+//! preference is honored.
+
+use crate::helper::{split_message, MESSAGE_MAX_LEN};
+use crate::{context::Context, log_internal};
+use anyhow::Result;
+use serenity::all::{CreateMessage, UserId};
+use std::time::Duration;
+
+/// How often to check for digests which are due
+pub const DIGEST_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Notify a user, either immediately via DM or by queuing for their next digest.
+pub async fn notify_user(ctx: &Context<'_>, user_id: UserId, content: String) -> Result<()> {
+    let digest_minutes = ctx
+        .pstate
+        .read()
+        .await
+        .notification_digest
+        .intervals_minutes
+        .get(&user_id)
+        .cloned();
+
+    if digest_minutes.is_some() {
+        ctx.vstate.write().await.digests.push(user_id, content);
+        return Ok(());
+    }
+
+    user_id
+        .to_user(ctx.http)
+        .await?
+        .direct_message(ctx.cache_http, CreateMessage::new().content(content))
+        .await?;
+    Ok(())
+}
+
+/// Deliver any queued digests whose interval has elapsed.
+pub async fn deliver_due_digests(ctx: &Context<'_>) -> Result<()> {
+    let intervals = ctx
+        .pstate
+        .read()
+        .await
+        .notification_digest
+        .intervals_minutes
+        .clone();
+
+    let due = ctx.vstate.read().await.digests.due(|user_id| {
+        // Users who disabled digests since the notification was queued get it on the next poll.
+        intervals
+            .get(&user_id)
+            .map(|minutes| Duration::from_secs(minutes * 60))
+            .unwrap_or(Duration::ZERO)
+    });
+
+    for (user_id, items) in due {
+        let mut content = format!("Notification digest ({} since last digest):\n", items.len());
+        for item in &items {
+            content.push_str("\n• ");
+            content.push_str(item);
+        }
+
+        // Keep going for other users if one can't be DM'd
+        let result = async {
+            let user = user_id.to_user(ctx.http).await?;
+            for chunk in split_message(&content, MESSAGE_MAX_LEN) {
+                user.direct_message(ctx.cache_http, CreateMessage::new().content(chunk))
+                    .await?;
+            }
+            Ok::<_, serenity::Error>(())
+        }
+        .await;
+        match result {
+            Ok(()) => ctx
+                .vstate
+                .write()
+                .await
+                .digests
+                .remove_delivered(user_id, items.len()),
+            // Left queued to retry on the next poll
+            Err(err) => log_internal!("Could not deliver digest to {}: {}", user_id, err),
+        }
+    }
+
+    Ok(())
+}
@@ -1,12 +1,15 @@
+use crate::error::MaintenanceMode;
 use anyhow::{anyhow, Result};
-use serenity::all::UserId;
+use serenity::all::{ChannelId, EmojiId, GuildId, MessageId, RoleId, StickerId, UserId};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     path::PathBuf,
+    time::SystemTime,
 };
 use tokio::io::AsyncReadExt;
 
 const PSTATE_PATH_REL_HOME: &str = ".config/digmbot/state.toml";
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
 
 /// State which persists across sessions
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -14,6 +17,73 @@ pub struct PersistentState {
     pub vc_notify: VcNotify,
     pub rivals_ratings: RivalsRatings,
     pub rivals_ratings_owners: RivalsRatingsOwners,
+    #[serde(default)]
+    pub stream_notify: StreamNotify,
+    #[serde(default)]
+    pub llm_optout: LlmOptOut,
+    #[serde(default)]
+    pub notification_digest: NotificationDigest,
+    #[serde(default)]
+    pub stats: Stats,
+    #[serde(default)]
+    pub moderation: Moderation,
+    #[serde(default)]
+    pub self_roles: SelfRoles,
+    #[serde(default)]
+    pub schedules: Schedules,
+    #[serde(default, skip_serializing_if = "SentLog::is_transient")]
+    pub sent_log: SentLog,
+    #[serde(default)]
+    pub backups: Backups,
+    #[serde(default)]
+    pub quiet: Quiet,
+    #[serde(default)]
+    pub reactions: Reactions,
+    #[serde(default)]
+    pub rivals_snapshot: RivalsSnapshot,
+    #[serde(default)]
+    pub rivals_seasons: RivalsSeasons,
+    #[serde(default)]
+    pub rivals_pending: RivalsPending,
+    #[serde(default)]
+    pub undo: UndoStack,
+    #[serde(default)]
+    pub topic_summaries: TopicSummaries,
+    #[serde(default)]
+    pub acl: Acl,
+    #[serde(default)]
+    pub wake_words: WakeWords,
+    #[serde(default)]
+    pub typing_pace: TypingPaceChannels,
+    #[serde(default)]
+    pub llm_profiles: LlmProfileChannels,
+    #[serde(default)]
+    pub faq: Faq,
+    #[serde(default)]
+    pub facts: Facts,
+    #[serde(default)]
+    pub blocklist: Blocklist,
+    #[serde(default)]
+    pub thread_titles: ThreadTitles,
+    #[serde(default)]
+    pub names: NameHistory,
+    #[serde(default)]
+    pub weather: WeatherLocations,
+    #[serde(default)]
+    pub trivia: TriviaScores,
+    #[serde(default)]
+    pub impersonate_optout: ImpersonateOptOut,
+    #[serde(default)]
+    pub word_puzzles: WordPuzzles,
+    #[serde(default)]
+    pub photo_contests: PhotoContests,
+    #[serde(default)]
+    pub rules_index: RulesIndex,
+    #[serde(default)]
+    pub todo: TodoLists,
+    /// Refuse to save during maintenance.  Mirrors `VolatileState::maintenance`.
+    #[serde(skip)]
+    pub read_only: bool,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -21,14 +91,976 @@ pub struct VcNotify {
     pub followers: HashSet<UserId>,
 }
 
+/// Users who opted in to having their streams announced
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct StreamNotify {
+    pub streamers: HashSet<UserId>,
+}
+
+/// Users whose messages must not be sent to, or replied to by, the LLM
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct LlmOptOut {
+    pub users: HashSet<UserId>,
+}
+
+/// Users who prefer DM notifications batched into periodic digests
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct NotificationDigest {
+    pub intervals_minutes: HashMap<UserId, u64>,
+}
+
+/// Activity statistics.  Timestamps are unix seconds.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Stats {
+    pub channels: HashMap<ChannelId, ChannelStats>,
+    pub roles: HashMap<RoleId, RoleStats>,
+    /// Uses of each guild's custom emoji, in messages and reactions
+    #[serde(default)]
+    pub emoji: HashMap<GuildId, HashMap<EmojiId, DailyUses>>,
+    #[serde(default)]
+    pub stickers: HashMap<GuildId, HashMap<StickerId, DailyUses>>,
+}
+
+/// Uses per day, oldest first
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct DailyUses(Vec<DailyUse>);
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DailyUse {
+    /// Days since the unix epoch
+    pub day: i64,
+    pub count: u64,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct ChannelStats {
+    pub messages: u64,
+    pub voice_joins: u64,
+    pub last_active: i64,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RoleStats {
+    pub last_active: i64,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Moderation {
+    /// Per-guild log of moderation actions, oldest first
+    pub cases: HashMap<GuildId, Vec<ModCase>>,
+    /// Temporary mutes awaiting removal of the mute role
+    pub active_mutes: Vec<ActiveMute>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModCase {
+    pub id: u64,
+    pub action: ModAction,
+    pub user_id: UserId,
+    pub moderator_id: UserId,
+    pub reason: String,
+    /// Unix seconds
+    pub timestamp: i64,
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModAction {
+    Warn,
+    Mute,
+    Unmute,
+    Kick,
+    Ban,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ActiveMute {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    /// Unix seconds
+    pub expires_at: i64,
+}
+
+/// Per-guild roles which members may assign to themselves
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct SelfRoles {
+    pub allowed: HashMap<GuildId, HashSet<RoleId>>,
+}
+
+/// Cron-like scheduled actions.  See `scheduler.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Schedules {
+    pub next_id: u64,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScheduleEntry {
+    pub id: u64,
+    pub guild_id: GuildId,
+    pub creator_id: UserId,
+    /// Cron expression
+    pub cron: String,
+    /// IANA timezone name in which `cron` is evaluated
+    pub timezone: String,
+    pub action: ScheduledAction,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ScheduledAction {
+    Post {
+        channel_id: ChannelId,
+        message: String,
+    },
+}
+
+/// Recent messages sent by the bot, for `debug sent`.  Only written to disk if configured.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct SentLog {
+    pub entries: VecDeque<SentEntry>,
+    /// Mirrors `[sent_log] persist`
+    #[serde(skip)]
+    pub persist: bool,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SentEntry {
+    /// Unix seconds
+    pub timestamp: i64,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    /// Message the bot was replying to, if any
+    pub trigger: Option<MessageId>,
+    /// Plugin which handled the trigger, if known
+    pub plugin: Option<String>,
+    /// Truncated SHA-256 of the content, to match reports without storing what was said
+    pub content_hash: String,
+    pub content_len: usize,
+}
+
+/// Backups which can only be found again by remembering them.  See `backup.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Backups {
+    /// Names of snapshots uploaded to S3, oldest first
+    pub s3_snapshots: VecDeque<String>,
+}
+
+/// Channels in which the bot must not speak.  Enforced by the dispatcher.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Quiet {
+    pub channels: HashMap<GuildId, HashMap<ChannelId, QuietChannel>>,
+}
+
+/// Channels in which `llm_reply` answers messages starting with the bot's name.  See
+/// `plugin/wake_word.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct WakeWords {
+    pub channels: HashMap<GuildId, HashSet<ChannelId>>,
+}
+
+/// Channels in which `llm_reply` paces its answers as if typing.  See `plugin/typing_pace.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct TypingPaceChannels {
+    pub channels: HashMap<GuildId, HashSet<ChannelId>>,
+}
+
+/// `[llm_profiles]` which channels' replies use instead of `[llm_reply]`.  See
+/// `plugin/llm_control.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct LlmProfileChannels {
+    pub channels: HashMap<ChannelId, String>,
+}
+
+/// Channels and users whose messages are ignored.  See `plugin/ignore.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Blocklist {
+    pub channels: HashSet<ChannelId>,
+    pub users: HashSet<UserId>,
+}
+
+/// Threads already summarized by `plugin/thread_titles.rs`, by the summary's message.  A thread is
+/// summarized again only if there's been activity since.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct ThreadTitles {
+    pub summarized: HashMap<ChannelId, MessageId>,
+}
+
+/// Each guild's daily word puzzle.  See `word_puzzle.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct WordPuzzles {
+    pub guilds: HashMap<GuildId, GuildPuzzle>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct GuildPuzzle {
+    pub number: u64,
+    pub word: String,
+    /// Unix seconds
+    pub posted: i64,
+    /// Today's guesses per player
+    pub guesses: HashMap<UserId, Vec<String>>,
+    /// Solves of recent puzzles, for the weekly leaderboard
+    pub solves: Vec<PuzzleSolve>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PuzzleSolve {
+    pub number: u64,
+    /// Unix seconds of the puzzle's posting
+    pub posted: i64,
+    pub user_id: UserId,
+    pub guesses: usize,
+}
+
+/// Photo contests in progress, and past winners.  See `photo_contest.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct PhotoContests {
+    pub channels: HashMap<ChannelId, ContestState>,
+    /// Winners per guild, oldest first
+    pub hall_of_fame: HashMap<GuildId, Vec<ContestWinner>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ContestState {
+    pub phase: ContestPhase,
+    /// In order of submission.  One per member.
+    pub entries: Vec<ContestEntry>,
+    /// The post members vote on by reacting
+    pub voting_message: Option<MessageId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContestPhase {
+    Submissions,
+    Voting,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ContestEntry {
+    pub message_id: MessageId,
+    pub author_id: UserId,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ContestWinner {
+    /// Unix seconds
+    pub timestamp: i64,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub user_id: UserId,
+    pub votes: u64,
+}
+
+/// Users whose messages `;impersonate` must not imitate
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct ImpersonateOptOut {
+    pub users: HashSet<UserId>,
+}
+
+/// Lifetime `;trivia` points, per guild
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct TriviaScores {
+    pub guilds: HashMap<GuildId, HashMap<UserId, u64>>,
+}
+
+/// Users' default `;weather` locations, as given
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct WeatherLocations {
+    pub users: HashMap<UserId, String>,
+}
+
+/// Members' previous names.  See `plugin/names.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct NameHistory {
+    pub users: HashMap<UserId, UserNames>,
+}
+
+/// A user's names, oldest first
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct UserNames {
+    #[serde(default)]
+    pub usernames: Vec<NameRecord>,
+    #[serde(default)]
+    pub display_names: Vec<NameRecord>,
+    /// Per-guild, as nicknames are
+    #[serde(default)]
+    pub nicknames: HashMap<GuildId, Vec<NameRecord>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct NameRecord {
+    pub name: String,
+    /// When the name was first seen, as unix seconds
+    pub since: i64,
+}
+
+/// Per-guild frequently asked questions.  See `plugin/faq.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Faq {
+    pub next_id: u64,
+    pub guilds: HashMap<GuildId, Vec<FaqEntry>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct FaqEntry {
+    pub id: u64,
+    pub question: String,
+    pub answer: String,
+    /// `question`'s embedding, from the `[faq]` model at the time it was added
+    pub embedding: Vec<f32>,
+}
+
+/// Per-guild facts included in LLM replies.  See `plugin/facts.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Facts {
+    pub next_id: u64,
+    pub guilds: HashMap<GuildId, Vec<Fact>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Fact {
+    pub id: u64,
+    pub text: String,
+    pub added_by: UserId,
+}
+
+/// Per-user and shared per-channel to-do lists.  See `plugin/todo.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct TodoLists {
+    pub users: HashMap<UserId, Vec<TodoItem>>,
+    pub channels: HashMap<ChannelId, Vec<TodoItem>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct TodoItem {
+    pub text: String,
+    pub added_by: UserId,
+    /// Unix seconds
+    pub due: Option<i64>,
+    /// Whether the reminder for `due` has been sent
+    #[serde(default)]
+    pub reminded: bool,
+}
+
+/// Passages of each guild's rules and FAQ channels.  See `plugin/rules_qa.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RulesIndex {
+    pub guilds: HashMap<GuildId, Vec<RulesPassage>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct RulesPassage {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub text: String,
+    /// `text`'s embedding, from the `[faq]` model at the time it was indexed
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct QuietChannel {
+    /// Whether to still record the channel's history, e.g. for summaries
+    pub record_history: bool,
+}
+
+/// Reactions received, for the leaderboard and weekly highlight
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Reactions {
+    /// All-time reactions received per guild member
+    pub received: HashMap<GuildId, HashMap<UserId, u64>>,
+    /// Reactions on messages from the past week
+    pub messages: HashMap<MessageId, ReactedMessage>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReactedMessage {
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub count: u64,
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct RivalsRatings(pub HashMap<String, usize>);
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct RivalsRatingsOwners(pub HashMap<String, UserId>);
 
+/// Ratings as of the start of the week, to show recent changes on the leaderboard
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RivalsSnapshot {
+    /// Unix seconds
+    pub taken: i64,
+    pub ratings: HashMap<String, usize>,
+}
+
+/// The current rivals season's matches, and archives of past seasons
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RivalsSeasons {
+    /// Name of the current season, if one was started
+    pub current: Option<String>,
+    /// When the current season started, as unix seconds
+    pub started: i64,
+    /// Matches reported this season, oldest first
+    pub matches: Vec<RivalsMatch>,
+    /// Oldest first
+    pub archived: Vec<RivalsSeason>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct RivalsMatch {
+    /// Unix seconds
+    pub timestamp: i64,
+    pub winner: String,
+    pub loser: String,
+    pub winner_before: usize,
+    pub winner_after: usize,
+    pub loser_before: usize,
+    pub loser_after: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct RivalsSeason {
+    pub name: String,
+    /// Unix seconds
+    pub started: i64,
+    pub ended: i64,
+    /// Final ratings
+    pub ratings: HashMap<String, usize>,
+    pub matches: Vec<RivalsMatch>,
+}
+
+/// Reported matches awaiting confirmation by the winner's owner
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RivalsPending {
+    pub next_id: u64,
+    pub matches: Vec<PendingMatch>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingMatch {
+    pub id: u64,
+    pub winner: String,
+    pub loser: String,
+    pub reporter: UserId,
+    pub winner_owner: UserId,
+    /// The bot's message asking for confirmation, which may be reacted to
+    pub prompt: MessageId,
+    /// Unix seconds
+    pub expires: i64,
+}
+
+/// Recent state changes which may be reverted with `undo`, oldest first
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct UndoStack {
+    pub entries: Vec<UndoEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct UndoEntry {
+    /// User who made the change
+    pub actor: UserId,
+    /// Unix seconds
+    pub timestamp: i64,
+    /// e.g. "report `a` beat `b`"
+    pub description: String,
+    pub inverse: UndoOp,
+}
+
+/// How to revert a change
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UndoOp {
+    /// Restore ratings as they were before a match report, and forget the match
+    UnreportMatch { ratings: Vec<(String, usize)> },
+    /// Remove a created player
+    DeletePlayer { player: String },
+    /// Restore a deleted player
+    RestorePlayer {
+        player: String,
+        rating: usize,
+        owner: UserId,
+    },
+}
+
+/// Rolling LLM summaries of each active channel's conversation, kept across restarts so the LLM
+/// has context before history is backfilled.  See `plugin/topic_summary.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct TopicSummaries {
+    pub channels: HashMap<ChannelId, TopicSummary>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct TopicSummary {
+    pub content: String,
+    /// Most recent message incorporated into the summary
+    pub through: MessageId,
+}
+
+/// Per-guild command access control lists, by capability.  See `acl.rs`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Acl {
+    pub guilds: HashMap<GuildId, HashMap<String, CapabilityAcl>>,
+    /// Capabilities bot owners have delegated to users, in every guild
+    #[serde(default)]
+    pub grants: HashMap<UserId, HashSet<String>>,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct CapabilityAcl {
+    #[serde(default)]
+    pub allow_users: HashSet<UserId>,
+    #[serde(default)]
+    pub allow_roles: HashSet<RoleId>,
+    #[serde(default)]
+    pub deny_users: HashSet<UserId>,
+    #[serde(default)]
+    pub deny_roles: HashSet<RoleId>,
+}
+
+impl CapabilityAcl {
+    pub fn is_empty(&self) -> bool {
+        self.allow_users.is_empty()
+            && self.allow_roles.is_empty()
+            && self.deny_users.is_empty()
+            && self.deny_roles.is_empty()
+    }
+
+    /// Whether the rules allow (`Some(true)`) or deny (`Some(false)`) a member, or don't say.
+    /// User rules take precedence over role rules, and denies over allows.  Once there are any
+    /// allow rules, members not allowed are denied.
+    pub fn decide(&self, user_id: UserId, roles: &[RoleId]) -> Option<bool> {
+        if self.deny_users.contains(&user_id) {
+            Some(false)
+        } else if self.allow_users.contains(&user_id) {
+            Some(true)
+        } else if roles.iter().any(|role| self.deny_roles.contains(role)) {
+            Some(false)
+        } else if roles.iter().any(|role| self.allow_roles.contains(role)) {
+            Some(true)
+        } else if !self.allow_users.is_empty() || !self.allow_roles.is_empty() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl Acl {
+    /// Whether a member may use `capability`, e.g. `rivals.delete`, or `None` if no rule applies.
+    /// A parent capability's denials, such as `rivals`, also apply to `rivals.delete`, but its
+    /// allows don't, so allowing a command doesn't grant its privileged subcommands.
+    pub fn decide(
+        &self,
+        guild_id: GuildId,
+        capability: &str,
+        user_id: UserId,
+        roles: &[RoleId],
+    ) -> Option<bool> {
+        let acls = self.guilds.get(&guild_id)?;
+        let mut decision = acls
+            .get(capability)
+            .and_then(|acl| acl.decide(user_id, roles));
+        let mut parent = capability;
+        while let Some((prefix, _)) = parent.rsplit_once('.') {
+            parent = prefix;
+            let parent_decision = acls.get(parent).and_then(|acl| acl.decide(user_id, roles));
+            if parent_decision == Some(false) {
+                decision = Some(false);
+            }
+        }
+        decision
+    }
+
+    /// Whether `user_id` was granted `capability`, or a parent of it such as `rivals` for
+    /// `rivals.delete`
+    pub fn is_granted(&self, user_id: UserId, capability: &str) -> bool {
+        let Some(grants) = self.grants.get(&user_id) else {
+            return false;
+        };
+        let mut capability = capability;
+        loop {
+            if grants.contains(capability) {
+                return true;
+            }
+            match capability.rsplit_once('.') {
+                Some((parent, _)) => capability = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl Stats {
+    /// Drop channels and roles last active before `cutoff` (unix seconds).  Returns the number of
+    /// records removed.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let len = self.channels.len() + self.roles.len();
+        self.channels.retain(|_, stats| stats.last_active >= cutoff);
+        self.roles.retain(|_, stats| stats.last_active >= cutoff);
+        let mut removed = len - self.channels.len() - self.roles.len();
+
+        let cutoff_day = cutoff.div_euclid(SECS_PER_DAY);
+        let usage = self
+            .emoji
+            .values_mut()
+            .flat_map(|emoji| emoji.values_mut())
+            .chain(
+                self.stickers
+                    .values_mut()
+                    .flat_map(|stickers| stickers.values_mut()),
+            );
+        for uses in usage {
+            removed += uses.remove_older_than(cutoff_day);
+        }
+        for emoji in self.emoji.values_mut() {
+            emoji.retain(|_, uses| !uses.0.is_empty());
+        }
+        for stickers in self.stickers.values_mut() {
+            stickers.retain(|_, uses| !uses.0.is_empty());
+        }
+        removed
+    }
+}
+
+impl DailyUses {
+    /// Count a use at `timestamp` (unix seconds)
+    pub fn record(&mut self, timestamp: i64) {
+        let day = timestamp.div_euclid(SECS_PER_DAY);
+        match self.0.last_mut() {
+            Some(last) if last.day == day => last.count += 1,
+            _ => self.0.push(DailyUse { day, count: 1 }),
+        }
+    }
+
+    /// Uses since `timestamp` (unix seconds), counting its whole day
+    pub fn since(&self, timestamp: i64) -> u64 {
+        let day = timestamp.div_euclid(SECS_PER_DAY);
+        self.0
+            .iter()
+            .filter(|uses| uses.day >= day)
+            .map(|uses| uses.count)
+            .sum()
+    }
+
+    /// Drop days before `day`.  Returns the number of days removed.
+    fn remove_older_than(&mut self, day: i64) -> usize {
+        let len = self.0.len();
+        self.0.retain(|uses| uses.day >= day);
+        len - self.0.len()
+    }
+}
+
+impl std::fmt::Display for ModAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            ModAction::Warn => "warn",
+            ModAction::Mute => "mute",
+            ModAction::Unmute => "unmute",
+            ModAction::Kick => "kick",
+            ModAction::Ban => "ban",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Moderation {
+    /// Record a new case, assigning it the next case number for the guild.
+    pub fn add_case(&mut self, guild_id: GuildId, mut case: ModCase) -> ModCase {
+        let cases = self.cases.entry(guild_id).or_default();
+        case.id = cases.last().map(|c| c.id + 1).unwrap_or(1);
+        cases.push(case.clone());
+        case
+    }
+}
+
+impl NameHistory {
+    /// Forget names which were replaced before `cutoff` (unix seconds).  Current names are kept.
+    /// Returns the number forgotten.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let mut removed = 0;
+        for names in self.users.values_mut() {
+            let lists = std::iter::once(&mut names.usernames)
+                .chain(std::iter::once(&mut names.display_names))
+                .chain(names.nicknames.values_mut());
+            for list in lists {
+                // Each name was used until the next was first seen
+                let replaced = list
+                    .windows(2)
+                    .take_while(|pair| pair[1].since < cutoff)
+                    .count();
+                list.drain(..replaced);
+                removed += replaced;
+            }
+        }
+        removed
+    }
+}
+
+impl UserNames {
+    /// Names kept of each kind, per user
+    const MAX_NAMES: usize = 20;
+
+    /// Record `name` as the current name in `names`, if it's changed.  Returns whether it had.
+    pub fn observe(names: &mut Vec<NameRecord>, name: &str, now: i64) -> bool {
+        if names.last().is_some_and(|last| last.name == name) {
+            return false;
+        }
+        names.push(NameRecord {
+            name: name.to_string(),
+            since: now,
+        });
+        if names.len() > Self::MAX_NAMES {
+            names.remove(0);
+        }
+        true
+    }
+}
+
+impl Faq {
+    pub fn add(&mut self, guild_id: GuildId, mut entry: FaqEntry) -> u64 {
+        self.next_id += 1;
+        entry.id = self.next_id;
+        self.guilds.entry(guild_id).or_default().push(entry);
+        self.next_id
+    }
+}
+
+impl Facts {
+    pub fn add(&mut self, guild_id: GuildId, mut fact: Fact) -> u64 {
+        self.next_id += 1;
+        fact.id = self.next_id;
+        self.guilds.entry(guild_id).or_default().push(fact);
+        self.next_id
+    }
+}
+
+impl Schedules {
+    pub fn add(&mut self, mut entry: ScheduleEntry) -> u64 {
+        self.next_id += 1;
+        entry.id = self.next_id;
+        self.entries.push(entry);
+        self.next_id
+    }
+}
+
+impl SentLog {
+    fn is_transient(&self) -> bool {
+        !self.persist
+    }
+
+    /// Drop entries older than `cutoff` (unix seconds).  Returns the number removed.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.timestamp >= cutoff);
+        len - self.entries.len()
+    }
+
+    pub fn push(&mut self, entry: SentEntry, capacity: usize) {
+        self.entries.push_back(entry);
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+impl TopicSummaries {
+    /// Drop summaries last updated with messages older than `cutoff` (unix seconds).  Returns the
+    /// number removed.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let len = self.channels.len();
+        self.channels
+            .retain(|_, summary| summary.through.created_at().unix_timestamp() >= cutoff);
+        len - self.channels.len()
+    }
+}
+
+impl Quiet {
+    pub fn get(&self, channel_id: ChannelId) -> Option<QuietChannel> {
+        self.channels
+            .values()
+            .find_map(|channels| channels.get(&channel_id))
+            .copied()
+    }
+}
+
+impl Reactions {
+    /// Count a reaction to `message_id`, unless the author reacted to their own message
+    pub fn add(&mut self, message_id: MessageId, message: ReactedMessage, reactor: UserId) {
+        if reactor == message.author_id {
+            return;
+        }
+        *self
+            .received
+            .entry(message.guild_id)
+            .or_default()
+            .entry(message.author_id)
+            .or_default() += 1;
+        self.messages
+            .entry(message_id)
+            .or_insert(ReactedMessage {
+                count: 0,
+                ..message
+            })
+            .count += 1;
+    }
+
+    /// Uncount a reaction.  Reactions to messages no longer tracked are ignored.
+    pub fn remove(&mut self, message_id: MessageId, reactor: UserId) {
+        let Some(message) = self.messages.get_mut(&message_id) else {
+            return;
+        };
+        if reactor == message.author_id || message.count == 0 {
+            return;
+        }
+        message.count -= 1;
+        if let Some(count) = self
+            .received
+            .get_mut(&message.guild_id)
+            .and_then(|users| users.get_mut(&message.author_id))
+        {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// Stop tracking messages posted before `cutoff` (unix seconds).  All-time totals are kept.
+    pub fn remove_older_than(&mut self, cutoff: i64) {
+        self.messages
+            .retain(|id, _| id.created_at().unix_timestamp() >= cutoff);
+    }
+
+    /// A guild's most reacted messages, most reacted first
+    pub fn top_messages(
+        &self,
+        guild_id: GuildId,
+        count: usize,
+    ) -> Vec<(MessageId, ReactedMessage)> {
+        let mut messages: Vec<_> = self
+            .messages
+            .iter()
+            .filter(|(_, message)| message.guild_id == guild_id && message.count > 0)
+            .map(|(id, message)| (*id, message.clone()))
+            .collect();
+        messages.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        messages.truncate(count);
+        messages
+    }
+
+    /// A guild's members who received the most reactions, most first
+    pub fn top_users(&self, guild_id: GuildId, count: usize) -> Vec<(UserId, u64)> {
+        let mut users: Vec<_> = self
+            .received
+            .get(&guild_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, received)| **received > 0)
+            .map(|(id, received)| (*id, *received))
+            .collect();
+        users.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        users.truncate(count);
+        users
+    }
+}
+
+impl RivalsSnapshot {
+    const MAX_AGE_SECS: i64 = 7 * 24 * 60 * 60;
+
+    /// Snapshot `ratings` if the current snapshot is a week old.  Call before changing ratings.
+    pub fn refresh(&mut self, ratings: &RivalsRatings, now: i64) {
+        if now - self.taken >= Self::MAX_AGE_SECS {
+            self.taken = now;
+            self.ratings = ratings.0.clone();
+        }
+    }
+}
+
+impl RivalsSeasons {
+    /// The current season's name, for archiving
+    pub fn current_name(&self) -> &str {
+        self.current.as_deref().unwrap_or("preseason")
+    }
+
+    pub fn find(&self, name: &str) -> Option<&RivalsSeason> {
+        self.archived
+            .iter()
+            .find(|season| season.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Drop match logs older than `cutoff` (unix seconds), in this and archived seasons.  Seasons'
+    /// final ratings are kept.  Returns the number of matches removed.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let logs = std::iter::once(&mut self.matches)
+            .chain(self.archived.iter_mut().map(|season| &mut season.matches));
+        let mut removed = 0;
+        for matches in logs {
+            let len = matches.len();
+            matches.retain(|m| m.timestamp >= cutoff);
+            removed += len - matches.len();
+        }
+        removed
+    }
+}
+
+impl RivalsPending {
+    pub fn remove_expired(&mut self, now: i64) {
+        self.matches.retain(|m| m.expires > now);
+    }
+}
+
+impl UndoStack {
+    /// Changes older than this may no longer be undone
+    pub const MAX_AGE_SECS: i64 = 60 * 60;
+    const CAPACITY: usize = 50;
+
+    pub fn push(&mut self, entry: UndoEntry) {
+        let cutoff = entry.timestamp - Self::MAX_AGE_SECS;
+        self.entries.retain(|e| e.timestamp >= cutoff);
+        self.entries.push(entry);
+        if self.entries.len() > Self::CAPACITY {
+            self.entries.remove(0);
+        }
+    }
+}
+
+impl UndoOp {
+    /// Apply the inverse operation.  Returns a description of what was done.
+    pub fn apply(self, pstate: &mut PersistentState) -> String {
+        match self {
+            UndoOp::UnreportMatch { ratings } => {
+                pstate.rivals_seasons.matches.pop();
+                let mut restored = Vec::new();
+                for (player, rating) in ratings {
+                    if let Some(current) = pstate.rivals_ratings.0.get_mut(&player) {
+                        restored.push(format!("`{}` {}% → {}%", player, current, rating));
+                        *current = rating;
+                    }
+                }
+                format!("Restored ratings: {}", restored.join(", "))
+            }
+            UndoOp::DeletePlayer { player } => {
+                pstate.rivals_ratings.0.remove(&player);
+                pstate.rivals_ratings_owners.0.remove(&player);
+                format!("Deleted player `{}`", player)
+            }
+            UndoOp::RestorePlayer {
+                player,
+                rating,
+                owner,
+            } => {
+                pstate.rivals_ratings.0.insert(player.clone(), rating);
+                pstate.rivals_ratings_owners.0.insert(player.clone(), owner);
+                format!("Restored player `{}` at {}%", player, rating)
+            }
+        }
+    }
+}
+
 impl PersistentState {
-    fn config_path() -> Result<PathBuf> {
+    pub fn config_path() -> Result<PathBuf> {
         dirs::home_dir()
             .map(|p| p.join(PSTATE_PATH_REL_HOME))
             .ok_or(anyhow!("Could not find home directory"))
@@ -65,7 +1097,58 @@ impl PersistentState {
         Ok(pstate)
     }
 
+    /// Modification time of `state.toml`
+    pub async fn modified() -> Result<SystemTime> {
+        let path = Self::config_path()?;
+        tokio::fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| {
+                anyhow!(
+                    "Could not stat state at `{}`: {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            })
+    }
+
+    /// Re-read `state.toml`, e.g. after manual edits, keeping volatile flags such as `read_only`.
+    pub async fn reload(&mut self) -> Result<()> {
+        let mut new = Self::load().await?;
+        new.read_only = self.read_only;
+        *self = new;
+        Ok(())
+    }
+
+    /// Names of the top-level sections which differ from `other`
+    pub fn changed_sections(&self, other: &Self) -> Result<Vec<String>> {
+        let toml::Value::Table(old) = toml::Value::try_from(self)? else {
+            return Err(anyhow!("State did not serialize to a table"));
+        };
+        let toml::Value::Table(new) = toml::Value::try_from(other)? else {
+            return Err(anyhow!("State did not serialize to a table"));
+        };
+        let mut changed: Vec<String> = old
+            .keys()
+            .chain(new.keys().filter(|key| !old.contains_key(*key)))
+            .filter(|key| old.get(*key) != new.get(*key))
+            .cloned()
+            .collect();
+        changed.sort_unstable();
+        Ok(changed)
+    }
+
+    /// Refuse during maintenance.  Commands check this as soon as they take the lock, before
+    /// changing anything, so a refused change doesn't take effect in memory regardless.
+    pub fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(MaintenanceMode.into());
+        }
+        Ok(())
+    }
+
     pub async fn save(&self) -> Result<()> {
+        self.ensure_writable()?;
         let path = Self::config_path()?;
         let pstate_str = toml::to_string_pretty(&self)
             .map_err(|e| anyhow!("Could not serialize state: {}", e))?;
@@ -104,3 +1187,235 @@ impl PersistentState {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rivals_match(timestamp: i64) -> RivalsMatch {
+        RivalsMatch {
+            timestamp,
+            winner: "a".to_string(),
+            loser: "b".to_string(),
+            winner_before: 1000,
+            winner_after: 1010,
+            loser_before: 1000,
+            loser_after: 990,
+        }
+    }
+
+    #[test]
+    fn removes_old_rivals_matches() {
+        let mut seasons = RivalsSeasons {
+            matches: vec![rivals_match(50), rivals_match(100), rivals_match(150)],
+            archived: vec![RivalsSeason {
+                name: "one".to_string(),
+                started: 0,
+                ended: 100,
+                ratings: HashMap::from([("a".to_string(), 1010)]),
+                matches: vec![rivals_match(10), rivals_match(99)],
+            }],
+            ..RivalsSeasons::default()
+        };
+
+        // Matches at the cutoff are kept
+        assert_eq!(seasons.remove_older_than(100), 3);
+        let timestamps: Vec<i64> = seasons.matches.iter().map(|m| m.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 150]);
+        assert!(seasons.archived[0].matches.is_empty());
+        assert_eq!(seasons.archived[0].ratings["a"], 1010);
+        assert_eq!(seasons.remove_older_than(100), 0);
+    }
+
+    const USER: UserId = UserId::new(1);
+    const OTHER_USER: UserId = UserId::new(2);
+    const GUILD: GuildId = GuildId::new(10);
+    const MEMBER_ROLE: RoleId = RoleId::new(100);
+    const MUTED_ROLE: RoleId = RoleId::new(101);
+    const STAFF_ROLE: RoleId = RoleId::new(102);
+
+    /// A capability's rules, from lists of allowed users, allowed roles, denied users and denied
+    /// roles
+    fn capability_acl(
+        allow_users: &[UserId],
+        allow_roles: &[RoleId],
+        deny_users: &[UserId],
+        deny_roles: &[RoleId],
+    ) -> CapabilityAcl {
+        CapabilityAcl {
+            allow_users: allow_users.iter().copied().collect(),
+            allow_roles: allow_roles.iter().copied().collect(),
+            deny_users: deny_users.iter().copied().collect(),
+            deny_roles: deny_roles.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn capability_acl_precedence() {
+        let roles = [MEMBER_ROLE, MUTED_ROLE];
+        let cases = [
+            ("no rules", capability_acl(&[], &[], &[], &[]), None),
+            (
+                "user allowed",
+                capability_acl(&[USER], &[], &[], &[]),
+                Some(true),
+            ),
+            (
+                "user deny beats user allow",
+                capability_acl(&[USER], &[], &[USER], &[]),
+                Some(false),
+            ),
+            (
+                "user allow beats role deny",
+                capability_acl(&[USER], &[], &[], &[MUTED_ROLE]),
+                Some(true),
+            ),
+            (
+                "user deny beats role allow",
+                capability_acl(&[], &[MEMBER_ROLE], &[USER], &[]),
+                Some(false),
+            ),
+            (
+                "role deny beats role allow",
+                capability_acl(&[], &[MEMBER_ROLE], &[], &[MUTED_ROLE]),
+                Some(false),
+            ),
+            (
+                "role allowed",
+                capability_acl(&[], &[MEMBER_ROLE], &[], &[]),
+                Some(true),
+            ),
+            (
+                "others allowed",
+                capability_acl(&[OTHER_USER], &[STAFF_ROLE], &[], &[]),
+                Some(false),
+            ),
+            (
+                "only others denied",
+                capability_acl(&[], &[], &[OTHER_USER], &[STAFF_ROLE]),
+                None,
+            ),
+        ];
+        for (name, acl, expected) in cases {
+            assert_eq!(acl.decide(USER, &roles), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn acl_parent_capabilities() {
+        let roles = [MEMBER_ROLE];
+        let cases = [
+            (
+                "parent allow isn't inherited",
+                "rivals.delete",
+                vec![("rivals", capability_acl(&[USER], &[], &[], &[]))],
+                None,
+            ),
+            (
+                "parent deny is inherited",
+                "rivals.delete",
+                vec![("rivals", capability_acl(&[], &[], &[USER], &[]))],
+                Some(false),
+            ),
+            (
+                "parent deny beats own allow",
+                "rivals.delete",
+                vec![
+                    ("rivals", capability_acl(&[], &[], &[], &[MEMBER_ROLE])),
+                    ("rivals.delete", capability_acl(&[USER], &[], &[], &[])),
+                ],
+                Some(false),
+            ),
+            (
+                "grandparent deny is inherited",
+                "rivals.delete.all",
+                vec![
+                    ("rivals", capability_acl(&[], &[], &[USER], &[])),
+                    ("rivals.delete", capability_acl(&[USER], &[], &[], &[])),
+                ],
+                Some(false),
+            ),
+            (
+                "parent allowing only others denies",
+                "rivals.delete",
+                vec![
+                    ("rivals", capability_acl(&[], &[STAFF_ROLE], &[], &[])),
+                    ("rivals.delete", capability_acl(&[USER], &[], &[], &[])),
+                ],
+                Some(false),
+            ),
+            (
+                "own allow with parent silent",
+                "rivals.delete",
+                vec![
+                    ("rivals", capability_acl(&[], &[], &[OTHER_USER], &[])),
+                    (
+                        "rivals.delete",
+                        capability_acl(&[], &[MEMBER_ROLE], &[], &[]),
+                    ),
+                ],
+                Some(true),
+            ),
+            (
+                "sibling deny doesn't apply",
+                "rivals.delete",
+                vec![("rivals.reset", capability_acl(&[], &[], &[USER], &[]))],
+                None,
+            ),
+        ];
+        for (name, capability, capabilities, expected) in cases {
+            let acl = Acl {
+                guilds: HashMap::from([(
+                    GUILD,
+                    capabilities
+                        .into_iter()
+                        .map(|(capability, acl)| (capability.to_string(), acl))
+                        .collect(),
+                )]),
+                ..Acl::default()
+            };
+            assert_eq!(
+                acl.decide(GUILD, capability, USER, &roles),
+                expected,
+                "{}",
+                name
+            );
+            assert_eq!(
+                acl.decide(GuildId::new(11), capability, USER, &roles),
+                None,
+                "{} in another guild",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn acl_grants() {
+        let acl = Acl {
+            grants: HashMap::from([
+                (USER, HashSet::from(["rivals".to_string()])),
+                (OTHER_USER, HashSet::from(["role.bulk".to_string()])),
+            ]),
+            ..Acl::default()
+        };
+        let cases = [
+            (USER, "rivals", true),
+            (USER, "rivals.delete", true),
+            (USER, "rivalsx", false),
+            (USER, "role.bulk", false),
+            (OTHER_USER, "role.bulk", true),
+            (OTHER_USER, "role.bulk.add", true),
+            (OTHER_USER, "role", false),
+            (UserId::new(3), "rivals", false),
+        ];
+        for (user_id, capability, expected) in cases {
+            assert_eq!(
+                acl.is_granted(user_id, capability),
+                expected,
+                "{} {}",
+                user_id,
+                capability
+            );
+        }
+    }
+}
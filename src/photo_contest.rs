@@ -0,0 +1,258 @@
+//! Photo contests, e.g. weekly, held in a channel
+//!
+//! Run on each `[[photo_contests]]` entry's schedules by `scheduler.rs`, or manually with the
+//! `contest` command.  Opening starts collecting submissions: each member's latest image in the
+//! channel, up to `MAX_ENTRIES`.  Voting posts the entries with a numbered reaction each, and
+//! closing counts the reactions, leaving out the bot's own and self-votes, and announces the
+//! winner, who joins the guild's hall of fame.
+
+use crate::{
+    context::Context,
+    helper::{MessageHelper, UserIdHelper},
+    persistent_state::{ContestPhase, ContestState, ContestWinner},
+};
+use anyhow::{anyhow, Result};
+use serenity::all::{
+    ChannelId, CreateEmbed, CreateMessage, Message, ReactionType, Timestamp, UserId,
+};
+
+/// One per numbered reaction, and Discord's limit on embeds per message
+pub const MAX_ENTRIES: usize = 10;
+const NUMBERS: [&str; MAX_ENTRIES] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+/// Most users Discord returns per request for a reaction
+const REACTION_PAGE: u8 = 100;
+
+/// Scheduled stages of a contest
+#[derive(Clone, Copy)]
+pub enum Step {
+    Open,
+    Vote,
+    Close,
+}
+
+pub async fn run_step(ctx: &Context<'_>, channel_id: ChannelId, step: Step) -> Result<()> {
+    match step {
+        Step::Open => open(ctx, channel_id).await,
+        Step::Vote => start_voting(ctx, channel_id).await,
+        Step::Close => close(ctx, channel_id).await,
+    }
+}
+
+/// Start collecting submissions, abandoning any contest already running in the channel
+pub async fn open(ctx: &Context<'_>, channel_id: ChannelId) -> Result<()> {
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate.photo_contests.channels.insert(
+        channel_id,
+        ContestState {
+            phase: ContestPhase::Submissions,
+            entries: Vec::new(),
+            voting_message: None,
+        },
+    );
+    pstate.save().await?;
+    drop(pstate);
+
+    channel_id
+        .say(
+            ctx.cache_http,
+            format!(
+                "📸 **The photo contest is open!**  Post an image here to enter; your latest one \
+                 counts.  Up to {} entries.",
+                MAX_ENTRIES
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Close submissions and post the entries to vote on
+pub async fn start_voting(ctx: &Context<'_>, channel_id: ChannelId) -> Result<()> {
+    let entries = match ctx
+        .pstate
+        .read()
+        .await
+        .photo_contests
+        .channels
+        .get(&channel_id)
+    {
+        Some(contest) if contest.phase == ContestPhase::Submissions => contest.entries.clone(),
+        _ => {
+            return Err(anyhow!(
+                "No contest is taking submissions in {}",
+                channel_id
+            ))
+        }
+    };
+
+    // Fetch each entry afresh; attachment URLs expire, and entries may have been deleted
+    let mut embeds = Vec::new();
+    let mut kept = Vec::new();
+    for entry in entries {
+        let Ok(message) = channel_id.message(ctx.cache_http, entry.message_id).await else {
+            continue;
+        };
+        let Some(url) = message.image_urls().into_iter().next() else {
+            continue;
+        };
+        let name = entry.author_id.nick_in_guild(ctx, message.guild_id).await;
+        embeds.push(
+            CreateEmbed::new()
+                .title(format!("{} {}", NUMBERS[kept.len()], name))
+                .url(message.link())
+                .image(url),
+        );
+        kept.push(entry);
+    }
+
+    if kept.is_empty() {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        pstate.photo_contests.channels.remove(&channel_id);
+        pstate.save().await?;
+        drop(pstate);
+        channel_id
+            .say(
+                ctx.cache_http,
+                "The photo contest had no entries this time.",
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let voting = channel_id
+        .send_message(
+            ctx.cache_http,
+            CreateMessage::new()
+                .content("🗳️ **Voting is open!**  React with the number of your favorite entry.")
+                .embeds(embeds),
+        )
+        .await?;
+    for number in &NUMBERS[..kept.len()] {
+        voting
+            .react(ctx.cache_http, ReactionType::Unicode(number.to_string()))
+            .await?;
+    }
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    if let Some(contest) = pstate.photo_contests.channels.get_mut(&channel_id) {
+        contest.phase = ContestPhase::Voting;
+        contest.entries = kept;
+        contest.voting_message = Some(voting.id);
+    }
+    pstate.save().await?;
+    Ok(())
+}
+
+/// Count the votes and announce the winner
+pub async fn close(ctx: &Context<'_>, channel_id: ChannelId) -> Result<()> {
+    let contest = ctx
+        .pstate
+        .read()
+        .await
+        .photo_contests
+        .channels
+        .get(&channel_id)
+        .filter(|contest| contest.phase == ContestPhase::Voting)
+        .and_then(|contest| Some((contest.voting_message?, contest.entries.clone())));
+    let Some((voting_message, entries)) = contest else {
+        return Err(anyhow!("No contest is voting in {}", channel_id));
+    };
+
+    let voting = channel_id.message(ctx.cache_http, voting_message).await?;
+    let mut votes = Vec::new();
+    for (entry, number) in entries.iter().zip(NUMBERS) {
+        votes.push(count_votes(ctx, &voting, number, entry.author_id).await?);
+    }
+    let most = votes.iter().copied().max().unwrap_or_default();
+    let winners: Vec<_> = entries
+        .iter()
+        .zip(&votes)
+        .filter(|(_, &count)| most > 0 && count == most)
+        .map(|(entry, _)| entry.clone())
+        .collect();
+
+    // Messages fetched over HTTP lack their guild
+    let guild_id = channel_id
+        .to_channel(ctx.cache_http)
+        .await?
+        .guild()
+        .map(|channel| channel.guild_id);
+    let mut names = Vec::new();
+    for winner in &winners {
+        names.push(format!(
+            "{} ({})",
+            winner.author_id.nick_in_guild(ctx, guild_id).await,
+            winner.message_id.link(channel_id, guild_id)
+        ));
+    }
+
+    {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        pstate.photo_contests.channels.remove(&channel_id);
+        if let Some(guild_id) = guild_id {
+            let hall_of_fame = pstate
+                .photo_contests
+                .hall_of_fame
+                .entry(guild_id)
+                .or_default();
+            for winner in &winners {
+                hall_of_fame.push(ContestWinner {
+                    timestamp: Timestamp::now().unix_timestamp(),
+                    channel_id,
+                    message_id: winner.message_id,
+                    user_id: winner.author_id,
+                    votes: most,
+                });
+            }
+        }
+        pstate.save().await?;
+    }
+
+    let content = match names.as_slice() {
+        [] => "The photo contest is over, but nobody voted.".to_string(),
+        [name] => format!(
+            "🏆 **The photo contest is over!**  The winner, with {} votes, is {}",
+            most, name
+        ),
+        names => format!(
+            "🏆 **The photo contest is over!**  It's a tie at {} votes between {}",
+            most,
+            names.join(" and ")
+        ),
+    };
+    channel_id.say(ctx.cache_http, content).await?;
+    Ok(())
+}
+
+/// Users who reacted with `number`, other than the bot and the entry's author
+async fn count_votes(
+    ctx: &Context<'_>,
+    voting: &Message,
+    number: &str,
+    author_id: UserId,
+) -> Result<u64> {
+    let me = ctx.cache.current_user().id;
+    let mut count = 0;
+    let mut after = None;
+    loop {
+        let users = voting
+            .reaction_users(
+                ctx.http,
+                ReactionType::Unicode(number.to_string()),
+                Some(REACTION_PAGE),
+                after,
+            )
+            .await?;
+        count += users
+            .iter()
+            .filter(|user| user.id != me && user.id != author_id && !user.bot)
+            .count() as u64;
+        match users.last() {
+            Some(last) if users.len() == REACTION_PAGE as usize => after = Some(last.id),
+            _ => return Ok(count),
+        }
+    }
+}
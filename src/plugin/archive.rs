@@ -0,0 +1,46 @@
+use crate::error::Result;
+use crate::{archive, event::*, plugin::*};
+
+/// Keeps local copies of attachments in history, if configured
+pub struct Archive;
+
+#[serenity::async_trait]
+impl Plugin for Archive {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        if msg.attachments.is_empty() {
+            return Ok(EventHandled::No);
+        }
+
+        // Don't hold the config lock while downloading.
+        let Some(cfg) = ctx.cfg.read().await.attachment_archive.clone() else {
+            return Ok(EventHandled::No);
+        };
+        let archived = archive::save(&cfg, msg).await?;
+        if archived.is_empty() {
+            return Ok(EventHandled::No);
+        }
+
+        let mut vstate = ctx.vstate.write().await;
+        let history = vstate.history.get_mut(ctx, msg.channel_id).await?;
+        if let Some(entry) = history.iter_mut().rev().find(|e| e.message_id == msg.id) {
+            entry.archived = archived;
+        }
+
+        Ok(EventHandled::No)
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Record
+    }
+}
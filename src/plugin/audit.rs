@@ -0,0 +1,149 @@
+//! Read-only reports over recorded activity stats to help admins tidy up large servers.
+
+use crate::error::Result;
+use crate::helper::{discord_timestamp, TimestampStyle};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelType, Message, Permissions, Timestamp};
+
+const DEFAULT_INACTIVITY_DAYS: i64 = 30;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+/// Keep the report within Discord's message length limit
+const MAX_LISTED: usize = 20;
+
+pub struct Audit;
+
+#[serenity::async_trait]
+impl Plugin for Audit {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} inactivity [days] - list channels and roles without activity (bot owner only)",
+            prefix,
+            self.name()
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+
+        acl::check(ctx, msg, self.name(), false).await?;
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        match args.as_slice() {
+            ["inactivity"] => handle_inactivity(ctx, msg, DEFAULT_INACTIVITY_DAYS).await,
+            ["inactivity", days] => match days.parse() {
+                Ok(days) => handle_inactivity(ctx, msg, days).await,
+                Err(_) => {
+                    msg.reply(ctx.cache_http, "Invalid number of days").await?;
+                    Ok(EventHandled::Yes)
+                }
+            },
+            _ => {
+                let prefix = &ctx.cfg.read().await.general.command_prefix;
+                msg.reply(
+                    ctx.cache_http,
+                    format!("Invalid command.  See `{}help`", prefix),
+                )
+                .await?;
+                Ok(EventHandled::Yes)
+            }
+        }
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn handle_inactivity(ctx: &Context<'_>, msg: &Message, days: i64) -> Result<EventHandled> {
+    let Some(guild_id) = msg.guild_id else {
+        msg.reply(ctx.cache_http, "This command only works within a server")
+            .await?;
+        return Ok(EventHandled::Yes);
+    };
+
+    let cutoff = Timestamp::now().unix_timestamp() - days * SECONDS_PER_DAY;
+    let describe = |last_active: Option<i64>| match last_active {
+        Some(last_active) => format!(
+            "last active {}",
+            discord_timestamp(last_active, TimestampStyle::Relative)
+        ),
+        None => "no recorded activity".to_string(),
+    };
+
+    let channels = guild_id.channels(ctx.http).await?;
+    let roles = guild_id.roles(ctx.http).await?;
+
+    let mut inactive_channels = Vec::new();
+    let mut inactive_roles = Vec::new();
+    {
+        let pstate = ctx.pstate.read().await;
+        let stats = &pstate.stats;
+
+        for (channel_id, channel) in &channels {
+            if !matches!(channel.kind, ChannelType::Text | ChannelType::Voice) {
+                continue;
+            }
+            let last_active = stats.channels.get(channel_id).map(|s| s.last_active);
+            if last_active.is_none_or(|last_active| last_active < cutoff) {
+                inactive_channels.push((last_active, format!("<#{}>", channel_id)));
+            }
+        }
+
+        for (role_id, role) in &roles {
+            // @everyone and integration-managed roles can't be pruned
+            if role_id.get() == guild_id.get() || role.managed {
+                continue;
+            }
+            let last_active = stats.roles.get(role_id).map(|s| s.last_active);
+            if last_active.is_none_or(|last_active| last_active < cutoff) {
+                inactive_roles.push((last_active, format!("@{}", role.name)));
+            }
+        }
+    }
+
+    // Least recently active first
+    inactive_channels.sort();
+    inactive_roles.sort();
+
+    let mut response = format!("Inactive for at least {} day(s):\n", days);
+    response.push_str("**Channels**\n");
+    if inactive_channels.is_empty() {
+        response.push_str("• none\n");
+    }
+    for (last_active, name) in inactive_channels.iter().take(MAX_LISTED) {
+        response.push_str(&format!("• {}: {}\n", name, describe(*last_active)));
+    }
+    if inactive_channels.len() > MAX_LISTED {
+        response.push_str(&format!(
+            "• …and {} more\n",
+            inactive_channels.len() - MAX_LISTED
+        ));
+    }
+    response.push_str("**Roles**\n");
+    if inactive_roles.is_empty() {
+        response.push_str("• none\n");
+    }
+    for (last_active, name) in inactive_roles.iter().take(MAX_LISTED) {
+        response.push_str(&format!("• `{}`: {}\n", name, describe(*last_active)));
+    }
+    if inactive_roles.len() > MAX_LISTED {
+        response.push_str(&format!(
+            "• …and {} more\n",
+            inactive_roles.len() - MAX_LISTED
+        ));
+    }
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
@@ -0,0 +1,122 @@
+//! Opens a thread for every new message in `[auto_threads]` channels, keeping support and
+//! question channels organized.
+
+use crate::config::AutoThreadTitle;
+use crate::error::Result;
+use crate::helper::{truncate, MessageHelper, UserHelper};
+use crate::llm::{LlmChatRequest, LlmSettings};
+use crate::{event::*, log_internal, plugin::*};
+use serenity::all::{CreateThread, Message, Permissions};
+
+/// Discord's limit on thread names
+const TITLE_MAX_LEN: usize = 100;
+
+const TITLE_SYSTEM: &str = "Write a short, descriptive title, at most eight words, for a \
+     discussion starting with the following Discord message.  Reply with only the title, without \
+     quotes.";
+
+pub struct AutoThread;
+
+#[serenity::async_trait]
+impl Plugin for AutoThread {
+    fn name(&self) -> &'static str {
+        "auto_thread"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        // Replies continue an existing conversation rather than starting one
+        if msg.author.bot || msg.message_reference.is_some() {
+            return Ok(EventHandled::No);
+        }
+        let Some(title_mode) = ctx
+            .cfg
+            .read()
+            .await
+            .auto_threads
+            .get(&msg.channel_id)
+            .map(|auto_thread| auto_thread.title)
+        else {
+            return Ok(EventHandled::No);
+        };
+
+        let title = match title_mode {
+            AutoThreadTitle::FirstLine => None,
+            AutoThreadTitle::Llm => match llm_title(ctx, msg).await {
+                Ok(title) => title,
+                Err(err) => {
+                    log_internal!("Could not title thread for {}: {}", msg.id, err);
+                    None
+                }
+            },
+        };
+        let title = match title {
+            Some(title) => title,
+            None => first_line_title(ctx, msg).await?,
+        };
+
+        msg.channel_id
+            .create_thread_from_message(
+                ctx.cache_http,
+                msg.id,
+                CreateThread::new(truncate(&title, TITLE_MAX_LEN - "...".len())),
+            )
+            .await?;
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::CREATE_PUBLIC_THREADS
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+}
+
+async fn first_line_title(ctx: &Context<'_>, msg: &Message) -> Result<String> {
+    let content = msg.human_format_content(ctx).await?;
+    match content.lines().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => Ok(line.to_string()),
+        None => Ok(format!(
+            "Thread by {}",
+            msg.author.nick_in_guild(ctx, msg.guild_id).await
+        )),
+    }
+}
+
+/// Title from the `[llm_summary]` model, if configured and the author hasn't opted out
+async fn llm_title(ctx: &Context<'_>, msg: &Message) -> anyhow::Result<Option<String>> {
+    if ctx
+        .pstate
+        .read()
+        .await
+        .llm_optout
+        .users
+        .contains(&msg.author.id)
+        || msg.content.trim().is_empty()
+    {
+        return Ok(None);
+    }
+    let content = msg.human_format_content(ctx).await?;
+    let request = {
+        let cfg = ctx.cfg.read().await;
+        let Some(summary_cfg) = cfg.llm_summary.as_ref() else {
+            return Ok(None);
+        };
+        let settings = LlmSettings {
+            system: TITLE_SYSTEM,
+            ..summary_cfg.as_llm_settings()
+        };
+        LlmChatRequest::from_prompt(&settings, content)
+    };
+    let title = request.post(ctx).await?;
+    let title = title.trim().trim_matches('"').trim();
+    Ok((!title.is_empty()).then(|| title.to_string()))
+}
@@ -0,0 +1,123 @@
+//! Manual overrides for scheduled channel opening and closing.  See `channel_schedule.rs`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::UserHelper;
+use crate::{acl, channel_schedule, event::*, plugin::*, scheduler};
+use serenity::all::{GuildId, Permissions};
+
+pub struct Channel;
+
+#[serenity::async_trait]
+impl Plugin for Channel {
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}channel <subcommand> -- channels opened and closed on a schedule\n\
+             | Subcommands:\n\
+             | open <#channel> - open a channel until its next scheduled change (requires Manage Channels)\n\
+             | close <#channel> - close a channel until its next scheduled change (requires Manage Channels)\n\
+             | list - list this server's channel schedules",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Channel schedules only work within a server".to_string(),
+            ));
+        };
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, guild_id).await,
+            [subcommand @ ("open" | "close"), channel] => {
+                let permitted = msg
+                    .author_permissions(ctx.cache)
+                    .is_some_and(|p| p.contains(Permissions::MANAGE_CHANNELS));
+                acl::check(ctx, msg, &format!("channel.{}", subcommand), permitted).await?;
+
+                let Some(channel_id) = serenity::utils::parse_channel_mention(channel) else {
+                    return Err(PluginError::UserError(
+                        "Invalid channel.  Mention it, e.g. `#general`.".to_string(),
+                    ));
+                };
+                if !ctx
+                    .cache
+                    .guild(guild_id)
+                    .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+                {
+                    return Err(PluginError::UserError(
+                        "That channel isn't in this server.".to_string(),
+                    ));
+                }
+
+                let open = *subcommand == "open";
+                let mode = ctx.cfg.read().await.channel_close_mode(channel_id);
+                let reason = format!("by {}", msg.author.nick_in_guild(ctx, Some(guild_id)).await);
+                channel_schedule::set_open(ctx, channel_id, mode, open, &reason).await?;
+                format!(
+                    "<#{}> is now {}.",
+                    channel_id,
+                    if open { "open" } else { "closed" }
+                )
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::MANAGE_ROLES)
+    }
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let cfg = ctx.cfg.read().await;
+    let default_timezone = cfg
+        .scheduler
+        .as_ref()
+        .map(|s| s.default_timezone.as_str())
+        .unwrap_or("UTC");
+    let in_guild = |schedule: &&crate::config::ChannelSchedule| {
+        ctx.cache
+            .guild(guild_id)
+            .is_some_and(|guild| guild.channels.contains_key(&schedule.channel))
+    };
+
+    let mut response = String::new();
+    for schedule in cfg.channel_schedules.iter().filter(in_guild) {
+        let timezone = schedule.timezone.as_deref().unwrap_or(default_timezone);
+        // Validate so that typos in the config are visible
+        let valid = scheduler::Cron::parse(&schedule.open).is_ok()
+            && scheduler::Cron::parse(&schedule.close).is_ok()
+            && scheduler::parse_timezone(timezone).is_ok();
+        response.push_str(&format!(
+            "• <#{}> opens `{}`, closes `{}` ({}){}\n",
+            schedule.channel,
+            schedule.open,
+            schedule.close,
+            timezone,
+            if valid { "" } else { " - invalid" }
+        ));
+    }
+    if response.is_empty() {
+        return "No channels are opened or closed on a schedule.".to_string();
+    }
+    format!("Channel schedules:\n{}", response)
+}
@@ -0,0 +1,181 @@
+//! Recreates channels from existing ones: permission overwrites, topic, and other settings.
+//!
+//! Templates in `[channel_templates]` clone several channels at once into a new category, e.g. to
+//! set up the channels for an event.
+
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelId, ChannelType, CreateChannel, GuildChannel, GuildId, Permissions};
+
+pub struct CloneChannel;
+
+#[serenity::async_trait]
+impl Plugin for CloneChannel {
+    fn name(&self) -> &'static str {
+        "clonechannel"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}clonechannel <#source/template> -- copy channels (admins only)\n\
+             | <#source> <new-name> - create a channel with the source's permissions and settings\n\
+             | template <template> <category name> - create a category of channels from a configured template",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Channels can only be cloned within a server".to_string(),
+            ));
+        };
+
+        let is_admin = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::ADMINISTRATOR));
+        acl::check(ctx, msg, self.name(), is_admin).await?;
+
+        let args = args.trim();
+        let (first, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let rest = rest.trim();
+        let response = if first.eq_ignore_ascii_case("template") {
+            let (template, category_name) = rest.split_once(' ').unwrap_or((rest, ""));
+            let category_name = category_name.trim();
+            if template.is_empty() || category_name.is_empty() {
+                return Err(PluginError::UserError(
+                    "Usage: clonechannel template <template> <category name>".to_string(),
+                ));
+            }
+            from_template(ctx, guild_id, template, category_name).await?
+        } else {
+            let Some(source_id) = serenity::utils::parse_channel_mention(first) else {
+                return Err(PluginError::UserError(
+                    "Usage: clonechannel <#source> <new-name>".to_string(),
+                ));
+            };
+            if rest.is_empty() {
+                return Err(PluginError::UserError(
+                    "Usage: clonechannel <#source> <new-name>".to_string(),
+                ));
+            }
+            let source = source_channel(ctx, guild_id, source_id).await?;
+            let channel = guild_id
+                .create_channel(ctx.cache_http, clone_of(&source, rest))
+                .await?;
+            format!("Created <#{}> as a copy of <#{}>", channel.id, source_id)
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+            .union(Permissions::MANAGE_CHANNELS)
+            .union(Permissions::MANAGE_ROLES)
+    }
+}
+
+/// Look up a channel to copy, which must be in `guild_id`
+async fn source_channel(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<GuildChannel> {
+    channel_id
+        .to_channel(ctx.cache_http)
+        .await?
+        .guild()
+        .filter(|channel| channel.guild_id == guild_id)
+        .ok_or_else(|| PluginError::UserError(format!("<#{}> is not in this server.", channel_id)))
+}
+
+/// Builder for a new channel named `name` with the same permissions and settings as `source`
+fn clone_of(source: &GuildChannel, name: &str) -> CreateChannel<'static> {
+    let mut builder = CreateChannel::new(name)
+        .kind(source.kind)
+        .nsfw(source.nsfw)
+        .permissions(source.permission_overwrites.clone());
+    if let Some(parent_id) = source.parent_id {
+        builder = builder.category(parent_id);
+    }
+    if let Some(topic) = &source.topic {
+        builder = builder.topic(topic);
+    }
+    if let Some(seconds) = source.rate_limit_per_user {
+        builder = builder.rate_limit_per_user(seconds);
+    }
+    if let Some(bitrate) = source.bitrate {
+        builder = builder.bitrate(bitrate);
+    }
+    if let Some(limit) = source.user_limit {
+        builder = builder.user_limit(limit);
+    }
+    if let Some(region) = &source.rtc_region {
+        builder = builder.rtc_region(region.clone());
+    }
+    if let Some(mode) = source.video_quality_mode {
+        builder = builder.video_quality_mode(mode);
+    }
+    if let Some(duration) = source.default_auto_archive_duration {
+        builder = builder.default_auto_archive_duration(duration);
+    }
+    builder
+}
+
+async fn from_template(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    template: &str,
+    category_name: &str,
+) -> Result<String> {
+    let (category_source, entries) = {
+        let cfg = ctx.cfg.read().await;
+        let Some(template_cfg) = cfg.channel_templates.get(template) else {
+            return Err(PluginError::UserError(format!(
+                "Template `{}` not found.",
+                template
+            )));
+        };
+        let entries: Vec<(ChannelId, String)> = template_cfg
+            .channels
+            .iter()
+            .map(|entry| (entry.source, entry.name.clone()))
+            .collect();
+        (template_cfg.category, entries)
+    };
+
+    // Check every source before creating anything, so a bad template doesn't leave a partial copy
+    let mut sources = Vec::new();
+    for (source_id, name) in entries {
+        sources.push((source_channel(ctx, guild_id, source_id).await?, name));
+    }
+    let mut category = CreateChannel::new(category_name).kind(ChannelType::Category);
+    if let Some(category_source) = category_source {
+        let category_source = source_channel(ctx, guild_id, category_source).await?;
+        category = category.permissions(category_source.permission_overwrites);
+    }
+
+    let category = guild_id.create_channel(ctx.cache_http, category).await?;
+    for (source, name) in &sources {
+        guild_id
+            .create_channel(ctx.cache_http, clone_of(source, name).category(category.id))
+            .await?;
+    }
+    Ok(format!(
+        "Created category `{}` with {} channels from template `{}`",
+        category_name,
+        sources.len(),
+        template
+    ))
+}
@@ -0,0 +1,57 @@
+use crate::error::{PluginError, Result};
+use crate::{event::*, plugin::*};
+use serenity::all::Permissions;
+
+/// Runs destructive operations once confirmed.  See `confirm.rs`.
+pub struct Confirm;
+
+#[serenity::async_trait]
+impl Plugin for Confirm {
+    fn name(&self) -> &'static str {
+        "confirm"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <token> - go ahead with an operation which asked for confirmation",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+
+        let pending = ctx
+            .vstate
+            .write()
+            .await
+            .confirmations
+            .take(args.trim(), msg.author.id);
+        let Some(pending) = pending else {
+            return Err(PluginError::UserError(
+                "Nothing to confirm with that token.  It may have expired; try the command again"
+                    .to_string(),
+            ));
+        };
+
+        let response = (pending.action)(ctx.owned()).await?;
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -0,0 +1,89 @@
+//! Respond only once when the same content triggers the bot in several channels at once, e.g.
+//! crossposts or copy-paste spam.  Later copies get a link to the first response instead.
+
+use crate::error::Result;
+use crate::helper::MessageHelper;
+use crate::{event::*, plugin::*};
+use serenity::all::{Message, Permissions};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Duration;
+
+/// How long after a trigger identical content elsewhere counts as a crosspost
+const WINDOW: Duration = Duration::from_secs(10);
+
+pub struct Crosspost;
+
+#[serenity::async_trait]
+impl Plugin for Crosspost {
+    fn name(&self) -> &'static str {
+        "crosspost"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+
+        // Our own replies reference the message which triggered them.
+        if msg.author.id == ctx.cache.current_user().id {
+            if let Some(trigger) = &msg.referenced_message {
+                ctx.vstate
+                    .write()
+                    .await
+                    .triggers
+                    .responded(trigger.id, msg.id);
+            }
+            return Ok(EventHandled::No);
+        }
+        if msg.author.bot || !is_trigger(ctx, msg).await? {
+            return Ok(EventHandled::No);
+        }
+
+        let mut hasher = DefaultHasher::new();
+        msg.content.trim().to_lowercase().hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let link = {
+            let mut vstate = ctx.vstate.write().await;
+            let Some(first) = vstate.triggers.check(hash, msg, WINDOW) else {
+                return Ok(EventHandled::No);
+            };
+            first
+                .response
+                .unwrap_or(first.message_id)
+                .link(first.channel_id, first.guild_id)
+        };
+
+        msg.reply(
+            ctx.cache_http,
+            format!("Already answered that here: {}", link),
+        )
+        .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+
+    fn sees_bot_messages(&self) -> bool {
+        true
+    }
+}
+
+/// Whether the bot would respond to `msg`: a command or a message addressed to it
+async fn is_trigger(ctx: &Context<'_>, msg: &Message) -> Result<bool> {
+    let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+    if msg.content.starts_with(&prefix) {
+        return Ok(true);
+    }
+    Ok(msg.is_to_me(ctx).await?)
+}
@@ -1,8 +1,16 @@
-use crate::{event::*, helper::*, log_event, logging::*, plugin::*};
-use anyhow::Result;
+use crate::error::Result;
+use crate::persistent_state::SentEntry;
+use crate::{acl, event::*, helper::*, log_event, logging::*, plugin::*};
+use serenity::all::{Message, Permissions};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 
-/// Prints debug information about event to stdout
+/// Sent messages remembered if `[sent_log]` isn't configured
+const DEFAULT_SENT_LOG_CAPACITY: usize = 100;
+/// Sent messages shown by `debug sent`
+const MAX_LISTED: usize = 8;
+
+/// Prints debug information about event to stdout and keeps an audit trail of sent messages
 pub struct Debug;
 
 #[serenity::async_trait]
@@ -11,8 +19,17 @@ impl Plugin for Debug {
         "debug"
     }
 
-    async fn usage(&self, _ctx: &Context) -> Option<String> {
-        None
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} sent - list messages the bot recently sent (bot owner only)",
+            prefix,
+            self.name()
+        ))
     }
 
     async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
@@ -35,6 +52,17 @@ impl Plugin for Debug {
                     Glue {}.color(),
                     msg.human_format_content(ctx).await?,
                 );
+
+                if msg.author.id == ctx.cache.current_user().id {
+                    record_sent(ctx, msg).await?;
+                }
+                if let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await {
+                    if args.trim() == "sent" {
+                        acl::check(ctx, msg, "debug.sent", false).await?;
+                        msg.reply(ctx.cache_http, list_sent(ctx).await).await?;
+                        return Ok(EventHandled::Yes);
+                    }
+                }
             }
             Event::VoiceStateUpdate { old, new } => match (old, new.channel_id) {
                 (Some(old), Some(new_id)) if old.channel_id == Some(new_id) => {
@@ -106,8 +134,113 @@ impl Plugin for Debug {
                     message
                 );
             }
+            Event::GuildMemberAddition(member) => log_event!(
+                "{} joined {}",
+                member.user.color(),
+                Some(member.guild_id).color(ctx.http).await,
+            ),
+            Event::GuildMemberRemoval { guild_id, user, .. } => log_event!(
+                "{} left {}",
+                user.color(),
+                Some(*guild_id).color(ctx.http).await,
+            ),
+            Event::GuildMemberUpdate(_) => {
+                // Frequent and mostly uninteresting, e.g. role changes
+                // Not currently debug logging this
+            }
         }
 
         Ok(EventHandled::No)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Run
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+
+    fn runs_when_ignored(&self) -> bool {
+        true
+    }
+
+    fn sees_bot_messages(&self) -> bool {
+        true
+    }
+}
+
+async fn record_sent(ctx: &Context<'_>, msg: &Message) -> Result<()> {
+    let hash = Sha256::digest(msg.content.as_bytes());
+    let trigger = msg.message_reference.as_ref().and_then(|r| r.message_id);
+    let plugin = match trigger {
+        Some(id) => ctx.vstate.read().await.handlers.get(id),
+        None => None,
+    };
+
+    let (capacity, persist) = match ctx.cfg.read().await.sent_log.as_ref() {
+        Some(cfg) => (cfg.capacity, cfg.persist),
+        None => (DEFAULT_SENT_LOG_CAPACITY, false),
+    };
+
+    let entry = SentEntry {
+        timestamp: msg.timestamp.unix_timestamp(),
+        guild_id: msg.guild_id,
+        channel_id: msg.channel_id,
+        message_id: msg.id,
+        trigger,
+        plugin: plugin.map(str::to_string),
+        content_hash: hash[..8].iter().map(|b| format!("{:02x}", b)).collect(),
+        content_len: msg.content.len(),
+    };
+
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate.sent_log.persist = persist;
+    pstate.sent_log.push(entry, capacity);
+    if persist {
+        pstate.save().await?;
+    }
+    Ok(())
+}
+
+async fn list_sent(ctx: &Context<'_>) -> String {
+    let pstate = ctx.pstate.read().await;
+    let vstate = ctx.vstate.read().await;
+    let entries = &pstate.sent_log.entries;
+    if entries.is_empty() {
+        return "I haven't sent anything recently.".to_string();
+    }
+
+    let mut response = String::from("Recently sent:\n");
+    for entry in entries.iter().rev().take(MAX_LISTED) {
+        // The reply may have been recorded before its plugin finished handling the trigger.
+        let plugin = entry
+            .plugin
+            .as_deref()
+            .or_else(|| entry.trigger.and_then(|id| vstate.handlers.get(id)))
+            .unwrap_or("-");
+        let trigger = match entry.trigger {
+            Some(id) => id.to_string(),
+            None => "-".to_string(),
+        };
+        response.push_str(&format!(
+            "• {} {} plugin `{}` trigger {} hash `{}` ({} bytes)\n",
+            discord_timestamp(entry.timestamp, TimestampStyle::LongTime),
+            entry.message_id.link(entry.channel_id, entry.guild_id),
+            plugin,
+            trigger,
+            entry.content_hash,
+            entry.content_len,
+        ));
+    }
+    response
 }
@@ -0,0 +1,243 @@
+//! Looks up definitions in a dictionary, or optionally Urban Dictionary, falling back on the LLM
+//! for terms neither knows.
+
+use crate::error::{PluginError, Result};
+use crate::helper::truncate;
+use crate::llm::{LlmChatRequest, LlmSettings};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{CreateEmbed, CreateEmbedFooter, CreateMessage, Permissions};
+
+const DICTIONARY_URL: &str = "https://api.dictionaryapi.dev/api/v2/entries/en/";
+const URBAN_URL: &str = "https://api.urbandictionary.com/v0/define";
+/// Definitions shown
+const MAX_DEFINITIONS: usize = 3;
+/// Discord's limit on the length of an embed field's value
+const FIELD_MAX_LEN: usize = 1024;
+
+const LLM_SYSTEM: &str = "Briefly define the following term in one or two sentences.  If you \
+     don't know it, say so rather than guessing.";
+
+pub struct Define;
+
+#[derive(serde::Deserialize)]
+struct DictionaryEntry {
+    word: String,
+    meanings: Vec<Meaning>,
+}
+
+#[derive(serde::Deserialize)]
+struct Meaning {
+    #[serde(rename = "partOfSpeech")]
+    part_of_speech: String,
+    definitions: Vec<DictionaryDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+struct DictionaryDefinition {
+    definition: String,
+    example: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct UrbanResponse {
+    list: Vec<UrbanDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+struct UrbanDefinition {
+    word: String,
+    definition: String,
+    example: String,
+    thumbs_up: u64,
+    thumbs_down: u64,
+    permalink: String,
+}
+
+#[serenity::async_trait]
+impl Plugin for Define {
+    fn name(&self) -> &'static str {
+        "define"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}define [urban] <term> - define a term, optionally from Urban Dictionary where enabled",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args = args.trim();
+        let (urban, term) = match args.split_once(char::is_whitespace) {
+            Some(("urban", term)) => (true, term.trim()),
+            _ => (false, args),
+        };
+        if term.is_empty() {
+            return Err(PluginError::UserError(
+                "Usage: `define [urban] <term>`".to_string(),
+            ));
+        }
+        if urban {
+            let enabled = match msg.guild_id {
+                Some(guild_id) => ctx
+                    .cfg
+                    .read()
+                    .await
+                    .define
+                    .as_ref()
+                    .is_some_and(|define| define.urban_guilds.contains(&guild_id)),
+                None => false,
+            };
+            if !enabled {
+                return Err(PluginError::UserError(
+                    "Urban Dictionary isn't enabled in this server.".to_string(),
+                ));
+            }
+        }
+
+        let typing = msg.channel_id.start_typing(ctx.http);
+        let embed = if urban {
+            urban_definitions(term).await?
+        } else {
+            dictionary_definitions(term).await?
+        };
+        let embed = match embed {
+            Some(embed) => embed,
+            None => llm_definition(ctx, term).await?,
+        };
+        typing.stop();
+
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new().embed(embed).reference_message(msg),
+            )
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::EMBED_LINKS)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// Definitions from the dictionary, or `None` if it doesn't have the term
+async fn dictionary_definitions(term: &str) -> Result<Option<CreateEmbed>> {
+    let mut url = reqwest::Url::parse(DICTIONARY_URL).map_err(anyhow::Error::from)?;
+    // Escapes e.g. slashes in the term
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid dictionary URL"))?
+        .pop_if_empty()
+        .push(term);
+    let response = reqwest::Client::new().get(url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let entries: Vec<DictionaryEntry> = response.error_for_status()?.json().await?;
+    let Some(word) = entries.first().map(|entry| entry.word.clone()) else {
+        return Ok(None);
+    };
+
+    let mut embed = CreateEmbed::new().title(word);
+    let definitions = entries
+        .iter()
+        .flat_map(|entry| &entry.meanings)
+        .flat_map(|meaning| {
+            meaning
+                .definitions
+                .iter()
+                .map(move |definition| (&meaning.part_of_speech, definition))
+        })
+        .take(MAX_DEFINITIONS);
+    for (i, (part_of_speech, definition)) in definitions.enumerate() {
+        let mut value = definition.definition.clone();
+        if let Some(example) = &definition.example {
+            value.push_str(&format!("\n*{}*", example));
+        }
+        embed = embed.field(
+            format!("{}. {}", i + 1, part_of_speech),
+            truncate(&value, FIELD_MAX_LEN - "...".len()),
+            false,
+        );
+    }
+    Ok(Some(embed))
+}
+
+/// Top-voted definitions from Urban Dictionary, or `None` if it doesn't have the term
+async fn urban_definitions(term: &str) -> Result<Option<CreateEmbed>> {
+    let mut definitions = reqwest::Client::new()
+        .get(URBAN_URL)
+        .query(&[("term", term)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<UrbanResponse>()
+        .await?
+        .list;
+    if definitions.is_empty() {
+        return Ok(None);
+    }
+    definitions.sort_by_key(|definition| std::cmp::Reverse(definition.thumbs_up));
+
+    let mut embed = CreateEmbed::new()
+        .title(definitions[0].word.clone())
+        .url(definitions[0].permalink.clone())
+        .footer(CreateEmbedFooter::new("From Urban Dictionary"));
+    for (i, definition) in definitions.iter().take(MAX_DEFINITIONS).enumerate() {
+        // Urban Dictionary marks links to other terms with brackets
+        let strip = |text: &str| text.replace(['[', ']'], "");
+        let mut value = strip(&definition.definition);
+        if !definition.example.trim().is_empty() {
+            value.push_str(&format!("\n*{}*", strip(definition.example.trim())));
+        }
+        embed = embed.field(
+            format!(
+                "{}. 👍 {} 👎 {}",
+                i + 1,
+                definition.thumbs_up,
+                definition.thumbs_down
+            ),
+            truncate(&value, FIELD_MAX_LEN - "...".len()),
+            false,
+        );
+    }
+    Ok(Some(embed))
+}
+
+/// Definition from the LLM, for terms not in the dictionary
+async fn llm_definition(ctx: &Context<'_>, term: &str) -> Result<CreateEmbed> {
+    let request = {
+        let cfg = ctx.cfg.read().await;
+        let settings = LlmSettings {
+            system: LLM_SYSTEM,
+            vision: false,
+            ..cfg.llm_reply.as_llm_settings()
+        };
+        LlmChatRequest::from_prompt(&settings, term.to_string())
+    };
+    let definition = request.post(ctx).await.map_err(PluginError::llm)?;
+    Ok(CreateEmbed::new()
+        .title(term)
+        .description(definition)
+        .footer(CreateEmbedFooter::new(
+            "Not found in the dictionary; this definition is from the LLM and may be wrong",
+        )))
+}
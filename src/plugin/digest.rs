@@ -0,0 +1,85 @@
+use crate::error::Result;
+use crate::{acl, event::*, log_internal, notification, plugin::*};
+use serenity::all::Permissions;
+
+/// Lets users batch DM notifications into a periodic digest
+pub struct Digest;
+
+#[serenity::async_trait]
+impl Plugin for Digest {
+    fn name(&self) -> &'static str {
+        "digest"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Notifications
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <minutes/off> - receive DM notifications as one digest every so many minutes",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(notification::DIGEST_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = notification::deliver_due_digests(&ctx.ctx()).await {
+                    log_internal!("Error delivering digests: {}", err);
+                }
+            }
+        })))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let id = msg.author.id;
+        let arg = args.trim();
+        let response = if arg == "off" {
+            let pstate = &mut ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            pstate.notification_digest.intervals_minutes.remove(&id);
+            pstate.save().await?;
+            "Digests disabled.  You will be notified immediately.".to_string()
+        } else if let Ok(minutes @ 1..) = arg.parse::<u64>() {
+            let pstate = &mut ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            pstate
+                .notification_digest
+                .intervals_minutes
+                .insert(id, minutes);
+            pstate.save().await?;
+            format!(
+                "Your notifications will be delivered as a digest at most every {} minute(s).",
+                minutes
+            )
+        } else {
+            let prefix = &ctx.cfg.read().await.general.command_prefix;
+            format!("Invalid command.  See `{}help`", prefix)
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -0,0 +1,143 @@
+//! Reports how often each of the guild's custom emoji and stickers has been used, as recorded by
+//! `stats.rs`, so admins can find unused ones to free up slots.
+
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Permissions, Timestamp};
+
+const DEFAULT_DAYS: i64 = 30;
+/// Entries in each of the most and least used lists
+const MAX_ENTRIES: usize = 10;
+
+pub struct EmojiStats;
+
+#[serenity::async_trait]
+impl Plugin for EmojiStats {
+    fn name(&self) -> &'static str {
+        "emojistats"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} [days] - most and least used custom emoji and stickers, over {} days by default",
+            prefix,
+            self.name(),
+            DEFAULT_DAYS
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let days = match args.trim() {
+            "" => DEFAULT_DAYS,
+            days => days.parse().ok().filter(|&days| days > 0).ok_or_else(|| {
+                PluginError::UserError(format!("`{}` is not a number of days.", days))
+            })?,
+        };
+        let since = Timestamp::now().unix_timestamp() - days * 24 * 60 * 60;
+
+        let (emoji, stickers) = usage(ctx, guild_id, since).await;
+        if emoji.is_empty() && stickers.is_empty() {
+            msg.reply(
+                ctx.cache_http,
+                "This server has no custom emoji or stickers.",
+            )
+            .await?;
+            return Ok(EventHandled::Yes);
+        }
+
+        let mut response = format!("Custom emoji and sticker uses in the last {} days:\n", days);
+        for (kind, usage) in [("emoji", emoji), ("stickers", stickers)] {
+            if usage.is_empty() {
+                continue;
+            }
+            let format = |entries: &[(String, u64)]| {
+                entries
+                    .iter()
+                    .map(|(name, count)| format!("{} {}", name, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let most = &usage[..usage.len().min(MAX_ENTRIES)];
+            response.push_str(&format!("**Most used {}:** {}\n", kind, format(most)));
+            // Only worth listing separately if they don't all fit in the first list
+            if usage.len() > MAX_ENTRIES {
+                let least = &usage[usage.len().saturating_sub(MAX_ENTRIES).max(MAX_ENTRIES)..];
+                response.push_str(&format!("**Least used {}:** {}\n", kind, format(least)));
+            }
+        }
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+/// The guild's current emoji and stickers, as they render in a message, with their uses since
+/// `since` (unix seconds), most used first
+async fn usage(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    since: i64,
+) -> (Vec<(String, u64)>, Vec<(String, u64)>) {
+    // Copy the names out so as not to hold the cache across awaits
+    let (emoji, stickers): (Vec<_>, Vec<_>) = match ctx.cache.guild(guild_id) {
+        Some(guild) => (
+            guild
+                .emojis
+                .values()
+                .map(|emoji| (emoji.id, emoji.to_string()))
+                .collect(),
+            guild
+                .stickers
+                .values()
+                .map(|sticker| (sticker.id, format!("`{}`", sticker.name)))
+                .collect(),
+        ),
+        None => Default::default(),
+    };
+
+    let pstate = ctx.pstate.read().await;
+    let mut emoji: Vec<(String, u64)> = emoji
+        .into_iter()
+        .map(|(id, name)| {
+            let uses = pstate
+                .stats
+                .emoji
+                .get(&guild_id)
+                .and_then(|emoji| emoji.get(&id))
+                .map_or(0, |uses| uses.since(since));
+            (name, uses)
+        })
+        .collect();
+    let mut stickers: Vec<(String, u64)> = stickers
+        .into_iter()
+        .map(|(id, name)| {
+            let uses = pstate
+                .stats
+                .stickers
+                .get(&guild_id)
+                .and_then(|stickers| stickers.get(&id))
+                .map_or(0, |uses| uses.since(since));
+            (name, uses)
+        })
+        .collect();
+    emoji.sort_unstable_by_key(|&(_, uses)| std::cmp::Reverse(uses));
+    stickers.sort_unstable_by_key(|&(_, uses)| std::cmp::Reverse(uses));
+    (emoji, stickers)
+}
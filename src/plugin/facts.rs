@@ -0,0 +1,222 @@
+//! Community lore for the LLM.  Members store facts about the server with `;remember`, and
+//! `llm_reply` includes them in its system prompt, so the bot knows them across restarts.  The
+//! newest facts are included first, up to a quarter of the reply model's context.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{split_message, MESSAGE_MAX_LEN};
+use crate::persistent_state::Fact;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{CreateAllowedMentions, CreateMessage, GuildId, Message, Permissions};
+
+/// Longest fact, in characters
+const MAX_FACT_LEN: usize = 300;
+const MAX_FACTS: usize = 100;
+
+pub struct Remember;
+pub struct Forget;
+
+#[serenity::async_trait]
+impl Plugin for Remember {
+    fn name(&self) -> &'static str {
+        "remember"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}remember <fact> - teach me something about this server\n\
+             | {}remember - list what I know about this server",
+            prefix, prefix
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+
+        let fact = args.trim();
+        let response = if fact.is_empty() {
+            list(ctx, guild_id).await
+        } else {
+            add(ctx, msg, guild_id, fact).await?
+        };
+
+        // Facts are quoted as given, so mention no one.  A full list may need several messages.
+        for (i, chunk) in split_message(&response, MESSAGE_MAX_LEN)
+            .into_iter()
+            .enumerate()
+        {
+            let mut message = CreateMessage::new()
+                .content(chunk)
+                .allowed_mentions(CreateAllowedMentions::new());
+            if i == 0 {
+                message = message.reference_message(msg);
+            }
+            msg.channel_id.send_message(ctx.cache_http, message).await?;
+        }
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+#[serenity::async_trait]
+impl Plugin for Forget {
+    fn name(&self) -> &'static str {
+        "forget"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}forget <id> - remove a fact (requires Manage Messages, unless you added it)",
+            prefix
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+        let Ok(id) = args.trim().trim_start_matches('#').parse::<u64>() else {
+            return Err(PluginError::UserError("Usage: `forget <id>`".to_string()));
+        };
+
+        let added_by = ctx
+            .pstate
+            .read()
+            .await
+            .facts
+            .guilds
+            .get(&guild_id)
+            .and_then(|facts| facts.iter().find(|fact| fact.id == id))
+            .map(|fact| fact.added_by);
+        let Some(added_by) = added_by else {
+            return Err(PluginError::UserError(format!("No fact #{}.", id)));
+        };
+        let permitted = added_by == msg.author.id
+            || msg
+                .author_permissions(ctx.cache)
+                .is_some_and(|p| p.contains(Permissions::MANAGE_MESSAGES));
+        acl::check(ctx, msg, self.name(), permitted).await?;
+
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        if let Some(facts) = pstate.facts.guilds.get_mut(&guild_id) {
+            facts.retain(|fact| fact.id != id);
+        }
+        pstate.save().await?;
+        drop(pstate);
+
+        msg.reply(ctx.cache_http, format!("Forgot fact #{}.", id))
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn add(ctx: &Context<'_>, msg: &Message, guild_id: GuildId, fact: &str) -> Result<String> {
+    if fact.chars().count() > MAX_FACT_LEN {
+        return Err(PluginError::UserError(format!(
+            "Please keep facts under {} characters.",
+            MAX_FACT_LEN
+        )));
+    }
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    if pstate
+        .facts
+        .guilds
+        .get(&guild_id)
+        .is_some_and(|facts| facts.len() >= MAX_FACTS)
+    {
+        return Err(PluginError::UserError(format!(
+            "I already know {} facts about this server.  `forget` some first.",
+            MAX_FACTS
+        )));
+    }
+    let id = pstate.facts.add(
+        guild_id,
+        Fact {
+            id: 0,
+            text: fact.to_string(),
+            added_by: msg.author.id,
+        },
+    );
+    pstate.save().await?;
+    Ok(format!("I'll remember that as fact #{}.", id))
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let pstate = ctx.pstate.read().await;
+    let Some(facts) = pstate
+        .facts
+        .guilds
+        .get(&guild_id)
+        .filter(|facts| !facts.is_empty())
+    else {
+        return "I don't know any facts about this server yet.".to_string();
+    };
+
+    let mut response = String::from("Facts:\n");
+    for fact in facts {
+        response.push_str(&format!("#{} {}\n", fact.id, fact.text));
+    }
+    response
+}
+
+/// The guild's facts for a system prompt, oldest first.  The newest are preferred if they don't
+/// all fit in a quarter of `context_size` tokens.
+pub async fn for_prompt(ctx: &Context<'_>, msg: &Message, context_size: usize) -> Vec<String> {
+    let Some(guild_id) = msg.guild_id else {
+        return Vec::new();
+    };
+    // Use byte count as a crude estimate of tokens, as `LlmChatRequest` does
+    let budget = context_size * 3 / 4;
+    let pstate = ctx.pstate.read().await;
+    let mut total_bytes = 0;
+    let mut facts: Vec<String> = pstate
+        .facts
+        .guilds
+        .get(&guild_id)
+        .into_iter()
+        .flatten()
+        .rev()
+        .map(|fact| fact.text.clone())
+        .take_while(|text| {
+            total_bytes += text.len();
+            total_bytes <= budget
+        })
+        .collect();
+    facts.reverse();
+    facts
+}
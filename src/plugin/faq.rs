@@ -0,0 +1,207 @@
+//! Frequently asked questions.  Admins store questions with canonical answers, and messages
+//! similar enough to a stored question, as judged by the `[faq]` embedding model, get that answer
+//! rather than reaching `llm_reply`.
+
+use crate::error::{PluginError, Result};
+use crate::persistent_state::FaqEntry;
+use crate::{acl, event::*, helper::truncate, llm, log_internal, plugin::*};
+use serenity::all::{GuildId, Message, Permissions};
+
+/// Longest question shown in `list`, in bytes
+const LIST_QUESTION_LEN: usize = 80;
+
+pub struct Faq;
+
+#[serenity::async_trait]
+impl Plugin for Faq {
+    fn name(&self) -> &'static str {
+        "faq"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}faq <subcommand> -- answer repeated questions\n\
+             | Subcommands:\n\
+             | add <question> | <answer> - answer questions like this one (requires Manage Server)\n\
+             | remove <id> - remove a question (requires Manage Server)\n\
+             | list - list this server's questions",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return match event {
+                Event::Message(msg) => answer(ctx, msg).await,
+                _ => Ok(EventHandled::No),
+            };
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "FAQs only work within a server".to_string(),
+            ));
+        };
+
+        let args = args.trim();
+        let (subcommand, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let response = match subcommand {
+            "list" => list(ctx, guild_id).await,
+            "add" | "remove" => {
+                let permitted = msg
+                    .author_permissions(ctx.cache)
+                    .is_some_and(|p| p.contains(Permissions::MANAGE_GUILD));
+                acl::check(ctx, msg, &format!("faq.{}", subcommand), permitted).await?;
+                if subcommand == "add" {
+                    add(ctx, guild_id, rest).await?
+                } else {
+                    remove(ctx, guild_id, rest).await?
+                }
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+}
+
+async fn add(ctx: &Context<'_>, guild_id: GuildId, args: &str) -> Result<String> {
+    let Some((question, answer)) = args
+        .split_once('|')
+        .map(|(q, a)| (q.trim(), a.trim()))
+        .filter(|(q, a)| !q.is_empty() && !a.is_empty())
+    else {
+        return Err(PluginError::UserError(
+            "Usage: `faq add <question> | <answer>`".to_string(),
+        ));
+    };
+    if ctx.cfg.read().await.faq.is_none() {
+        return Err(PluginError::UserError(
+            "No `[faq]` embedding model is configured.".to_string(),
+        ));
+    }
+
+    let embedding = llm::embed(ctx, question).await.map_err(PluginError::llm)?;
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let id = pstate.faq.add(
+        guild_id,
+        FaqEntry {
+            id: 0,
+            question: question.to_string(),
+            answer: answer.to_string(),
+            embedding,
+        },
+    );
+    pstate.save().await?;
+    Ok(format!("Added FAQ #{}.", id))
+}
+
+async fn remove(ctx: &Context<'_>, guild_id: GuildId, args: &str) -> Result<String> {
+    let Ok(id) = args.trim().trim_start_matches('#').parse::<u64>() else {
+        return Err(PluginError::UserError(
+            "Usage: `faq remove <id>`".to_string(),
+        ));
+    };
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let Some(entries) = pstate.faq.guilds.get_mut(&guild_id) else {
+        return Err(PluginError::UserError(format!("No FAQ #{}.", id)));
+    };
+    let len = entries.len();
+    entries.retain(|entry| entry.id != id);
+    if entries.len() == len {
+        return Err(PluginError::UserError(format!("No FAQ #{}.", id)));
+    }
+    pstate.save().await?;
+    Ok(format!("Removed FAQ #{}.", id))
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let pstate = ctx.pstate.read().await;
+    let Some(entries) = pstate
+        .faq
+        .guilds
+        .get(&guild_id)
+        .filter(|entries| !entries.is_empty())
+    else {
+        return "No FAQs.".to_string();
+    };
+
+    let mut response = String::from("FAQs:\n");
+    for entry in entries {
+        response.push_str(&format!(
+            "• #{}: {}\n",
+            entry.id,
+            truncate(&entry.question, LIST_QUESTION_LEN)
+        ));
+    }
+    response
+}
+
+/// Reply with the answer to the stored question most similar to `msg`, if similar enough
+async fn answer(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(EventHandled::No);
+    };
+    if msg.author.bot || !msg.content.contains('?') {
+        return Ok(EventHandled::No);
+    }
+    let Some(threshold) = ctx.cfg.read().await.faq.as_ref().map(|faq| faq.threshold) else {
+        return Ok(EventHandled::No);
+    };
+    {
+        let pstate = ctx.pstate.read().await;
+        // Users who opted out of LLM features don't have their messages embedded either
+        if pstate.llm_optout.users.contains(&msg.author.id)
+            || pstate
+                .faq
+                .guilds
+                .get(&guild_id)
+                .is_none_or(|entries| entries.is_empty())
+        {
+            return Ok(EventHandled::No);
+        }
+    }
+
+    // Not worth failing the message over; let `llm_reply` have it instead
+    let embedding = match llm::embed(ctx, &msg.content).await {
+        Ok(embedding) => embedding,
+        Err(err) => {
+            log_internal!("Could not embed message for FAQ: {}", err);
+            return Ok(EventHandled::No);
+        }
+    };
+
+    let best = {
+        let pstate = ctx.pstate.read().await;
+        pstate.faq.guilds.get(&guild_id).and_then(|entries| {
+            entries
+                .iter()
+                .map(|entry| (llm::similarity(&embedding, &entry.embedding), entry))
+                .filter(|(similarity, _)| *similarity >= threshold)
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, entry)| entry.answer.clone())
+        })
+    };
+    let Some(answer) = best else {
+        return Ok(EventHandled::No);
+    };
+    msg.reply(ctx.cache_http, answer).await?;
+    Ok(EventHandled::Yes)
+}
@@ -0,0 +1,197 @@
+//! Lets bot owners delegate capabilities to trusted users without changing their roles.  Grants
+//! are checked by `acl.rs`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{parse_user, MessageHelper, UserIdHelper};
+use crate::{event::*, plugin::perm::known_capability, plugin::*};
+use serenity::all::{Message, Permissions, UserId};
+
+pub struct Grant;
+pub struct Revoke;
+
+#[serenity::async_trait]
+impl Plugin for Grant {
+    fn name(&self) -> &'static str {
+        "grant"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}grant <@user> <capability> - let a user use a command, e.g. `rivals.delete`, anywhere (bot owner only)\n\
+             | {}grant list - list grants",
+            prefix, prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        if !msg.is_from_owner(ctx).await {
+            return Err(PluginError::PermissionDenied);
+        }
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, msg).await,
+            [user, capability] => {
+                let user_id = parse_target(user)?;
+                let capability = known_capability(ctx, capability).await?;
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                pstate
+                    .acl
+                    .grants
+                    .entry(user_id)
+                    .or_default()
+                    .insert(capability.clone());
+                pstate.save().await?;
+                drop(pstate);
+                format!(
+                    "Granted `{}` to `@{}`.",
+                    capability,
+                    user_id.nick_in_guild(ctx, msg.guild_id).await
+                )
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+#[serenity::async_trait]
+impl Plugin for Revoke {
+    fn name(&self) -> &'static str {
+        "revoke"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}revoke <@user> [capability] - revoke a grant, or all of a user's grants (bot owner only)",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        if !msg.is_from_owner(ctx).await {
+            return Err(PluginError::PermissionDenied);
+        }
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let (user_id, capability) = match args.as_slice() {
+            [user] => (parse_target(user)?, None),
+            [user, capability] => (parse_target(user)?, Some(capability.to_lowercase())),
+            _ => {
+                return Err(PluginError::UserError(
+                    "Invalid command.  See help for usage.".to_string(),
+                ))
+            }
+        };
+
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let grants = &mut pstate.acl.grants;
+        let revoked = match (&capability, grants.get_mut(&user_id)) {
+            (_, None) => false,
+            (None, Some(_)) => grants.remove(&user_id).is_some(),
+            (Some(capability), Some(user_grants)) => {
+                let revoked = user_grants.remove(capability);
+                if user_grants.is_empty() {
+                    grants.remove(&user_id);
+                }
+                revoked
+            }
+        };
+        if revoked {
+            pstate.save().await?;
+        }
+        drop(pstate);
+
+        let name = user_id.nick_in_guild(ctx, msg.guild_id).await;
+        let response = match (revoked, capability) {
+            (false, Some(capability)) => {
+                format!("`@{}` has not been granted `{}`.", name, capability)
+            }
+            (false, None) => format!("`@{}` has no grants.", name),
+            (true, Some(capability)) => format!("Revoked `{}` from `@{}`.", capability, name),
+            (true, None) => format!("Revoked all grants from `@{}`.", name),
+        };
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+fn parse_target(arg: &str) -> Result<UserId> {
+    parse_user(arg).ok_or_else(|| {
+        PluginError::UserError(format!(
+            "`{}` is not a user.  Mention them, e.g. `@someone`.",
+            arg
+        ))
+    })
+}
+
+async fn list(ctx: &Context<'_>, msg: &Message) -> String {
+    // Copy the grants out so as not to hold the lock while looking up names
+    let mut grants: Vec<(UserId, Vec<String>)> = ctx
+        .pstate
+        .read()
+        .await
+        .acl
+        .grants
+        .iter()
+        .map(|(user_id, capabilities)| {
+            let mut capabilities: Vec<String> = capabilities.iter().cloned().collect();
+            capabilities.sort_unstable();
+            (*user_id, capabilities)
+        })
+        .collect();
+    if grants.is_empty() {
+        return "No grants.".to_string();
+    }
+    grants.sort_unstable_by_key(|(user_id, _)| *user_id);
+
+    let mut response = String::from("Grants:\n");
+    for (user_id, capabilities) in grants {
+        response.push_str(&format!(
+            "• `@{}`: {}\n",
+            user_id.nick_in_guild(ctx, msg.guild_id).await,
+            capabilities
+                .iter()
+                .map(|capability| format!("`{}`", capability))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    response
+}
@@ -1,5 +1,9 @@
-use crate::{event::*, plugin::*};
-use anyhow::Result;
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, locale, plugin::*};
+use serenity::all::{CreateEmbed, CreateMessage, Permissions};
+
+/// Discord's limit on the length of an embed field's value
+const FIELD_MAX_LEN: usize = 1024;
 
 pub struct Help;
 
@@ -9,32 +13,119 @@ impl Plugin for Help {
         "help"
     }
 
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
     async fn usage(&self, ctx: &Context) -> Option<String> {
         let prefix = &ctx.cfg.read().await.general.command_prefix;
         Some(format!(
-            "{}{} - show this help message",
+            "{}{} [command] - show this help message, or details on one command",
             prefix,
             self.name()
         ))
     }
 
     async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
-        let Some((msg, _)) = event.is_bot_cmd(ctx, self.name()).await else {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
             return Ok(EventHandled::No);
         };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+        let command = args.trim();
+        let command = command.strip_prefix(prefix.as_str()).unwrap_or(command);
+        if !command.is_empty() {
+            for plugin in crate::plugin::plugins() {
+                let Some(usage) = plugin.usage(ctx).await else {
+                    continue;
+                };
+                if plugin.name() != command && command_name(&usage, &prefix) != Some(command) {
+                    continue;
+                }
+                let detail = locale::detailed_usage(ctx, msg.guild_id, plugin.as_ref())
+                    .await
+                    .unwrap_or(usage);
+                msg.reply(ctx.cache_http, format!("```\n{}\n```", detail))
+                    .await?;
+                return Ok(EventHandled::Yes);
+            }
+            return Err(PluginError::UserError(format!(
+                "Unknown command `{}`.  See `{}{}` for a list of commands.",
+                command,
+                prefix,
+                self.name()
+            )));
+        }
 
-        let mut reply = String::new();
-        reply.push_str("```\n");
-        reply.push_str("Commands:\n");
+        // First line of each usage, grouped by category
+        let mut sections: Vec<(Category, Vec<String>)> = Category::ALL
+            .iter()
+            .map(|&category| (category, Vec::new()))
+            .collect();
         for plugin in crate::plugin::plugins() {
-            if let Some(usage) = plugin.usage(ctx).await {
-                reply.push_str(&usage);
-                reply.push('\n');
+            let Some(usage) = locale::usage(ctx, msg.guild_id, plugin.as_ref()).await else {
+                continue;
+            };
+            let summary = usage.lines().next().unwrap_or_default().to_string();
+            if let Some((_, lines)) = sections.iter_mut().find(|(c, _)| *c == plugin.category()) {
+                lines.push(summary);
+            }
+        }
+
+        let description = locale::text(
+            ctx,
+            msg.guild_id,
+            "Use `{prefix}help <command>` for details on a command.",
+        )
+        .await
+        .replace("{prefix}", &prefix);
+        let mut embed = CreateEmbed::new()
+            .title(locale::text(ctx, msg.guild_id, "Commands").await)
+            .description(description);
+        for (category, lines) in sections {
+            // Split across several fields if needed to fit Discord's limit
+            let mut fields: Vec<String> = Vec::new();
+            for line in lines {
+                match fields.last_mut() {
+                    Some(field) if field.len() + line.len() + "\n```".len() < FIELD_MAX_LEN => {
+                        field.push_str(&line);
+                        field.push('\n');
+                    }
+                    _ => fields.push(format!("```\n{}\n", line)),
+                }
+            }
+            let name = locale::text(ctx, msg.guild_id, category.name()).await;
+            let continued = locale::text(ctx, msg.guild_id, "(continued)").await;
+            for (i, field) in fields.into_iter().enumerate() {
+                let name = if i == 0 {
+                    name.clone()
+                } else {
+                    format!("{} {}", name, continued)
+                };
+                embed = embed.field(name, format!("{}```", field), false);
             }
         }
-        reply.push_str("```\n");
 
-        msg.reply(ctx.cache_http, &reply).await?;
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new().embed(embed).reference_message(msg),
+            )
+            .await?;
         Ok(EventHandled::Yes)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::EMBED_LINKS)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// Command a usage string documents, e.g. `rivals` for `;rivals <subcommand> -- ...`
+fn command_name<'a>(usage: &'a str, prefix: &str) -> Option<&'a str> {
+    usage.strip_prefix(prefix)?.split_whitespace().next()
 }
@@ -1,5 +1,7 @@
+use crate::error::Result;
+use crate::volatile_state::{History as VolatileHistory, HistoryEntry};
 use crate::{event::*, plugin::*};
-use anyhow::Result;
+use serenity::all::Permissions;
 
 /// Initializes and maintains room history
 pub struct History;
@@ -19,8 +21,35 @@ impl Plugin for History {
             return Ok(EventHandled::No);
         };
 
-        ctx.vstate.write().await.history.push(ctx, msg).await?;
+        // Do the slow parts without holding the state lock so other events aren't held up.
+        VolatileHistory::ensure_backfilled(ctx, msg.channel_id).await?;
+        let entry = HistoryEntry::from_message(ctx, msg).await?;
+        let history_max = ctx.cfg.read().await.history.channel_max_message_count;
+        #[cfg(feature = "redis")]
+        crate::redis_state::push_history(ctx, msg.channel_id, &entry, history_max).await;
+        ctx.vstate
+            .write()
+            .await
+            .history
+            .push(ctx, msg.channel_id, entry, history_max)
+            .await?;
 
         Ok(EventHandled::No)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::VIEW_CHANNEL.union(Permissions::READ_MESSAGE_HISTORY)
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Record
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+
+    fn sees_bot_messages(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,106 @@
+use crate::error::{PluginError, Result};
+use crate::helper::truncate;
+use crate::volatile_state::History as VolatileHistory;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::Permissions;
+use std::time::Duration;
+
+/// Keep replies under Discord's message length limit
+const MAX_RESULTS: usize = 8;
+const COOLDOWN: Duration = Duration::from_secs(30);
+/// Longest quoted message in results, in bytes
+const SNIPPET_LEN: usize = 80;
+
+/// Searches the current channel's recorded history
+pub struct HistorySearch;
+
+#[serenity::async_trait]
+impl Plugin for HistorySearch {
+    fn name(&self) -> &'static str {
+        "history_search"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some("history search")
+    }
+
+    async fn cooldown(&self, _ctx: &Context) -> Option<Duration> {
+        Some(COOLDOWN)
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}history search <terms> - find recent messages in this channel containing all terms",
+            prefix,
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, "history search").await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, "history", true).await?;
+
+        let terms: Vec<String> = args.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            let prefix = &ctx.cfg.read().await.general.command_prefix;
+            return Err(PluginError::UserError(format!(
+                "Usage: {}history search <terms>",
+                prefix
+            )));
+        }
+
+        VolatileHistory::ensure_backfilled(ctx, msg.channel_id).await?;
+        let mut results = Vec::new();
+        let mut matches = 0;
+        {
+            let mut vstate = ctx.vstate.write().await;
+            let history = vstate.history.get(ctx, msg.channel_id).await?;
+            // Newest first, skipping the search itself
+            for entry in history
+                .iter()
+                .rev()
+                .filter(|entry| entry.message_id != msg.id)
+            {
+                let content = entry.llm_content();
+                let lowercase = content.to_lowercase();
+                if !terms.iter().all(|term| lowercase.contains(term.as_str())) {
+                    continue;
+                }
+                matches += 1;
+                if results.len() < MAX_RESULTS {
+                    results.push(format!(
+                        "{}: {} {}",
+                        entry.author_name,
+                        truncate(&content, SNIPPET_LEN),
+                        entry.message_id.link(msg.channel_id, msg.guild_id),
+                    ));
+                }
+            }
+        }
+
+        let response = if results.is_empty() {
+            "No matching messages in this channel's recent history".to_string()
+        } else if matches > results.len() {
+            format!(
+                "{}\n({} more matches; try more specific terms)",
+                results.join("\n"),
+                matches - results.len()
+            )
+        } else {
+            results.join("\n")
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -0,0 +1,143 @@
+//! Ignored channels and users.  The dispatcher drops their messages before any plugin runs, other
+//! than those whose `runs_when_ignored()` is set, such as debug logging.  Channels and users may
+//! also be ignored in `[blocklist]`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{parse_user, UserIdHelper};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{Message, Permissions};
+
+pub struct Ignore;
+
+#[serenity::async_trait]
+impl Plugin for Ignore {
+    fn name(&self) -> &'static str {
+        "ignore"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}ignore <subcommand> -- channels and users whose messages I ignore (bot owner only)\n\
+             | Subcommands:\n\
+             | channel <#channel> - ignore a channel\n\
+             | user <@user> - ignore a user\n\
+             | remove <#channel/@user> - stop ignoring a channel or user\n\
+             | list - list ignored channels and users",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), false).await?;
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, msg).await,
+            ["channel", channel] => {
+                let channel_id =
+                    serenity::utils::parse_channel_mention(channel).ok_or_else(|| {
+                        PluginError::UserError(
+                            "Invalid channel.  Mention it, e.g. `#general`.".to_string(),
+                        )
+                    })?;
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                pstate.blocklist.channels.insert(channel_id);
+                pstate.save().await?;
+                format!("I'll ignore messages in <#{}>.", channel_id)
+            }
+            ["user", user] => {
+                let user_id = parse_user(user).ok_or_else(|| {
+                    PluginError::UserError(
+                        "Invalid user.  Mention them, e.g. `@someone`.".to_string(),
+                    )
+                })?;
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                pstate.blocklist.users.insert(user_id);
+                pstate.save().await?;
+                drop(pstate);
+                format!(
+                    "I'll ignore messages from `@{}`.",
+                    user_id.nick_in_guild(ctx, msg.guild_id).await
+                )
+            }
+            ["remove", target] => {
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let removed =
+                    if let Some(channel_id) = serenity::utils::parse_channel_mention(target) {
+                        pstate.blocklist.channels.remove(&channel_id)
+                    } else if let Some(user_id) = parse_user(target) {
+                        pstate.blocklist.users.remove(&user_id)
+                    } else {
+                        return Err(PluginError::UserError(
+                            "Invalid channel or user.  Mention it, e.g. `#general` or `@someone`."
+                                .to_string(),
+                        ));
+                    };
+                if removed {
+                    pstate.save().await?;
+                    "No longer ignored.".to_string()
+                } else {
+                    "That wasn't ignored.  It may be in the `[blocklist]` config instead."
+                        .to_string()
+                }
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+async fn list(ctx: &Context<'_>, msg: &Message) -> String {
+    let (mut channels, mut users): (Vec<_>, Vec<_>) = {
+        let pstate = ctx.pstate.read().await;
+        (
+            pstate.blocklist.channels.iter().copied().collect(),
+            pstate.blocklist.users.iter().copied().collect(),
+        )
+    };
+    if let Some(blocklist) = &ctx.cfg.read().await.blocklist {
+        channels.extend(&blocklist.channels);
+        users.extend(&blocklist.users);
+    }
+    channels.sort_unstable();
+    channels.dedup();
+    users.sort_unstable();
+    users.dedup();
+    if channels.is_empty() && users.is_empty() {
+        return "Nothing is ignored.".to_string();
+    }
+
+    let mut response = String::from("Ignored:\n");
+    for channel_id in channels {
+        response.push_str(&format!("• <#{}>\n", channel_id));
+    }
+    // Names rather than mentions, which would ping
+    for user_id in users {
+        response.push_str(&format!(
+            "• `@{}`\n",
+            user_id.nick_in_guild(ctx, msg.guild_id).await
+        ));
+    }
+    response
+}
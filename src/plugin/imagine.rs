@@ -0,0 +1,182 @@
+//! Generates an image from a prompt with the `[imagine]` text-to-image backend, which speaks the
+//! AUTOMATIC1111 `txt2img` API, and posts it as an attachment.  Each user has a cooldown between
+//! images.  Unless the guild has turned the NSFW filter off and the channel is age-restricted,
+//! prompts with NSFW terms are refused and the rest are steered away from NSFW output.
+
+use crate::error::{PluginError, Result, Service};
+use crate::{acl, event::*, plugin::*};
+use base64::Engine;
+use serenity::all::{CreateAttachment, CreateMessage, Message, Permissions};
+use std::time::Duration;
+
+/// Generation is slow on modest hardware
+const TIMEOUT: Duration = Duration::from_secs(180);
+const WIDTH: u32 = 512;
+const HEIGHT: u32 = 512;
+/// Refused outright while filtering
+const NSFW_TERMS: &[&str] = &[
+    "nsfw",
+    "nude",
+    "nudes",
+    "nudity",
+    "naked",
+    "topless",
+    "porn",
+    "porno",
+    "pornographic",
+    "explicit",
+    "sex",
+    "sexual",
+    "hentai",
+    "erotic",
+    "lewd",
+    "genitals",
+    "gore",
+];
+/// Steers the model away from NSFW output while filtering
+const NSFW_NEGATIVE_PROMPT: &str = "nsfw, nudity, naked, sexual content, gore";
+
+pub struct Imagine;
+
+#[derive(serde::Serialize)]
+struct Txt2ImgRequest<'a> {
+    prompt: &'a str,
+    negative_prompt: &'a str,
+    steps: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde::Deserialize)]
+struct Txt2ImgResponse {
+    /// Base64 PNGs
+    images: Vec<String>,
+}
+
+#[serenity::async_trait]
+impl Plugin for Imagine {
+    fn name(&self) -> &'static str {
+        "imagine"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn cooldown(&self, ctx: &Context) -> Option<Duration> {
+        let cfg = ctx.cfg.read().await;
+        let imagine = cfg.imagine.as_ref()?;
+        Some(Duration::from_secs(imagine.cooldown_seconds))
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <prompt> - generate an image",
+            prefix,
+            self.name()
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let prompt = args.trim();
+        let Some((url, steps, unfiltered)) = ctx.cfg.read().await.imagine.as_ref().map(|imagine| {
+            (
+                imagine.url.clone(),
+                imagine.steps,
+                msg.guild_id
+                    .is_some_and(|guild_id| imagine.unfiltered_guilds.contains(&guild_id)),
+            )
+        }) else {
+            return Err(PluginError::UserError(
+                "Image generation isn't set up.".to_string(),
+            ));
+        };
+        if prompt.is_empty() {
+            return Err(PluginError::UserError(
+                "Describe the image, e.g. `imagine a lighthouse at dusk`.".to_string(),
+            ));
+        }
+
+        let filtered = !(unfiltered && is_nsfw_channel(ctx, msg).await);
+        if filtered && has_nsfw_terms(prompt) {
+            return Err(PluginError::UserError(
+                "Sorry, I can't make that here.".to_string(),
+            ));
+        }
+
+        let typing = msg.channel_id.start_typing(ctx.http);
+        let image = generate(&url, prompt, steps, filtered).await;
+        typing.stop();
+
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new()
+                    .add_file(CreateAttachment::bytes(image?, "imagine.png"))
+                    .reference_message(msg),
+            )
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::ATTACH_FILES)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+async fn is_nsfw_channel(ctx: &Context<'_>, msg: &Message) -> bool {
+    match msg.channel_id.to_channel(ctx.cache_http).await {
+        Ok(channel) => channel.guild().is_some_and(|channel| channel.nsfw),
+        Err(_) => false,
+    }
+}
+
+fn has_nsfw_terms(prompt: &str) -> bool {
+    prompt
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| NSFW_TERMS.contains(&word))
+}
+
+/// The PNG for `prompt`
+async fn generate(url: &str, prompt: &str, steps: u32, filtered: bool) -> Result<Vec<u8>> {
+    let request = Txt2ImgRequest {
+        prompt,
+        negative_prompt: if filtered { NSFW_NEGATIVE_PROMPT } else { "" },
+        steps,
+        width: WIDTH,
+        height: HEIGHT,
+    };
+    let response = reqwest::Client::new()
+        .post(url)
+        .timeout(TIMEOUT)
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Txt2ImgResponse>()
+        .await?;
+    let Some(image) = response.images.into_iter().next() else {
+        return Err(PluginError::Backend(
+            Service::Web,
+            anyhow::anyhow!("The image backend returned no images"),
+        ));
+    };
+    base64::engine::general_purpose::STANDARD
+        .decode(image)
+        .map_err(|err| PluginError::Backend(Service::Web, err.into()))
+}
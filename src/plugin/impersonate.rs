@@ -0,0 +1,195 @@
+//! Imitates a member with a word-level Markov chain built from their messages in the guild's
+//! recorded history.  Only enabled in the guilds listed in `[impersonate]`, and members may opt
+//! out.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{parse_user, UserIdHelper};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, Permissions};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// Fewer messages make for a chain which can only repeat them
+const MIN_MESSAGES: usize = 10;
+const MAX_WORDS: usize = 40;
+/// Tries at generating something other than one of the member's own messages
+const ATTEMPTS: usize = 5;
+
+/// The two previous words, with "" before the start of a message
+type State<'a> = (&'a str, &'a str);
+
+pub struct Impersonate;
+
+#[serenity::async_trait]
+impl Plugin for Impersonate {
+    fn name(&self) -> &'static str {
+        "impersonate"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <@user/optout/optin> - write a message in the style of a member's recent messages, or opt out of being imitated",
+            prefix,
+            self.name()
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+        let enabled = ctx
+            .cfg
+            .read()
+            .await
+            .impersonate
+            .as_ref()
+            .is_some_and(|impersonate| impersonate.guilds.contains(&guild_id));
+        if !enabled {
+            return Err(PluginError::UserError(
+                "Impersonation isn't enabled in this server.".to_string(),
+            ));
+        }
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args = args.trim();
+        if let "optout" | "optin" = args {
+            let mut pstate = ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            let response = if args == "optout" {
+                pstate.impersonate_optout.users.insert(msg.author.id);
+                "You have opted out.  Nobody can impersonate you."
+            } else {
+                pstate.impersonate_optout.users.remove(&msg.author.id);
+                "You have opted back in to impersonation."
+            };
+            pstate.save().await?;
+            drop(pstate);
+            msg.reply(ctx.cache_http, response).await?;
+            return Ok(EventHandled::Yes);
+        }
+
+        let Some(user_id) = parse_user(args) else {
+            return Err(PluginError::UserError(
+                "Mention the member to impersonate, e.g. `impersonate @user`.".to_string(),
+            ));
+        };
+        let name = user_id.nick_in_guild(ctx, Some(guild_id)).await;
+        let opted_out = ctx
+            .pstate
+            .read()
+            .await
+            .impersonate_optout
+            .users
+            .contains(&user_id);
+        if opted_out {
+            return Err(PluginError::UserError(format!(
+                "{} has opted out of impersonation.",
+                name
+            )));
+        }
+
+        // Only draw on channels the requester can see, so as not to leak messages from others
+        let channels: Vec<ChannelId> = match ctx.cache.guild(guild_id) {
+            Some(guild) => match guild.members.get(&msg.author.id) {
+                Some(member) => guild
+                    .channels
+                    .values()
+                    .filter(|channel| guild.user_permissions_in(channel, member).view_channel())
+                    .map(|channel| channel.id)
+                    .collect(),
+                None => vec![msg.channel_id],
+            },
+            None => vec![msg.channel_id],
+        };
+        let messages: Vec<String> = {
+            let vstate = ctx.vstate.read().await;
+            channels
+                .iter()
+                .filter_map(|channel_id| vstate.history.recorded(*channel_id))
+                .flatten()
+                .filter(|entry| entry.author_id == user_id && entry.message_id != msg.id)
+                .map(|entry| entry.human_format_content.clone())
+                .filter(|content| !content.trim().is_empty())
+                .collect()
+        };
+        if messages.len() < MIN_MESSAGES {
+            return Err(PluginError::UserError(format!(
+                "I haven't seen enough of {}'s messages to impersonate them.",
+                name
+            )));
+        }
+
+        let chain = build_chain(&messages);
+        let random = std::collections::hash_map::RandomState::new();
+        let mut text = String::new();
+        for attempt in 0..ATTEMPTS {
+            text = generate(&chain, &random, attempt);
+            if !messages.contains(&text) {
+                break;
+            }
+        }
+
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new()
+                    .content(format!("*{}:* {}", name, text))
+                    .allowed_mentions(CreateAllowedMentions::new())
+                    .reference_message(msg),
+            )
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+/// Words seen after each pair of words.  "" marks the end of a message.
+fn build_chain(messages: &[String]) -> HashMap<State<'_>, Vec<&str>> {
+    let mut chain: HashMap<State, Vec<&str>> = HashMap::new();
+    for message in messages {
+        let mut state = ("", "");
+        for word in message.split_whitespace() {
+            chain.entry(state).or_default().push(word);
+            state = (state.1, word);
+        }
+        chain.entry(state).or_default().push("");
+    }
+    chain
+}
+
+fn generate(
+    chain: &HashMap<State<'_>, Vec<&str>>,
+    random: &impl BuildHasher,
+    attempt: usize,
+) -> String {
+    let mut words = Vec::new();
+    let mut state = ("", "");
+    for i in 0..MAX_WORDS {
+        let Some(next) = chain.get(&state) else {
+            break;
+        };
+        let word = next[random.hash_one((attempt, i)) as usize % next.len()];
+        if word.is_empty() {
+            break;
+        }
+        words.push(word);
+        state = (state.1, word);
+    }
+    words.join(" ")
+}
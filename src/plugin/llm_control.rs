@@ -0,0 +1,182 @@
+use crate::error::{PluginError, Result};
+use crate::helper::Args;
+use crate::subcommand::{Access, SubcommandRouter};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{CreateAttachment, CreateMessage, Message, Permissions};
+
+/// User-facing controls over how the bot's LLM features treat them, and owner controls over which
+/// `[llm_profiles]` a channel's replies use and for inspecting `[llm_audit]`
+pub struct LlmControl;
+
+#[serenity::async_trait]
+impl Plugin for LlmControl {
+    fn name(&self) -> &'static str {
+        "llm"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(router().usage(prefix))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        router().dispatch(ctx, msg, args).await
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+fn router() -> SubcommandRouter {
+    SubcommandRouter::new("llm", "how my LLM features treat you")
+        .subcommand(
+            "optout",
+            "exclude your messages from LLM context and replies",
+            Access::Everyone,
+            |ctx, msg, _args| Box::pin(set_optout(ctx, msg, true)),
+        )
+        .subcommand(
+            "optin",
+            "include your messages in LLM context and replies again",
+            Access::Everyone,
+            |ctx, msg, _args| Box::pin(set_optout(ctx, msg, false)),
+        )
+        .subcommand(
+            "profile [name]",
+            "show or switch the profile this channel's replies use",
+            Access::Owner,
+            |ctx, msg, args| Box::pin(profile(ctx, msg, args)),
+        )
+        .subcommand(
+            "lastprompt",
+            "DM you the most recent LLM request and response",
+            Access::Owner,
+            |ctx, msg, _args| Box::pin(last_prompt(ctx, msg)),
+        )
+        .note("Use `profile default` to go back to the `[llm_reply]` settings.")
+}
+
+async fn set_optout(ctx: &Context<'_>, msg: &Message, optout: bool) -> Result<EventHandled> {
+    let id = msg.author.id;
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let opted_out = pstate.llm_optout.users.contains(&id);
+
+    let response = match (optout, opted_out) {
+        (true, true) => "You are already opted out of LLM features",
+        (true, false) => {
+            pstate.llm_optout.users.insert(id);
+            pstate.save().await?;
+            "You have opted out.  Your messages will no longer be sent to the LLM and it will not reply to you"
+        }
+        (false, true) => {
+            pstate.llm_optout.users.remove(&id);
+            pstate.save().await?;
+            "You have opted back in to LLM features"
+        }
+        (false, false) => "You are not opted out of LLM features",
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn profile(ctx: &Context<'_>, msg: &Message, args: &Args) -> Result<EventHandled> {
+    let mut available: Vec<String> = ctx.cfg.read().await.llm_profiles.keys().cloned().collect();
+    available.sort();
+
+    let response = match args.get(0) {
+        None => {
+            let current = ctx
+                .pstate
+                .read()
+                .await
+                .llm_profiles
+                .channels
+                .get(&msg.channel_id)
+                .cloned();
+            let available = if available.is_empty() {
+                "none are configured".to_string()
+            } else {
+                available.join(", ")
+            };
+            format!(
+                "Replies here use the `{}` profile.  Available: {}",
+                current.as_deref().unwrap_or("default"),
+                available
+            )
+        }
+        Some(name) if name.eq_ignore_ascii_case("default") => {
+            let pstate = &mut ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            pstate.llm_profiles.channels.remove(&msg.channel_id);
+            pstate.save().await?;
+            "Replies here use the default settings again.".to_string()
+        }
+        Some(name) => {
+            if !available.iter().any(|profile| profile == name) {
+                return Err(PluginError::UserError(format!(
+                    "No profile named `{}`.  Available: {}",
+                    name,
+                    available.join(", ")
+                )));
+            }
+            let pstate = &mut ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            pstate
+                .llm_profiles
+                .channels
+                .insert(msg.channel_id, name.to_string());
+            pstate.save().await?;
+            format!("Replies here now use the `{}` profile.", name)
+        }
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn last_prompt(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    if ctx.cfg.read().await.llm_audit.is_none() {
+        return Err(PluginError::UserError(
+            "LLM requests aren't recorded; configure `[llm_audit]` first.".to_string(),
+        ));
+    }
+    let Some(prompt) = ctx.vstate.read().await.last_prompt.clone() else {
+        return Err(PluginError::UserError(
+            "No LLM requests since startup.".to_string(),
+        ));
+    };
+
+    // Prompts include whole conversations, so rarely fit in a message
+    msg.author
+        .direct_message(
+            ctx.cache_http,
+            CreateMessage::new()
+                .content("Most recent LLM request:")
+                .add_file(CreateAttachment::bytes(prompt, "lastprompt.json")),
+        )
+        .await?;
+    if msg.guild_id.is_some() {
+        msg.reply(ctx.cache_http, "Sent you a DM.").await?;
+    }
+    Ok(EventHandled::Yes)
+}
@@ -1,7 +1,14 @@
-use crate::helper::MessageHelper;
+use crate::error::{PluginError, Result};
+use crate::helper::{split_message, truncate, MessageHelper, UserHelper, MESSAGE_MAX_LEN};
 use crate::llm::LlmChatRequest;
-use crate::{event::*, plugin::*};
-use anyhow::Result;
+use crate::{
+    event::*, plugin::facts, plugin::memory, plugin::typing_pace, plugin::wake_word, plugin::*,
+};
+use serenity::all::{ChannelId, CreateThread, Message, Permissions};
+use std::time::Duration;
+
+/// Discord's limit on thread names
+const THREAD_NAME_MAX_LEN: usize = 100;
 
 pub struct LlmReply;
 
@@ -21,21 +28,133 @@ impl Plugin for LlmReply {
         };
 
         // Only respond if the message is to the bot
-        if !msg.is_to_me(ctx).await? {
+        if !msg.is_to_me(ctx).await? && !wake_word::is_woken(ctx, msg).await {
             return Ok(EventHandled::No);
         }
 
-        let typing = msg.channel_id.start_typing(ctx.http);
+        // Never reply to users who opted out of LLM features
+        if ctx
+            .pstate
+            .read()
+            .await
+            .llm_optout
+            .users
+            .contains(&msg.author.id)
+        {
+            return Ok(EventHandled::No);
+        }
 
-        let cfg = ctx.cfg.read().await;
-        let llm_settings = cfg.llm_reply.as_llm_settings();
+        let thread_id = reply_thread(ctx, msg).await?;
+        let typing = thread_id.unwrap_or(msg.channel_id).start_typing(ctx.http);
+        let memories = memory::recall(ctx, msg).await;
+
+        let profile = ctx
+            .pstate
+            .read()
+            .await
+            .llm_profiles
+            .channels
+            .get(&msg.channel_id)
+            .cloned();
+        // Copied out so the config lock isn't held while waiting on the LLM, nor while taking the
+        // vstate lock, which `History::push` takes first
+        let (reply_cfg, profile_cfg, window) = {
+            let cfg = ctx.cfg.read().await;
+            (
+                cfg.llm_reply.clone(),
+                profile.and_then(|p| cfg.llm_profiles.get(&p).cloned()),
+                cfg.conversation
+                    .as_ref()
+                    .map(|conversation| Duration::from_secs(conversation.window_seconds)),
+            )
+        };
+        // A profile since removed from the config falls back on `[llm_reply]`
+        let (llm_settings, fallback) = match &profile_cfg {
+            Some(profile) => (profile.as_llm_settings(), profile.fallback.clone()),
+            None => (reply_cfg.as_llm_settings(), reply_cfg.fallback.clone()),
+        };
+        let facts = facts::for_prompt(ctx, msg, llm_settings.context_size).await;
         let response = LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
             .await?
-            .post(ctx)
-            .await?;
+            .with_facts(facts)
+            .with_memories(memories)
+            .post_or(ctx, fallback)
+            .await
+            .map_err(PluginError::llm)?;
 
-        msg.reply(ctx.cache_http, response).await?;
+        match thread_id {
+            Some(thread_id) => {
+                for chunk in split_message(&response, MESSAGE_MAX_LEN) {
+                    thread_id.say(ctx.cache_http, chunk).await?;
+                }
+            }
+            None => typing_pace::reply(ctx, msg, &response).await?,
+        }
         typing.stop();
+
+        if let Some(window) = window {
+            ctx.vstate.write().await.conversations.record(
+                thread_id.unwrap_or(msg.channel_id),
+                msg.author.id,
+                window,
+            );
+        }
         Ok(EventHandled::Yes)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// The thread to reply to `msg` in, if its channel is one of `[llm_reply] thread_channels`:
+/// `msg`'s thread, started if it hasn't been already.  Later messages in the thread are then
+/// replied to within it, with the thread's own history.
+async fn reply_thread(ctx: &Context<'_>, msg: &Message) -> Result<Option<ChannelId>> {
+    if !ctx
+        .cfg
+        .read()
+        .await
+        .llm_reply
+        .thread_channels
+        .contains(&msg.channel_id)
+    {
+        return Ok(None);
+    }
+    if let Some(thread) = &msg.thread {
+        return Ok(Some(thread.id));
+    }
+
+    let name = match msg
+        .content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+    {
+        Some(line) => line.to_string(),
+        None => format!(
+            "Reply to {}",
+            msg.author.nick_in_guild(ctx, msg.guild_id).await
+        ),
+    };
+    let created = msg
+        .channel_id
+        .create_thread_from_message(
+            ctx.cache_http,
+            msg.id,
+            CreateThread::new(truncate(&name, THREAD_NAME_MAX_LEN - "...".len())),
+        )
+        .await;
+    match created {
+        Ok(thread) => Ok(Some(thread.id)),
+        // Another plugin, e.g. `auto_thread`, may have started one in the meantime
+        Err(err) => match msg.channel_id.message(ctx.cache_http, msg.id).await?.thread {
+            Some(thread) => Ok(Some(thread.id)),
+            None => Err(err.into()),
+        },
+    }
 }
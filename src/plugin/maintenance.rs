@@ -0,0 +1,96 @@
+//! Read-only maintenance mode, e.g. for migrating `state.toml` or upgrading the LLM backend.
+//!
+//! While on, the bot still logs and records history, but saving state and LLM requests fail with
+//! `MaintenanceMode`, which the dispatcher explains to the user.  State is saved on entering
+//! maintenance and reloaded from disk on leaving it, picking up any edits made in the meantime.
+
+use crate::error::{PluginError, Result};
+use crate::persistent_state::PersistentState;
+use crate::volatile_state::Maintenance as MaintenanceState;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::Permissions;
+use tokio::time::Instant;
+
+pub struct Maintenance;
+
+#[serenity::async_trait]
+impl Plugin for Maintenance {
+    fn name(&self) -> &'static str {
+        "maintenance"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <on/off> - refuse state changes and LLM requests, e.g. while migrating state (bot owner only)",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), false).await?;
+
+        let response = match args.trim() {
+            "on" => {
+                let mut pstate = ctx.pstate.write().await;
+                if pstate.read_only {
+                    "Already in maintenance mode".to_string()
+                } else {
+                    // Flush what we have so edits start from the latest state.
+                    pstate.save().await?;
+                    pstate.read_only = true;
+                    ctx.vstate.write().await.maintenance = Some(MaintenanceState {
+                        since: Instant::now(),
+                        by: msg.author.id,
+                    });
+                    "Maintenance mode on.  State will not be saved until it is turned off, at \
+                     which point state is reloaded from disk."
+                        .to_string()
+                }
+            }
+            "off" => {
+                let mut pstate = ctx.pstate.write().await;
+                if !pstate.read_only {
+                    "Not in maintenance mode".to_string()
+                } else {
+                    let reloaded = PersistentState::load().await.map_err(|err| {
+                        PluginError::UserError(format!(
+                            "Staying in maintenance mode; could not reload state: {}",
+                            err
+                        ))
+                    })?;
+                    *pstate = reloaded;
+                    ctx.vstate.write().await.maintenance = None;
+                    "Maintenance mode off.  State reloaded from disk.".to_string()
+                }
+            }
+            _ => {
+                let prefix = &ctx.cfg.read().await.general.command_prefix;
+                return Err(PluginError::UserError(format!(
+                    "Usage: {}{} <on/off>",
+                    prefix,
+                    self.name()
+                )));
+            }
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -0,0 +1,279 @@
+//! Long-term memory for LLM replies.  When `[llm_memory]` is configured, each message is embedded
+//! and appended to `~/.config/digmbot/memory.jsonl`, a local vector store which is loaded on
+//! startup.  Replies then include the channel's past messages most similar to the one being
+//! replied to, so the bot recalls conversations long gone from the recent history.
+//!
+//! Entries beyond `max_entries_per_channel` are forgotten, and so are those older than
+//! `[retention] history_days`.  The file is rewritten without forgotten entries on startup, when
+//! retention applies, and whenever they come to outnumber those remembered.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{MessageHelper, UserHelper};
+use crate::volatile_state::MemoryEntry;
+use crate::{event::*, llm, log_internal, plugin::*};
+use anyhow::anyhow;
+use chrono::DateTime;
+use serenity::all::{Message, Permissions};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+const MEMORY_PATH_REL_HOME: &str = ".config/digmbot/memory.jsonl";
+
+/// Keeps concurrent messages from interleaving their lines, and the file in step with the
+/// volatile state
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Lines in the file for entries since forgotten
+static STALE_LINES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Memory;
+
+#[serenity::async_trait]
+impl Plugin for Memory {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        let ctx = ctx.ctx();
+        let Some(capacity) = ctx
+            .cfg
+            .read()
+            .await
+            .llm_memory
+            .as_ref()
+            .map(|cfg| cfg.max_entries_per_channel)
+        else {
+            return Ok(None);
+        };
+        load(&ctx, capacity).await?;
+        Ok(None)
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        let Some((url, model, capacity)) = ({
+            let cfg = ctx.cfg.read().await;
+            if msg.content.starts_with(&cfg.general.command_prefix) {
+                None
+            } else {
+                cfg.llm_memory.as_ref().map(|memory| {
+                    (
+                        memory.embed_url.clone(),
+                        memory.model_name.clone(),
+                        memory.max_entries_per_channel,
+                    )
+                })
+            }
+        }) else {
+            return Ok(EventHandled::No);
+        };
+        // Users who opted out of LLM features don't have their messages embedded either
+        if msg.content.trim().is_empty()
+            || ctx
+                .pstate
+                .read()
+                .await
+                .llm_optout
+                .users
+                .contains(&msg.author.id)
+        {
+            return Ok(EventHandled::No);
+        }
+
+        let text = format!(
+            "{}: {}",
+            msg.author.nick_in_guild(ctx, msg.guild_id).await,
+            msg.human_format_content(ctx).await?
+        );
+        let embedding = llm::embed_with(ctx, &url, &model, &text)
+            .await
+            .map_err(PluginError::llm)?;
+        let entry = MemoryEntry {
+            channel_id: msg.channel_id,
+            message_id: msg.id,
+            timestamp: msg.timestamp.unix_timestamp(),
+            author_id: msg.author.id,
+            text,
+            embedding,
+        };
+
+        let compact_due = {
+            let _lock = WRITE_LOCK.lock().await;
+            append(&entry).await?;
+            let mut vstate = ctx.vstate.write().await;
+            let forgot = vstate.memory.push(entry, capacity);
+            let stale = STALE_LINES.fetch_add(forgot, Ordering::Relaxed) + forgot;
+            stale > 0 && stale >= vstate.memory.entries().count()
+        };
+        if compact_due {
+            compact(ctx).await?;
+        }
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::VIEW_CHANNEL.union(Permissions::READ_MESSAGE_HISTORY)
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Record
+    }
+}
+
+fn memory_path() -> anyhow::Result<PathBuf> {
+    dirs::home_dir()
+        .map(|p| p.join(MEMORY_PATH_REL_HOME))
+        .ok_or(anyhow!("Could not find home directory"))
+}
+
+/// Load the store into the volatile state, rewriting the file without any entries beyond
+/// `capacity`
+async fn load(ctx: &Context<'_>, capacity: usize) -> anyhow::Result<()> {
+    let path = memory_path()?;
+    if !tokio::fs::try_exists(&path).await? {
+        return Ok(());
+    }
+    let contents = tokio::fs::read_to_string(&path).await?;
+
+    let forgot = {
+        let mut vstate = ctx.vstate.write().await;
+        let mut forgot = false;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<MemoryEntry>(line) {
+                Ok(entry) => forgot |= vstate.memory.push(entry, capacity) > 0,
+                Err(err) => {
+                    log_internal!("Skipping unreadable memory entry: {}", err);
+                    forgot = true;
+                }
+            }
+        }
+        log_internal!(
+            "Loaded {} long-term memory entries",
+            vstate.memory.entries().count()
+        );
+        forgot
+    };
+    if forgot {
+        compact(ctx).await?;
+    }
+    Ok(())
+}
+
+/// Rewrite the file with only the entries still remembered
+async fn compact(ctx: &Context<'_>) -> anyhow::Result<()> {
+    let path = memory_path()?;
+    let _lock = WRITE_LOCK.lock().await;
+    let mut compacted = String::new();
+    for entry in ctx.vstate.read().await.memory.entries() {
+        compacted.push_str(&serde_json::to_string(entry)?);
+        compacted.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp_path, compacted).await?;
+    tokio::fs::rename(&tmp_path, &path).await?;
+    STALE_LINES.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Forget entries older than `cutoff` (unix seconds), in the file as well.  Returns the number
+/// forgotten.
+pub async fn remove_older_than(ctx: &Context<'_>, cutoff: i64) -> anyhow::Result<usize> {
+    let removed = ctx.vstate.write().await.memory.remove_older_than(cutoff);
+    if removed > 0 && tokio::fs::try_exists(memory_path()?).await? {
+        compact(ctx).await?;
+    }
+    Ok(removed)
+}
+
+/// Append `entry` to the file.  Callers hold `WRITE_LOCK`.
+async fn append(entry: &MemoryEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let path = memory_path()?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// The past messages in `msg`'s channel most relevant to it, oldest first, excluding those still in
+/// the recent history.  Empty if `[llm_memory]` isn't configured or the embedding fails, as replies
+/// can go ahead without.
+pub async fn recall(ctx: &Context<'_>, msg: &Message) -> Vec<String> {
+    let Some((url, model, top_k, threshold)) =
+        ctx.cfg.read().await.llm_memory.as_ref().map(|memory| {
+            (
+                memory.embed_url.clone(),
+                memory.model_name.clone(),
+                memory.top_k,
+                memory.threshold,
+            )
+        })
+    else {
+        return Vec::new();
+    };
+    let embedding = match msg.human_format_content(ctx).await {
+        Ok(content) => llm::embed_with(ctx, &url, &model, &content).await,
+        Err(err) => Err(err),
+    };
+    let embedding = match embedding {
+        Ok(embedding) => embedding,
+        Err(err) => {
+            log_internal!("Could not recall memories for {}: {}", msg.channel_id, err);
+            return Vec::new();
+        }
+    };
+
+    let opted_out = ctx.pstate.read().await.llm_optout.users.clone();
+    let vstate = ctx.vstate.read().await;
+    let recent: HashSet<_> = vstate
+        .history
+        .recorded(msg.channel_id)
+        .into_iter()
+        .flatten()
+        .map(|entry| entry.message_id)
+        .collect();
+    let mut scored: Vec<_> = vstate
+        .memory
+        .channel(msg.channel_id)
+        .filter(|entry| !recent.contains(&entry.message_id))
+        .filter(|entry| !opted_out.contains(&entry.author_id))
+        .map(|entry| (llm::similarity(&embedding, &entry.embedding), entry))
+        .filter(|(similarity, _)| *similarity >= threshold)
+        .collect();
+    scored.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top_k);
+    scored.sort_unstable_by_key(|(_, entry)| entry.timestamp);
+    scored
+        .into_iter()
+        .map(|(_, entry)| format_entry(entry))
+        .collect()
+}
+
+fn format_entry(entry: &MemoryEntry) -> String {
+    match DateTime::from_timestamp(entry.timestamp, 0) {
+        Some(time) => format!("[{}] {}", time.format("%Y-%m-%d"), entry.text),
+        None => entry.text.clone(),
+    }
+}
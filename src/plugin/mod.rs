@@ -1,35 +1,239 @@
+use crate::error::Result;
 use crate::{
-    context::Context,
+    context::{Context, OwnedContext},
     event::{Event, EventHandled},
+    log_internal,
+    volatile_state::VolatileState,
 };
-use anyhow::Result;
+use serenity::all::Permissions;
+use std::time::Duration;
+use tokio::{sync::RwLock, task::JoinHandle};
 
+mod archive;
+mod audit;
+mod auto_thread;
+mod channel;
+mod clonechannel;
+mod confirm;
+mod crosspost;
 mod debug;
+mod define;
+mod digest;
+mod emoji_stats;
+mod facts;
+mod faq;
+mod grant;
 mod help;
 mod history;
-mod ignore_bots;
+mod history_search;
+mod ignore;
+mod imagine;
+mod impersonate;
+mod llm_control;
 mod llm_reply;
+mod maintenance;
+mod memory;
+pub mod moderation;
+mod moveconvo;
 mod music;
+mod names;
+mod perm;
+mod permcheck;
+mod photo_contest;
+mod queue;
+mod quickpoll;
+mod quiet;
 mod react;
+mod reactions;
 mod reload;
+mod retention;
 mod rivals_rating;
+mod role;
+mod rules_qa;
+mod schedule;
+mod self_test;
+mod stats;
+mod stats_snapshot;
+mod status;
+mod stream_notify;
+mod thread_titles;
+mod todo;
+mod topic_summary;
+mod translate;
+mod trivia;
+mod typing_pace;
+mod undo;
 mod vc_notify;
+mod vc_role;
+mod wake_word;
+mod watchdog;
+mod weather;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+mod welcome;
+mod wiki;
+mod word_filter;
+mod word_puzzle;
 mod xkcd;
 
 #[serenity::async_trait]
 pub trait Plugin: Sync + Send {
     /// Plugin name.  Used for debug
     fn name(&self) -> &'static str;
+    /// Command the plugin answers, without the prefix, if it has one.  Used to explain in DMs
+    /// that a guild-only command doesn't work there.
+    fn command(&self) -> Option<&'static str> {
+        None
+    }
     /// Plugin usage description in help.  None if no help message
     async fn usage(&self, ctx: &Context) -> Option<String>;
+    /// Extended usage shown by `help <command>`.  Defaults to `usage()`.
+    async fn detailed_usage(&self, ctx: &Context) -> Option<String> {
+        self.usage(ctx).await
+    }
+    /// Section of the help message in which the plugin's usage is listed
+    fn category(&self) -> Category {
+        Category::Core
+    }
     /// Potentially handle event.  Returns:
     /// - Ok(EventHandled::Yes) if the event has been handled and no other plugin should attempt to
     /// handle it
     /// - Ok(EventHandled::No) if another plugin should attempt to handle the event
-    /// - Err if an error occurred
+    /// - Err if an error occurred.  See `PluginError` for how each kind is responded to.
+    ///   `UserError` and `PermissionDenied` count as handling the event.
     async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled>;
+    /// Discord permissions the bot needs in a channel for this plugin to work there.  Used by
+    /// `permcheck`.
+    fn required_permissions(&self) -> Permissions {
+        Permissions::empty()
+    }
+    /// Passive plugins merely observe events.  They run concurrently with other plugins, so their
+    /// `EventHandled` is ignored and they aren't affected by whether other plugins handle the
+    /// event.  Anything which must happen before later plugins run, such as recording history
+    /// for the LLM, must not be passive.
+    fn passive(&self) -> bool {
+        false
+    }
+    /// Whether the plugin may run for events in quiet channels.  See `quiet.rs`.
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Skip
+    }
+    /// Whether the plugin may run for direct messages.  See `Event::is_dm()`.
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::GuildOnly
+    }
+    /// Whether the plugin still runs for messages from ignored channels and users.  See
+    /// `ignore.rs`.
+    fn runs_when_ignored(&self) -> bool {
+        false
+    }
+    /// How long each user must wait between uses of the plugin's `command()`, if it's rate
+    /// limited.  Enforced by the cooldowns middleware.  See `middleware.rs`.
+    async fn cooldown(&self, _ctx: &Context) -> Option<Duration> {
+        None
+    }
+    /// Whether the plugin sees messages from bots, including the bot's own.  Passive plugins
+    /// always do.  See `middleware.rs`.
+    fn sees_bot_messages(&self) -> bool {
+        false
+    }
+    /// Start the plugin's background task, if it has one.  Called once, when the bot first
+    /// connects; Ready fires again on reconnect, but this doesn't.  The task is aborted on
+    /// shutdown, before `stop()` is called.
+    async fn start(&self, _ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(None)
+    }
+    /// Clean up on shutdown, after background tasks have been aborted
+    async fn stop(&self) {}
 }
 
+/// Start every plugin's background task, unless they've already been started
+pub async fn start_all(ctx: &Context<'_>) {
+    {
+        let mut vstate = ctx.vstate.write().await;
+        if vstate.plugin_tasks.is_some() {
+            return;
+        }
+        vstate.plugin_tasks = Some(Vec::new());
+    }
+
+    for plugin in plugins() {
+        match plugin.start(ctx.owned()).await {
+            Ok(Some(task)) => {
+                let mut vstate = ctx.vstate.write().await;
+                if let Some(tasks) = vstate.plugin_tasks.as_mut() {
+                    tasks.push(task);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => log_internal!("Could not start {}: {}", plugin.name(), err),
+        }
+    }
+}
+
+/// Abort every plugin's background task, then let each plugin clean up
+pub async fn stop_all(vstate: &RwLock<VolatileState>) {
+    let tasks = vstate.write().await.plugin_tasks.take().unwrap_or_default();
+    for task in tasks {
+        task.abort();
+    }
+    for plugin in plugins() {
+        plugin.stop().await;
+    }
+}
+
+/// How a plugin behaves in direct messages
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DmPolicy {
+    /// Run the plugin for DMs as well as in servers
+    Allow,
+    /// Don't run the plugin for DMs.  Its command is answered by the dispatcher explaining it
+    /// only works within a server.
+    GuildOnly,
+}
+
+/// How a plugin behaves in quiet channels, where the bot must not reply or react
+pub enum QuietMode {
+    /// Don't run the plugin
+    Skip,
+    /// The plugin only records history; run it if the channel still records history
+    Record,
+    /// The plugin never speaks in the channel, other than to bot owners and admins managing it
+    Run,
+}
+
+/// Sections of the help message
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Core,
+    Games,
+    Llm,
+    Notifications,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Core,
+        Category::Games,
+        Category::Llm,
+        Category::Notifications,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Core => "Core",
+            Category::Games => "Games",
+            Category::Llm => "LLM",
+            Category::Notifications => "Notifications",
+        }
+    }
+}
+
+/// Permissions needed to reply to commands in a channel
+pub const REPLY_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::READ_MESSAGE_HISTORY);
+
 /// Ordered list of available plugins
 pub fn plugins() -> Vec<Box<dyn Plugin>> {
     use crate::plugin::*;
@@ -38,16 +242,77 @@ pub fn plugins() -> Vec<Box<dyn Plugin>> {
         // Core bot operations
         Box::new(debug::Debug),
         Box::new(history::History),
-        // In order to avoid two bots triggering each other into spam, we consider bot created
-        // messages "handled" at this point such that they don't activate any following plugins.
-        Box::new(ignore_bots::IgnoreBots),
+        Box::new(retention::Retention),
+        Box::new(stats_snapshot::StatsSnapshot),
+        Box::new(watchdog::Watchdog),
+        Box::new(self_test::SelfTest),
+        // Sees the bot's own replies to link crossposts to them
+        Box::new(crosspost::Crosspost),
+        // Removes filtered messages before anything responds to them
+        Box::new(word_filter::WordFilter),
+        // Passive recording of human activity.  Passive plugins run concurrently with the rest,
+        // regardless of their position here.
+        Box::new(stats::Stats),
+        Box::new(photo_contest::PhotoSubmissions),
+        Box::new(emoji_stats::EmojiStats),
+        Box::new(archive::Archive),
+        Box::new(topic_summary::TopicSummary),
+        Box::new(memory::Memory),
+        Box::new(thread_titles::ThreadTitles),
+        Box::new(auto_thread::AutoThread),
         // Miscellaneous plugins
         Box::new(help::Help),
+        Box::new(permcheck::PermCheck),
+        Box::new(perm::Perm),
+        Box::new(grant::Grant),
+        Box::new(grant::Revoke),
+        Box::new(confirm::Confirm),
+        Box::new(undo::Undo),
+        Box::new(quiet::Quiet),
+        Box::new(ignore::Ignore),
+        Box::new(status::Status),
+        Box::new(history_search::HistorySearch),
+        Box::new(audit::Audit),
+        Box::new(moderation::Moderation),
+        Box::new(names::Names),
         Box::new(xkcd::Xkcd),
+        Box::new(weather::Weather),
+        Box::new(define::Define),
+        Box::new(wiki::Wiki),
         Box::new(music::Music),
         Box::new(reload::Reload),
+        Box::new(maintenance::Maintenance),
         Box::new(vc_notify::VcNotify),
+        Box::new(vc_role::VcRole),
+        Box::new(stream_notify::StreamNotify),
+        Box::new(queue::Queue),
+        Box::new(welcome::Welcome),
+        Box::new(schedule::Schedule),
+        Box::new(channel::Channel),
+        #[cfg(feature = "webhooks")]
+        Box::new(webhooks::Webhooks),
+        Box::new(role::Role),
+        Box::new(clonechannel::CloneChannel),
+        Box::new(llm_control::LlmControl),
+        Box::new(wake_word::WakeWord),
+        Box::new(typing_pace::TypingPace),
+        Box::new(digest::Digest),
         Box::new(rivals_rating::RivalsRating),
+        Box::new(reactions::Reactions),
+        Box::new(quickpoll::QuickPoll),
+        Box::new(trivia::Trivia),
+        Box::new(impersonate::Impersonate),
+        Box::new(imagine::Imagine),
+        Box::new(word_puzzle::WordPuzzle),
+        Box::new(photo_contest::PhotoContest),
+        Box::new(todo::Todo),
+        Box::new(moveconvo::MoveConvo),
+        Box::new(translate::Translate),
+        Box::new(facts::Remember),
+        Box::new(facts::Forget),
+        // Canned answers, which take precedence over the generic responses
+        Box::new(faq::Faq),
+        Box::new(rules_qa::RulesQa),
         // Generic responses, used if no other plugin handles the event.
         // Keep last.
         Box::new(llm_reply::LlmReply),
@@ -0,0 +1,484 @@
+//! Moderation commands with a per-guild case log.
+//!
+//! Moderators need the corresponding Discord permission (e.g. "Kick Members" to kick) and must be
+//! above their target in the role hierarchy.  Bot owners bypass both checks.  Temporary mutes are
+//! implemented by adding a configured mute role, which a background task removes once the mute
+//! expires.  With a `[moderation.escalation]` policy, warnings automatically escalate to a mute,
+//! kick, or ban once a member has accumulated enough unexpired ones.
+
+use crate::{
+    acl,
+    context::{Context, OwnedContext},
+    error::{PluginError, Result},
+    event::{Event, EventHandled},
+    helper::{discord_timestamp, post_mod_log, Args, MessageHelper, TimestampStyle},
+    log_internal,
+    persistent_state::{ActiveMute, ModAction, ModCase},
+    plugin::{Plugin, REPLY_PERMISSIONS},
+};
+use serenity::all::{CreateMessage, GuildId, Message, Permissions, Timestamp, UserId};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const UNMUTE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Number of cases to show in `history`
+const HISTORY_LIMIT: usize = 15;
+
+pub struct Moderation;
+
+#[serenity::async_trait]
+impl Plugin for Moderation {
+    fn name(&self) -> &'static str {
+        "mod"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}mod <subcommand> -- moderation (requires the matching Discord permission)\n\
+             | Subcommands:\n\
+             | warn <@user> [reason] - warn a member\n\
+             | mute <@user> [duration] [reason] - mute a member, e.g. for `10m` or `1h30m`\n\
+             | unmute <@user> [reason] - unmute a member\n\
+             | kick <@user> [reason] - kick a member\n\
+             | ban <@user> [reason] - ban a member\n\
+             | history <@user> - show a member's moderation cases",
+            prefix
+        ))
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UNMUTE_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = remove_expired_mutes(&ctx.ctx()).await {
+                    log_internal!("Error removing expired mutes: {}", err);
+                }
+            }
+        })))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args_str)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Moderation commands only work within a server".to_string(),
+            ));
+        };
+
+        let args = Args::parse(args_str);
+        let action = match args.get(0).map(|s| s.to_lowercase()).as_deref() {
+            Some("warn") => ModAction::Warn,
+            Some("mute") => ModAction::Mute,
+            Some("unmute") => ModAction::Unmute,
+            Some("kick") => ModAction::Kick,
+            Some("ban") => ModAction::Ban,
+            Some("history") => return handle_history(ctx, msg, guild_id, &args.skip(1)).await,
+            Some(_) => {
+                msg.reply(ctx.cache_http, "Unknown subcommand.").await?;
+                return Ok(EventHandled::Yes);
+            }
+            None => {
+                msg.reply(
+                    ctx.cache_http,
+                    "Please provide a subcommand. See help for usage.",
+                )
+                .await?;
+                return Ok(EventHandled::Yes);
+            }
+        };
+
+        handle_action(ctx, msg, guild_id, action, &args.skip(1)).await
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+            .union(Permissions::KICK_MEMBERS)
+            .union(Permissions::BAN_MEMBERS)
+            .union(Permissions::MANAGE_ROLES)
+    }
+}
+
+fn required_permission(action: ModAction) -> Permissions {
+    match action {
+        ModAction::Warn | ModAction::Mute | ModAction::Unmute => Permissions::MODERATE_MEMBERS,
+        ModAction::Kick => Permissions::KICK_MEMBERS,
+        ModAction::Ban => Permissions::BAN_MEMBERS,
+    }
+}
+
+async fn handle_action(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    action: ModAction,
+    args: &Args,
+) -> Result<EventHandled> {
+    let is_owner = msg.is_from_owner(ctx).await;
+    let permitted = msg
+        .author_permissions(ctx.cache)
+        .is_some_and(|p| p.contains(required_permission(action)));
+    acl::check(ctx, msg, &format!("mod.{}", action), permitted).await?;
+
+    let Some(user_id) = args.get_as::<UserId>(0) else {
+        msg.reply(
+            ctx.cache_http,
+            format!("Usage: {} <@user> [reason]", action),
+        )
+        .await?;
+        return Ok(EventHandled::Yes);
+    };
+
+    // Moderators may only act on members below them in the role hierarchy.
+    if !is_owner {
+        let outranks = msg.guild(ctx.cache).is_some_and(|guild| {
+            guild.greater_member_hierarchy(ctx.cache, msg.author.id, user_id) == Some(msg.author.id)
+        });
+        if !outranks {
+            return Err(PluginError::PermissionDenied);
+        }
+    }
+
+    // Only mutes take a duration
+    let duration = match action {
+        ModAction::Mute => args.get_as::<Duration>(1),
+        _ => None,
+    };
+    let rest = args.skip(if duration.is_some() { 2 } else { 1 });
+    let rest = rest.positional();
+    let reason = if rest.is_empty() {
+        "No reason provided".to_string()
+    } else {
+        rest.join(" ")
+    };
+
+    let case = apply_action(
+        ctx,
+        guild_id,
+        user_id,
+        action,
+        duration,
+        reason,
+        msg.author.id,
+    )
+    .await?;
+    let summary = describe_case(&case);
+    post_mod_log(ctx, guild_id, &summary).await?;
+    msg.reply(ctx.cache_http, summary).await?;
+
+    if action == ModAction::Warn {
+        if let Some(case) = escalate(ctx, guild_id, user_id).await? {
+            let summary = describe_case(&case);
+            post_mod_log(ctx, guild_id, &summary).await?;
+            msg.reply(ctx.cache_http, summary).await?;
+        }
+    }
+    Ok(EventHandled::Yes)
+}
+
+/// Carry out a moderation action and record it as a case
+async fn apply_action(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    user_id: UserId,
+    action: ModAction,
+    duration: Option<Duration>,
+    reason: String,
+    moderator_id: UserId,
+) -> Result<ModCase> {
+    let mute_role = ctx
+        .cfg
+        .read()
+        .await
+        .moderation
+        .as_ref()
+        .and_then(|m| m.mute_roles.get(&guild_id).cloned());
+
+    match action {
+        ModAction::Warn => {
+            let guild_name = guild_id
+                .name(ctx.cache)
+                .unwrap_or_else(|| "the server".to_string());
+            let warning = CreateMessage::new().content(format!(
+                "You have been warned in {}: {}",
+                guild_name, reason
+            ));
+            // The member may not accept DMs; the warning is still recorded.
+            if let Err(err) = user_id.direct_message(ctx.cache_http, warning).await {
+                log_internal!("Could not DM warning to {}: {}", user_id, err);
+            }
+        }
+        ModAction::Mute | ModAction::Unmute => {
+            let Some(mute_role) = mute_role else {
+                return Err(PluginError::UserError(
+                    "No mute role is configured for this server".to_string(),
+                ));
+            };
+            if action == ModAction::Mute {
+                ctx.http
+                    .add_member_role(guild_id, user_id, mute_role, Some(&reason))
+                    .await?;
+            } else {
+                ctx.http
+                    .remove_member_role(guild_id, user_id, mute_role, Some(&reason))
+                    .await?;
+            }
+        }
+        ModAction::Kick => {
+            guild_id
+                .kick_with_reason(ctx.http, user_id, &reason)
+                .await?
+        }
+        ModAction::Ban => {
+            guild_id
+                .ban_with_reason(ctx.http, user_id, 0, &reason)
+                .await?
+        }
+    }
+
+    let now = Timestamp::now().unix_timestamp();
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let moderation = &mut pstate.moderation;
+
+    // Any new mute or unmute supersedes a pending expiry
+    if matches!(action, ModAction::Mute | ModAction::Unmute) {
+        moderation
+            .active_mutes
+            .retain(|m| !(m.guild_id == guild_id && m.user_id == user_id));
+    }
+    if let Some(duration) = duration {
+        moderation.active_mutes.push(ActiveMute {
+            guild_id,
+            user_id,
+            expires_at: now + duration.as_secs() as i64,
+        });
+    }
+
+    let case = moderation.add_case(
+        guild_id,
+        ModCase {
+            id: 0,
+            action,
+            user_id,
+            moderator_id,
+            reason,
+            timestamp: now,
+            duration_secs: duration.map(|d| d.as_secs()),
+        },
+    );
+    pstate.save().await?;
+    Ok(case)
+}
+
+/// Warn a member on the bot's behalf, e.g. for `[auto_mod]`, escalating per the guild's policy.
+/// The resulting cases are posted to the moderation log.
+pub async fn warn(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    user_id: UserId,
+    reason: String,
+) -> Result<()> {
+    let me = ctx.cache.current_user().id;
+    let case = apply_action(ctx, guild_id, user_id, ModAction::Warn, None, reason, me).await?;
+    post_mod_log(ctx, guild_id, &describe_case(&case)).await?;
+    if let Some(case) = escalate(ctx, guild_id, user_id).await? {
+        post_mod_log(ctx, guild_id, &describe_case(&case)).await?;
+    }
+    Ok(())
+}
+
+/// Apply the guild's `[moderation.escalation]` policy to a member who was just warned.  Returns
+/// the resulting case, if the member's unexpired warnings reached a step.
+async fn escalate(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Option<ModCase>> {
+    let now = Timestamp::now().unix_timestamp();
+    let (warnings, action, duration) = {
+        let cfg = ctx.cfg.read().await;
+        let Some(policy) = cfg
+            .moderation
+            .as_ref()
+            .and_then(|m| m.escalation.get(&guild_id))
+        else {
+            return Ok(None);
+        };
+        let cutoff = policy
+            .warning_expiry_days
+            .map(|days| now - (days * 86400) as i64)
+            .unwrap_or(i64::MIN);
+        let warnings = ctx
+            .pstate
+            .read()
+            .await
+            .moderation
+            .cases
+            .get(&guild_id)
+            .map(|cases| {
+                cases
+                    .iter()
+                    .filter(|case| {
+                        case.action == ModAction::Warn
+                            && case.user_id == user_id
+                            && case.timestamp >= cutoff
+                    })
+                    .count()
+            })
+            .unwrap_or_default();
+        let Some(step) = policy.step_for(warnings) else {
+            return Ok(None);
+        };
+        let duration = step
+            .duration_minutes
+            .filter(|_| step.action == ModAction::Mute)
+            .map(|minutes| Duration::from_secs(minutes * 60));
+        (warnings, step.action, duration)
+    };
+
+    if !matches!(action, ModAction::Mute | ModAction::Kick | ModAction::Ban) {
+        log_internal!(
+            "Escalation policy for {} has invalid action {}",
+            guild_id,
+            action
+        );
+        return Ok(None);
+    }
+    let reason = format!("Automatic escalation after {} warnings", warnings);
+    let me = ctx.cache.current_user().id;
+    apply_action(ctx, guild_id, user_id, action, duration, reason, me)
+        .await
+        .map(Some)
+}
+
+async fn handle_history(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    args: &Args,
+) -> Result<EventHandled> {
+    let permitted = msg
+        .author_permissions(ctx.cache)
+        .is_some_and(|p| p.contains(Permissions::MODERATE_MEMBERS));
+    acl::check(ctx, msg, "mod.history", permitted).await?;
+
+    let Some(user_id) = args.get_as::<UserId>(0) else {
+        msg.reply(ctx.cache_http, "Usage: history <@user>").await?;
+        return Ok(EventHandled::Yes);
+    };
+
+    let cases: Vec<ModCase> = ctx
+        .pstate
+        .read()
+        .await
+        .moderation
+        .cases
+        .get(&guild_id)
+        .map(|cases| {
+            cases
+                .iter()
+                .filter(|case| case.user_id == user_id)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let response = if cases.is_empty() {
+        format!("<@{}> has no moderation cases.", user_id)
+    } else {
+        let mut response = format!("Moderation cases for <@{}>:\n", user_id);
+        // Most recent cases
+        for case in cases.iter().rev().take(HISTORY_LIMIT) {
+            response.push_str(&format!(
+                "• #{} {} by <@{}> {}: {}\n",
+                case.id,
+                case.action,
+                case.moderator_id,
+                discord_timestamp(case.timestamp, TimestampStyle::Relative),
+                case.reason
+            ));
+        }
+        if cases.len() > HISTORY_LIMIT {
+            response.push_str(&format!("…and {} older\n", cases.len() - HISTORY_LIMIT));
+        }
+        response
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+fn describe_case(case: &ModCase) -> String {
+    let duration = case
+        .duration_secs
+        .map(|secs| format!(" for {}", format_duration(secs)))
+        .unwrap_or_default();
+    format!(
+        "Case #{}: {} <@{}>{} by <@{}>: {}",
+        case.id, case.action, case.user_id, duration, case.moderator_id, case.reason
+    )
+}
+
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h{}m", h, m),
+        (d, h, _) => format!("{}d{}h", d, h),
+    }
+}
+
+async fn remove_expired_mutes(ctx: &Context<'_>) -> Result<()> {
+    let now = Timestamp::now().unix_timestamp();
+    let expired: Vec<(GuildId, UserId)> = {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let mutes = &mut pstate.moderation.active_mutes;
+        let expired: Vec<_> = mutes
+            .iter()
+            .filter(|m| m.expires_at <= now)
+            .map(|m| (m.guild_id, m.user_id))
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+        mutes.retain(|m| m.expires_at > now);
+        pstate.save().await?;
+        expired
+    };
+
+    for (guild_id, user_id) in expired {
+        let mute_role = ctx
+            .cfg
+            .read()
+            .await
+            .moderation
+            .as_ref()
+            .and_then(|m| m.mute_roles.get(&guild_id).cloned());
+        let Some(mute_role) = mute_role else {
+            continue;
+        };
+        if let Err(err) = ctx
+            .http
+            .remove_member_role(guild_id, user_id, mute_role, Some("Mute expired"))
+            .await
+        {
+            log_internal!("Could not unmute {}: {}", user_id, err);
+            continue;
+        }
+        post_mod_log(ctx, guild_id, &format!("Mute expired for <@{}>", user_id)).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, llm, plugin::*};
+use serenity::all::{CreateEmbed, CreateMessage, Permissions};
+
+/// Recent messages summarized for the destination channel
+const SUMMARIZED_MESSAGES: usize = 30;
+
+/// Moves an off-topic conversation to a better channel, carrying a summary along
+pub struct MoveConvo;
+
+#[serenity::async_trait]
+impl Plugin for MoveConvo {
+    fn name(&self) -> &'static str {
+        "moveconvo"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <#channel> - continue the current conversation in another channel, with a summary (moderators only)",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+
+        let permitted = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::MANAGE_MESSAGES));
+        acl::check(ctx, msg, self.name(), permitted).await?;
+
+        let Some(destination) = serenity::utils::parse_channel_mention(args.trim()) else {
+            return Err(PluginError::UserError(
+                "Mention the channel to move to, e.g. `#general`.".to_string(),
+            ));
+        };
+        let same_guild = destination
+            .to_channel(ctx.cache_http)
+            .await?
+            .guild()
+            .is_some_and(|channel| Some(channel.guild_id) == msg.guild_id);
+        if !same_guild || destination == msg.channel_id {
+            return Err(PluginError::UserError(
+                "Pick another channel in this server.".to_string(),
+            ));
+        }
+
+        let typing = msg.channel_id.start_typing(ctx.http);
+        let summary = llm::summarize_recent(ctx, msg.channel_id, SUMMARIZED_MESSAGES, msg.id)
+            .await
+            .map_err(PluginError::llm)?;
+        typing.stop();
+        let Some(summary) = summary else {
+            return Err(PluginError::UserError(
+                "Conversation summaries are not configured; see `[llm_summary]`.".to_string(),
+            ));
+        };
+
+        let embed = CreateEmbed::new()
+            .title(format!(
+                "Continued from #{}",
+                msg.channel_id.name(ctx.cache_http).await?
+            ))
+            .url(msg.link())
+            .description(summary);
+        let moved = destination
+            .send_message(ctx.cache_http, CreateMessage::new().embed(embed))
+            .await?;
+
+        msg.reply(
+            ctx.cache_http,
+            format!("Let's continue in <#{}>: {}", destination, moved.link()),
+        )
+        .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::EMBED_LINKS)
+    }
+}
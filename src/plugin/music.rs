@@ -1,5 +1,6 @@
-use crate::{event::*, plugin::*};
-use anyhow::Result;
+use crate::error::Result;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::Permissions;
 
 pub struct Music;
 
@@ -9,6 +10,14 @@ impl Plugin for Music {
         "music"
     }
 
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
     async fn usage(&self, ctx: &Context) -> Option<String> {
         let prefix = &ctx.cfg.read().await.general.command_prefix;
         Some(format!(
@@ -22,9 +31,18 @@ impl Plugin for Music {
         let Some((msg, _)) = event.is_bot_cmd(ctx, self.name()).await else {
             return Ok(EventHandled::No);
         };
+        acl::check(ctx, msg, self.name(), true).await?;
 
         const MUSIC_URL: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
         msg.reply(ctx.cache_http, MUSIC_URL).await?;
         Ok(EventHandled::Yes)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
 }
@@ -0,0 +1,119 @@
+//! Keeps a history of members' usernames, display names, and nicknames, from member updates, so
+//! moderators can tell who someone used to be.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{discord_timestamp, parse_user, TimestampStyle};
+use crate::persistent_state::{NameRecord, UserNames};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildMemberUpdateEvent, Permissions, Timestamp};
+
+pub struct Names;
+
+#[serenity::async_trait]
+impl Plugin for Names {
+    fn name(&self) -> &'static str {
+        "names"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <@user> - list a member's previous names (requires Moderate Members)",
+            prefix,
+            self.name()
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        if let Event::GuildMemberUpdate(update) = event {
+            record(ctx, update).await?;
+            return Ok(EventHandled::No);
+        }
+
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        let permitted = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::MODERATE_MEMBERS));
+        acl::check(ctx, msg, self.name(), permitted).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Name history only works within a server".to_string(),
+            ));
+        };
+        let Some(user_id) = parse_user(args.trim()) else {
+            return Err(PluginError::UserError(
+                "Invalid user.  Mention them, e.g. `@someone`.".to_string(),
+            ));
+        };
+
+        let response = {
+            let pstate = ctx.pstate.read().await;
+            match pstate.names.users.get(&user_id) {
+                None => format!("I have no name history for <@{}>.", user_id),
+                Some(names) => {
+                    let mut response = format!("Names of <@{}>:\n", user_id);
+                    let nicknames = names.nicknames.get(&guild_id).map(Vec::as_slice);
+                    for (label, records) in [
+                        ("Usernames", names.usernames.as_slice()),
+                        ("Display names", names.display_names.as_slice()),
+                        ("Nicknames", nicknames.unwrap_or_default()),
+                    ] {
+                        if !records.is_empty() {
+                            response.push_str(&format!("{}: {}\n", label, describe(records)));
+                        }
+                    }
+                    response
+                }
+            }
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn record(ctx: &Context<'_>, update: &GuildMemberUpdateEvent) -> Result<()> {
+    let now = Timestamp::now().unix_timestamp();
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let names = pstate.names.users.entry(update.user.id).or_default();
+    let mut changed = UserNames::observe(&mut names.usernames, &update.user.name, now);
+    if let Some(display_name) = &update.user.global_name {
+        changed |= UserNames::observe(&mut names.display_names, display_name, now);
+    }
+    if let Some(nick) = &update.nick {
+        let nicknames = names.nicknames.entry(update.guild_id).or_default();
+        changed |= UserNames::observe(nicknames, nick, now);
+    }
+    if changed {
+        pstate.save().await?;
+    }
+    Ok(())
+}
+
+/// Names, most recent first, with when each was first seen
+fn describe(records: &[NameRecord]) -> String {
+    records
+        .iter()
+        .rev()
+        .map(|record| {
+            format!(
+                "`{}` ({})",
+                record.name,
+                discord_timestamp(record.since, TimestampStyle::ShortDate)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,270 @@
+//! Manages the per-guild command access control lists checked by `acl.rs`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{parse_user, UserIdHelper};
+use crate::persistent_state::CapabilityAcl;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Permissions, RoleId, UserId};
+use std::collections::HashSet;
+
+pub struct Perm;
+
+#[serenity::async_trait]
+impl Plugin for Perm {
+    fn name(&self) -> &'static str {
+        "perm"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}perm <subcommand> -- command permissions, e.g. `rivals` or `rivals.delete` (admins only)\n\
+             | Subcommands:\n\
+             | allow <command> <@role/@user> - allow a command; once any are allowed, others are denied\n\
+             | deny <command> <@role/@user> - deny a command\n\
+             | clear <command> [@role/@user] - remove a command's rules, or just those for a role/user\n\
+             | list [command] - list rules",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Command permissions only work within a server".to_string(),
+            ));
+        };
+
+        let is_admin = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::ADMINISTRATOR));
+        acl::check(ctx, msg, self.name(), is_admin).await?;
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, guild_id, None).await,
+            ["list", capability] => list(ctx, guild_id, Some(&capability.to_lowercase())).await,
+            [subcommand @ ("allow" | "deny"), capability, subject] => {
+                let capability = known_capability(ctx, capability).await?;
+                let subject = parse_subject(ctx, guild_id, subject)?;
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let acl = pstate
+                    .acl
+                    .guilds
+                    .entry(guild_id)
+                    .or_default()
+                    .entry(capability.clone())
+                    .or_default();
+                // A subject is either allowed or denied, never both
+                subject.remove_from(acl);
+                let allow = *subcommand == "allow";
+                match (subject, allow) {
+                    (Subject::User(id), true) => acl.allow_users.insert(id),
+                    (Subject::User(id), false) => acl.deny_users.insert(id),
+                    (Subject::Role(id), true) => acl.allow_roles.insert(id),
+                    (Subject::Role(id), false) => acl.deny_roles.insert(id),
+                };
+                pstate.save().await?;
+                drop(pstate);
+                format!(
+                    "{} {} `{}`.",
+                    if allow { "Allowed" } else { "Denied" },
+                    subject.describe(ctx, guild_id).await,
+                    capability
+                )
+            }
+            ["clear", capability, subject @ ..] if subject.len() <= 1 => {
+                let capability = capability.to_lowercase();
+                let subject = match subject.first() {
+                    Some(subject) => Some(parse_subject(ctx, guild_id, subject)?),
+                    None => None,
+                };
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let Some(acls) = pstate.acl.guilds.get_mut(&guild_id) else {
+                    return Err(PluginError::UserError(format!(
+                        "No rules for `{}`.",
+                        capability
+                    )));
+                };
+                match (subject, acls.get_mut(&capability)) {
+                    (_, None) => {
+                        return Err(PluginError::UserError(format!(
+                            "No rules for `{}`.",
+                            capability
+                        )))
+                    }
+                    (None, Some(_)) => {
+                        acls.remove(&capability);
+                    }
+                    (Some(subject), Some(acl)) => {
+                        subject.remove_from(acl);
+                        if acl.is_empty() {
+                            acls.remove(&capability);
+                        }
+                    }
+                }
+                pstate.save().await?;
+                drop(pstate);
+                match subject {
+                    Some(subject) => format!(
+                        "Cleared rules for {} on `{}`.",
+                        subject.describe(ctx, guild_id).await,
+                        capability
+                    ),
+                    None => format!("Cleared all rules for `{}`.", capability),
+                }
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Subject {
+    User(UserId),
+    Role(RoleId),
+}
+
+impl Subject {
+    fn remove_from(self, acl: &mut CapabilityAcl) {
+        match self {
+            Subject::User(id) => {
+                acl.allow_users.remove(&id);
+                acl.deny_users.remove(&id);
+            }
+            Subject::Role(id) => {
+                acl.allow_roles.remove(&id);
+                acl.deny_roles.remove(&id);
+            }
+        }
+    }
+
+    /// Name without mentioning, which would ping
+    async fn describe(self, ctx: &Context<'_>, guild_id: GuildId) -> String {
+        match self {
+            Subject::User(id) => format!("`@{}`", id.nick_in_guild(ctx, Some(guild_id)).await),
+            Subject::Role(id) => {
+                let name = ctx
+                    .cache
+                    .guild(guild_id)
+                    .and_then(|guild| guild.roles.get(&id).map(|role| role.name.clone()))
+                    .unwrap_or_else(|| "<unknown-role>".to_string());
+                format!("`@{}`", name)
+            }
+        }
+    }
+}
+
+/// Parse a role mention, the ID of one of the guild's roles, or a user
+fn parse_subject(ctx: &Context<'_>, guild_id: GuildId, arg: &str) -> Result<Subject> {
+    if let Some(role_id) = serenity::utils::parse_role_mention(arg) {
+        return Ok(Subject::Role(role_id));
+    }
+    let is_role = |id: RoleId| {
+        ctx.cache
+            .guild(guild_id)
+            .is_some_and(|guild| guild.roles.contains_key(&id))
+    };
+    if let Some(role_id) = arg.parse().ok().filter(|id| is_role(*id)) {
+        return Ok(Subject::Role(role_id));
+    }
+    parse_user(arg).map(Subject::User).ok_or_else(|| {
+        PluginError::UserError(format!(
+            "`{}` is not a role or user.  Mention them, e.g. `@Moderators`.",
+            arg
+        ))
+    })
+}
+
+/// Lowercase `capability`, checking its command exists so typos don't silently do nothing
+pub async fn known_capability(ctx: &Context<'_>, capability: &str) -> Result<String> {
+    let capability = capability.to_lowercase();
+    let command = capability.split('.').next().unwrap_or_default();
+    let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+    for plugin in crate::plugin::plugins() {
+        if plugin.name() == command {
+            return Ok(capability);
+        }
+        let Some(usage) = plugin.usage(ctx).await else {
+            continue;
+        };
+        if usage
+            .strip_prefix(prefix.as_str())
+            .and_then(|usage| usage.split_whitespace().next())
+            == Some(command)
+        {
+            return Ok(capability);
+        }
+    }
+    Err(PluginError::UserError(format!(
+        "Unknown command `{}`.",
+        command
+    )))
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId, capability: Option<&str>) -> String {
+    // Copy the rules out so as not to hold the lock while looking up names
+    let mut rules: Vec<(String, Vec<Subject>, Vec<Subject>)> = {
+        let pstate = ctx.pstate.read().await;
+        let Some(acls) = pstate.acl.guilds.get(&guild_id) else {
+            return "No command permission rules.".to_string();
+        };
+        acls.iter()
+            .filter(|(name, _)| {
+                capability.is_none_or(|c| *name == c || name.starts_with(&format!("{}.", c)))
+            })
+            .map(|(name, acl)| {
+                let subjects = |users: &HashSet<UserId>, roles: &HashSet<RoleId>| {
+                    users
+                        .iter()
+                        .map(|id| Subject::User(*id))
+                        .chain(roles.iter().map(|id| Subject::Role(*id)))
+                        .collect()
+                };
+                (
+                    name.clone(),
+                    subjects(&acl.allow_users, &acl.allow_roles),
+                    subjects(&acl.deny_users, &acl.deny_roles),
+                )
+            })
+            .collect()
+    };
+    if rules.is_empty() {
+        return "No command permission rules.".to_string();
+    }
+    rules.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut response = String::from("Command permission rules:\n");
+    for (name, allowed, denied) in rules {
+        response.push_str(&format!("• `{}`", name));
+        for (label, subjects) in [("allow", allowed), ("deny", denied)] {
+            if subjects.is_empty() {
+                continue;
+            }
+            let mut names = Vec::new();
+            for subject in subjects {
+                names.push(subject.describe(ctx, guild_id).await);
+            }
+            response.push_str(&format!(" {}: {}", label, names.join(" ")));
+        }
+        response.push('\n');
+    }
+    response
+}
@@ -0,0 +1,96 @@
+use crate::error::Result;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelId, Message, Permissions};
+
+/// Reports Discord permissions the bot lacks for its plugins to work in a channel
+pub struct PermCheck;
+
+#[serenity::async_trait]
+impl Plugin for PermCheck {
+    fn name(&self) -> &'static str {
+        "permcheck"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} [#channel] - check the bot has the permissions its plugins need",
+            prefix,
+            self.name()
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let channel_id = match args.trim() {
+            "" => msg.channel_id,
+            arg => match serenity::utils::parse_channel_mention(arg) {
+                Some(channel_id) => channel_id,
+                None => {
+                    msg.reply(
+                        ctx.cache_http,
+                        "Invalid channel.  Mention it, e.g. `#general`.",
+                    )
+                    .await?;
+                    return Ok(EventHandled::Yes);
+                }
+            },
+        };
+
+        let Some(bot_permissions) = bot_permissions_in(ctx, msg, channel_id) else {
+            msg.reply(
+                ctx.cache_http,
+                "Could not determine my permissions there.  Is it a channel in this server?",
+            )
+            .await?;
+            return Ok(EventHandled::Yes);
+        };
+
+        let mut missing = Vec::new();
+        for plugin in crate::plugin::plugins() {
+            let lacking = plugin.required_permissions() - bot_permissions;
+            if !lacking.is_empty() {
+                missing.push(format!("• `{}`: {}", plugin.name(), lacking));
+            }
+        }
+
+        let response = if missing.is_empty() {
+            format!(
+                "I have every permission my plugins need in <#{}>.",
+                channel_id
+            )
+        } else {
+            format!(
+                "I am missing permissions in <#{}>:\n{}",
+                channel_id,
+                missing.join("\n")
+            )
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+fn bot_permissions_in(
+    ctx: &Context<'_>,
+    msg: &Message,
+    channel_id: ChannelId,
+) -> Option<Permissions> {
+    let guild = msg.guild(ctx.cache)?;
+    let channel = guild.channels.get(&channel_id)?;
+    let bot = guild.members.get(&ctx.cache.current_user().id)?;
+    Some(guild.user_permissions_in(channel, bot))
+}
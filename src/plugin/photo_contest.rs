@@ -0,0 +1,178 @@
+//! Collects photo contest submissions, and manages contests.  See `photo_contest.rs`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{discord_timestamp, MessageHelper, TimestampStyle, UserIdHelper};
+use crate::persistent_state::{ContestEntry, ContestPhase};
+use crate::photo_contest::{self, MAX_ENTRIES};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Permissions, ReactionType};
+
+/// Entries listed by `contest halloffame`
+const MAX_WINNERS: usize = 10;
+const SUBMITTED_EMOJI: &str = "📸";
+
+/// Records images posted in channels taking submissions
+pub struct PhotoSubmissions;
+
+pub struct PhotoContest;
+
+#[serenity::async_trait]
+impl Plugin for PhotoSubmissions {
+    fn name(&self) -> &'static str {
+        "photo_submissions"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        if msg.author.bot || msg.image_urls().is_empty() {
+            return Ok(EventHandled::No);
+        }
+
+        let mut pstate = ctx.pstate.write().await;
+        let Some(contest) = pstate.photo_contests.channels.get_mut(&msg.channel_id) else {
+            return Ok(EventHandled::No);
+        };
+        if contest.phase != ContestPhase::Submissions {
+            return Ok(EventHandled::No);
+        }
+        let entry = ContestEntry {
+            message_id: msg.id,
+            author_id: msg.author.id,
+        };
+        // A member's latest image replaces their earlier one
+        match contest
+            .entries
+            .iter()
+            .position(|entry| entry.author_id == msg.author.id)
+        {
+            Some(i) => contest.entries[i] = entry,
+            None if contest.entries.len() < MAX_ENTRIES => contest.entries.push(entry),
+            None => {
+                drop(pstate);
+                msg.reply(
+                    ctx.cache_http,
+                    format!("Sorry, the contest already has {} entries.", MAX_ENTRIES),
+                )
+                .await?;
+                return Ok(EventHandled::No);
+            }
+        }
+        pstate.save().await?;
+        drop(pstate);
+
+        msg.react(
+            ctx.cache_http,
+            ReactionType::Unicode(SUBMITTED_EMOJI.to_string()),
+        )
+        .await?;
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::ADD_REACTIONS)
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+}
+
+#[serenity::async_trait]
+impl Plugin for PhotoContest {
+    fn name(&self) -> &'static str {
+        "contest"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}contest <subcommand> -- photo contests\n\
+             | Subcommands:\n\
+             | halloffame - past winners\n\
+             | open/vote/close - start taking submissions, voting, or announce the winner in this channel now (moderators only)",
+            prefix
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+
+        let step = match args.trim() {
+            "halloffame" => {
+                acl::check(ctx, msg, "contest.halloffame", true).await?;
+                let response = hall_of_fame(ctx, guild_id).await;
+                msg.reply(ctx.cache_http, response).await?;
+                return Ok(EventHandled::Yes);
+            }
+            "open" => photo_contest::Step::Open,
+            "vote" => photo_contest::Step::Vote,
+            "close" => photo_contest::Step::Close,
+            _ => {
+                return Err(PluginError::UserError(
+                    "Invalid command.  See help for usage.".to_string(),
+                ))
+            }
+        };
+        let can_manage = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::MANAGE_CHANNELS));
+        acl::check(ctx, msg, "contest.manage", can_manage).await?;
+
+        photo_contest::run_step(ctx, msg.channel_id, step)
+            .await
+            .map_err(|err| PluginError::UserError(err.to_string()))?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+            .union(Permissions::EMBED_LINKS)
+            .union(Permissions::ADD_REACTIONS)
+    }
+}
+
+async fn hall_of_fame(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let winners = ctx
+        .pstate
+        .read()
+        .await
+        .photo_contests
+        .hall_of_fame
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default();
+    if winners.is_empty() {
+        return "No photo contest winners yet.".to_string();
+    }
+
+    let mut response = String::from("Photo contest hall of fame:\n");
+    for winner in winners.iter().rev().take(MAX_WINNERS) {
+        response.push_str(&format!(
+            "• {} {} ({} votes) {}\n",
+            discord_timestamp(winner.timestamp, TimestampStyle::ShortDate),
+            winner.user_id.nick_in_guild(ctx, Some(guild_id)).await,
+            winner.votes,
+            winner.message_id.link(winner.channel_id, Some(guild_id))
+        ));
+    }
+    response
+}
@@ -0,0 +1,254 @@
+//! Ordered waiting lists for voice channels with a limited number of slots, e.g. 5-stack games.
+//!
+//! When someone leaves a voice channel which has a queue, the next person in line is pinged in
+//! the text channel where the queue was last used.
+//!
+//! Calling up the next person with `next` requires Move Members, or the `queue.next` ACL.
+
+use crate::error::{PluginError, Result};
+use crate::helper::UserIdHelper;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelId, ChannelType, Message, Permissions, UserId, VoiceState};
+
+pub struct Queue;
+
+#[serenity::async_trait]
+impl Plugin for Queue {
+    fn name(&self) -> &'static str {
+        "queue"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <join/leave/next/list> [voice channel] - wait in line for a voice channel",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        match event {
+            Event::VoiceStateUpdate { old, new } => handle_voice_state_update(ctx, old, new).await,
+            _ => {
+                let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+                    return Ok(EventHandled::No);
+                };
+                acl::check(ctx, msg, self.name(), true).await?;
+                handle_command(ctx, msg, args).await
+            }
+        }
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn handle_command(ctx: &Context<'_>, msg: &Message, args: &str) -> Result<EventHandled> {
+    let mut args = args.split_whitespace();
+    let subcommand = args.next().map(str::to_lowercase);
+    let channel_arg = args.collect::<Vec<_>>().join(" ");
+
+    let Some(vc_id) = find_voice_channel(ctx, msg, &channel_arg)? else {
+        msg.reply(
+            ctx.cache_http,
+            "Could not determine the voice channel.  Join one or specify it, e.g. `#General`.",
+        )
+        .await?;
+        return Ok(EventHandled::Yes);
+    };
+
+    if subcommand.as_deref() == Some("next") {
+        let can_move = msg
+            .author_permissions(ctx.cache)
+            .is_some_and(|p| p.contains(Permissions::MOVE_MEMBERS));
+        acl::check(ctx, msg, "queue.next", can_move).await?;
+    }
+
+    let id = msg.author.id;
+    let text_channel = msg.channel_id;
+    let response = match subcommand.as_deref() {
+        Some("join") => match join(ctx, vc_id, text_channel, id).await {
+            Some(position) => format!("You are #{} in the queue for <#{}>", position, vc_id),
+            None => format!("You are already queued for <#{}>", vc_id),
+        },
+        Some("leave") if leave(ctx, vc_id, text_channel, id).await => {
+            format!("You have left the queue for <#{}>", vc_id)
+        }
+        Some("leave") => format!("You are not queued for <#{}>", vc_id),
+        Some("next") => match pop_next(ctx, vc_id, Some(text_channel), None).await {
+            Some((next, _)) => format!("<@{}>, you're up for <#{}>!", next, vc_id),
+            None => format!("Nobody is queued for <#{}>", vc_id),
+        },
+        Some("list") => {
+            let members = members(ctx, vc_id, text_channel).await;
+            if members.is_empty() {
+                format!("Nobody is queued for <#{}>", vc_id)
+            } else {
+                let mut list = format!("Queue for <#{}>:\n", vc_id);
+                for (i, member) in members.iter().enumerate() {
+                    let name = member.nick_in_guild(ctx, msg.guild_id).await;
+                    list.push_str(&format!("{}. {}\n", i + 1, name));
+                }
+                list
+            }
+        }
+        _ => {
+            let prefix = &ctx.cfg.read().await.general.command_prefix;
+            format!("Invalid command.  See `{}help`", prefix)
+        }
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Voice channel specified by mention or name, falling back to the author's current voice
+/// channel.  Mentioning a channel other than a voice channel is an error.
+fn find_voice_channel(ctx: &Context<'_>, msg: &Message, arg: &str) -> Result<Option<ChannelId>> {
+    let Some(guild) = msg.guild(ctx.cache) else {
+        return Ok(None);
+    };
+
+    if arg.is_empty() {
+        return Ok(guild
+            .voice_states
+            .get(&msg.author.id)
+            .and_then(|state| state.channel_id));
+    }
+
+    if let Some(channel_id) = serenity::utils::parse_channel_mention(arg) {
+        return match guild.channels.get(&channel_id) {
+            Some(channel) if channel.kind == ChannelType::Voice => Ok(Some(channel_id)),
+            _ => Err(PluginError::UserError(format!(
+                "<#{}> is not a voice channel",
+                channel_id
+            ))),
+        };
+    }
+
+    let name = arg.trim_start_matches('#');
+    Ok(guild
+        .channels
+        .values()
+        .find(|channel| {
+            channel.kind == ChannelType::Voice && channel.name.eq_ignore_ascii_case(name)
+        })
+        .map(|channel| channel.id))
+}
+
+async fn handle_voice_state_update(
+    ctx: &Context<'_>,
+    old: &Option<VoiceState>,
+    new: &VoiceState,
+) -> Result<EventHandled> {
+    // Only care about someone leaving a voice channel, freeing up a slot
+    let Some(old_channel_id) = old.as_ref().and_then(|o| o.channel_id) else {
+        return Ok(EventHandled::No);
+    };
+    if new.channel_id == Some(old_channel_id) {
+        return Ok(EventHandled::No);
+    }
+
+    // Someone already in line may have been the one to leave
+    let next = pop_next(ctx, old_channel_id, None, Some(new.user_id)).await;
+    let Some((next, text_channel)) = next else {
+        return Ok(EventHandled::No);
+    };
+
+    text_channel
+        .say(
+            ctx.cache_http,
+            format!("<@{}>, a spot opened up in <#{}>!", next, old_channel_id),
+        )
+        .await?;
+
+    // Other plugins might also want to act on this event.
+    Ok(EventHandled::No)
+}
+
+// Each of the following uses the queue shared in Redis if configured, otherwise the volatile
+// state.  Commands also record their text channel as where to ping the next in line.
+
+/// Append `user_id` to the queue.  Their position, or None if already queued.
+async fn join(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: ChannelId,
+    user_id: UserId,
+) -> Option<usize> {
+    #[cfg(feature = "redis")]
+    if let Some(position) = crate::redis_state::queue_join(ctx, vc_id, text_channel, user_id).await
+    {
+        return position;
+    }
+
+    let mut vstate = ctx.vstate.write().await;
+    let queue = vstate.vc_queues.get_mut(vc_id, text_channel);
+    if queue.members.contains(&user_id) {
+        return None;
+    }
+    queue.members.push_back(user_id);
+    Some(queue.members.len())
+}
+
+/// Remove `user_id` from the queue.  Whether they were in it.
+async fn leave(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: ChannelId,
+    user_id: UserId,
+) -> bool {
+    #[cfg(feature = "redis")]
+    if let Some(left) = crate::redis_state::queue_leave(ctx, vc_id, text_channel, user_id).await {
+        return left;
+    }
+
+    let mut vstate = ctx.vstate.write().await;
+    let queue = vstate.vc_queues.get_mut(vc_id, text_channel);
+    let len = queue.members.len();
+    queue.members.retain(|member| *member != user_id);
+    queue.members.len() < len
+}
+
+/// Everyone in the queue, first in line first
+async fn members(ctx: &Context<'_>, vc_id: ChannelId, text_channel: ChannelId) -> Vec<UserId> {
+    #[cfg(feature = "redis")]
+    if let Some(members) = crate::redis_state::queue_members(ctx, vc_id, text_channel).await {
+        return members;
+    }
+
+    let mut vstate = ctx.vstate.write().await;
+    let queue = vstate.vc_queues.get_mut(vc_id, text_channel);
+    queue.members.iter().copied().collect()
+}
+
+/// Remove `left` from the queue, if given, then take the next in line along with the text
+/// channel in which to ping them.  Without a `text_channel`, only an existing queue is used.
+async fn pop_next(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: Option<ChannelId>,
+    left: Option<UserId>,
+) -> Option<(UserId, ChannelId)> {
+    #[cfg(feature = "redis")]
+    if let Some(next) = crate::redis_state::queue_pop(ctx, vc_id, text_channel, left).await {
+        return next;
+    }
+
+    let mut vstate = ctx.vstate.write().await;
+    let queue = match text_channel {
+        Some(text_channel) => vstate.vc_queues.get_mut(vc_id, text_channel),
+        None => vstate.vc_queues.get_existing_mut(vc_id)?,
+    };
+    if let Some(left) = left {
+        queue.members.retain(|member| *member != left);
+    }
+    let next = queue.members.pop_front()?;
+    Some((next, queue.text_channel))
+}
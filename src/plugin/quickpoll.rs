@@ -0,0 +1,95 @@
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, log_internal, plugin::*};
+use serenity::all::{ChannelId, MessageId, Permissions, ReactionType};
+use std::time::Duration;
+
+const OPTIONS: [&str; 3] = ["👍", "👎", "🤷"];
+/// Used if `[quickpoll]` isn't configured
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Casual polls: reacts to the question with options, and later replies with the tally
+pub struct QuickPoll;
+
+#[serenity::async_trait]
+impl Plugin for QuickPoll {
+    fn name(&self) -> &'static str {
+        "quickpoll"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <question> - ask for a quick {} vote",
+            prefix,
+            self.name(),
+            OPTIONS.join("/"),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, question)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+        if question.trim().is_empty() {
+            let prefix = &ctx.cfg.read().await.general.command_prefix;
+            return Err(PluginError::UserError(format!(
+                "Usage: {}{} <question>",
+                prefix,
+                self.name()
+            )));
+        }
+
+        for option in OPTIONS {
+            msg.react(ctx.cache_http, ReactionType::Unicode(option.to_string()))
+                .await?;
+        }
+
+        let window = match &ctx.cfg.read().await.quickpoll {
+            Some(cfg) => Duration::from_secs(cfg.window_minutes * 60),
+            None => DEFAULT_WINDOW,
+        };
+        let owned = ctx.owned();
+        let (channel_id, message_id) = (msg.channel_id, msg.id);
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            if let Err(err) = tally(&owned.ctx(), channel_id, message_id).await {
+                log_internal!("Could not tally quick poll: {}", err);
+            }
+        });
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+            .union(Permissions::ADD_REACTIONS)
+            .union(Permissions::READ_MESSAGE_HISTORY)
+    }
+}
+
+async fn tally(ctx: &Context<'_>, channel_id: ChannelId, message_id: MessageId) -> Result<()> {
+    let msg = channel_id.message(ctx.cache_http, message_id).await?;
+    let counts: Vec<String> = OPTIONS
+        .iter()
+        .map(|option| {
+            // Don't count the bot's own reaction
+            let count = msg
+                .reactions
+                .iter()
+                .find(|r| matches!(&r.reaction_type, ReactionType::Unicode(e) if e == option))
+                .map(|r| r.count - u64::from(r.me))
+                .unwrap_or(0);
+            format!("{} {}", option, count)
+        })
+        .collect();
+    msg.reply(
+        ctx.cache_http,
+        format!("Poll results: {}", counts.join("  ")),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! Quiet channels, in which the bot is fully passive.
+//!
+//! The dispatcher skips plugins in quiet channels according to their `in_quiet_channels()`, so
+//! individual plugins needn't check.  This plugin itself still runs there so a quiet channel can be
+//! made un-quiet from within it.
+
+use crate::error::{PluginError, Result};
+use crate::persistent_state::QuietChannel;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Permissions};
+
+pub struct Quiet;
+
+#[serenity::async_trait]
+impl Plugin for Quiet {
+    fn name(&self) -> &'static str {
+        "quiet"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}quiet <subcommand> -- channels in which the bot never replies or reacts\n\
+             | Subcommands:\n\
+             | on <#channel> [nohistory] - make a channel quiet, optionally not recording its history\n\
+             | off <#channel> - make a channel no longer quiet\n\
+             | list - list this server's quiet channels",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            msg.reply(ctx.cache_http, "Quiet channels only work within a server")
+                .await?;
+            return Ok(EventHandled::Yes);
+        };
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, guild_id).await,
+            [subcommand @ ("on" | "off"), channel, options @ ..] => {
+                let permitted = msg
+                    .author_permissions(ctx.cache)
+                    .is_some_and(|p| p.contains(Permissions::MANAGE_CHANNELS));
+                acl::check(ctx, msg, &format!("quiet.{}", subcommand), permitted).await?;
+
+                let Some(channel_id) = serenity::utils::parse_channel_mention(channel) else {
+                    return Err(PluginError::UserError(
+                        "Invalid channel.  Mention it, e.g. `#general`.".to_string(),
+                    ));
+                };
+                if !ctx
+                    .cache
+                    .guild(guild_id)
+                    .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+                {
+                    return Err(PluginError::UserError(
+                        "That channel isn't in this server.".to_string(),
+                    ));
+                }
+
+                let pstate = &mut ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let channels = pstate.quiet.channels.entry(guild_id).or_default();
+                let response = if *subcommand == "on" {
+                    let record_history = !options.contains(&"nohistory");
+                    channels.insert(channel_id, QuietChannel { record_history });
+                    format!(
+                        "I'll stay quiet in <#{}>{}.",
+                        channel_id,
+                        if record_history {
+                            ""
+                        } else {
+                            " and won't record its history"
+                        }
+                    )
+                } else if channels.remove(&channel_id).is_some() {
+                    format!("<#{}> is no longer quiet.", channel_id)
+                } else {
+                    format!("<#{}> wasn't quiet.", channel_id)
+                };
+                pstate.save().await?;
+                response
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Run
+    }
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let pstate = ctx.pstate.read().await;
+    let Some(channels) = pstate
+        .quiet
+        .channels
+        .get(&guild_id)
+        .filter(|c| !c.is_empty())
+    else {
+        return "No channels are quiet.".to_string();
+    };
+
+    let mut response = String::from("Quiet channels:\n");
+    for (channel_id, quiet) in channels {
+        response.push_str(&format!(
+            "• <#{}>{}\n",
+            channel_id,
+            if quiet.record_history {
+                ""
+            } else {
+                " (history not recorded)"
+            }
+        ));
+    }
+    response
+}
@@ -1,6 +1,6 @@
+use crate::error::Result;
 use crate::{event::*, helper::*, plugin::*};
-use anyhow::Result;
-use serenity::all::ReactionType;
+use serenity::all::{Permissions, ReactionType};
 
 pub struct React;
 
@@ -31,4 +31,14 @@ impl Plugin for React {
         msg.react(ctx.cache_http, reaction).await?;
         Ok(EventHandled::Yes)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::VIEW_CHANNEL
+            .union(Permissions::READ_MESSAGE_HISTORY)
+            .union(Permissions::ADD_REACTIONS)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
 }
@@ -0,0 +1,72 @@
+use crate::error::{PluginError, Result};
+use crate::helper::{format_number, UserIdHelper};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::Permissions;
+
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Shows who has received the most reactions.  Reactions are recorded by `stats`.
+pub struct Reactions;
+
+#[serenity::async_trait]
+impl Plugin for Reactions {
+    fn name(&self) -> &'static str {
+        "reactions"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} - list who has received the most reactions",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, _args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "This command only works within a server".to_string(),
+            ));
+        };
+
+        let top = ctx
+            .pstate
+            .read()
+            .await
+            .reactions
+            .top_users(guild_id, LEADERBOARD_SIZE);
+        let mut response = String::from("Most reactions received:");
+        if top.is_empty() {
+            response.push_str("\nNo reactions yet");
+        }
+        for (rank, (user_id, received)) in top.iter().enumerate() {
+            let name = user_id.nick_in_guild(ctx, Some(guild_id)).await;
+            response.push_str(&format!(
+                "\n{}. {}: {}",
+                rank + 1,
+                name,
+                format_number(*received)
+            ));
+        }
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
@@ -1,8 +1,34 @@
-use crate::helper::MessageHelper;
-use crate::llm::LlmChatRequest;
-use crate::{event::*, plugin::*};
-use anyhow::Result;
-use std::borrow::Cow;
+//! Reloads `config.toml` and `state.toml` after manual edits.
+//!
+//! Replacing the loaded state discards anything changed since the file was edited, so `state`
+//! lists the sections which would change and asks for confirmation first.  The configuration is
+//! also reloaded automatically whenever the file changes, as reported by the operating system's
+//! file notifications, or by polling its modification time where those are unavailable.
+//!
+//! With `[state_watch]`, the state is likewise reloaded when `state.toml` changes, so that several
+//! bot processes sharing it see each other's changes.  The bot's own saves match the loaded state
+//! and are ignored.  If the loaded state has changes not yet saved, e.g. during maintenance mode,
+//! it is left alone for `;reload state` to resolve.  Concurrent changes by two processes aren't
+//! merged; the last save wins.
+
+use crate::error::{PluginError, Result};
+use crate::{
+    acl, config::Config, confirm, context::OwnedContext, event::*, log_internal,
+    persistent_state::PersistentState, plugin::*,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serenity::all::{Message, Permissions};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+/// How often to check files' modification times when file notifications are unavailable, unless
+/// `[config_watch]` or `[state_watch]` says otherwise
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Editors may write a file in several steps, so wait for them to finish before reloading
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
 
 pub struct Reload;
 
@@ -12,37 +38,222 @@ impl Plugin for Reload {
         "reload"
     }
 
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
     async fn usage(&self, ctx: &Context) -> Option<String> {
         let prefix = &ctx.cfg.read().await.general.command_prefix;
         Some(format!(
-            "{}{} - reload config (bot owner only)",
+            "{}{} [config/state/all] - reload config, state, or both (bot owner only)",
             prefix,
             self.name()
         ))
     }
 
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            watch(ctx).await;
+        })))
+    }
+
     async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
-        let Some((msg, _)) = event.is_bot_cmd(ctx, self.name()).await else {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
             return Ok(EventHandled::No);
         };
 
-        let response = if msg.is_from_owner(ctx).await {
-            ctx.cfg.write().await.reload().await?;
-            Cow::Borrowed("Configuration reloaded successfully")
-        } else {
-            let typing = msg.channel_id.start_typing(ctx.http);
-            let cfg = ctx.cfg.read().await;
-            let llm_settings = cfg.llm_permission_denied.as_llm_settings();
-            let response = LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
-                .await?
-                .post(ctx)
-                .await
-                .map(Cow::Owned)?;
-            typing.stop();
-            response
-        };
+        acl::check(ctx, msg, self.name(), false).await?;
 
-        msg.reply(ctx.cache_http, response).await?;
+        match args.trim().to_lowercase().as_str() {
+            "" | "config" => {
+                ctx.cfg.write().await.reload().await?;
+                msg.reply(ctx.cache_http, "Configuration reloaded successfully")
+                    .await?;
+            }
+            "state" => reload_state(ctx, msg).await?,
+            "all" => {
+                ctx.cfg.write().await.reload().await?;
+                msg.reply(ctx.cache_http, "Configuration reloaded successfully")
+                    .await?;
+                reload_state(ctx, msg).await?;
+            }
+            _ => {
+                return Err(PluginError::UserError(
+                    "Unknown subcommand.  See help for usage.".to_string(),
+                ))
+            }
+        }
         Ok(EventHandled::Yes)
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// Summarize how `state.toml` differs from the loaded state, and replace it once confirmed
+async fn reload_state(ctx: &Context<'_>, msg: &Message) -> Result<()> {
+    let new = PersistentState::load().await?;
+    let changed = ctx.pstate.read().await.changed_sections(&new)?;
+    if changed.is_empty() {
+        msg.reply(ctx.cache_http, "State file matches the loaded state")
+            .await?;
+        return Ok(());
+    }
+
+    let description = format!(
+        "replace the loaded state with `state.toml`, changing {}",
+        changed
+            .iter()
+            .map(|section| format!("`{}`", section))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let action: confirm::Action = Box::new(move |owned| {
+        Box::pin(async move {
+            owned.ctx().pstate.write().await.reload().await?;
+            Ok("State reloaded successfully".to_string())
+        })
+    });
+    confirm::request(ctx, msg, &description, action).await
+}
+
+/// Notify `sender` of changes to `config.toml` and `state.toml`
+fn start_watcher(sender: mpsc::UnboundedSender<()>) -> anyhow::Result<RecommendedWatcher> {
+    let paths = [Config::config_path()?, PersistentState::config_path()?];
+    let names: Vec<OsString> = paths
+        .iter()
+        .filter_map(|path| path.file_name().map(OsString::from))
+        .collect();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let relevant = event.paths.iter().any(|path| {
+            path.file_name()
+                .is_some_and(|name| names.iter().any(|watched| watched == name))
+        });
+        if relevant && !event.kind.is_access() {
+            let _ = sender.send(());
+        }
+    })?;
+    // Watch the directories rather than the files, which editors often replace
+    let dirs: HashSet<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+/// Reload the configuration, and the state with `[state_watch]`, whenever their files change
+async fn watch(owned: OwnedContext) {
+    let mut config_modified = Config::modified().await.ok();
+    let mut state_modified = PersistentState::modified().await.ok();
+    let on_disk = PersistentState::load()
+        .await
+        .and_then(|state| Ok(toml::Value::try_from(&state)?));
+    let mut on_disk = match on_disk {
+        Ok(on_disk) => on_disk,
+        Err(err) => {
+            log_internal!("File watch: could not load state: {}", err);
+            return;
+        }
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    // Dropping the watcher would stop it
+    let watcher = start_watcher(sender);
+    if let Err(err) = &watcher {
+        log_internal!(
+            "File watch: notifications unavailable, polling instead: {}",
+            err
+        );
+    }
+
+    loop {
+        let ctx = owned.ctx();
+        if watcher.is_ok() {
+            if receiver.recv().await.is_none() {
+                return;
+            }
+            tokio::time::sleep(SETTLE_DELAY).await;
+            while receiver.try_recv().is_ok() {}
+        } else {
+            let poll = {
+                let cfg = ctx.cfg.read().await;
+                cfg.config_watch
+                    .as_ref()
+                    .map(|watch| watch.poll_seconds)
+                    .into_iter()
+                    .chain(cfg.state_watch.as_ref().map(|watch| watch.poll_seconds))
+                    .min()
+                    .map(Duration::from_secs)
+            };
+            tokio::time::sleep(poll.unwrap_or(DEFAULT_POLL_INTERVAL)).await;
+        }
+
+        if let Some(modified) = newly_modified(Config::modified().await, config_modified) {
+            config_modified = Some(modified);
+            match ctx.cfg.write().await.reload().await {
+                Ok(()) => log_internal!("Config watch: configuration reloaded"),
+                Err(err) => {
+                    log_internal!("Config watch: could not reload configuration: {}", err)
+                }
+            }
+        }
+
+        if ctx.cfg.read().await.state_watch.is_none() {
+            continue;
+        }
+        if let Some(modified) = newly_modified(PersistentState::modified().await, state_modified) {
+            state_modified = Some(modified);
+            if let Err(err) = reload_watched_state(&ctx, &mut on_disk).await {
+                log_internal!("State watch: {}", err);
+            }
+        }
+    }
+}
+
+/// The file's modification time if it differs from `last`
+fn newly_modified(
+    modified: anyhow::Result<SystemTime>,
+    last: Option<SystemTime>,
+) -> Option<SystemTime> {
+    match modified {
+        Ok(modified) if last != Some(modified) => Some(modified),
+        Ok(_) => None,
+        Err(err) => {
+            log_internal!("File watch: {}", err);
+            None
+        }
+    }
+}
+
+/// Replace the loaded state with `state.toml`, unless the former has changes not yet saved, i.e.
+/// differs from the file's previous contents, `on_disk`
+async fn reload_watched_state(ctx: &Context<'_>, on_disk: &mut toml::Value) -> anyhow::Result<()> {
+    let mut new = PersistentState::load().await?;
+    let previous = std::mem::replace(on_disk, toml::Value::try_from(&new)?);
+    let mut pstate = ctx.pstate.write().await;
+    let changed = pstate.changed_sections(&new)?;
+    // Most likely our own save
+    if changed.is_empty() {
+        return Ok(());
+    }
+    if toml::Value::try_from(&*pstate)? != previous {
+        log_internal!(
+            "State watch: not reloading {}, as the loaded state has unsaved changes.  Use \
+             `reload state` to replace it.",
+            changed.join(", ")
+        );
+        return Ok(());
+    }
+    new.read_only = pstate.read_only;
+    *pstate = new;
+    log_internal!("State watch: reloaded {}", changed.join(", "));
+    Ok(())
 }
@@ -0,0 +1,136 @@
+//! Enforces the configured data retention policy, so deployments can guarantee that old data is
+//! actually deleted.
+//!
+//! `history_days` covers everything derived from what members said or were called: history,
+//! archived attachments, long-term memory, topic summaries, replaced names, the sent log, and the
+//! LLM audit log.  `matches_days` covers `[rivals]` match logs.  Moderation cases and server facts
+//! are exempt; see `config::Retention`.
+
+use crate::error::Result;
+use crate::{archive, event::*, log_internal, plugin::memory, plugin::*, prompt_audit};
+use serenity::all::Timestamp;
+use std::time::{Duration, SystemTime};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub struct Retention;
+
+#[serenity::async_trait]
+impl Plugin for Retention {
+    fn name(&self) -> &'static str {
+        "retention"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = cleanup(&ctx.ctx()).await {
+                    log_internal!("Error enforcing retention policy: {}", err);
+                }
+            }
+        })))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+}
+
+async fn cleanup(ctx: &Context<'_>) -> Result<()> {
+    let (history_days, stats_days, matches_days) = match &ctx.cfg.read().await.retention {
+        Some(retention) => (
+            retention.history_days,
+            retention.stats_days,
+            retention.matches_days,
+        ),
+        None => return Ok(()),
+    };
+
+    let now = Timestamp::now().unix_timestamp();
+    let cutoff = |days: u64| now - days as i64 * SECONDS_PER_DAY;
+
+    if let Some(days) = history_days {
+        let removed = ctx
+            .vstate
+            .write()
+            .await
+            .history
+            .remove_older_than(cutoff(days));
+        if removed > 0 {
+            log_internal!("Retention: removed {} history entries", removed);
+        }
+
+        let removed = archive::remove_older_than(
+            SystemTime::now() - Duration::from_secs(days * SECONDS_PER_DAY as u64),
+        )
+        .await?;
+        if removed > 0 {
+            log_internal!("Retention: removed {} archived attachments", removed);
+        }
+
+        #[cfg(feature = "redis")]
+        {
+            let removed = crate::redis_state::remove_history_older_than(ctx, cutoff(days)).await?;
+            if removed > 0 {
+                log_internal!("Retention: removed {} history entries from Redis", removed);
+            }
+        }
+
+        let removed = memory::remove_older_than(ctx, cutoff(days)).await?;
+        if removed > 0 {
+            log_internal!("Retention: removed {} long-term memory entries", removed);
+        }
+
+        let removed = prompt_audit::remove_older_than(
+            SystemTime::now() - Duration::from_secs(days * SECONDS_PER_DAY as u64),
+        )
+        .await?;
+        if removed > 0 {
+            log_internal!("Retention: removed {} LLM audit logs", removed);
+        }
+
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let summaries = pstate.topic_summaries.remove_older_than(cutoff(days));
+        let names = pstate.names.remove_older_than(cutoff(days));
+        let sent = pstate.sent_log.remove_older_than(cutoff(days));
+        if summaries + names + sent > 0 {
+            pstate.save().await?;
+            log_internal!(
+                "Retention: removed {} topic summaries, {} replaced names, and {} sent log entries",
+                summaries,
+                names,
+                sent
+            );
+        }
+    }
+
+    if let Some(days) = stats_days {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let removed = pstate.stats.remove_older_than(cutoff(days));
+        if removed > 0 {
+            pstate.save().await?;
+            log_internal!("Retention: removed {} stats records", removed);
+        }
+    }
+
+    if let Some(days) = matches_days {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let removed = pstate.rivals_seasons.remove_older_than(cutoff(days));
+        if removed > 0 {
+            pstate.save().await?;
+            log_internal!("Retention: removed {} rivals matches", removed);
+        }
+    }
+
+    Ok(())
+}
@@ -3,37 +3,47 @@
 //!
 //! Ratings units are in percentage and tied to SSBM/Rivals damage percentages.  The difference in
 //! player ratings indicates the amount of damage the stronger player should start with for an even
-//! match.  Multiples of a constant threshold percentage are treated as stocks.  For example, if
-//! this threshold is 150%, then when two players with a 200% difference in rating play, the
+//! match.  Multiples of a threshold percentage are treated as stocks.  For example, if this
+//! threshold is 150% (the default; see `[rivals]` in the config), then when two players with a 200% difference in rating play, the
 //! stronger should start one stock down and with 50% damage.
 //!
 //! If player ratings are too far apart, this system breaks down, and thus only player ratings
 //! within a certain window are valid when updating rating values.
 //!
 //! After a match, the player ratings are updated similarly to Elo ratings, but scaled such that an
-//! evenly rated match results in the winner gaining 10% rating and the loser losing 10% rating.
+//! evenly rated match results in the winner gaining 10% rating and the loser losing 10% rating by
+//! default.
 //!
 //! A discord user may have multiple players registered here.  For example, they may like to have
 //! their different game characters ratings tracked independently.  The owner is stored when
 //! creating a player.  Only the player owner or a bot owner may delete a player.  Only the losing
 //! player owner or a bot owner may report a match.
 
+use crate::error::{PluginError, Result};
 use crate::{
+    acl,
+    config::Config,
+    confirm,
     context::Context,
     event::{Event, EventHandled},
-    helper::{MessageHelper, UserHelper},
-    llm::LlmChatRequest,
-    plugin::Plugin,
+    helper::{discord_timestamp, format_number, TimestampStyle, UserHelper},
+    ladder::Ladder,
+    persistent_state::{
+        PendingMatch, PersistentState, RivalsMatch, RivalsSeason, UndoEntry, UndoOp,
+    },
+    plugin::{Category, DmPolicy, Plugin, REPLY_PERMISSIONS},
+    subcommand::{Access, SubcommandRouter},
+};
+use anyhow::anyhow;
+use serenity::all::{
+    CreateEmbed, CreateEmbedFooter, CreateMessage, Message, Permissions, Reaction, ReactionType,
+    Timestamp, UserId,
 };
-use anyhow::{anyhow, Result};
-use serenity::all::Message;
-use std::borrow::Cow;
 use std::cmp::Ordering;
 
 // Constants for rating adjustments and handicaps.
-const STOCK_VALUE: usize = 150; // 150% rating difference equates to one stock.
-const MAX_DELTA: usize = 300; // Maximum allowed rating difference (in percent) to update ratings.
-const K_FACTOR: f64 = 10.0; // Total rating change in an even match.
+const LEADERBOARD_PAGE_SIZE: usize = 10;
+const CONFIRM_EMOJI: &str = "✅";
 
 pub struct RivalsRating;
 
@@ -44,59 +54,135 @@ impl Plugin for RivalsRating {
         "rivals"
     }
 
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
     async fn usage(&self, ctx: &Context) -> Option<String> {
         let prefix = &ctx.cfg.read().await.general.command_prefix;
-        Some(format!(
-            "{}rivals <subcommand> -- manage rivals ratings\n\
-             | Subcommands:\n\
-             | create <initial_rating> [player_name] - create a player\n\
-             | delete <player_name> - delete a player\n\
-             | list - list all players\n\
-             | preview <player1> <player2> - show ratings and starting handicap\n\
-             | report <player1> beat <player2> - report a match result (you must own the loser)",
-            prefix
-        ))
+        Some(router().usage(prefix))
     }
 
     async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        if let Event::ReactionAdd(reaction) = event {
+            return handle_reaction(ctx, reaction).await;
+        }
         let Some((msg, args_str)) = event.is_bot_cmd(ctx, self.name()).await else {
             return Ok(EventHandled::No);
         };
+        acl::check(ctx, msg, self.name(), true).await?;
 
-        let args: Vec<&str> = args_str.split_whitespace().collect();
-        if args.is_empty() {
-            msg.reply(
-                ctx.cache_http,
-                "Please provide a subcommand. See help for usage.",
-            )
-            .await?;
-            return Ok(EventHandled::Yes);
-        }
+        router().dispatch(ctx, msg, args_str).await
+    }
 
-        match args[0].to_lowercase().as_str() {
-            "create" => handle_create(ctx, msg, &args[1..]).await,
-            "delete" => handle_delete(ctx, msg, &args[1..]).await,
-            "list" => handle_list(ctx, msg).await,
-            "preview" => handle_preview(ctx, msg, &args[1..]).await,
-            "report" => handle_report(ctx, msg, &args[1..]).await,
-            _ => {
-                msg.reply(ctx.cache_http, "Unknown subcommand.").await?;
-                Ok(EventHandled::Yes)
-            }
-        }
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
     }
 }
 
-async fn handle_create(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
-    if args.is_empty() {
-        msg.reply(
-            ctx.cache_http,
-            "Usage: create <initial_rating> [player_name]",
+/// Player names may be quoted to contain spaces; see `Args`.
+fn router() -> SubcommandRouter {
+    SubcommandRouter::new("rivals", "manage rivals ratings")
+        .subcommand(
+            "create <initial_rating> [player_name]",
+            "create a player",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_create(ctx, msg, &args.positional()).await })
+            },
         )
-        .await?;
-        return Ok(EventHandled::Yes);
-    }
+        .subcommand(
+            "delete <player_name>",
+            "delete a player, after confirmation",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_delete(ctx, msg, &args.positional()).await })
+            },
+        )
+        .subcommand(
+            "list",
+            "list all players",
+            Access::Everyone,
+            |ctx, msg, _args| Box::pin(handle_list(ctx, msg)),
+        )
+        .subcommand(
+            "leaderboard [page] [--mine]",
+            "show ranked players, optionally only your own",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move {
+                    handle_leaderboard(ctx, msg, &args.positional(), args.flag("mine")).await
+                })
+            },
+        )
+        .subcommand(
+            "preview <player1> <player2>",
+            "show ratings and starting handicap",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_preview(ctx, msg, &args.positional()).await })
+            },
+        )
+        .subcommand(
+            "h2h <player1> <player2>",
+            "show the players' record against each other",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_h2h(ctx, msg, &args.positional()).await })
+            },
+        )
+        .subcommand(
+            "report <player1> beat <player2>",
+            "report a match result (you must own the loser)",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_report(ctx, msg, &args.positional()).await })
+            },
+        )
+        .subcommand(
+            "confirm <match_id>",
+            "confirm a reported match (you must own the winner)",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_confirm(ctx, msg, &args.positional()).await })
+            },
+        )
+        .subcommand(
+            "season start <name>",
+            "archive this season and soft-reset ratings",
+            Access::Owner,
+            |ctx, msg, args| {
+                Box::pin(async move { handle_season_start(ctx, msg, args.positional()[0]).await })
+            },
+        )
+        .subcommand(
+            "season list",
+            "list past seasons",
+            Access::Everyone,
+            |ctx, msg, _args| Box::pin(handle_season_list(ctx, msg)),
+        )
+        .subcommand(
+            "season standings <name>",
+            "show a past season's final ratings",
+            Access::Everyone,
+            |ctx, msg, args| {
+                Box::pin(
+                    async move { handle_season_standings(ctx, msg, args.positional()[0]).await },
+                )
+            },
+        )
+        .note("Quote names containing spaces, e.g. `\"Big Jim\"`")
+}
 
+async fn handle_create(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
     let initial_rating: usize = match args[0].parse() {
         Ok(rating) => rating,
         Err(_) => {
@@ -114,6 +200,7 @@ async fn handle_create(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
     };
 
     let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
     // Check if the player already exists.
     if pstate.rivals_ratings.0.contains_key(&player_name) {
         msg.reply(
@@ -132,6 +219,14 @@ async fn handle_create(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         .rivals_ratings_owners
         .0
         .insert(player_name.clone(), msg.author.id);
+    pstate.undo.push(UndoEntry {
+        actor: msg.author.id,
+        timestamp: Timestamp::now().unix_timestamp(),
+        description: format!("create player `{}`", player_name),
+        inverse: UndoOp::DeletePlayer {
+            player: player_name.clone(),
+        },
+    });
 
     pstate.save().await?;
 
@@ -147,14 +242,9 @@ async fn handle_create(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
 }
 
 async fn handle_delete(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
-    if args.is_empty() {
-        msg.reply(ctx.cache_http, "Usage: delete <player_name>")
-            .await?;
-        return Ok(EventHandled::Yes);
-    }
-
     let player_name = args[0].to_string();
-    let mut pstate = ctx.pstate.write().await;
+    let may_delete_any = acl::permitted(ctx, msg, "rivals.delete", false).await;
+    let pstate = ctx.pstate.read().await;
     if !pstate.rivals_ratings.0.contains_key(&player_name) {
         msg.reply(
             ctx.cache_http,
@@ -164,47 +254,47 @@ async fn handle_delete(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         return Ok(EventHandled::Yes);
     }
 
-    // Only the player owner or a bot owner may delete.
-    if !msg.is_from_owner(ctx).await {
+    // Only the player owner, or those permitted to delete any player, may delete.
+    if !may_delete_any {
         if let Some(owner) = pstate.rivals_ratings_owners.0.get(&player_name) {
             if *owner != msg.author.id {
-                let typing = msg.channel_id.start_typing(ctx.http);
-                let cfg = ctx.cfg.read().await;
-                let llm_settings = cfg.llm_permission_denied.as_llm_settings();
-                let response =
-                    LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
-                        .await?
-                        .post(ctx)
-                        .await
-                        .map(Cow::Owned)?;
-                typing.stop();
-                msg.reply(ctx.cache_http, response).await?;
-                return Ok(EventHandled::Yes);
+                return Err(PluginError::PermissionDenied);
             }
         } else {
-            let typing = msg.channel_id.start_typing(ctx.http);
-            let cfg = ctx.cfg.read().await;
-            let llm_settings = cfg.llm_permission_denied.as_llm_settings();
-            let response = LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
-                .await?
-                .post(ctx)
-                .await
-                .map(Cow::Owned)?;
-            typing.stop();
-            msg.reply(ctx.cache_http, response).await?;
-            return Ok(EventHandled::Yes);
+            return Err(PluginError::PermissionDenied);
         }
     }
 
-    pstate.rivals_ratings.0.remove(&player_name);
-    pstate.rivals_ratings_owners.0.remove(&player_name);
-    pstate.save().await?;
-
-    msg.reply(
-        ctx.cache_http,
-        format!("Player `{}` has been deleted.", player_name),
-    )
-    .await?;
+    drop(pstate);
+
+    let description = format!("delete player `{}` and their rating", player_name);
+    let actor = msg.author.id;
+    let action: confirm::Action = Box::new(move |owned| {
+        Box::pin(async move {
+            let ctx = owned.ctx();
+            let mut pstate = ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            // May have been deleted while awaiting confirmation
+            let Some(rating) = pstate.rivals_ratings.0.remove(&player_name) else {
+                return Ok(format!("Player `{}` not found.", player_name));
+            };
+            if let Some(owner) = pstate.rivals_ratings_owners.0.remove(&player_name) {
+                pstate.undo.push(UndoEntry {
+                    actor,
+                    timestamp: Timestamp::now().unix_timestamp(),
+                    description: format!("delete player `{}`", player_name),
+                    inverse: UndoOp::RestorePlayer {
+                        player: player_name.clone(),
+                        rating,
+                        owner,
+                    },
+                });
+            }
+            pstate.save().await?;
+            Ok(format!("Player `{}` has been deleted.", player_name))
+        })
+    });
+    confirm::request(ctx, msg, &description, action).await?;
     Ok(EventHandled::Yes)
 }
 
@@ -242,15 +332,97 @@ async fn handle_list(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
     Ok(EventHandled::Yes)
 }
 
-async fn handle_preview(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
-    if args.len() < 2 {
-        msg.reply(ctx.cache_http, "Usage: preview <player1> <player2>")
+async fn handle_leaderboard(
+    ctx: &Context<'_>,
+    msg: &Message,
+    args: &[&str],
+    mut mine: bool,
+) -> Result<EventHandled> {
+    let mut page = 1;
+    for arg in args {
+        match (arg.to_lowercase().as_str(), arg.parse::<usize>()) {
+            ("mine", _) => mine = true,
+            (_, Ok(n)) if n > 0 => page = n,
+            _ => {
+                msg.reply(ctx.cache_http, "Usage: leaderboard [page] [--mine]")
+                    .await?;
+                return Ok(EventHandled::Yes);
+            }
+        }
+    }
+
+    let pstate = ctx.pstate.read().await;
+    // Rank among all players, even when only showing the invoker's.
+    let mut ranked: Vec<(&String, usize)> = pstate
+        .rivals_ratings
+        .0
+        .iter()
+        .map(|(player, rating)| (player, *rating))
+        .collect();
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let lines: Vec<String> = ranked
+        .iter()
+        .enumerate()
+        .filter(|(_, (player, _))| {
+            !mine || pstate.rivals_ratings_owners.0.get(*player) == Some(&msg.author.id)
+        })
+        .map(|(rank, (player, rating))| {
+            let change = match pstate.rivals_snapshot.ratings.get(*player) {
+                Some(previous) => match rating.cmp(previous) {
+                    Ordering::Greater => format!(" ▲{}", rating - previous),
+                    Ordering::Less => format!(" ▼{}", previous - rating),
+                    Ordering::Equal => String::new(),
+                },
+                None => " (new)".to_string(),
+            };
+            format!("**{}.** `{}`: {}%{}", rank + 1, player, rating, change)
+        })
+        .collect();
+    drop(pstate);
+
+    if lines.is_empty() {
+        let response = if mine {
+            "You don't own any players."
+        } else {
+            "No players registered yet."
+        };
+        msg.reply(ctx.cache_http, response).await?;
+        return Ok(EventHandled::Yes);
+    }
+
+    let pages = lines.len().div_ceil(LEADERBOARD_PAGE_SIZE);
+    if page > pages {
+        msg.reply(ctx.cache_http, format!("There are only {} page(s).", pages))
             .await?;
         return Ok(EventHandled::Yes);
     }
+    let start = (page - 1) * LEADERBOARD_PAGE_SIZE;
+    let end = (start + LEADERBOARD_PAGE_SIZE).min(lines.len());
+
+    let embed = CreateEmbed::new()
+        .title(if mine {
+            "Rivals leaderboard: your players"
+        } else {
+            "Rivals leaderboard"
+        })
+        .description(lines[start..end].join("\n"))
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} · changes since the start of the week",
+            page, pages
+        )));
+    msg.channel_id
+        .send_message(
+            ctx.cache_http,
+            CreateMessage::new().embed(embed).reference_message(msg),
+        )
+        .await?;
+    Ok(EventHandled::Yes)
+}
 
+async fn handle_preview(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
     let player1 = args[0];
     let player2 = args[1];
+    let ladder = Ladder::rivals(&*ctx.cfg.read().await, msg.guild_id);
 
     let pstate = ctx.pstate.read().await;
     let rating1 = match pstate.rivals_ratings.0.get(player1) {
@@ -270,42 +442,114 @@ async fn handle_preview(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resu
         }
     };
 
-    let (higher, high_rating, low_rating) = match rating1.cmp(&rating2) {
-        Ordering::Greater => (player1, rating1, rating2),
-        Ordering::Less => (player2, rating2, rating1),
-        Ordering::Equal => {
-            msg.reply(
-                ctx.cache_http,
-                format!(
-                    "Both `{}` and `{}` have equal ratings ({}%). No handicap.",
-                    player1, player2, rating1
-                ),
-            )
-            .await?;
-            return Ok(EventHandled::Yes);
-        }
-    };
-
-    let diff = high_rating - low_rating;
-    let stocks = diff / STOCK_VALUE;
-    let remainder = diff % STOCK_VALUE;
-
-    let handicap = format!(
-        "Handicap: `{}` should start with {} stock(s) and {}% extra damage.",
-        higher, stocks, remainder
-    );
+    if rating1 == rating2 {
+        msg.reply(
+            ctx.cache_http,
+            format!(
+                "Both `{}` and `{}` have equal ratings ({}%). No handicap.",
+                player1, player2, rating1
+            ),
+        )
+        .await?;
+        return Ok(EventHandled::Yes);
+    }
 
     let response = format!(
         "Player ratings:\n• `{}`: {}%\n• `{}`: {}%\n{}",
-        player1, rating1, player2, rating2, handicap
+        player1,
+        rating1,
+        player2,
+        rating2,
+        ladder.handicap(player1, rating1, player2, rating2)
     );
     msg.reply(ctx.cache_http, response).await?;
     Ok(EventHandled::Yes)
 }
 
+async fn handle_h2h(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
+    let (player1, player2) = (&args[0], &args[1]);
+    if player1 == player2 {
+        return Err(PluginError::UserError(
+            "Pick two different players.".to_string(),
+        ));
+    }
+
+    let ladder = Ladder::rivals(&*ctx.cfg.read().await, msg.guild_id);
+    let embed = {
+        let pstate = ctx.pstate.read().await;
+        let ratings = &pstate.rivals_ratings.0;
+        let (Some(&rating1), Some(&rating2)) = (ratings.get(*player1), ratings.get(*player2))
+        else {
+            return Err(PluginError::UserError(format!(
+                "Both `{}` and `{}` must be registered players.",
+                player1, player2
+            )));
+        };
+
+        // Lifetime, across archived seasons and this one
+        let seasons = &pstate.rivals_seasons;
+        let matches: Vec<&RivalsMatch> = seasons
+            .archived
+            .iter()
+            .flat_map(|season| season.matches.iter())
+            .chain(seasons.matches.iter())
+            .filter(|m| {
+                (m.winner == *player1 && m.loser == *player2)
+                    || (m.winner == *player2 && m.loser == *player1)
+            })
+            .collect();
+        let wins1 = matches.iter().filter(|m| m.winner == *player1).count();
+        let wins2 = matches.len() - wins1;
+
+        let record = if matches.is_empty() {
+            "No recorded matches.".to_string()
+        } else {
+            // Positive when player1 was the higher rated
+            let total_delta: i64 = matches
+                .iter()
+                .map(|m| {
+                    let delta = m.winner_before as i64 - m.loser_before as i64;
+                    if m.winner == *player1 {
+                        delta
+                    } else {
+                        -delta
+                    }
+                })
+                .sum();
+            let average_delta = total_delta as f64 / matches.len() as f64;
+            format!(
+                "`{}` {} - {} `{}`\nAverage rating difference at match time: {:+.0}% for `{}`",
+                player1, wins1, wins2, player2, average_delta, player1
+            )
+        };
+
+        CreateEmbed::new()
+            .title(format!("{} vs {}", player1, player2))
+            .field("Record", record, false)
+            .field(
+                "Current ratings",
+                format!("`{}`: {}%\n`{}`: {}%", player1, rating1, player2, rating2),
+                false,
+            )
+            .field(
+                "Next match",
+                ladder.handicap(player1, rating1, player2, rating2),
+                false,
+            )
+    };
+
+    msg.channel_id
+        .send_message(
+            ctx.cache_http,
+            CreateMessage::new().embed(embed).reference_message(msg),
+        )
+        .await?;
+    Ok(EventHandled::Yes)
+}
+
 async fn handle_report(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
     // Expected format: report <winner> beat <loser>
-    if args.len() < 3 || args[1].to_lowercase() != "beat" {
+    if args[1].to_lowercase() != "beat" {
         msg.reply(ctx.cache_http, "Usage: report <player1> beat <player2>")
             .await?;
         return Ok(EventHandled::Yes);
@@ -323,7 +567,9 @@ async fn handle_report(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         return Ok(EventHandled::Yes);
     }
 
+    let may_report_any = acl::permitted(ctx, msg, "rivals.report", false).await;
     let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
     let winner_rating = match pstate.rivals_ratings.0.get(winner_name) {
         Some(&r) => r,
         None => {
@@ -347,46 +593,21 @@ async fn handle_report(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         }
     };
 
-    // Only the loser’s owner or a bot owner may report a match.
-    if !msg.is_from_owner(ctx).await {
+    // Only the loser’s owner, or those permitted to report any match, may report a match.
+    if !may_report_any {
         if let Some(owner) = pstate.rivals_ratings_owners.0.get(loser_name) {
             if *owner != msg.author.id {
-                let typing = msg.channel_id.start_typing(ctx.http);
-                let cfg = ctx.cfg.read().await;
-                let llm_settings = cfg.llm_permission_denied.as_llm_settings();
-                let response =
-                    LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
-                        .await?
-                        .post(ctx)
-                        .await
-                        .map(Cow::Owned)?;
-                typing.stop();
-                msg.reply(ctx.cache_http, response).await?;
-                return Ok(EventHandled::Yes);
+                return Err(PluginError::PermissionDenied);
             }
         } else {
-            let typing = msg.channel_id.start_typing(ctx.http);
-            let cfg = ctx.cfg.read().await;
-            let llm_settings = cfg.llm_permission_denied.as_llm_settings();
-            let response = LlmChatRequest::from_recent_history(ctx, msg.channel_id, &llm_settings)
-                .await?
-                .post(ctx)
-                .await
-                .map(Cow::Owned)?;
-            typing.stop();
-            msg.reply(ctx.cache_http, response).await?;
-            return Ok(EventHandled::Yes);
+            return Err(PluginError::PermissionDenied);
         }
     }
 
     // Disallow update if ratings are too far apart.
-    let rating_diff = if winner_rating > loser_rating {
-        winner_rating - loser_rating
-    } else {
-        loser_rating - winner_rating
-    };
-
-    if rating_diff > MAX_DELTA {
+    let can_rate =
+        Ladder::rivals(&*ctx.cfg.read().await, msg.guild_id).can_rate(winner_rating, loser_rating);
+    if !can_rate {
         msg.reply(
             ctx.cache_http,
             "Player ratings are too far apart to update.",
@@ -395,14 +616,74 @@ async fn handle_report(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         return Ok(EventHandled::Yes);
     }
 
-    // Calculate expected score for the winner using a logistic curve.
-    // Using D = 200 for scaling.
-    let expected_winner =
-        1.0 / (1.0 + 10f64.powf((loser_rating as f64 - winner_rating as f64) / 200.0));
-    let change = K_FACTOR * (1.0 - expected_winner);
-    let new_winner = ((winner_rating as f64) + change).round() as usize;
-    let new_loser = ((loser_rating as f64) - change).round() as usize;
+    // Optionally let the winner's owner confirm, unless they reported it themselves.
+    let timeout_minutes = ctx
+        .cfg
+        .read()
+        .await
+        .rivals_confirmation
+        .as_ref()
+        .map(|cfg| cfg.timeout_minutes);
+    let winner_owner = pstate.rivals_ratings_owners.0.get(winner_name).copied();
+    if let (Some(timeout_minutes), Some(winner_owner)) = (timeout_minutes, winner_owner) {
+        if winner_owner != msg.author.id {
+            drop(pstate);
+            return request_confirmation(
+                ctx,
+                msg,
+                winner_name,
+                loser_name,
+                winner_owner,
+                timeout_minutes,
+            )
+            .await;
+        }
+    }
+
+    let response = record_match(
+        &mut pstate,
+        &*ctx.cfg.read().await,
+        winner_name,
+        loser_name,
+        msg.author.id,
+    )?;
+    pstate.save().await?;
+    drop(pstate);
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Apply a match result to the ratings, recording it for the season and `undo`.  Returns a
+/// description of the rating changes.
+fn record_match(
+    pstate: &mut PersistentState,
+    cfg: &Config,
+    winner_name: &str,
+    loser_name: &str,
+    actor: UserId,
+) -> Result<String> {
+    let ratings = &pstate.rivals_ratings.0;
+    let (Some(&winner_rating), Some(&loser_rating)) =
+        (ratings.get(winner_name), ratings.get(loser_name))
+    else {
+        return Err(PluginError::UserError(format!(
+            "`{}` or `{}` no longer exists.",
+            winner_name, loser_name
+        )));
+    };
+    // Ratings may have changed since a pending match was reported.
+    let ladder = Ladder::rivals(cfg, None);
+    if !ladder.can_rate(winner_rating, loser_rating) {
+        return Err(PluginError::UserError(
+            "Player ratings are too far apart to update.".to_string(),
+        ));
+    }
+
+    let (new_winner, new_loser) = ladder.formula.rate(winner_rating, loser_rating);
 
+    let now = Timestamp::now().unix_timestamp();
+    pstate.rivals_snapshot.refresh(&pstate.rivals_ratings, now);
     pstate
         .rivals_ratings
         .0
@@ -411,12 +692,299 @@ async fn handle_report(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Resul
         .rivals_ratings
         .0
         .insert(loser_name.to_owned(), new_loser);
-    pstate.save().await?;
-
-    let response = format!(
+    pstate.rivals_seasons.matches.push(RivalsMatch {
+        timestamp: now,
+        winner: winner_name.to_owned(),
+        loser: loser_name.to_owned(),
+        winner_before: winner_rating,
+        winner_after: new_winner,
+        loser_before: loser_rating,
+        loser_after: new_loser,
+    });
+    pstate.undo.push(UndoEntry {
+        actor,
+        timestamp: now,
+        description: format!("report `{}` beat `{}`", winner_name, loser_name),
+        inverse: UndoOp::UnreportMatch {
+            ratings: vec![
+                (winner_name.to_owned(), winner_rating),
+                (loser_name.to_owned(), loser_rating),
+            ],
+        },
+    });
+
+    Ok(format!(
         "Match reported:\n• Winner `{}`: {}% → {}%\n• Loser `{}`: {}% → {}%",
         winner_name, winner_rating, new_winner, loser_name, loser_rating, new_loser
+    ))
+}
+
+/// Hold a reported match until the winner's owner confirms it
+async fn request_confirmation(
+    ctx: &Context<'_>,
+    msg: &Message,
+    winner_name: &str,
+    loser_name: &str,
+    winner_owner: UserId,
+    timeout_minutes: u64,
+) -> Result<EventHandled> {
+    let now = Timestamp::now().unix_timestamp();
+    let expires = now + timeout_minutes as i64 * 60;
+    let id = {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        pstate.rivals_pending.remove_expired(now);
+        pstate.rivals_pending.next_id += 1;
+        pstate.rivals_pending.next_id
+    };
+
+    let prompt = msg
+        .reply(
+            ctx.cache_http,
+            format!(
+                "`{}` reported losing to `{}`.  <@{}>, confirm with `rivals confirm {}` or react \
+                 {} within {} minutes.",
+                loser_name, winner_name, winner_owner, id, CONFIRM_EMOJI, timeout_minutes
+            ),
+        )
+        .await?;
+    prompt
+        .react(
+            ctx.cache_http,
+            ReactionType::Unicode(CONFIRM_EMOJI.to_string()),
+        )
+        .await?;
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate.rivals_pending.matches.push(PendingMatch {
+        id,
+        winner: winner_name.to_owned(),
+        loser: loser_name.to_owned(),
+        reporter: msg.author.id,
+        winner_owner,
+        prompt: prompt.id,
+        expires,
+    });
+    pstate.save().await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn handle_confirm(ctx: &Context<'_>, msg: &Message, args: &[&str]) -> Result<EventHandled> {
+    let Some(id) = args.first().and_then(|id| id.parse::<u64>().ok()) else {
+        return Err(PluginError::UserError(
+            "Usage: `confirm <match_id>`".to_string(),
+        ));
+    };
+    let may_confirm_any = acl::permitted(ctx, msg, "rivals.confirm", false).await;
+    let response = confirm_match(ctx, |m| m.id == id, msg.author.id, may_confirm_any).await?;
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Confirm a pending match via a reaction to its prompt
+async fn handle_reaction(ctx: &Context<'_>, reaction: &Reaction) -> Result<EventHandled> {
+    let (ReactionType::Unicode(emoji), Some(user_id)) = (&reaction.emoji, reaction.user_id) else {
+        return Ok(EventHandled::No);
+    };
+    if emoji != CONFIRM_EMOJI || user_id == ctx.cache.current_user().id {
+        return Ok(EventHandled::No);
+    }
+    let is_pending = ctx
+        .pstate
+        .read()
+        .await
+        .rivals_pending
+        .matches
+        .iter()
+        .any(|m| m.prompt == reaction.message_id);
+    if !is_pending {
+        return Ok(EventHandled::No);
+    }
+
+    // Reactions carry no message to reply to, and others' reactions are just ignored.
+    match confirm_match(ctx, |m| m.prompt == reaction.message_id, user_id, false).await {
+        Ok(response) => {
+            reaction.channel_id.say(ctx.cache_http, response).await?;
+        }
+        Err(PluginError::UserError(_) | PluginError::PermissionDenied) => {}
+        Err(err) => return Err(err),
+    }
+    Ok(EventHandled::Yes)
+}
+
+/// Apply the pending match selected by `select`, if `user_id` may confirm it.  Returns the reply.
+async fn confirm_match(
+    ctx: &Context<'_>,
+    select: impl Fn(&PendingMatch) -> bool,
+    user_id: UserId,
+    is_owner: bool,
+) -> Result<String> {
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate
+        .rivals_pending
+        .remove_expired(Timestamp::now().unix_timestamp());
+    let Some(index) = pstate.rivals_pending.matches.iter().position(select) else {
+        return Err(PluginError::UserError(
+            "No such pending match.  It may have expired; report it again.".to_string(),
+        ));
+    };
+    if !is_owner && pstate.rivals_pending.matches[index].winner_owner != user_id {
+        return Err(PluginError::PermissionDenied);
+    }
+
+    let pending = pstate.rivals_pending.matches.remove(index);
+    // Attribute the change to the reporter, so they may still undo it.
+    let result = record_match(
+        &mut pstate,
+        &*ctx.cfg.read().await,
+        &pending.winner,
+        &pending.loser,
+        pending.reporter,
     );
+    pstate.save().await?;
+    result
+}
+
+async fn handle_season_start(ctx: &Context<'_>, msg: &Message, name: &str) -> Result<EventHandled> {
+    let Some((baseline, carryover)) = ctx
+        .cfg
+        .read()
+        .await
+        .rivals_seasons
+        .as_ref()
+        .map(|cfg| (cfg.baseline, cfg.carryover.clamp(0.0, 1.0)))
+    else {
+        return Err(PluginError::UserError(
+            "Seasons are not configured; see `[rivals_seasons]` in the README".to_string(),
+        ));
+    };
+
+    let name = name.to_string();
+    let description = {
+        let pstate = ctx.pstate.read().await;
+        let seasons = &pstate.rivals_seasons;
+        if seasons.current_name().eq_ignore_ascii_case(&name) || seasons.find(&name).is_some() {
+            return Err(PluginError::UserError(format!(
+                "There is already a season named `{}`",
+                name
+            )));
+        }
+        format!(
+            "archive season `{}` and reset all ratings {}% of the way to {}%",
+            seasons.current_name(),
+            ((1.0 - carryover) * 100.0).round(),
+            baseline
+        )
+    };
+
+    let action: confirm::Action = Box::new(move |owned| {
+        Box::pin(async move {
+            let ctx = owned.ctx();
+            let now = Timestamp::now().unix_timestamp();
+            let mut pstate = ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            let pstate = &mut *pstate;
+
+            let seasons = &mut pstate.rivals_seasons;
+            let ended = seasons.current_name().to_string();
+            seasons.archived.push(RivalsSeason {
+                name: ended.clone(),
+                started: seasons.started,
+                ended: now,
+                ratings: pstate.rivals_ratings.0.clone(),
+                matches: std::mem::take(&mut seasons.matches),
+            });
+            seasons.current = Some(name.clone());
+            seasons.started = now;
+
+            for rating in pstate.rivals_ratings.0.values_mut() {
+                let distance = *rating as f64 - baseline as f64;
+                *rating = (baseline as f64 + distance * carryover).round().max(0.0) as usize;
+            }
+            // Changes from last season are no longer meaningful, nor safely reversible.
+            pstate.rivals_snapshot.taken = now;
+            pstate.rivals_snapshot.ratings = pstate.rivals_ratings.0.clone();
+            pstate.undo.entries.clear();
+            pstate.save().await?;
+
+            Ok(format!(
+                "Season `{}` archived.  Season `{}` has begun!",
+                ended, name
+            ))
+        })
+    });
+    confirm::request(ctx, msg, &description, action).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn handle_season_list(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    let pstate = ctx.pstate.read().await;
+    let seasons = &pstate.rivals_seasons;
+    let mut response = format!(
+        "Current season: `{}` ({} matches)\n",
+        seasons.current_name(),
+        seasons.matches.len()
+    );
+    if seasons.archived.is_empty() {
+        response.push_str("No past seasons yet.");
+    }
+    for season in seasons.archived.iter().rev() {
+        response.push_str(&format!(
+            "• `{}`: {} to {}, {} matches\n",
+            season.name,
+            discord_timestamp(season.started, TimestampStyle::ShortDate),
+            discord_timestamp(season.ended, TimestampStyle::ShortDate),
+            format_number(season.matches.len() as u64)
+        ));
+    }
+    drop(pstate);
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn handle_season_standings(
+    ctx: &Context<'_>,
+    msg: &Message,
+    name: &str,
+) -> Result<EventHandled> {
+    let response = {
+        let pstate = ctx.pstate.read().await;
+        let Some(season) = pstate.rivals_seasons.find(name) else {
+            return Err(PluginError::UserError(format!(
+                "No past season named `{}`",
+                name
+            )));
+        };
+
+        let mut standings: Vec<(&String, &usize)> = season.ratings.iter().collect();
+        standings.sort_unstable_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let mut response = format!("Final standings for season `{}`:\n", season.name);
+        for (rank, (player, rating)) in standings.iter().take(LEADERBOARD_PAGE_SIZE).enumerate() {
+            let wins = season
+                .matches
+                .iter()
+                .filter(|m| m.winner == **player)
+                .count();
+            let losses = season
+                .matches
+                .iter()
+                .filter(|m| m.loser == **player)
+                .count();
+            response.push_str(&format!(
+                "**{}.** `{}`: {}% ({}-{})\n",
+                rank + 1,
+                player,
+                rating,
+                wins,
+                losses
+            ));
+        }
+        response
+    };
+
     msg.reply(ctx.cache_http, response).await?;
     Ok(EventHandled::Yes)
 }
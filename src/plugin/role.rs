@@ -0,0 +1,492 @@
+//! Self-assignable roles.
+//!
+//! Bot owners maintain a per-guild allowlist of roles which members may then grant to or remove
+//! from themselves.  Roles at or above the bot's highest role can't be granted by Discord, and
+//! integration-managed roles can't be granted at all, so neither may be allowlisted.
+//!
+//! Administrators may also give a role to, or remove it from, every member at once.  This runs in
+//! a background task paced against Discord's rate limits, posting its progress as it goes.
+//!
+//! As allowing and bulk changes may be delegated through the ACL, whoever does so must outrank
+//! the role, and only the bot or server owner may hand out roles with moderation permissions.
+
+use crate::error::{PluginError, Result};
+use crate::helper::MessageHelper;
+use crate::{acl, confirm, event::*, log_internal, plugin::*};
+use serenity::all::{ChannelId, EditMessage, GuildId, Member, Message, Permissions, RoleId};
+use std::borrow::Cow;
+use std::time::Duration;
+
+/// Delay between role changes in bulk operations, to stay clear of Discord's rate limits
+const BULK_PACE: Duration = Duration::from_millis(500);
+/// Update the progress message after this many members
+const BULK_PROGRESS_EVERY: usize = 25;
+/// Members fetched per request, Discord's maximum
+const MEMBER_PAGE_SIZE: u64 = 1000;
+/// Only owners may allow or bulk change roles with any of these
+const PRIVILEGED_PERMISSIONS: Permissions = Permissions::ADMINISTRATOR
+    .union(Permissions::MANAGE_ROLES)
+    .union(Permissions::MANAGE_GUILD)
+    .union(Permissions::BAN_MEMBERS);
+
+pub struct Role;
+
+#[serenity::async_trait]
+impl Plugin for Role {
+    fn name(&self) -> &'static str {
+        "role"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}role <subcommand> -- self-assignable roles\n\
+             | Subcommands:\n\
+             | add <role> - give yourself a role\n\
+             | remove <role> - remove a role from yourself\n\
+             | list - list self-assignable roles\n\
+             | allow <role> - make a role self-assignable (bot owner only)\n\
+             | disallow <role> - make a role no longer self-assignable (bot owner only)\n\
+             | giveall <role> [humans/bots/@role] - give a role to every matching member (admins only)\n\
+             | removeall <role> - remove a role from every member (admins only)",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            msg.reply(ctx.cache_http, "Roles only work within a server")
+                .await?;
+            return Ok(EventHandled::Yes);
+        };
+
+        let args = args.trim();
+        let (subcommand, role_arg) = args.split_once(' ').unwrap_or((args, ""));
+        let role_arg = role_arg.trim();
+
+        let response = match subcommand.to_lowercase().as_str() {
+            "list" => list(ctx, guild_id).await,
+            "giveall" | "removeall" => return bulk(ctx, msg, guild_id, subcommand, role_arg).await,
+            "add" | "remove" | "allow" | "disallow" if role_arg.is_empty() => {
+                Cow::Owned(format!("Usage: {} <role>", subcommand))
+            }
+            "add" | "remove" | "allow" | "disallow" => {
+                let Some(role_id) = find_role(ctx, guild_id, role_arg) else {
+                    return Err(PluginError::UserError(format!(
+                        "Role `{}` not found.",
+                        role_arg
+                    )));
+                };
+                match subcommand.to_lowercase().as_str() {
+                    "add" => add(ctx, msg, guild_id, role_id).await?,
+                    "remove" => remove(ctx, msg, guild_id, role_id).await?,
+                    "allow" => {
+                        acl::check(ctx, msg, "role.allow", false).await?;
+                        if !may_delegate(ctx, msg, guild_id, role_id).await {
+                            return Err(PluginError::PermissionDenied);
+                        }
+                        allow(ctx, guild_id, role_id).await?
+                    }
+                    _ => {
+                        acl::check(ctx, msg, "role.disallow", false).await?;
+                        disallow(ctx, guild_id, role_id).await?
+                    }
+                }
+            }
+            "" => Cow::Borrowed("Please provide a subcommand. See help for usage."),
+            _ => Cow::Borrowed("Unknown subcommand."),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::MANAGE_ROLES)
+    }
+}
+
+/// Find a role by mention, ID, or case-insensitive name
+fn find_role(ctx: &Context<'_>, guild_id: GuildId, arg: &str) -> Option<RoleId> {
+    if let Some(role_id) = serenity::utils::parse_role_mention(arg) {
+        return Some(role_id);
+    }
+    let guild = ctx.cache.guild(guild_id)?;
+    if let Some(role_id) = arg.parse().ok().filter(|id| guild.roles.contains_key(id)) {
+        return Some(role_id);
+    }
+    let name = arg.trim_start_matches('@');
+    guild
+        .roles
+        .values()
+        .find(|role| role.name.eq_ignore_ascii_case(name))
+        .map(|role| role.id)
+}
+
+fn role_name(ctx: &Context<'_>, guild_id: GuildId, role_id: RoleId) -> String {
+    ctx.cache
+        .guild(guild_id)
+        .and_then(|guild| guild.roles.get(&role_id).map(|role| role.name.clone()))
+        .unwrap_or_else(|| "<unknown-role>".to_string())
+}
+
+/// Whether the bot is able to grant the role per Discord's role hierarchy
+fn is_grantable(ctx: &Context<'_>, guild_id: GuildId, role_id: RoleId) -> bool {
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+    let Some(role) = guild.roles.get(&role_id) else {
+        return false;
+    };
+    if role.managed || role_id.get() == guild_id.get() {
+        return false;
+    }
+    let Some(bot) = guild.members.get(&ctx.cache.current_user().id) else {
+        return false;
+    };
+    guild
+        .member_highest_role(bot)
+        .is_some_and(|top| top.position > role.position)
+}
+
+/// Whether the author may hand out the role to others: the bot and server owners may hand out
+/// any, and anyone else only unprivileged roles below their own highest role
+async fn may_delegate(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    role_id: RoleId,
+) -> bool {
+    if msg.is_from_owner(ctx).await {
+        return true;
+    }
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+    if guild.owner_id == msg.author.id {
+        return true;
+    }
+    let Some(role) = guild.roles.get(&role_id) else {
+        return false;
+    };
+    if role.permissions.intersects(PRIVILEGED_PERMISSIONS) {
+        return false;
+    }
+    guild
+        .members
+        .get(&msg.author.id)
+        .and_then(|member| guild.member_highest_role(member))
+        .is_some_and(|top| top.position > role.position)
+}
+
+async fn is_allowed(ctx: &Context<'_>, guild_id: GuildId, role_id: RoleId) -> bool {
+    ctx.pstate
+        .read()
+        .await
+        .self_roles
+        .allowed
+        .get(&guild_id)
+        .is_some_and(|allowed| allowed.contains(&role_id))
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> Cow<'static, str> {
+    let allowed = ctx
+        .pstate
+        .read()
+        .await
+        .self_roles
+        .allowed
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default();
+    if allowed.is_empty() {
+        return Cow::Borrowed("No self-assignable roles are configured.");
+    }
+
+    let mut names: Vec<String> = allowed
+        .iter()
+        .map(|role_id| role_name(ctx, guild_id, *role_id))
+        .collect();
+    names.sort_unstable();
+
+    let mut response = String::from("Self-assignable roles:\n");
+    for name in names {
+        response.push_str(&format!("• `{}`\n", name));
+    }
+    Cow::Owned(response)
+}
+
+async fn add(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    role_id: RoleId,
+) -> Result<Cow<'static, str>> {
+    let name = role_name(ctx, guild_id, role_id);
+    if !is_allowed(ctx, guild_id, role_id).await {
+        return Ok(Cow::Owned(format!("`{}` is not self-assignable.", name)));
+    }
+    if !is_grantable(ctx, guild_id, role_id) {
+        return Ok(Cow::Owned(format!(
+            "`{}` is above my highest role, so I can't grant it.",
+            name
+        )));
+    }
+
+    ctx.http
+        .add_member_role(guild_id, msg.author.id, role_id, Some("Self-assigned role"))
+        .await?;
+    Ok(Cow::Owned(format!("You now have the `{}` role.", name)))
+}
+
+async fn remove(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    role_id: RoleId,
+) -> Result<Cow<'static, str>> {
+    let name = role_name(ctx, guild_id, role_id);
+    if !is_allowed(ctx, guild_id, role_id).await {
+        return Ok(Cow::Owned(format!("`{}` is not self-assignable.", name)));
+    }
+    if !is_grantable(ctx, guild_id, role_id) {
+        return Ok(Cow::Owned(format!(
+            "`{}` is above my highest role, so I can't remove it.",
+            name
+        )));
+    }
+
+    ctx.http
+        .remove_member_role(guild_id, msg.author.id, role_id, Some("Self-removed role"))
+        .await?;
+    Ok(Cow::Owned(format!(
+        "You no longer have the `{}` role.",
+        name
+    )))
+}
+
+async fn allow(ctx: &Context<'_>, guild_id: GuildId, role_id: RoleId) -> Result<Cow<'static, str>> {
+    let name = role_name(ctx, guild_id, role_id);
+    if !is_grantable(ctx, guild_id, role_id) {
+        return Ok(Cow::Owned(format!(
+            "`{}` is managed by an integration or above my highest role, so I can't grant it.",
+            name
+        )));
+    }
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate
+        .self_roles
+        .allowed
+        .entry(guild_id)
+        .or_default()
+        .insert(role_id);
+    pstate.save().await?;
+    Ok(Cow::Owned(format!("`{}` is now self-assignable.", name)))
+}
+
+async fn disallow(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    role_id: RoleId,
+) -> Result<Cow<'static, str>> {
+    let name = role_name(ctx, guild_id, role_id);
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let removed = pstate
+        .self_roles
+        .allowed
+        .get_mut(&guild_id)
+        .is_some_and(|allowed| allowed.remove(&role_id));
+    if !removed {
+        return Ok(Cow::Owned(format!("`{}` is not self-assignable.", name)));
+    }
+    pstate.save().await?;
+    Ok(Cow::Owned(format!(
+        "`{}` is no longer self-assignable.",
+        name
+    )))
+}
+
+/// Which members a bulk role change applies to
+#[derive(Clone, Copy)]
+enum MemberFilter {
+    All,
+    Humans,
+    Bots,
+    WithRole(RoleId),
+}
+
+impl MemberFilter {
+    fn parse(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "humans" => Some(MemberFilter::Humans),
+            "bots" => Some(MemberFilter::Bots),
+            _ => serenity::utils::parse_role_mention(arg).map(MemberFilter::WithRole),
+        }
+    }
+
+    fn matches(self, member: &Member) -> bool {
+        match self {
+            MemberFilter::All => true,
+            MemberFilter::Humans => !member.user.bot,
+            MemberFilter::Bots => member.user.bot,
+            MemberFilter::WithRole(role_id) => member.roles.contains(&role_id),
+        }
+    }
+}
+
+async fn bulk(
+    ctx: &Context<'_>,
+    msg: &Message,
+    guild_id: GuildId,
+    subcommand: &str,
+    args: &str,
+) -> Result<EventHandled> {
+    let is_admin = msg
+        .author_permissions(ctx.cache)
+        .is_some_and(|p| p.contains(Permissions::ADMINISTRATOR));
+    let capability = format!("role.{}", subcommand.to_lowercase());
+    acl::check(ctx, msg, &capability, is_admin).await?;
+
+    let give = subcommand.eq_ignore_ascii_case("giveall");
+    // A trailing filter is only recognized for `giveall`, and only if the rest names a role
+    let (role_arg, filter) = match args.rsplit_once(' ') {
+        Some((role_arg, filter_arg)) if give => match MemberFilter::parse(filter_arg) {
+            Some(filter) if find_role(ctx, guild_id, role_arg.trim()).is_some() => {
+                (role_arg.trim(), filter)
+            }
+            _ => (args, MemberFilter::All),
+        },
+        _ => (args, MemberFilter::All),
+    };
+    if role_arg.is_empty() {
+        return Err(PluginError::UserError(format!(
+            "Usage: {} <role>{}",
+            subcommand,
+            if give { " [humans/bots/@role]" } else { "" }
+        )));
+    }
+    let Some(role_id) = find_role(ctx, guild_id, role_arg) else {
+        return Err(PluginError::UserError(format!(
+            "Role `{}` not found.",
+            role_arg
+        )));
+    };
+    if !may_delegate(ctx, msg, guild_id, role_id).await {
+        return Err(PluginError::PermissionDenied);
+    }
+    let name = role_name(ctx, guild_id, role_id);
+    if !is_grantable(ctx, guild_id, role_id) {
+        return Err(PluginError::UserError(format!(
+            "`{}` is managed by an integration or above my highest role, so I can't change it.",
+            name
+        )));
+    }
+
+    let description = if give {
+        format!("give `{}` to every matching member", name)
+    } else {
+        format!("remove `{}` from every member", name)
+    };
+    let channel_id = msg.channel_id;
+    let action: confirm::Action = Box::new(move |owned| {
+        Box::pin(async move {
+            tokio::spawn(async move {
+                let ctx = owned.ctx();
+                if let Err(err) = run_bulk(&ctx, channel_id, guild_id, role_id, give, filter).await
+                {
+                    log_internal!("Bulk role change of {} failed: {}", role_id, err);
+                }
+            });
+            Ok(format!(
+                "Updating `{}` for every matching member.  Progress will be posted here.",
+                name
+            ))
+        })
+    });
+    confirm::request(ctx, msg, &description, action).await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Give or remove `role_id` to/from every member of the guild matching `filter`, posting progress
+/// in `channel_id`
+async fn run_bulk(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    guild_id: GuildId,
+    role_id: RoleId,
+    give: bool,
+    filter: MemberFilter,
+) -> Result<()> {
+    let mut members = Vec::new();
+    let mut after = None;
+    loop {
+        let page = guild_id
+            .members(ctx.http, Some(MEMBER_PAGE_SIZE), after)
+            .await?;
+        after = page.last().map(|member| member.user.id);
+        let done = (page.len() as u64) < MEMBER_PAGE_SIZE;
+        members.extend(
+            page.into_iter()
+                .filter(|member| filter.matches(member))
+                .filter(|member| member.roles.contains(&role_id) != give)
+                .map(|member| member.user.id),
+        );
+        if done {
+            break;
+        }
+    }
+
+    let name = role_name(ctx, guild_id, role_id);
+    let verb = if give { "Giving" } else { "Removing" };
+    let total = members.len();
+    let mut progress = channel_id
+        .say(ctx.cache_http, format!("{} `{}`: 0/{}", verb, name, total))
+        .await?;
+
+    let mut failed = 0;
+    for (i, user_id) in members.into_iter().enumerate() {
+        let result = if give {
+            ctx.http
+                .add_member_role(guild_id, user_id, role_id, Some("Bulk role change"))
+                .await
+        } else {
+            ctx.http
+                .remove_member_role(guild_id, user_id, role_id, Some("Bulk role change"))
+                .await
+        };
+        if let Err(err) = result {
+            log_internal!("Could not change {} for {}: {}", role_id, user_id, err);
+            failed += 1;
+        }
+        if (i + 1) % BULK_PROGRESS_EVERY == 0 {
+            progress
+                .edit(
+                    ctx.cache_http,
+                    EditMessage::new().content(format!("{} `{}`: {}/{}", verb, name, i + 1, total)),
+                )
+                .await?;
+        }
+        tokio::time::sleep(BULK_PACE).await;
+    }
+
+    let mut summary = format!("{} `{}`: done, {}/{}", verb, name, total - failed, total);
+    if failed > 0 {
+        summary.push_str(&format!(" ({} failed)", failed));
+    }
+    progress
+        .edit(ctx.cache_http, EditMessage::new().content(summary))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,311 @@
+//! Answers questions in each `[llm_rules_qa]` guild's questions channel from its rules and FAQ
+//! channels.  Messages in those channels are split into paragraphs and embedded with the `[faq]`
+//! model.  The passages most similar to a question are given to the LLM, which answers citing
+//! them, and the reply links to each.  If none is similar enough, the helper role is pinged
+//! instead.
+
+use crate::error::{PluginError, Result};
+use crate::helper::reply_in_chunks;
+use crate::llm::{self, LlmChatRequest};
+use crate::persistent_state::RulesPassage;
+use crate::{acl, event::*, log_internal, plugin::*};
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateMessage, GetMessages, GuildId, Message, Permissions,
+};
+
+/// Messages indexed per source channel by `reindex`, the most Discord returns in one request
+const MAX_MESSAGES: u8 = 100;
+/// Passages given to the LLM per question
+const MAX_PASSAGES: usize = 4;
+
+pub struct RulesQa;
+
+#[serenity::async_trait]
+impl Plugin for RulesQa {
+    fn name(&self) -> &'static str {
+        "rulesqa"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} reindex - reread the rules and FAQ channels questions are answered from (requires Manage Server)",
+            prefix,
+            self.name()
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        if let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await {
+            return handle_command(ctx, msg, args).await;
+        }
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+        if msg.author.bot {
+            return Ok(EventHandled::No);
+        }
+        let (questions_channel, is_source) = match &ctx.cfg.read().await.llm_rules_qa {
+            Some(cfg) => match cfg.guilds.get(&guild_id) {
+                Some(guild) => (
+                    guild.questions_channel,
+                    guild.source_channels.contains(&msg.channel_id),
+                ),
+                None => return Ok(EventHandled::No),
+            },
+            None => return Ok(EventHandled::No),
+        };
+
+        if is_source {
+            // Not worth failing the message over; `reindex` can catch up later
+            match passages(ctx, msg).await {
+                Ok(passages) => {
+                    let mut pstate = ctx.pstate.write().await;
+                    pstate.ensure_writable()?;
+                    pstate
+                        .rules_index
+                        .guilds
+                        .entry(guild_id)
+                        .or_default()
+                        .extend(passages);
+                    pstate.save().await?;
+                }
+                Err(err) => log_internal!("Could not index rules message: {}", err),
+            }
+            return Ok(EventHandled::No);
+        }
+        if msg.channel_id == questions_channel {
+            return answer(ctx, guild_id, msg).await;
+        }
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn handle_command(ctx: &Context<'_>, msg: &Message, args: &str) -> Result<EventHandled> {
+    acl::check(ctx, msg, "rulesqa", true).await?;
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(EventHandled::No);
+    };
+    if args.trim() != "reindex" {
+        return Err(PluginError::UserError(
+            "Invalid command.  See help for usage.".to_string(),
+        ));
+    }
+    let permitted = msg
+        .author_permissions(ctx.cache)
+        .is_some_and(|p| p.contains(Permissions::MANAGE_GUILD));
+    acl::check(ctx, msg, "rulesqa.reindex", permitted).await?;
+
+    let channels = ctx
+        .cfg
+        .read()
+        .await
+        .llm_rules_qa
+        .as_ref()
+        .and_then(|cfg| cfg.guilds.get(&guild_id))
+        .map(|guild| guild.source_channels.clone());
+    let Some(channels) = channels else {
+        return Err(PluginError::UserError(
+            "Rules questions aren't set up for this server in `[llm_rules_qa]`.".to_string(),
+        ));
+    };
+
+    let typing = msg.channel_id.start_typing(ctx.http);
+    let indexed = reindex(ctx, &channels).await;
+    typing.stop();
+    let indexed = indexed?;
+    let count = indexed.len();
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate.rules_index.guilds.insert(guild_id, indexed);
+    pstate.save().await?;
+    drop(pstate);
+
+    msg.reply(
+        ctx.cache_http,
+        format!(
+            "Indexed {} passages from {} channels.",
+            count,
+            channels.len()
+        ),
+    )
+    .await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Passages of the recent messages in `channels`, oldest first
+async fn reindex(ctx: &Context<'_>, channels: &[ChannelId]) -> Result<Vec<RulesPassage>> {
+    let mut indexed = Vec::new();
+    for channel_id in channels {
+        let messages = channel_id
+            .messages(ctx.cache_http, GetMessages::new().limit(MAX_MESSAGES))
+            .await?;
+        for message in messages.iter().rev() {
+            indexed.extend(passages(ctx, message).await.map_err(PluginError::llm)?);
+        }
+    }
+    Ok(indexed)
+}
+
+/// `msg` split into paragraphs, each embedded
+async fn passages(ctx: &Context<'_>, msg: &Message) -> anyhow::Result<Vec<RulesPassage>> {
+    let mut passages = Vec::new();
+    for text in msg
+        .content
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+    {
+        passages.push(RulesPassage {
+            channel_id: msg.channel_id,
+            message_id: msg.id,
+            text: text.to_string(),
+            embedding: llm::embed(ctx, text).await?,
+        });
+    }
+    Ok(passages)
+}
+
+/// Answer `msg` from the most relevant passages, or ping the helper role if none are relevant
+async fn answer(ctx: &Context<'_>, guild_id: GuildId, msg: &Message) -> Result<EventHandled> {
+    {
+        let pstate = ctx.pstate.read().await;
+        // Users who opted out of LLM features don't have their messages embedded either
+        if pstate.llm_optout.users.contains(&msg.author.id)
+            || pstate
+                .rules_index
+                .guilds
+                .get(&guild_id)
+                .is_none_or(|passages| passages.is_empty())
+        {
+            return Ok(EventHandled::No);
+        }
+    }
+
+    let typing = msg.channel_id.start_typing(ctx.http);
+    let response = grounded_answer(ctx, guild_id, msg).await;
+    typing.stop();
+    if let Some(response) = response? {
+        reply_in_chunks(ctx, msg, &response).await?;
+        return Ok(EventHandled::Yes);
+    }
+
+    let helper_role = ctx
+        .cfg
+        .read()
+        .await
+        .llm_rules_qa
+        .as_ref()
+        .and_then(|cfg| cfg.guilds.get(&guild_id))
+        .and_then(|guild| guild.helper_role);
+    let (content, mentions) = match helper_role {
+        Some(role_id) => (
+            format!(
+                "I couldn't find that in the server's rules or FAQ.  <@&{}>, can you help?",
+                role_id
+            ),
+            CreateAllowedMentions::new().roles(vec![role_id]),
+        ),
+        None => (
+            "I couldn't find that in the server's rules or FAQ.".to_string(),
+            CreateAllowedMentions::new(),
+        ),
+    };
+    msg.channel_id
+        .send_message(
+            ctx.cache_http,
+            CreateMessage::new()
+                .content(content)
+                .allowed_mentions(mentions)
+                .reference_message(msg),
+        )
+        .await?;
+    Ok(EventHandled::Yes)
+}
+
+/// The LLM's answer from the passages relevant to `msg`, followed by links to them, or None if
+/// none are relevant enough
+async fn grounded_answer(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    msg: &Message,
+) -> Result<Option<String>> {
+    let embedding = llm::embed(ctx, &msg.content)
+        .await
+        .map_err(PluginError::llm)?;
+    let Some(threshold) = ctx
+        .cfg
+        .read()
+        .await
+        .llm_rules_qa
+        .as_ref()
+        .map(|cfg| cfg.threshold)
+    else {
+        return Ok(None);
+    };
+
+    let relevant: Vec<RulesPassage> = {
+        let pstate = ctx.pstate.read().await;
+        let mut scored: Vec<_> = pstate
+            .rules_index
+            .guilds
+            .get(&guild_id)
+            .into_iter()
+            .flatten()
+            .map(|passage| (llm::similarity(&embedding, &passage.embedding), passage))
+            .filter(|(similarity, _)| *similarity >= threshold)
+            .collect();
+        scored.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        scored
+            .into_iter()
+            .take(MAX_PASSAGES)
+            .map(|(_, passage)| passage.clone())
+            .collect()
+    };
+    if relevant.is_empty() {
+        return Ok(None);
+    }
+
+    let mut content = String::from("Passages:\n");
+    for (i, passage) in relevant.iter().enumerate() {
+        content.push_str(&format!("[{}] {}\n\n", i + 1, passage.text));
+    }
+    content.push_str(&format!("Question: {}", msg.content));
+    let request = {
+        let cfg = ctx.cfg.read().await;
+        let Some(rules_cfg) = cfg.llm_rules_qa.as_ref() else {
+            return Ok(None);
+        };
+        LlmChatRequest::from_prompt(&rules_cfg.as_llm_settings(), content)
+    };
+    let mut response = request.post(ctx).await.map_err(PluginError::llm)?;
+
+    let sources: Vec<String> = relevant
+        .iter()
+        .enumerate()
+        .map(|(i, passage)| {
+            format!(
+                "[{}](<{}>)",
+                i + 1,
+                passage.message_id.link(passage.channel_id, Some(guild_id))
+            )
+        })
+        .collect();
+    response.push_str(&format!("\n\n**Sources:** {}", sources.join(" ")));
+    Ok(Some(response))
+}
@@ -0,0 +1,219 @@
+//! Scheduled announcements
+//!
+//! Server managers may schedule messages to be posted to a channel on a cron-like schedule.  The
+//! schedules themselves are evaluated by `scheduler.rs`.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{discord_timestamp, TimestampStyle};
+use crate::persistent_state::{ScheduleEntry, ScheduledAction};
+use crate::{acl, event::*, plugin::*, scheduler};
+use serenity::all::{GuildId, Message, Permissions};
+
+pub struct Schedule;
+
+#[serenity::async_trait]
+impl Plugin for Schedule {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Notifications
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}schedule <subcommand> -- scheduled announcements\n\
+             | Subcommands:\n\
+             | add \"<cron>\" [timezone] <#channel> <message> - post a message on a schedule, e.g.\n\
+             |     add \"0 9 * * MON\" America/New_York #general Weekly standup!\n\
+             | list - list this server's schedules\n\
+             | remove <id> - remove a schedule",
+            prefix
+        ))
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(scheduler::run(ctx))))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            msg.reply(ctx.cache_http, "Schedules only work within a server")
+                .await?;
+            return Ok(EventHandled::Yes);
+        };
+
+        let args = args.trim();
+        let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let rest = rest.trim();
+
+        let response = match subcommand.to_lowercase().as_str() {
+            "list" => list(ctx, guild_id).await,
+            "add" => {
+                check_permission(ctx, msg, "add").await?;
+                add(ctx, msg, guild_id, rest).await?
+            }
+            "remove" => {
+                check_permission(ctx, msg, "remove").await?;
+                remove(ctx, guild_id, rest).await?
+            }
+            "" => "Please provide a subcommand. See help for usage.".to_string(),
+            _ => "Unknown subcommand.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn check_permission(ctx: &Context<'_>, msg: &Message, subcommand: &str) -> Result<()> {
+    let permitted = msg
+        .author_permissions(ctx.cache)
+        .is_some_and(|p| p.contains(Permissions::MANAGE_GUILD));
+    acl::check(ctx, msg, &format!("schedule.{}", subcommand), permitted).await
+}
+
+async fn add(ctx: &Context<'_>, msg: &Message, guild_id: GuildId, args: &str) -> Result<String> {
+    let usage = || {
+        PluginError::UserError("Usage: add \"<cron>\" [timezone] <#channel> <message>".to_string())
+    };
+
+    let Some((cron, rest)) = args.strip_prefix('"').and_then(|args| args.split_once('"')) else {
+        return Err(usage());
+    };
+    if let Err(err) = scheduler::Cron::parse(cron) {
+        return Err(PluginError::UserError(format!("Invalid schedule: {}", err)));
+    }
+
+    let mut words = rest.split_whitespace().peekable();
+    let timezone = match words.peek() {
+        Some(word) if serenity::utils::parse_channel_mention(word).is_none() => {
+            let timezone = words.next().unwrap_or_default().to_string();
+            if let Err(err) = scheduler::parse_timezone(&timezone) {
+                return Err(PluginError::UserError(err.to_string()));
+            }
+            timezone
+        }
+        _ => ctx
+            .cfg
+            .read()
+            .await
+            .scheduler
+            .as_ref()
+            .map(|s| s.default_timezone.clone())
+            .unwrap_or_else(|| "UTC".to_string()),
+    };
+
+    let Some(channel_id) = words
+        .next()
+        .and_then(serenity::utils::parse_channel_mention)
+    else {
+        return Err(usage());
+    };
+    let message = words.collect::<Vec<_>>().join(" ");
+    if message.is_empty() {
+        return Err(usage());
+    }
+    if !ctx
+        .cache
+        .guild(guild_id)
+        .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+    {
+        return Err(PluginError::UserError(
+            "That channel isn't in this server.".to_string(),
+        ));
+    }
+
+    let entry = ScheduleEntry {
+        id: 0,
+        guild_id,
+        creator_id: msg.author.id,
+        cron: cron.to_string(),
+        timezone,
+        action: ScheduledAction::Post {
+            channel_id,
+            message,
+        },
+    };
+    let next = describe_next_run(&entry);
+
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let id = pstate.schedules.add(entry);
+    pstate.save().await?;
+    Ok(format!("Added schedule #{}.  {}", id, next))
+}
+
+async fn remove(ctx: &Context<'_>, guild_id: GuildId, args: &str) -> Result<String> {
+    let Ok(id) = args.parse::<u64>() else {
+        return Err(PluginError::UserError("Usage: remove <id>".to_string()));
+    };
+
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let entries = &mut pstate.schedules.entries;
+    let len = entries.len();
+    entries.retain(|entry| entry.id != id || entry.guild_id != guild_id);
+    if entries.len() == len {
+        return Err(PluginError::UserError(format!("No schedule #{}.", id)));
+    }
+    pstate.save().await?;
+    Ok(format!("Removed schedule #{}.", id))
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let pstate = ctx.pstate.read().await;
+    let entries: Vec<&ScheduleEntry> = pstate
+        .schedules
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .collect();
+    if entries.is_empty() {
+        return "No schedules are configured.".to_string();
+    }
+
+    let mut response = String::from("Schedules:\n");
+    for entry in entries {
+        let ScheduledAction::Post {
+            channel_id,
+            message,
+        } = &entry.action;
+        response.push_str(&format!(
+            "• #{} `{}` ({}) in <#{}>: {}  {}\n",
+            entry.id,
+            entry.cron,
+            entry.timezone,
+            channel_id,
+            message,
+            describe_next_run(entry),
+        ));
+    }
+    response
+}
+
+fn describe_next_run(entry: &ScheduleEntry) -> String {
+    match scheduler::next_run(entry) {
+        Ok(Some(next)) => format!(
+            "Next run {}.",
+            discord_timestamp(next, TimestampStyle::Relative)
+        ),
+        Ok(None) => "It will never run.".to_string(),
+        Err(err) => format!("Invalid: {}", err),
+    }
+}
@@ -0,0 +1,169 @@
+//! Checks on startup that the bot's dependencies work, and posts the results to the log channel.
+//!
+//! Covers the LLM endpoint, writing `state.toml`, and that configured channels exist and grant
+//! the bot the permissions its plugins need.  A failing LLM is marked degraded, like any failed
+//! request.
+
+use crate::config::LlmOptions;
+use crate::error::Result;
+use crate::llm::{LlmChatRequest, LlmSettings};
+use crate::{event::*, log_internal, plugin::*};
+use serenity::all::{ChannelId, Permissions};
+use std::time::Duration;
+
+const LLM_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct SelfTest;
+
+#[serenity::async_trait]
+impl Plugin for SelfTest {
+    fn name(&self) -> &'static str {
+        "self_test"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        if ctx.ctx().cfg.read().await.self_test.is_none() {
+            return Ok(None);
+        }
+
+        // The LLM may be slow to answer; don't hold up other plugins starting.
+        Ok(Some(tokio::spawn(async move {
+            let ctx = ctx.ctx();
+            let results = run(&ctx).await;
+            if let Err(err) = post_results(&ctx, &results).await {
+                log_internal!("Could not post self-test results: {}", err);
+            }
+        })))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+}
+
+/// Outcome of each check, as whether it passed and a description
+async fn run(ctx: &Context<'_>) -> Vec<(bool, String)> {
+    let mut results = vec![check_llm(ctx).await, check_state(ctx).await];
+
+    let channels = match &ctx.cfg.read().await.self_test {
+        Some(cfg) => cfg.channels.clone(),
+        None => Vec::new(),
+    };
+    let required = crate::plugin::plugins()
+        .iter()
+        .fold(Permissions::empty(), |all, plugin| {
+            all | plugin.required_permissions()
+        });
+    for channel_id in channels {
+        results.push(check_channel(ctx, channel_id, required).await);
+    }
+
+    for (passed, description) in &results {
+        log_internal!(
+            "Self-test {}: {}",
+            if *passed { "passed" } else { "FAILED" },
+            description
+        );
+    }
+    results
+}
+
+async fn check_llm(ctx: &Context<'_>) -> (bool, String) {
+    let model_name = ctx.cfg.read().await.llm_reply.model_name.clone();
+    let settings = LlmSettings {
+        model_name: &model_name,
+        system: "You are a health check.  Reply with only the word OK.",
+        context_size: 256,
+        temperature: 0.0,
+        vision: false,
+        options: &LlmOptions::default(),
+    };
+    let start = std::time::Instant::now();
+    let request = LlmChatRequest::from_prompt(&settings, "Are you there?".to_string());
+    match tokio::time::timeout(LLM_TIMEOUT, request.post(ctx)).await {
+        Ok(Ok(_)) => (
+            true,
+            format!("LLM responded in {} ms", start.elapsed().as_millis()),
+        ),
+        Ok(Err(err)) => (false, format!("LLM request failed: `{}`", err)),
+        Err(_) => (
+            false,
+            format!("LLM did not respond within {}s", LLM_TIMEOUT.as_secs()),
+        ),
+    }
+}
+
+async fn check_state(ctx: &Context<'_>) -> (bool, String) {
+    match ctx.pstate.read().await.save().await {
+        Ok(()) => (true, "State is writable".to_string()),
+        Err(err) => (false, format!("Could not write state: `{}`", err)),
+    }
+}
+
+async fn check_channel(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    required: Permissions,
+) -> (bool, String) {
+    let channel = match channel_id.to_channel(ctx.cache_http).await {
+        Ok(channel) => channel,
+        Err(err) => {
+            return (
+                false,
+                format!("Channel {} not found: `{}`", channel_id, err),
+            )
+        }
+    };
+    let Some(channel) = channel.guild() else {
+        return (true, format!("<#{}> exists", channel_id));
+    };
+
+    let bot_permissions = ctx.cache.guild(channel.guild_id).and_then(|guild| {
+        let bot = guild.members.get(&ctx.cache.current_user().id)?;
+        Some(guild.user_permissions_in(&channel, bot))
+    });
+    match bot_permissions {
+        Some(permissions) if (required - permissions).is_empty() => (
+            true,
+            format!("<#{}> has all permissions needed", channel_id),
+        ),
+        Some(permissions) => (
+            false,
+            format!(
+                "<#{}> lacks permissions: {}",
+                channel_id,
+                required - permissions
+            ),
+        ),
+        None => (
+            false,
+            format!("Could not determine permissions in <#{}>", channel_id),
+        ),
+    }
+}
+
+async fn post_results(ctx: &Context<'_>, results: &[(bool, String)]) -> anyhow::Result<()> {
+    let channel = {
+        let cfg = ctx.cfg.read().await;
+        let log_channel = cfg.self_test.as_ref().and_then(|cfg| cfg.log_channel);
+        log_channel.or(cfg.error_reports.as_ref().and_then(|cfg| cfg.channel))
+    };
+    let Some(channel) = channel else {
+        return Ok(());
+    };
+
+    let mut content = String::from("Startup self-test:");
+    for (passed, description) in results {
+        content.push_str(&format!(
+            "\n{} {}",
+            if *passed { "✅" } else { "❌" },
+            description
+        ));
+    }
+    channel.say(ctx.cache_http, content).await?;
+    Ok(())
+}
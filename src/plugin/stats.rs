@@ -0,0 +1,191 @@
+use crate::error::Result;
+use crate::persistent_state::{PersistentState, ReactedMessage};
+use crate::{event::*, plugin::*};
+use serenity::all::{
+    ChannelId, EmojiId, Message, Reaction, ReactionType, RoleId, StickerId, Timestamp,
+};
+use std::time::Duration;
+
+/// Don't rewrite the state file on every message; activity this recent may be lost on restart.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long messages' reactions are tracked, for the weekly highlight
+const REACTION_WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Records message and voice activity per channel and role, reactions received per user, and uses of
+/// each guild's custom emoji and stickers
+pub struct Stats;
+
+#[serenity::async_trait]
+impl Plugin for Stats {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let (channel_id, roles, is_message): (ChannelId, &[RoleId], bool) = match event {
+            // Passive plugins run regardless of `ignore_bots`
+            Event::Message(msg) if msg.author.bot => return Ok(EventHandled::No),
+            Event::Message(msg) => {
+                record_message_usage(ctx, msg).await?;
+                let roles = msg.member.as_ref().map(|m| m.roles.as_slice());
+                (msg.channel_id, roles.unwrap_or_default(), true)
+            }
+            Event::VoiceStateUpdate { old, new } => {
+                let Some(channel_id) = new.channel_id else {
+                    return Ok(EventHandled::No);
+                };
+                // Only count joins, not mutes/unmutes within the channel
+                if old.as_ref().and_then(|o| o.channel_id) == Some(channel_id) {
+                    return Ok(EventHandled::No);
+                }
+                let roles = new.member.as_ref().map(|m| m.roles.as_slice());
+                (channel_id, roles.unwrap_or_default(), false)
+            }
+            Event::ReactionAdd(reaction) => {
+                record_reaction(ctx, reaction, true).await?;
+                return Ok(EventHandled::No);
+            }
+            Event::ReactionRemove(reaction) => {
+                record_reaction(ctx, reaction, false).await?;
+                return Ok(EventHandled::No);
+            }
+            _ => return Ok(EventHandled::No),
+        };
+
+        let now = Timestamp::now().unix_timestamp();
+        let mut pstate = ctx.pstate.write().await;
+        let stats = &mut pstate.stats;
+
+        let channel_stats = stats.channels.entry(channel_id).or_default();
+        channel_stats.last_active = now;
+        if is_message {
+            channel_stats.messages += 1;
+        } else {
+            channel_stats.voice_joins += 1;
+        }
+        for role_id in roles {
+            stats.roles.entry(*role_id).or_default().last_active = now;
+        }
+
+        save_throttled(ctx, &pstate).await?;
+        Ok(EventHandled::No)
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Run
+    }
+}
+
+async fn record_reaction(ctx: &Context<'_>, reaction: &Reaction, added: bool) -> Result<()> {
+    let (Some(guild_id), Some(reactor)) = (reaction.guild_id, reaction.user_id) else {
+        return Ok(());
+    };
+
+    let guild_emoji = match &reaction.emoji {
+        ReactionType::Custom { id, .. } => ctx
+            .cache
+            .guild(guild_id)
+            .filter(|guild| guild.emojis.contains_key(id))
+            .map(|_| *id),
+        _ => None,
+    };
+
+    let mut pstate = ctx.pstate.write().await;
+    if let (true, Some(emoji_id)) = (added, guild_emoji) {
+        pstate
+            .stats
+            .emoji
+            .entry(guild_id)
+            .or_default()
+            .entry(emoji_id)
+            .or_default()
+            .record(Timestamp::now().unix_timestamp());
+    }
+    let reactions = &mut pstate.reactions;
+    if added {
+        // Only provided on add.  Removals are matched against the tracked message.
+        let Some(author_id) = reaction.message_author_id else {
+            return Ok(());
+        };
+        reactions.remove_older_than(Timestamp::now().unix_timestamp() - REACTION_WINDOW_SECS);
+        let message = ReactedMessage {
+            guild_id,
+            channel_id: reaction.channel_id,
+            author_id,
+            count: 0,
+        };
+        reactions.add(reaction.message_id, message, reactor);
+    } else {
+        reactions.remove(reaction.message_id, reactor);
+    }
+
+    save_throttled(ctx, &pstate).await
+}
+
+/// Count uses of the guild's own custom emoji and stickers, for `;emojistats`
+async fn record_message_usage(ctx: &Context<'_>, msg: &Message) -> Result<()> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+    let (emoji, stickers): (Vec<EmojiId>, Vec<StickerId>) = {
+        let Some(guild) = ctx.cache.guild(guild_id) else {
+            return Ok(());
+        };
+        let emoji = custom_emoji(&msg.content)
+            .filter(|id| guild.emojis.contains_key(id))
+            .collect();
+        let stickers = msg
+            .sticker_items
+            .iter()
+            .map(|sticker| sticker.id)
+            .filter(|id| guild.stickers.contains_key(id))
+            .collect();
+        (emoji, stickers)
+    };
+    if emoji.is_empty() && stickers.is_empty() {
+        return Ok(());
+    }
+
+    let now = Timestamp::now().unix_timestamp();
+    let mut pstate = ctx.pstate.write().await;
+    let stats = &mut pstate.stats;
+    for id in emoji {
+        let uses = stats.emoji.entry(guild_id).or_default().entry(id);
+        uses.or_default().record(now);
+    }
+    for id in stickers {
+        let uses = stats.stickers.entry(guild_id).or_default().entry(id);
+        uses.or_default().record(now);
+    }
+    Ok(())
+}
+
+/// IDs of the custom emoji in message content, e.g. `<:name:123>`
+fn custom_emoji(content: &str) -> impl Iterator<Item = EmojiId> + '_ {
+    content.match_indices('<').filter_map(|(start, _)| {
+        let len = content[start..].find('>')?;
+        serenity::utils::parse_emoji(&content[start..=start + len]).map(|emoji| emoji.id)
+    })
+}
+
+async fn save_throttled(ctx: &Context<'_>, pstate: &PersistentState) -> Result<()> {
+    let mut vstate = ctx.vstate.write().await;
+    if vstate
+        .stats_saved
+        .is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL)
+    {
+        vstate.stats_saved = Some(tokio::time::Instant::now());
+        drop(vstate);
+        pstate.save().await?;
+    }
+    Ok(())
+}
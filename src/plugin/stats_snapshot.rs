@@ -0,0 +1,112 @@
+//! Writes a small JSON snapshot of the bot's activity to `stats.json` in the state directory every
+//! `[stats_snapshot]` interval, for deployments which don't run Prometheus.  Scripts and
+//! dashboards can read it without scraping or pinging anything.
+
+use crate::error::{Result, Service};
+use crate::persistent_state::PersistentState;
+use crate::{event::*, log_internal, plugin::*};
+use anyhow::anyhow;
+use serenity::all::Timestamp;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// How often to check whether snapshots have been configured, when they aren't
+const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct StatsSnapshot;
+
+#[derive(serde::Serialize)]
+struct Snapshot {
+    /// Unix seconds
+    written_at: i64,
+    uptime_seconds: u64,
+    guilds: usize,
+    events: u64,
+    /// Events each plugin handled or failed on
+    handled: BTreeMap<&'static str, u64>,
+    /// Percentiles of recent LLM chat round trips, absent if there haven't been any
+    llm_latency_ms: BTreeMap<&'static str, u128>,
+    /// Backends which have recently failed
+    degraded: Vec<String>,
+}
+
+#[serenity::async_trait]
+impl Plugin for StatsSnapshot {
+    fn name(&self) -> &'static str {
+        "stats_snapshot"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            loop {
+                let ctx = ctx.ctx();
+                let interval = ctx
+                    .cfg
+                    .read()
+                    .await
+                    .stats_snapshot
+                    .as_ref()
+                    .map(|snapshot| Duration::from_secs(snapshot.interval_seconds.max(1)));
+                let Some(interval) = interval else {
+                    tokio::time::sleep(IDLE_INTERVAL).await;
+                    continue;
+                };
+                if let Err(err) = write(&ctx).await {
+                    log_internal!("Error writing stats snapshot: {}", err);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+}
+
+async fn write(ctx: &Context<'_>) -> anyhow::Result<()> {
+    let snapshot = {
+        let vstate = ctx.vstate.read().await;
+        let metrics = &vstate.metrics;
+        Snapshot {
+            written_at: Timestamp::now().unix_timestamp(),
+            uptime_seconds: vstate.started.elapsed().as_secs(),
+            guilds: ctx.cache.guild_count(),
+            events: metrics.events,
+            handled: metrics.handled.iter().map(|(&k, &v)| (k, v)).collect(),
+            llm_latency_ms: [("p50", 50), ("p90", 90), ("p99", 99)]
+                .into_iter()
+                .filter_map(|(name, percentile)| {
+                    let latency = metrics.llm_latency_percentile(percentile)?;
+                    Some((name, latency.as_millis()))
+                })
+                .collect(),
+            degraded: Service::MONITORED
+                .into_iter()
+                .filter(|service| vstate.degraded.get(*service).is_some())
+                .map(|service| service.to_string())
+                .collect(),
+        }
+    };
+
+    let path = PersistentState::config_path()?.with_file_name("stats.json");
+    let tmp_path = path.with_extension("json.new");
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    tokio::fs::write(&tmp_path, json)
+        .await
+        .map_err(|e| anyhow!("Could not write `{}`: {}", tmp_path.to_string_lossy(), e))?;
+    // Readers never see a partly written file
+    tokio::fs::rename(&tmp_path, &path).await.map_err(|e| {
+        anyhow!(
+            "Could not rename `{}` to `{}`: {}",
+            tmp_path.to_string_lossy(),
+            path.to_string_lossy(),
+            e
+        )
+    })?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::error::Result;
+use crate::helper::UserIdHelper;
+use crate::{acl, event::*, health::Health, plugin::*};
+use serenity::all::Permissions;
+
+/// Reports the health of the bot and its backends
+pub struct Status;
+
+#[serenity::async_trait]
+impl Plugin for Status {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} - show uptime, latency, and whether the bot's backends are working",
+            prefix,
+            self.name()
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, _)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let typing = msg.channel_id.start_typing(ctx.http);
+        let health = Health::check(ctx).await;
+        typing.stop();
+
+        let uptime = health.uptime_seconds;
+        let mut response = format!(
+            "Status:\n\
+             • Uptime: {}d {}h {}m\n\
+             • Gateway latency: {}\n\
+             • Servers: {}\n\
+             • LLM endpoint: {}\n\
+             • Memory: {}\n",
+            uptime / 86400,
+            uptime % 86400 / 3600,
+            uptime % 3600 / 60,
+            match health.gateway_latency_ms {
+                Some(ms) => format!("{} ms", ms),
+                None => "unknown".to_string(),
+            },
+            health.guilds,
+            match &health.llm_ping_ms {
+                Ok(ms) => format!("reachable ({} ms)", ms),
+                Err(err) => format!("unreachable (`{}`)", err),
+            },
+            match health.memory_bytes {
+                Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+                None => "unknown".to_string(),
+            },
+        );
+        for (service, err) in &health.degraded {
+            response.push_str(&format!("• {} backend degraded: `{}`\n", service, err));
+        }
+        let llm_fallbacks = ctx.vstate.read().await.metrics.llm_fallbacks;
+        if llm_fallbacks > 0 {
+            response.push_str(&format!(
+                "• LLM fallback replies since startup: {}\n",
+                llm_fallbacks
+            ));
+        }
+        let llm_cache_hits = ctx.vstate.read().await.metrics.llm_cache_hits;
+        if llm_cache_hits > 0 {
+            response.push_str(&format!(
+                "• LLM cache hits since startup: {}\n",
+                llm_cache_hits
+            ));
+        }
+        let maintenance = ctx
+            .vstate
+            .read()
+            .await
+            .maintenance
+            .as_ref()
+            .map(|m| (m.since.elapsed().as_secs() / 60, m.by));
+        if let Some((minutes, by)) = maintenance {
+            let by = by.nick_in_guild(ctx, msg.guild_id).await;
+            response.push_str(&format!(
+                "• Maintenance mode for {}m, turned on by {}\n",
+                minutes, by
+            ));
+        }
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
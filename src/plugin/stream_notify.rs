@@ -0,0 +1,158 @@
+use crate::error::Result;
+use crate::helper::UserIdHelper;
+use crate::{acl, event::*, plugin::*};
+use anyhow::anyhow;
+use serenity::all::{ActivityType, Message, Permissions, VoiceState};
+use std::borrow::Cow;
+
+/// Announces when an opted-in member starts streaming ("going live") in a voice channel.
+pub struct StreamNotify;
+
+#[serenity::async_trait]
+impl Plugin for StreamNotify {
+    fn name(&self) -> &'static str {
+        "stream-notify"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some("stream-notify")
+    }
+
+    fn category(&self) -> Category {
+        Category::Notifications
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <optin/optout> - announce when you start streaming in a voice channel",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        match event {
+            Event::Message(msg) => handle_message(ctx, msg).await,
+            Event::VoiceStateUpdate { old, new } => handle_voice_state_update(ctx, old, new).await,
+            _ => Ok(EventHandled::No),
+        }
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+async fn handle_message(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    let cmd_prefix = &ctx.cfg.read().await.general.command_prefix;
+
+    let terms: Vec<&str> = msg.content.split_whitespace().collect();
+    if terms.first().and_then(|cmd| cmd.strip_prefix(cmd_prefix)) != Some("stream-notify") {
+        return Ok(EventHandled::No);
+    }
+    acl::check(ctx, msg, "stream-notify", true).await?;
+
+    let id = msg.author.id;
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let streamers = &mut pstate.stream_notify.streamers;
+    let opted_in = streamers.contains(&id);
+
+    let response = match (terms.get(1), opted_in) {
+        (Some(&"optin"), true) => Cow::Borrowed("Your streams are already being announced"),
+        (Some(&"optin"), false) => {
+            streamers.insert(id);
+            pstate.save().await?;
+            Cow::Borrowed("Your streams will now be announced")
+        }
+        (Some(&"optout"), true) => {
+            streamers.remove(&id);
+            pstate.save().await?;
+            Cow::Borrowed("Your streams will no longer be announced")
+        }
+        (Some(&"optout"), false) => Cow::Borrowed("Your streams are not being announced"),
+        _ => Cow::Owned(format!("Invalid command.  See `{}help`", cmd_prefix)),
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn handle_voice_state_update(
+    ctx: &Context<'_>,
+    old: &Option<VoiceState>,
+    new: &VoiceState,
+) -> Result<EventHandled> {
+    // Only care about the transition into streaming
+    let was_streaming = old.as_ref().and_then(|o| o.self_stream).unwrap_or(false);
+    let is_streaming = new.self_stream.unwrap_or(false);
+    if was_streaming || !is_streaming {
+        return Ok(EventHandled::No);
+    }
+
+    if !ctx
+        .pstate
+        .read()
+        .await
+        .stream_notify
+        .streamers
+        .contains(&new.user_id)
+    {
+        return Ok(EventHandled::No);
+    }
+
+    let guild_id = new.guild_id.ok_or(anyhow!("unable to get guild_id"))?;
+    let announce_channel = match &ctx.cfg.read().await.stream_notify {
+        Some(stream_notify) => stream_notify.announce_channels.get(&guild_id).cloned(),
+        None => None,
+    };
+    let Some(announce_channel) = announce_channel else {
+        return Ok(EventHandled::No);
+    };
+
+    // Don't announce the same streamer too often, e.g. if they restart their stream
+    {
+        let timestamps = &mut ctx.vstate.write().await.stream_notify_timestamp;
+        if !timestamps.okay_to_notify(ctx, new.user_id).await {
+            return Ok(EventHandled::No);
+        }
+        timestamps.update_notify_timestamp(new.user_id).await;
+    }
+
+    // Mention what they're playing if their presence is available
+    let activity = ctx.cache.guild(guild_id).and_then(|guild| {
+        guild
+            .presences
+            .get(&new.user_id)?
+            .activities
+            .iter()
+            .find(|activity| activity.kind == ActivityType::Playing)
+            .map(|activity| activity.name.clone())
+    });
+
+    let streamer_name = new.user_id.nick_in_guild(ctx, Some(guild_id)).await;
+    let channel_name = new
+        .channel_id
+        .map(|id| format!("<#{}>", id))
+        .unwrap_or("a VC channel".to_string());
+    let content = match activity {
+        Some(activity) => format!(
+            "{} started streaming {} in {}.  Come watch!",
+            streamer_name, activity, channel_name
+        ),
+        None => format!(
+            "{} started streaming in {}.  Come watch!",
+            streamer_name, channel_name
+        ),
+    };
+
+    announce_channel.say(ctx.cache_http, content).await?;
+
+    // Other plugins might also want to act on this event.
+    Ok(EventHandled::No)
+}
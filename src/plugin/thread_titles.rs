@@ -0,0 +1,181 @@
+//! Summarizes threads which have gone quiet and renames them to a descriptive title, so archived
+//! thread lists are searchable.  See `[thread_titles]`.
+
+use crate::error::Result;
+use crate::helper::{truncate, MessageHelper, UserHelper};
+use crate::llm::{LlmChatRequest, LlmSettings};
+use crate::{context::OwnedContext, event::*, log_internal, plugin::*};
+use serenity::all::{EditThread, GetMessages, GuildChannel, GuildId, Permissions, Timestamp};
+use std::time::Duration;
+
+/// How often to check for inactive threads
+const POLL_INTERVAL: Duration = Duration::from_secs(300);
+/// Most recent messages read from a thread
+const MAX_MESSAGES: u8 = 50;
+/// Threads with fewer messages aren't worth summarizing
+const MIN_MESSAGES: usize = 3;
+/// Discord's limit on thread names
+const TITLE_MAX_LEN: usize = 100;
+
+const SUMMARY_SYSTEM: &str =
+    "Summarize the following Discord thread in one sentence.  Reply with only the summary.";
+const TITLE_SYSTEM: &str = "Write a short, descriptive title, at most eight words, for the \
+     following Discord thread.  Reply with only the title, without quotes.";
+
+pub struct ThreadTitles;
+
+#[serenity::async_trait]
+impl Plugin for ThreadTitles {
+    fn name(&self) -> &'static str {
+        "thread_titles"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(poll_loop(ctx))))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::SEND_MESSAGES_IN_THREADS
+            .union(Permissions::READ_MESSAGE_HISTORY)
+            .union(Permissions::MANAGE_THREADS)
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+}
+
+async fn poll_loop(owned: OwnedContext) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let ctx = owned.ctx();
+        let Some(inactive_minutes) = ctx
+            .cfg
+            .read()
+            .await
+            .thread_titles
+            .as_ref()
+            .map(|cfg| cfg.inactive_minutes)
+        else {
+            continue;
+        };
+        let cutoff = Timestamp::now().unix_timestamp() - (inactive_minutes * 60) as i64;
+
+        for guild_id in ctx.cache.guilds() {
+            if let Err(err) = poll_guild(&ctx, guild_id, cutoff).await {
+                log_internal!("Could not check threads in {}: {}", guild_id, err);
+            }
+        }
+    }
+}
+
+/// Summarize the guild's active threads which have been inactive since `cutoff` (unix seconds)
+async fn poll_guild(ctx: &Context<'_>, guild_id: GuildId, cutoff: i64) -> Result<()> {
+    let threads = guild_id.get_active_threads(ctx.http).await?.threads;
+
+    let due: Vec<GuildChannel> = {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        // Forget threads which have since archived
+        let before = pstate.thread_titles.summarized.len();
+        pstate
+            .thread_titles
+            .summarized
+            .retain(|id, _| threads.iter().any(|thread| thread.id == *id));
+        if pstate.thread_titles.summarized.len() != before {
+            pstate.save().await?;
+        }
+
+        threads
+            .into_iter()
+            .filter(|thread| {
+                let Some(last) = thread.last_message_id else {
+                    return false;
+                };
+                let quiet = [Some(thread.id), thread.parent_id]
+                    .into_iter()
+                    .flatten()
+                    .any(|id| pstate.quiet.get(id).is_some());
+                last.created_at().unix_timestamp() < cutoff
+                    && !quiet
+                    && pstate.thread_titles.summarized.get(&thread.id) != Some(&last)
+            })
+            .collect()
+    };
+
+    for thread in due {
+        if let Err(err) = summarize_thread(ctx, &thread).await {
+            log_internal!("Could not summarize thread {}: {}", thread.id, err);
+        }
+    }
+    Ok(())
+}
+
+async fn summarize_thread(ctx: &Context<'_>, thread: &GuildChannel) -> Result<()> {
+    let opted_out = ctx.pstate.read().await.llm_optout.users.clone();
+    let messages = thread
+        .id
+        .messages(ctx.cache_http, GetMessages::new().limit(MAX_MESSAGES))
+        .await?;
+    let mut entries = Vec::new();
+    // Oldest first
+    for msg in messages.iter().rev() {
+        if opted_out.contains(&msg.author.id) || msg.content.is_empty() {
+            continue;
+        }
+        entries.push(format!(
+            "{}: {}",
+            msg.author.nick_in_guild(ctx, Some(thread.guild_id)).await,
+            msg.human_format_content(ctx).await?
+        ));
+    }
+    if entries.len() < MIN_MESSAGES {
+        return Ok(());
+    }
+    let transcript = entries.join("\n");
+
+    let (summary_request, title_request) = {
+        let cfg = ctx.cfg.read().await;
+        let Some(summary_cfg) = cfg.llm_summary.as_ref() else {
+            return Ok(());
+        };
+        let settings = summary_cfg.as_llm_settings();
+        let with_system = |system| LlmSettings { system, ..settings };
+        (
+            LlmChatRequest::from_prompt(&with_system(SUMMARY_SYSTEM), transcript.clone()),
+            LlmChatRequest::from_prompt(&with_system(TITLE_SYSTEM), transcript),
+        )
+    };
+    let summary = summary_request.post(ctx).await?;
+    let title = title_request.post(ctx).await?;
+    let title = title.trim().trim_matches('"').trim();
+
+    let posted = thread
+        .id
+        .say(ctx.cache_http, format!("Summary: {}", summary.trim()))
+        .await?;
+    if !title.is_empty() {
+        thread
+            .id
+            .edit_thread(
+                ctx.http,
+                EditThread::new().name(truncate(title, TITLE_MAX_LEN - "...".len())),
+            )
+            .await?;
+    }
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    pstate.thread_titles.summarized.insert(thread.id, posted.id);
+    pstate.save().await?;
+    Ok(())
+}
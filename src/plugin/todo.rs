@@ -0,0 +1,300 @@
+//! Numbered to-do lists: a private one per user, and a shared one per channel.  Items may have a
+//! due date, given as a duration from now; a background task reminds whoever added the item once
+//! it's due, by DM for personal items (see `notification.rs`) or in the channel for shared ones.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{discord_timestamp, parse_duration, TimestampStyle};
+use crate::persistent_state::{TodoItem, TodoLists};
+use crate::{acl, event::*, log_internal, notification, plugin::*};
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateMessage, Message, Permissions, Timestamp, UserId,
+};
+use std::time::Duration;
+
+const REMINDER_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_ITEMS: usize = 50;
+
+pub struct Todo;
+
+/// Which list a command applies to
+#[derive(Clone, Copy)]
+enum List {
+    User(UserId),
+    Channel(ChannelId),
+}
+
+impl List {
+    fn items(self, lists: &mut TodoLists) -> &mut Vec<TodoItem> {
+        match self {
+            List::User(user_id) => lists.users.entry(user_id).or_default(),
+            List::Channel(channel_id) => lists.channels.entry(channel_id).or_default(),
+        }
+    }
+
+    fn get(self, lists: &TodoLists) -> Option<&Vec<TodoItem>> {
+        match self {
+            List::User(user_id) => lists.users.get(&user_id),
+            List::Channel(channel_id) => lists.channels.get(&channel_id),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            List::User(_) => "your to-do list",
+            List::Channel(_) => "this channel's to-do list",
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl Plugin for Todo {
+    fn name(&self) -> &'static str {
+        "todo"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}todo [channel] <subcommand> -- your to-do list, or with `channel`, this channel's shared one\n\
+             | Subcommands:\n\
+             | add <item> [| <due in, e.g. 2d or 1h30m>] - add an item, with a reminder once it's due\n\
+             | list - list the items\n\
+             | done <number> - remove a finished item\n\
+             | clear - remove every item (requires Manage Messages for a channel's list)",
+            prefix
+        ))
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REMINDER_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = send_reminders(&ctx.ctx()).await {
+                    log_internal!("Error sending to-do reminders: {}", err);
+                }
+            }
+        })))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args = args.trim();
+        let (list, args) = match args.split_once(char::is_whitespace) {
+            Some(("channel", rest)) => {
+                if msg.guild_id.is_none() {
+                    return Err(PluginError::UserError(
+                        "Shared to-do lists only work within a server.".to_string(),
+                    ));
+                }
+                (List::Channel(msg.channel_id), rest.trim())
+            }
+            _ => (List::User(msg.author.id), args),
+        };
+        let (subcommand, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let response = match subcommand {
+            "add" => add(ctx, msg, list, rest.trim()).await?,
+            "list" => show(ctx, list).await,
+            "done" => done(ctx, list, rest.trim()).await?,
+            "clear" => {
+                if let List::Channel(_) = list {
+                    let permitted = msg
+                        .author_permissions(ctx.cache)
+                        .is_some_and(|p| p.contains(Permissions::MANAGE_MESSAGES));
+                    acl::check(ctx, msg, "todo.clear", permitted).await?;
+                }
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                list.items(&mut pstate.todo).clear();
+                prune(&mut pstate.todo);
+                pstate.save().await?;
+                format!("Cleared {}.", list.name())
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new()
+                    .content(response)
+                    .allowed_mentions(CreateAllowedMentions::new())
+                    .reference_message(msg),
+            )
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+async fn add(ctx: &Context<'_>, msg: &Message, list: List, args: &str) -> Result<String> {
+    let (text, due) = match args.split_once('|') {
+        Some((text, due)) => {
+            let Some(due) = parse_duration(due.trim()) else {
+                return Err(PluginError::UserError(format!(
+                    "`{}` is not a duration, e.g. `2d` or `1h30m`.",
+                    due.trim()
+                )));
+            };
+            (
+                text.trim(),
+                Some(Timestamp::now().unix_timestamp() + due.as_secs() as i64),
+            )
+        }
+        None => (args, None),
+    };
+    if text.is_empty() {
+        return Err(PluginError::UserError(
+            "Usage: `todo [channel] add <item> [| <due in>]`".to_string(),
+        ));
+    }
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let items = list.items(&mut pstate.todo);
+    if items.len() >= MAX_ITEMS {
+        return Err(PluginError::UserError(format!(
+            "Sorry, {} is full, at {} items.",
+            list.name(),
+            MAX_ITEMS
+        )));
+    }
+    items.push(TodoItem {
+        text: text.to_string(),
+        added_by: msg.author.id,
+        due,
+        reminded: false,
+    });
+    let number = items.len();
+    pstate.save().await?;
+
+    Ok(match due {
+        Some(due) => format!(
+            "Added #{}, due {}.  I'll remind you then.",
+            number,
+            discord_timestamp(due, TimestampStyle::Relative)
+        ),
+        None => format!("Added #{}.", number),
+    })
+}
+
+async fn show(ctx: &Context<'_>, list: List) -> String {
+    let items = list
+        .get(&ctx.pstate.read().await.todo)
+        .cloned()
+        .unwrap_or_default();
+    if items.is_empty() {
+        return format!("Nothing on {}.", list.name());
+    }
+
+    let mut response = String::new();
+    for (i, item) in items.iter().enumerate() {
+        response.push_str(&format!("{}. {}", i + 1, item.text));
+        if let Some(due) = item.due {
+            response.push_str(&format!(
+                " (due {})",
+                discord_timestamp(due, TimestampStyle::Relative)
+            ));
+        }
+        response.push('\n');
+    }
+    response
+}
+
+async fn done(ctx: &Context<'_>, list: List, args: &str) -> Result<String> {
+    let Ok(number) = args.trim_start_matches('#').parse::<usize>() else {
+        return Err(PluginError::UserError(
+            "Usage: `todo [channel] done <number>`".to_string(),
+        ));
+    };
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let items = list.items(&mut pstate.todo);
+    if number == 0 || number > items.len() {
+        prune(&mut pstate.todo);
+        return Err(PluginError::UserError(format!(
+            "No #{} on {}.",
+            number,
+            list.name()
+        )));
+    }
+    let item = items.remove(number - 1);
+    prune(&mut pstate.todo);
+    pstate.save().await?;
+    Ok(format!("Done: {}", item.text))
+}
+
+/// Drop empty lists, to keep the state file tidy
+fn prune(lists: &mut TodoLists) {
+    lists.users.retain(|_, items| !items.is_empty());
+    lists.channels.retain(|_, items| !items.is_empty());
+}
+
+/// Remind whoever added each item which has come due
+async fn send_reminders(ctx: &Context<'_>) -> anyhow::Result<()> {
+    let now = Timestamp::now().unix_timestamp();
+    let due: Vec<(List, TodoItem)> = {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let lists = &mut pstate.todo;
+        let mut due = Vec::new();
+        let users = lists
+            .users
+            .iter_mut()
+            .map(|(user_id, items)| (List::User(*user_id), items));
+        let channels = lists
+            .channels
+            .iter_mut()
+            .map(|(channel_id, items)| (List::Channel(*channel_id), items));
+        for (list, items) in users.chain(channels) {
+            for item in items.iter_mut() {
+                if !item.reminded && item.due.is_some_and(|due| due <= now) {
+                    item.reminded = true;
+                    due.push((list, item.clone()));
+                }
+            }
+        }
+        if due.is_empty() {
+            return Ok(());
+        }
+        pstate.save().await?;
+        due
+    };
+
+    for (list, item) in due {
+        match list {
+            List::User(user_id) => {
+                notification::notify_user(ctx, user_id, format!("⏰ To-do due: {}", item.text))
+                    .await?;
+            }
+            List::Channel(channel_id) => {
+                channel_id
+                    .send_message(
+                        ctx.cache_http,
+                        CreateMessage::new()
+                            .content(format!("⏰ <@{}>, to-do due: {}", item.added_by, item.text))
+                            .allowed_mentions(
+                                CreateAllowedMentions::new().users(vec![item.added_by]),
+                            ),
+                    )
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
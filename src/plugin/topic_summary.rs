@@ -0,0 +1,69 @@
+//! Keeps a persistent rolling summary of each active channel's conversation, so the LLM retains
+//! context across restarts before history is backfilled.  See `llm::update_topic_summary()`.
+
+use crate::error::Result;
+use crate::{context::OwnedContext, event::*, llm, log_internal, plugin::*};
+use std::time::Duration;
+
+/// How often to check for channels due an update
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct TopicSummary;
+
+#[serenity::async_trait]
+impl Plugin for TopicSummary {
+    fn name(&self) -> &'static str {
+        "topic_summary"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(update_loop(ctx))))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        if msg.guild_id.is_some() && ctx.cfg.read().await.topic_summaries.is_some() {
+            ctx.vstate
+                .write()
+                .await
+                .topic_activity
+                .record(msg.channel_id);
+        }
+        Ok(EventHandled::No)
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+}
+
+async fn update_loop(owned: OwnedContext) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let ctx = owned.ctx();
+        let Some(threshold) = ctx
+            .cfg
+            .read()
+            .await
+            .topic_summaries
+            .as_ref()
+            .map(|cfg| cfg.every_messages)
+        else {
+            continue;
+        };
+
+        let due = ctx.vstate.write().await.topic_activity.take_due(threshold);
+        for channel_id in due {
+            if let Err(err) = llm::update_topic_summary(&ctx, channel_id).await {
+                log_internal!("Could not update topic summary for {}: {}", channel_id, err);
+            }
+        }
+    }
+}
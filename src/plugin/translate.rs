@@ -0,0 +1,105 @@
+//! Translates text, or the message replied to, with the `[llm_translate]` profile.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{self, MessageHelper};
+use crate::llm::LlmChatRequest;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::Permissions;
+
+/// Request sent to the LLM, given the target language and the text
+const PROMPT: &str = "Translate the text below into {lang}.  Reply in exactly this format, \
+     omitting the transliteration line if the translation is written in the Latin alphabet:\n\
+     Detected language: <source language>\n\
+     Translation: <translation>\n\
+     Transliteration: <romanization of the translation>\n\n\
+     Text:\n";
+
+pub struct Translate;
+
+#[serenity::async_trait]
+impl Plugin for Translate {
+    fn name(&self) -> &'static str {
+        "translate"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <language> [text] - translate text, or the message replied to",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args = args.trim();
+        let (lang, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        if lang.is_empty() {
+            return Err(PluginError::UserError(
+                "Usage: `translate <language> [text]`, or reply to a message with `translate <language>`".to_string(),
+            ));
+        }
+
+        let text = match (text.trim(), &msg.referenced_message) {
+            ("", Some(referenced)) => {
+                let opted_out = ctx
+                    .pstate
+                    .read()
+                    .await
+                    .llm_optout
+                    .users
+                    .contains(&referenced.author.id);
+                if opted_out {
+                    return Err(PluginError::UserError(
+                        "That message's author has opted out of LLM features.".to_string(),
+                    ));
+                }
+                referenced.human_format_content(ctx).await?
+            }
+            ("", None) => {
+                return Err(PluginError::UserError(
+                    "Give some text to translate, or reply to a message.".to_string(),
+                ))
+            }
+            (text, _) => text.to_string(),
+        };
+
+        let typing = msg.channel_id.start_typing(ctx.http);
+        let request = {
+            let cfg = ctx.cfg.read().await;
+            let Some(translate_cfg) = cfg.llm_translate.as_ref() else {
+                return Err(PluginError::UserError(
+                    "Translation is not configured; see `[llm_translate]`.".to_string(),
+                ));
+            };
+            let content = format!("{}{}", PROMPT.replace("{lang}", lang), text);
+            LlmChatRequest::from_prompt(&translate_cfg.as_llm_settings(), content)
+        };
+        let response = request.post(ctx).await.map_err(PluginError::llm)?;
+        typing.stop();
+
+        helper::reply_in_chunks(ctx, msg, &response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -0,0 +1,457 @@
+//! Multiple-choice trivia games.  Questions come from OpenTDB, or from the `[llm_trivia]` profile
+//! for categories OpenTDB doesn't have.  The first correct answer to each question scores a point;
+//! points are totalled per game and over the guild's lifetime.
+
+use crate::error::{PluginError, Result, Service};
+use crate::helper::UserIdHelper;
+use crate::llm::LlmChatRequest;
+use crate::volatile_state::TriviaQuestion;
+use crate::{acl, context::OwnedContext, event::*, log_internal, plugin::*};
+use base64::Engine;
+use serenity::all::{ChannelId, GuildId, Mentionable, Message, Permissions, UserId};
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+const CATEGORIES_URL: &str = "https://opentdb.com/api_category.php";
+const QUESTIONS_URL: &str = "https://opentdb.com/api.php";
+/// Questions per game
+const QUESTIONS: usize = 5;
+/// How long each question accepts answers
+const ANSWER_WINDOW: Duration = Duration::from_secs(20);
+/// How often to check whether a question has been answered
+const ANSWER_POLL: Duration = Duration::from_secs(1);
+/// Pause after revealing an answer before the next question
+const BETWEEN_QUESTIONS: Duration = Duration::from_secs(3);
+/// Entries in the lifetime scoreboard
+const MAX_SCORES: usize = 10;
+const LETTERS: [char; 4] = ['A', 'B', 'C', 'D'];
+
+const LLM_PROMPT: &str = "Write {count} multiple-choice trivia questions about {category}.  Reply \
+     with only a JSON array, each element of the form \
+     {\"question\": \"...\", \"correct_answer\": \"...\", \"incorrect_answers\": [\"...\", \"...\", \"...\"]}";
+
+pub struct Trivia;
+
+#[derive(serde::Deserialize)]
+struct CategoriesResponse {
+    trivia_categories: Vec<OpenTdbCategory>,
+}
+
+#[derive(serde::Deserialize)]
+struct OpenTdbCategory {
+    id: u32,
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct QuestionsResponse {
+    response_code: u32,
+    results: Vec<Question>,
+}
+
+/// As returned by OpenTDB, and requested of the LLM
+#[derive(serde::Deserialize)]
+struct Question {
+    question: String,
+    correct_answer: String,
+    incorrect_answers: Vec<String>,
+}
+
+#[serenity::async_trait]
+impl Plugin for Trivia {
+    fn name(&self) -> &'static str {
+        "trivia"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}trivia <subcommand> -- multiple-choice trivia; answer with a letter or the answer\n\
+             | Subcommands:\n\
+             | start [category] - start a game, e.g. `start history`\n\
+             | stop - end the game after the current question\n\
+             | scores - lifetime scoreboard",
+            prefix
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return match event {
+                Event::Message(msg) => handle_answer(ctx, msg).await,
+                _ => Ok(EventHandled::No),
+            };
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Trivia only works within a server".to_string(),
+            ));
+        };
+
+        let args = args.trim();
+        let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+        match subcommand {
+            "start" => {
+                acl::check(ctx, msg, "trivia.start", true).await?;
+                start(ctx, msg, guild_id, rest.trim()).await?;
+            }
+            "stop" => {
+                acl::check(ctx, msg, "trivia.stop", true).await?;
+                let response = match ctx.vstate.write().await.trivia.get_mut(msg.channel_id) {
+                    Some(session) => {
+                        session.stopping = true;
+                        "Trivia will end after this question."
+                    }
+                    None => "No trivia game is running here.",
+                };
+                msg.reply(ctx.cache_http, response).await?;
+            }
+            "scores" => {
+                acl::check(ctx, msg, "trivia.scores", true).await?;
+                let response = lifetime_scores(ctx, guild_id).await;
+                msg.reply(ctx.cache_http, response).await?;
+            }
+            _ => {
+                return Err(PluginError::UserError(
+                    "Invalid command.  See help for usage.".to_string(),
+                ))
+            }
+        }
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn start(ctx: &Context<'_>, msg: &Message, guild_id: GuildId, category: &str) -> Result<()> {
+    let questions = if category.is_empty() {
+        opentdb_questions(None).await?
+    } else {
+        match find_category(category).await? {
+            Some(id) => opentdb_questions(Some(id)).await?,
+            None => {
+                let typing = msg.channel_id.start_typing(ctx.http);
+                let questions = llm_questions(ctx, category).await;
+                typing.stop();
+                questions?
+            }
+        }
+    };
+    if questions.is_empty() {
+        return Err(PluginError::UserError(
+            "No trivia questions found.  Try another category.".to_string(),
+        ));
+    }
+
+    if !ctx.vstate.write().await.trivia.start(msg.channel_id) {
+        return Err(PluginError::UserError(
+            "A trivia game is already running here.".to_string(),
+        ));
+    }
+    msg.reply(
+        ctx.cache_http,
+        format!(
+            "Starting trivia with {} questions.  Answer with a letter or the answer itself; one \
+             guess each.",
+            questions.len()
+        ),
+    )
+    .await?;
+    tokio::spawn(run_game(ctx.owned(), guild_id, msg.channel_id, questions));
+    Ok(())
+}
+
+/// OpenTDB category whose name contains `query`
+async fn find_category(query: &str) -> Result<Option<u32>> {
+    let query = query.to_lowercase();
+    let categories = reqwest::Client::new()
+        .get(CATEGORIES_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<CategoriesResponse>()
+        .await?
+        .trivia_categories;
+    Ok(categories
+        .into_iter()
+        .find(|category| category.name.to_lowercase().contains(&query))
+        .map(|category| category.id))
+}
+
+async fn opentdb_questions(category: Option<u32>) -> Result<Vec<Question>> {
+    let mut query = vec![
+        ("amount", QUESTIONS.to_string()),
+        ("type", "multiple".to_string()),
+        // Otherwise text is HTML-escaped
+        ("encode", "base64".to_string()),
+    ];
+    if let Some(category) = category {
+        query.push(("category", category.to_string()));
+    }
+    let response = reqwest::Client::new()
+        .get(QUESTIONS_URL)
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<QuestionsResponse>()
+        .await?;
+    // 1 means too few questions in the category; return what there is, i.e. none
+    if response.response_code > 1 {
+        return Err(PluginError::Backend(
+            Service::Web,
+            anyhow::anyhow!("OpenTDB response code {}", response.response_code),
+        ));
+    }
+
+    let decode = |text: &str| -> Result<String> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .map_err(anyhow::Error::from)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    };
+    response
+        .results
+        .iter()
+        .map(|question| {
+            Ok(Question {
+                question: decode(&question.question)?,
+                correct_answer: decode(&question.correct_answer)?,
+                incorrect_answers: question
+                    .incorrect_answers
+                    .iter()
+                    .map(|answer| decode(answer))
+                    .collect::<Result<_>>()?,
+            })
+        })
+        .collect()
+}
+
+async fn llm_questions(ctx: &Context<'_>, category: &str) -> Result<Vec<Question>> {
+    let request = {
+        let cfg = ctx.cfg.read().await;
+        let Some(trivia_cfg) = cfg.llm_trivia.as_ref() else {
+            return Err(PluginError::UserError(format!(
+                "No trivia category matches `{}`.",
+                category
+            )));
+        };
+        let content = LLM_PROMPT
+            .replace("{count}", &QUESTIONS.to_string())
+            .replace("{category}", category);
+        LlmChatRequest::from_prompt(&trivia_cfg.as_llm_settings(), content)
+    };
+    let response = request.post(ctx).await.map_err(PluginError::llm)?;
+
+    // Models often wrap JSON in a code block
+    let json = response
+        .find('[')
+        .zip(response.rfind(']'))
+        .map(|(start, end)| &response[start..=end])
+        .unwrap_or_default();
+    let questions: Vec<Question> = serde_json::from_str(json).map_err(PluginError::llm)?;
+    Ok(questions
+        .into_iter()
+        .filter(|question| question.incorrect_answers.len() == LETTERS.len() - 1)
+        .take(QUESTIONS)
+        .collect())
+}
+
+async fn run_game(
+    owned: OwnedContext,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    questions: Vec<Question>,
+) {
+    let ctx = owned.ctx();
+    let count = questions.len();
+    for (i, question) in questions.into_iter().enumerate() {
+        if let Err(err) = ask(&ctx, channel_id, i + 1, count, question).await {
+            log_internal!("Trivia in {}: {}", channel_id, err);
+            break;
+        }
+        let stopping = ctx
+            .vstate
+            .read()
+            .await
+            .trivia
+            .get(channel_id)
+            .is_none_or(|session| session.stopping);
+        if stopping {
+            break;
+        }
+        tokio::time::sleep(BETWEEN_QUESTIONS).await;
+    }
+
+    let Some(session) = ctx.vstate.write().await.trivia.finish(channel_id) else {
+        return;
+    };
+    if let Err(err) = finish(&ctx, guild_id, channel_id, session.scores).await {
+        log_internal!("Trivia in {}: {}", channel_id, err);
+    }
+}
+
+/// Post a question, wait for the answer window or a correct answer, then reveal it
+async fn ask(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    number: usize,
+    count: usize,
+    question: Question,
+) -> Result<()> {
+    let mut choices = question.incorrect_answers;
+    let correct = std::collections::hash_map::RandomState::new().hash_one(&question.question)
+        as usize
+        % (choices.len() + 1);
+    choices.insert(correct, question.correct_answer);
+
+    let mut text = format!("**Question {}/{}:** {}\n", number, count, question.question);
+    for (letter, choice) in LETTERS.iter().zip(&choices) {
+        text.push_str(&format!("**{}.** {}\n", letter, choice));
+    }
+    channel_id.say(ctx.cache_http, text).await?;
+
+    let answer = format!("**{}.** {}", LETTERS[correct], choices[correct]);
+    if let Some(session) = ctx.vstate.write().await.trivia.get_mut(channel_id) {
+        session.question = Some(TriviaQuestion {
+            choices,
+            correct,
+            guessed: HashSet::new(),
+            winner: None,
+        });
+    }
+
+    let deadline = tokio::time::Instant::now() + ANSWER_WINDOW;
+    let winner = loop {
+        tokio::time::sleep(ANSWER_POLL).await;
+        let mut vstate = ctx.vstate.write().await;
+        let Some(session) = vstate.trivia.get_mut(channel_id) else {
+            return Ok(());
+        };
+        let winner = session.question.as_ref().and_then(|q| q.winner);
+        if winner.is_some() || tokio::time::Instant::now() >= deadline {
+            session.question = None;
+            if let Some(winner) = winner {
+                *session.scores.entry(winner).or_default() += 1;
+            }
+            break winner;
+        }
+    };
+
+    let response = match winner {
+        Some(winner) => format!("{} got it!  The answer was {}.", winner.mention(), answer),
+        None => format!("Time's up!  The answer was {}.", answer),
+    };
+    channel_id.say(ctx.cache_http, response).await?;
+    Ok(())
+}
+
+/// Record a guess at the current question, if the message is one
+async fn handle_answer(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    if msg.author.bot {
+        return Ok(EventHandled::No);
+    }
+    let guess = msg.content.trim().to_lowercase();
+    let mut vstate = ctx.vstate.write().await;
+    let Some(question) = vstate
+        .trivia
+        .get_mut(msg.channel_id)
+        .and_then(|session| session.question.as_mut())
+    else {
+        return Ok(EventHandled::No);
+    };
+
+    let choice = LETTERS
+        .iter()
+        .zip(&question.choices)
+        .position(|(letter, choice)| {
+            guess == letter.to_lowercase().to_string() || guess == choice.to_lowercase()
+        });
+    // Otherwise just chatting
+    let Some(choice) = choice else {
+        return Ok(EventHandled::No);
+    };
+    if question.winner.is_some() || !question.guessed.insert(msg.author.id) {
+        return Ok(EventHandled::Yes);
+    }
+    if choice == question.correct {
+        question.winner = Some(msg.author.id);
+    }
+    Ok(EventHandled::Yes)
+}
+
+/// Post the game's scoreboard and add it to the lifetime scores
+async fn finish(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    scores: HashMap<UserId, u32>,
+) -> Result<()> {
+    if scores.is_empty() {
+        channel_id
+            .say(ctx.cache_http, "Trivia over!  Nobody scored.")
+            .await?;
+        return Ok(());
+    }
+
+    {
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let lifetime = pstate.trivia.guilds.entry(guild_id).or_default();
+        for (user_id, points) in &scores {
+            *lifetime.entry(*user_id).or_default() += u64::from(*points);
+        }
+        pstate.save().await?;
+    }
+
+    let mut scores: Vec<(UserId, u32)> = scores.into_iter().collect();
+    scores.sort_unstable_by_key(|&(_, points)| std::cmp::Reverse(points));
+    let mut response = String::from("Trivia over!  Scores:\n");
+    for (user_id, points) in scores {
+        response.push_str(&format!(
+            "• {}: {}\n",
+            user_id.nick_in_guild(ctx, Some(guild_id)).await,
+            points
+        ));
+    }
+    channel_id.say(ctx.cache_http, response).await?;
+    Ok(())
+}
+
+async fn lifetime_scores(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    // Copy the scores out so as not to hold the lock while looking up names
+    let mut scores: Vec<(UserId, u64)> = ctx
+        .pstate
+        .read()
+        .await
+        .trivia
+        .guilds
+        .get(&guild_id)
+        .map(|scores| scores.iter().map(|(id, points)| (*id, *points)).collect())
+        .unwrap_or_default();
+    if scores.is_empty() {
+        return "Nobody has scored at trivia yet.".to_string();
+    }
+    scores.sort_unstable_by_key(|&(_, points)| std::cmp::Reverse(points));
+
+    let mut response = String::from("Trivia scoreboard:\n");
+    for (user_id, points) in scores.into_iter().take(MAX_SCORES) {
+        response.push_str(&format!(
+            "• {}: {}\n",
+            user_id.nick_in_guild(ctx, Some(guild_id)).await,
+            points
+        ));
+    }
+    response
+}
@@ -0,0 +1,177 @@
+//! Typing pace: in enabled channels, `llm_reply` waits before each message for as long as typing
+//! it would take at the `[typing_pace]` speed, with the typing indicator shown, so the bot feels
+//! less instantaneous.  Long replies are sent as a couple of messages, each paced.
+
+use crate::error::{PluginError, Result};
+use crate::helper::{reply_in_chunks, split_message, Args, MESSAGE_MAX_LEN};
+use crate::subcommand::{Access, SubcommandRouter};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{ChannelId, Message, Permissions};
+use std::time::Duration;
+
+pub struct TypingPace;
+
+#[serenity::async_trait]
+impl Plugin for TypingPace {
+    fn name(&self) -> &'static str {
+        "typingpace"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(router().usage(prefix))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        if msg.guild_id.is_none() {
+            return Err(PluginError::UserError(
+                "Typing pace only works within a server".to_string(),
+            ));
+        }
+        router().dispatch(ctx, msg, args).await
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+}
+
+fn router() -> SubcommandRouter {
+    SubcommandRouter::new(
+        "typingpace",
+        "channels in which I take time to type my replies",
+    )
+    .subcommand(
+        "on <#channel>",
+        "pace replies in a channel",
+        Access::Permissions(Permissions::MANAGE_CHANNELS),
+        |ctx, msg, args| Box::pin(set(ctx, msg, args, true)),
+    )
+    .subcommand(
+        "off <#channel>",
+        "reply instantly in a channel",
+        Access::Permissions(Permissions::MANAGE_CHANNELS),
+        |ctx, msg, args| Box::pin(set(ctx, msg, args, false)),
+    )
+    .subcommand(
+        "list",
+        "list this server's channels with paced replies",
+        Access::Everyone,
+        |ctx, msg, _args| Box::pin(list(ctx, msg)),
+    )
+}
+
+async fn set(ctx: &Context<'_>, msg: &Message, args: &Args, on: bool) -> Result<EventHandled> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(EventHandled::No);
+    };
+    let Some(channel_id) = args.get_as::<ChannelId>(0) else {
+        return Err(PluginError::UserError(
+            "Invalid channel.  Mention it, e.g. `#general`.".to_string(),
+        ));
+    };
+    if !ctx
+        .cache
+        .guild(guild_id)
+        .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+    {
+        return Err(PluginError::UserError(
+            "That channel isn't in this server.".to_string(),
+        ));
+    }
+
+    let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let channels = pstate.typing_pace.channels.entry(guild_id).or_default();
+    let response = if on {
+        channels.insert(channel_id);
+        format!("Replies in <#{}> are paced.", channel_id)
+    } else if channels.remove(&channel_id) {
+        format!("Replies in <#{}> are instant.", channel_id)
+    } else {
+        format!("Replies in <#{}> weren't paced.", channel_id)
+    };
+    pstate.save().await?;
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn list(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(EventHandled::No);
+    };
+    let response = {
+        let pstate = ctx.pstate.read().await;
+        match pstate
+            .typing_pace
+            .channels
+            .get(&guild_id)
+            .filter(|c| !c.is_empty())
+        {
+            Some(channels) => {
+                let mut response = String::from("Replies are paced in:\n");
+                for channel_id in channels {
+                    response.push_str(&format!("• <#{}>\n", channel_id));
+                }
+                response
+            }
+            None => "Replies aren't paced in any channels.".to_string(),
+        }
+    };
+
+    msg.reply(ctx.cache_http, response).await?;
+    Ok(EventHandled::Yes)
+}
+
+/// Reply with `text`, paced if `msg`'s channel has typing pace enabled and `[typing_pace]` is
+/// configured, or as `reply_in_chunks` does otherwise
+pub async fn reply(ctx: &Context<'_>, msg: &Message, text: &str) -> Result<()> {
+    let Some(pace) = ctx.cfg.read().await.typing_pace.clone() else {
+        return Ok(reply_in_chunks(ctx, msg, text).await?);
+    };
+    let enabled = {
+        let pstate = ctx.pstate.read().await;
+        msg.guild_id
+            .and_then(|guild_id| pstate.typing_pace.channels.get(&guild_id))
+            .is_some_and(|channels| channels.contains(&msg.channel_id))
+    };
+    if !enabled {
+        return Ok(reply_in_chunks(ctx, msg, text).await?);
+    }
+
+    // A couple of messages, each about half the reply, splitting where `split_message` prefers
+    let max_len = if text.len() > pace.split_len {
+        (text.len().div_ceil(2) + text.len() / 10).min(MESSAGE_MAX_LEN)
+    } else {
+        MESSAGE_MAX_LEN
+    };
+    for (i, part) in split_message(text, max_len).into_iter().enumerate() {
+        // Sending a message clears the indicator, so show it again for each later part
+        let typing = (i > 0).then(|| msg.channel_id.start_typing(ctx.http));
+        let seconds = part.chars().count() as f64 / pace.chars_per_second;
+        tokio::time::sleep(Duration::from_secs_f64(seconds.min(pace.max_delay_seconds))).await;
+        if let Some(typing) = typing {
+            typing.stop();
+        }
+        if i == 0 {
+            msg.reply(ctx.cache_http, part).await?;
+        } else {
+            msg.channel_id.say(ctx.cache_http, part).await?;
+        }
+    }
+    Ok(())
+}
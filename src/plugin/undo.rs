@@ -0,0 +1,73 @@
+use crate::error::{PluginError, Result};
+use crate::helper::MessageHelper;
+use crate::persistent_state::UndoStack;
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{Permissions, Timestamp};
+
+/// Reverts the most recent state change recorded in `PersistentState::undo`
+pub struct Undo;
+
+#[serenity::async_trait]
+impl Plugin for Undo {
+    fn name(&self) -> &'static str {
+        "undo"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} - revert the most recent change, such as a rivals report, if you made it",
+            prefix,
+            self.name(),
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, _args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let is_owner = msg.is_from_owner(ctx).await;
+        let cutoff = Timestamp::now().unix_timestamp() - UndoStack::MAX_AGE_SECS;
+        let mut pstate = ctx.pstate.write().await;
+        pstate.ensure_writable()?;
+        let Some(last) = pstate
+            .undo
+            .entries
+            .last()
+            .filter(|entry| entry.timestamp >= cutoff)
+        else {
+            return Err(PluginError::UserError("Nothing recent to undo".to_string()));
+        };
+        if !is_owner && last.actor != msg.author.id {
+            return Err(PluginError::PermissionDenied);
+        }
+
+        let Some(entry) = pstate.undo.entries.pop() else {
+            return Ok(EventHandled::Yes);
+        };
+        let result = entry.inverse.apply(&mut pstate);
+        pstate.save().await?;
+        drop(pstate);
+
+        msg.reply(
+            ctx.cache_http,
+            format!("Undid {}.  {}", entry.description, result),
+        )
+        .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
@@ -1,7 +1,9 @@
-use crate::helper::UserIdHelper;
-use crate::{event::*, plugin::*};
-use anyhow::{anyhow, Result};
-use serenity::all::{CreateMessage, Message, VoiceState};
+use crate::error::Result;
+use crate::helper::{Args, UserIdHelper};
+use crate::notification::notify_user;
+use crate::{acl, event::*, plugin::*};
+use anyhow::anyhow;
+use serenity::all::{Message, Permissions, VoiceState};
 use std::borrow::Cow;
 
 pub struct VcNotify;
@@ -12,6 +14,14 @@ impl Plugin for VcNotify {
         "vc-notify"
     }
 
+    fn command(&self) -> Option<&'static str> {
+        Some("vc-notify")
+    }
+
+    fn category(&self) -> Category {
+        Category::Notifications
+    }
+
     async fn usage(&self, ctx: &Context) -> Option<String> {
         let prefix = &ctx.cfg.read().await.general.command_prefix;
         Some(format!(
@@ -28,40 +38,50 @@ impl Plugin for VcNotify {
             _ => Ok(EventHandled::No),
         }
     }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
 }
 
 async fn handle_message(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
     let cmd_prefix = &ctx.cfg.read().await.general.command_prefix;
 
-    let terms: Vec<&str> = msg.content.split_whitespace().collect();
-    if terms.first().and_then(|cmd| cmd.strip_prefix(cmd_prefix)) != Some("vc-notify") {
+    let args = Args::parse(&msg.content);
+    if args.get(0).and_then(|cmd| cmd.strip_prefix(cmd_prefix)) != Some("vc-notify") {
         return Ok(EventHandled::No);
     }
+    acl::check(ctx, msg, "vc-notify", true).await?;
 
     let id = msg.author.id;
     let pstate = &mut ctx.pstate.write().await;
+    pstate.ensure_writable()?;
     let followers = &mut pstate.vc_notify.followers;
     let following = followers.contains(&id);
 
-    let response = match (terms.get(1), following) {
-        (Some(&"follow"), true) => {
+    let response = match (args.get(1), following) {
+        (Some("follow"), true) => {
             Cow::Borrowed("You are already subscribed to voice channel activity notifications")
         }
-        (Some(&"follow"), false) => {
+        (Some("follow"), false) => {
             followers.insert(id);
             pstate.save().await?;
             Cow::Borrowed(
                 "You have successfully subscribed to voice channel activity notifications",
             )
         }
-        (Some(&"unfollow"), true) => {
+        (Some("unfollow"), true) => {
             followers.remove(&id);
             pstate.save().await?;
             Cow::Borrowed(
                 "You have successfully unsubscribed from voice channel activity notifications",
             )
         }
-        (Some(&"unfollow"), false) => {
+        (Some("unfollow"), false) => {
             Cow::Borrowed("You are not subscribed to voice channel activity notifications")
         }
         _ => Cow::Owned(format!("Invalid command.  See `{}help`", cmd_prefix)),
@@ -118,42 +138,42 @@ async fn handle_voice_state_update(
     }
 
     // Notify registered users
-    let pstate = &ctx.pstate.write().await;
-    let followers = &pstate.vc_notify.followers;
+    let followers = ctx.pstate.read().await.vc_notify.followers.clone();
 
     let channel_name = new
         .channel_id
         .map(|id| format!("<#{}>", id))
         .unwrap_or("a VC channel".to_string());
 
-    let cmd_prefix = &ctx.cfg.read().await.general.command_prefix;
+    let cmd_prefix = ctx.cfg.read().await.general.command_prefix.clone();
     let new_user_name = new.user_id.nick_in_guild(ctx, Some(guild_id)).await;
-    let message = CreateMessage::new().content(format!(
+    let content = format!(
         "{} joined VC channel {} in {}\n\
             \n\
             You can opt out of these notifications by replying `{}vc-notify unfollow`\n",
         new_user_name, channel_name, guild.name, cmd_prefix
-    ));
-
-    let timestamps = &mut ctx.vstate.write().await.notify_timestamp;
-
-    for follower_id in followers.iter() {
-        // Don't DM the user who just joined
-        if *follower_id == new.user_id {
-            continue;
+    );
+
+    let mut recipients = Vec::new();
+    {
+        let timestamps = &mut ctx.vstate.write().await.notify_timestamp;
+        for follower_id in followers.iter() {
+            // Don't DM the user who just joined
+            if *follower_id == new.user_id {
+                continue;
+            }
+            // Don't DM the user too often
+            if !timestamps.okay_to_notify(ctx, *follower_id).await {
+                continue;
+            }
+
+            timestamps.update_notify_timestamp(*follower_id).await;
+            recipients.push(*follower_id);
         }
-        // Don't DM the user too often
-        if !timestamps.okay_to_notify(ctx, *follower_id).await {
-            continue;
-        }
-
-        timestamps.update_notify_timestamp(*follower_id).await;
+    }
 
-        follower_id
-            .to_user(&ctx.http)
-            .await?
-            .direct_message(ctx.cache_http, message.clone())
-            .await?;
+    for follower_id in recipients {
+        notify_user(ctx, follower_id, content.clone()).await?;
     }
 
     // While we handled the event, we did not do so exclusively; other plugins might also want
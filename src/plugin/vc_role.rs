@@ -0,0 +1,76 @@
+use crate::error::Result;
+use crate::{event::*, plugin::*};
+use serenity::all::{Permissions, VoiceState};
+
+/// Gives members the configured `[vc_role]` role while they are in a voice channel
+pub struct VcRole;
+
+#[serenity::async_trait]
+impl Plugin for VcRole {
+    fn name(&self) -> &'static str {
+        "vc_role"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        if let Event::VoiceStateUpdate { new, .. } = event {
+            update_role(ctx, new).await?;
+        }
+        // Other voice plugins also act on these events
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::MANAGE_ROLES
+    }
+
+    fn passive(&self) -> bool {
+        true
+    }
+}
+
+async fn update_role(ctx: &Context<'_>, new: &VoiceState) -> Result<()> {
+    let Some(guild_id) = new.guild_id else {
+        return Ok(());
+    };
+    let Some(role_id) = ctx
+        .cfg
+        .read()
+        .await
+        .vc_role
+        .as_ref()
+        .and_then(|cfg| cfg.roles.get(&guild_id).copied())
+    else {
+        return Ok(());
+    };
+
+    let afk_channel_id = ctx
+        .cache
+        .guild(guild_id)
+        .and_then(|guild| guild.afk_metadata.as_ref().map(|afk| afk.afk_channel_id));
+    let in_vc = new.channel_id.is_some() && new.channel_id != afk_channel_id;
+    // Without the member we can't tell whether the role is already set; Discord ignores
+    // redundant changes anyway.
+    let has_role = new
+        .member
+        .as_ref()
+        .map(|member| member.roles.contains(&role_id));
+
+    match (in_vc, has_role) {
+        (true, Some(false) | None) => {
+            ctx.http
+                .add_member_role(guild_id, new.user_id, role_id, Some("Joined voice channel"))
+                .await?;
+        }
+        (false, Some(true) | None) => {
+            ctx.http
+                .remove_member_role(guild_id, new.user_id, role_id, Some("Left voice channel"))
+                .await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
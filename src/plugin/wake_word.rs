@@ -0,0 +1,169 @@
+//! Wake words: in enabled channels, `llm_reply` answers messages starting with the bot's name or
+//! one of the `[wake_words]` aliases, e.g. "hey digm, ...", as though the bot were mentioned.
+
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Message, Permissions};
+
+/// Words which may precede the bot's name, e.g. "hey digm"
+const GREETINGS: &[&str] = &["hey", "hi", "hello", "ok", "okay", "yo"];
+
+pub struct WakeWord;
+
+#[serenity::async_trait]
+impl Plugin for WakeWord {
+    fn name(&self) -> &'static str {
+        "wakeword"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}wakeword <subcommand> -- channels in which starting a message with my name addresses me\n\
+             | Subcommands:\n\
+             | on <#channel> - enable wake words in a channel (requires Manage Channels)\n\
+             | off <#channel> - disable wake words in a channel (requires Manage Channels)\n\
+             | list - list this server's channels with wake words enabled",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let Some(guild_id) = msg.guild_id else {
+            return Err(PluginError::UserError(
+                "Wake words only work within a server".to_string(),
+            ));
+        };
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["list"] => list(ctx, guild_id).await,
+            [subcommand @ ("on" | "off"), channel] => {
+                let permitted = msg
+                    .author_permissions(ctx.cache)
+                    .is_some_and(|p| p.contains(Permissions::MANAGE_CHANNELS));
+                acl::check(ctx, msg, &format!("wakeword.{}", subcommand), permitted).await?;
+
+                let Some(channel_id) = serenity::utils::parse_channel_mention(channel) else {
+                    return Err(PluginError::UserError(
+                        "Invalid channel.  Mention it, e.g. `#general`.".to_string(),
+                    ));
+                };
+                if !ctx
+                    .cache
+                    .guild(guild_id)
+                    .is_some_and(|guild| guild.channels.contains_key(&channel_id))
+                {
+                    return Err(PluginError::UserError(
+                        "That channel isn't in this server.".to_string(),
+                    ));
+                }
+
+                let pstate = &mut ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let channels = pstate.wake_words.channels.entry(guild_id).or_default();
+                let response = if *subcommand == "on" {
+                    channels.insert(channel_id);
+                    format!("Wake words are enabled in <#{}>.", channel_id)
+                } else if channels.remove(&channel_id) {
+                    format!("Wake words are disabled in <#{}>.", channel_id)
+                } else {
+                    format!("Wake words weren't enabled in <#{}>.", channel_id)
+                };
+                pstate.save().await?;
+                response
+            }
+            _ => "Invalid command.  See help for usage.".to_string(),
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn category(&self) -> Category {
+        Category::Llm
+    }
+}
+
+/// Whether `msg` is in a channel with wake words enabled and starts with one
+pub async fn is_woken(ctx: &Context<'_>, msg: &Message) -> bool {
+    let Some(guild_id) = msg.guild_id else {
+        return false;
+    };
+    if !ctx
+        .pstate
+        .read()
+        .await
+        .wake_words
+        .channels
+        .get(&guild_id)
+        .is_some_and(|channels| channels.contains(&msg.channel_id))
+    {
+        return false;
+    }
+
+    let mut names: Vec<String> = ctx
+        .cfg
+        .read()
+        .await
+        .wake_words
+        .as_ref()
+        .map(|wake_words| wake_words.aliases.clone())
+        .unwrap_or_default();
+    let me = ctx.cache.current_user().clone();
+    names.push(me.name.clone());
+    if let Some(nick) = ctx
+        .cache
+        .guild(guild_id)
+        .and_then(|guild| guild.members.get(&me.id).and_then(|m| m.nick.clone()))
+    {
+        names.push(nick);
+    }
+
+    let content = msg.content.trim_start().to_lowercase();
+    let content = GREETINGS
+        .iter()
+        .find_map(|greeting| {
+            content
+                .strip_prefix(greeting)
+                .filter(|rest| rest.starts_with(char::is_whitespace))
+        })
+        .unwrap_or(&content)
+        .trim_start();
+    names.iter().any(|name| {
+        content
+            .strip_prefix(name.to_lowercase().as_str())
+            .is_some_and(|rest| !rest.starts_with(char::is_alphanumeric))
+    })
+}
+
+async fn list(ctx: &Context<'_>, guild_id: GuildId) -> String {
+    let pstate = ctx.pstate.read().await;
+    let Some(channels) = pstate
+        .wake_words
+        .channels
+        .get(&guild_id)
+        .filter(|c| !c.is_empty())
+    else {
+        return "Wake words aren't enabled in any channels.".to_string();
+    };
+
+    let mut response = String::from("Wake words are enabled in:\n");
+    for channel_id in channels {
+        response.push_str(&format!("• <#{}>\n", channel_id));
+    }
+    response
+}
@@ -0,0 +1,71 @@
+//! Rescues users from plugins which hang, e.g. on a stuck LLM request.
+//!
+//! Complements per-plugin timeouts: once any plugin has taken longer than the watchdog timeout, it
+//! is cancelled, which stops any typing indicator it started, and the user is told we gave up.
+
+use crate::error::Result;
+use crate::{event::*, log_internal, plugin::*};
+use serenity::all::{CreateMessage, Permissions};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct Watchdog;
+
+#[serenity::async_trait]
+impl Plugin for Watchdog {
+    fn name(&self) -> &'static str {
+        "watchdog"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        Ok(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                check(&ctx.ctx()).await;
+            }
+        })))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+}
+
+async fn check(ctx: &Context<'_>) {
+    let timeout = ctx.cfg.read().await.watchdog_timeout();
+
+    let mut stuck = Vec::new();
+    for op in ctx.vstate.write().await.in_flight.stuck(timeout) {
+        if let Some(cancel) = op.cancel.take() {
+            let _ = cancel.send(());
+        }
+        stuck.push((op.plugin, op.message));
+    }
+
+    for (plugin, message) in stuck {
+        log_internal!(
+            "Watchdog: cancelled `{}` after {} seconds",
+            plugin,
+            timeout.as_secs()
+        );
+        let Some((channel_id, message_id)) = message else {
+            continue;
+        };
+        let reply = CreateMessage::new()
+            .content("Sorry, that was taking far too long, so I gave up.")
+            .reference_message((channel_id, message_id));
+        if let Err(err) = channel_id.send_message(ctx.cache_http, reply).await {
+            log_internal!("Watchdog: could not send timeout reply: {}", err);
+        }
+    }
+}
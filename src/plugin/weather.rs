@@ -0,0 +1,296 @@
+//! Current conditions and a short forecast from Open-Meteo, which needs no API key.
+
+use crate::config::Units;
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{CreateEmbed, CreateMessage, Permissions};
+
+const GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+/// Days of forecast shown, including today
+const FORECAST_DAYS: usize = 3;
+
+pub struct Weather;
+
+#[derive(serde::Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<Place>,
+}
+
+#[derive(serde::Deserialize)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    country: Option<String>,
+    /// State, province, or similar
+    admin1: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ForecastResponse {
+    current: Current,
+    daily: Daily,
+}
+
+#[derive(serde::Deserialize)]
+struct Current {
+    temperature_2m: f64,
+    apparent_temperature: f64,
+    relative_humidity_2m: f64,
+    wind_speed_10m: f64,
+    weather_code: u8,
+}
+
+#[derive(serde::Deserialize)]
+struct Daily {
+    /// ISO 8601 dates, e.g. `2024-06-01`
+    time: Vec<String>,
+    weather_code: Vec<u8>,
+    temperature_2m_max: Vec<f64>,
+    temperature_2m_min: Vec<f64>,
+    precipitation_probability_max: Vec<Option<u8>>,
+}
+
+#[serenity::async_trait]
+impl Plugin for Weather {
+    fn name(&self) -> &'static str {
+        "weather"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}weather [location] -- current weather and forecast, by default for your saved location\n\
+             | set <location> - save your default location\n\
+             | clear - forget your default location",
+            prefix
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args = args.trim();
+        let (subcommand, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let location = match subcommand {
+            "set" => {
+                let location = rest.trim();
+                if location.is_empty() {
+                    return Err(PluginError::UserError(
+                        "Usage: `weather set <location>`".to_string(),
+                    ));
+                }
+                // Check it exists before saving it
+                let place = geocode(location).await?;
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                pstate
+                    .weather
+                    .users
+                    .insert(msg.author.id, location.to_string());
+                pstate.save().await?;
+                drop(pstate);
+                msg.reply(
+                    ctx.cache_http,
+                    format!("Saved your location as {}.", describe_place(&place)),
+                )
+                .await?;
+                return Ok(EventHandled::Yes);
+            }
+            "clear" => {
+                let mut pstate = ctx.pstate.write().await;
+                pstate.ensure_writable()?;
+                let response = if pstate.weather.users.remove(&msg.author.id).is_some() {
+                    pstate.save().await?;
+                    "Forgot your location."
+                } else {
+                    "You haven't saved a location."
+                };
+                drop(pstate);
+                msg.reply(ctx.cache_http, response).await?;
+                return Ok(EventHandled::Yes);
+            }
+            "" => ctx
+                .pstate
+                .read()
+                .await
+                .weather
+                .users
+                .get(&msg.author.id)
+                .cloned()
+                .ok_or_else(|| {
+                    PluginError::UserError(
+                        "Give a location, or save one with `weather set <location>`.".to_string(),
+                    )
+                })?,
+            _ => args.to_string(),
+        };
+
+        let units = ctx.cfg.read().await.weather_units(msg.guild_id);
+        let place = geocode(&location).await?;
+        let forecast = forecast(&place, units).await?;
+
+        msg.channel_id
+            .send_message(
+                ctx.cache_http,
+                CreateMessage::new()
+                    .embed(render(&place, &forecast, units))
+                    .reference_message(msg),
+            )
+            .await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::EMBED_LINKS)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+async fn geocode(location: &str) -> Result<Place> {
+    reqwest::Client::new()
+        .get(GEOCODING_URL)
+        .query(&[("name", location), ("count", "1")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<GeocodingResponse>()
+        .await?
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| PluginError::UserError(format!("I couldn't find `{}`.", location)))
+}
+
+async fn forecast(place: &Place, units: Units) -> Result<ForecastResponse> {
+    let (temperature_unit, wind_speed_unit) = match units {
+        Units::Metric => ("celsius", "kmh"),
+        Units::Imperial => ("fahrenheit", "mph"),
+    };
+    Ok(reqwest::Client::new()
+        .get(FORECAST_URL)
+        .query(&[
+            ("latitude", place.latitude.to_string().as_str()),
+            ("longitude", place.longitude.to_string().as_str()),
+            (
+                "current",
+                "temperature_2m,apparent_temperature,relative_humidity_2m,wind_speed_10m,weather_code",
+            ),
+            (
+                "daily",
+                "weather_code,temperature_2m_max,temperature_2m_min,precipitation_probability_max",
+            ),
+            ("timezone", "auto"),
+            ("forecast_days", FORECAST_DAYS.to_string().as_str()),
+            ("temperature_unit", temperature_unit),
+            ("wind_speed_unit", wind_speed_unit),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn describe_place(place: &Place) -> String {
+    [
+        Some(place.name.as_str()),
+        place.admin1.as_deref(),
+        place.country.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn render(place: &Place, forecast: &ForecastResponse, units: Units) -> CreateEmbed {
+    let (degrees, speed) = match units {
+        Units::Metric => ("°C", "km/h"),
+        Units::Imperial => ("°F", "mph"),
+    };
+    let current = &forecast.current;
+    let (emoji, description) = describe_code(current.weather_code);
+    let mut embed = CreateEmbed::new()
+        .title(format!("Weather in {}", describe_place(place)))
+        .description(format!(
+            "{} {}, {:.0}{} (feels like {:.0}{})\nHumidity {:.0}%, wind {:.0} {}",
+            emoji,
+            description,
+            current.temperature_2m,
+            degrees,
+            current.apparent_temperature,
+            degrees,
+            current.relative_humidity_2m,
+            current.wind_speed_10m,
+            speed
+        ))
+        .footer(serenity::all::CreateEmbedFooter::new(
+            "Weather data by Open-Meteo.com",
+        ));
+
+    let daily = &forecast.daily;
+    for (i, date) in daily.time.iter().enumerate().take(FORECAST_DAYS) {
+        let (Some(code), Some(max), Some(min)) = (
+            daily.weather_code.get(i),
+            daily.temperature_2m_max.get(i),
+            daily.temperature_2m_min.get(i),
+        ) else {
+            continue;
+        };
+        let (emoji, description) = describe_code(*code);
+        let precipitation = daily
+            .precipitation_probability_max
+            .get(i)
+            .copied()
+            .flatten()
+            .map(|p| format!("\n{}% chance of precipitation", p))
+            .unwrap_or_default();
+        embed = embed.field(
+            date,
+            format!(
+                "{} {}\n{:.0}{} / {:.0}{}{}",
+                emoji, description, max, degrees, min, degrees, precipitation
+            ),
+            true,
+        );
+    }
+    embed
+}
+
+/// Emoji and description of a WMO weather interpretation code
+fn describe_code(code: u8) -> (&'static str, &'static str) {
+    match code {
+        0 => ("☀️", "Clear"),
+        1 => ("🌤️", "Mainly clear"),
+        2 => ("⛅", "Partly cloudy"),
+        3 => ("☁️", "Overcast"),
+        45 | 48 => ("🌫️", "Fog"),
+        51 | 53 | 55 => ("🌦️", "Drizzle"),
+        56 | 57 => ("🌧️", "Freezing drizzle"),
+        61 | 63 | 65 => ("🌧️", "Rain"),
+        66 | 67 => ("🌧️", "Freezing rain"),
+        71 | 73 | 75 | 77 => ("🌨️", "Snow"),
+        80..=82 => ("🌦️", "Rain showers"),
+        85 | 86 => ("🌨️", "Snow showers"),
+        95 => ("⛈️", "Thunderstorm"),
+        96 | 99 => ("⛈️", "Thunderstorm with hail"),
+        _ => ("🌡️", "Unknown"),
+    }
+}
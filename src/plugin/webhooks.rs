@@ -0,0 +1,31 @@
+use crate::error::Result;
+use crate::{event::*, log_internal, plugin::*, webhook};
+
+/// Starts the webhook listener, if configured
+pub struct Webhooks;
+
+#[serenity::async_trait]
+impl Plugin for Webhooks {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn start(&self, ctx: OwnedContext) -> Result<Option<JoinHandle<()>>> {
+        if ctx.ctx().cfg.read().await.webhooks.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(tokio::spawn(async move {
+            if let Err(err) = webhook::serve(ctx).await {
+                log_internal!("Webhook listener stopped: {}", err);
+            }
+        })))
+    }
+
+    async fn handle(&self, _ctx: &Context, _event: &Event) -> Result<EventHandled> {
+        Ok(EventHandled::No)
+    }
+}
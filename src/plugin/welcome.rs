@@ -0,0 +1,89 @@
+//! Templated welcome and farewell messages.
+//!
+//! Templates may use the following placeholders:
+//! - `{user}` - a mention of the member for welcomes, or their name for farewells
+//! - `{guild}` - the guild name
+//! - `{membercount}` - the guild's member count
+
+use crate::error::Result;
+use crate::{event::*, log_internal, plugin::*};
+use serenity::all::{CreateMessage, GuildId, Mentionable};
+
+pub struct Welcome;
+
+#[serenity::async_trait]
+impl Plugin for Welcome {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let (guild_id, user_text, is_join) = match event {
+            Event::GuildMemberAddition(member) => {
+                (member.guild_id, member.mention().to_string(), true)
+            }
+            Event::GuildMemberRemoval {
+                guild_id,
+                user,
+                member,
+            } => {
+                // Prefer their per-server name if it was cached
+                let name = match member {
+                    Some(member) => member.display_name().to_string(),
+                    None => user.name.clone(),
+                };
+                (*guild_id, name, false)
+            }
+            _ => return Ok(EventHandled::No),
+        };
+
+        let (channel, template, rules_dm) = {
+            let cfg = ctx.cfg.read().await;
+            let Some(guild_cfg) = cfg.welcome.as_ref().and_then(|w| w.guilds.get(&guild_id)) else {
+                return Ok(EventHandled::No);
+            };
+            let template = if is_join {
+                guild_cfg.welcome.clone()
+            } else {
+                guild_cfg.farewell.clone()
+            };
+            (guild_cfg.channel, template, guild_cfg.rules_dm.clone())
+        };
+
+        if let Some(template) = template {
+            let content = fill_template(ctx, &template, guild_id, &user_text);
+            channel.say(ctx.cache_http, content).await?;
+        }
+
+        if let (Event::GuildMemberAddition(member), Some(rules_dm)) = (event, rules_dm) {
+            let content = fill_template(ctx, &rules_dm, guild_id, &user_text);
+            // The member may not accept DMs
+            if let Err(err) = member
+                .user
+                .direct_message(ctx.cache_http, CreateMessage::new().content(content))
+                .await
+            {
+                log_internal!("Could not DM rules to {}: {}", member.user.name, err);
+            }
+        }
+
+        // Other plugins might also want to act on this event.
+        Ok(EventHandled::No)
+    }
+}
+
+fn fill_template(ctx: &Context<'_>, template: &str, guild_id: GuildId, user: &str) -> String {
+    let (guild_name, member_count) = match ctx.cache.guild(guild_id) {
+        Some(guild) => (guild.name.clone(), guild.member_count.to_string()),
+        None => ("the server".to_string(), "?".to_string()),
+    };
+
+    template
+        .replace("{user}", user)
+        .replace("{guild}", &guild_name)
+        .replace("{membercount}", &member_count)
+}
@@ -0,0 +1,200 @@
+//! Wikipedia summaries.  Ambiguous queries list the matching pages, and the requester picks one by
+//! replying with its number.
+
+use crate::error::{PluginError, Result};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{CreateEmbed, CreateMessage, Message, Permissions};
+
+const SEARCH_URL: &str = "https://en.wikipedia.org/w/rest.php/v1/search/title";
+const SUMMARY_URL: &str = "https://en.wikipedia.org/api/rest_v1/page/summary/";
+/// Pages offered for an ambiguous query
+const MAX_CHOICES: usize = 5;
+/// Wikimedia asks that API clients identify themselves
+const USER_AGENT: &str = concat!("digmbot/", env!("CARGO_PKG_VERSION"));
+
+pub struct Wiki;
+
+#[derive(serde::Deserialize)]
+struct SearchResponse {
+    pages: Vec<SearchPage>,
+}
+
+#[derive(serde::Deserialize)]
+struct SearchPage {
+    title: String,
+    description: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Summary {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    extract: String,
+    thumbnail: Option<Thumbnail>,
+    content_urls: ContentUrls,
+}
+
+#[derive(serde::Deserialize)]
+struct Thumbnail {
+    source: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ContentUrls {
+    desktop: PageUrls,
+}
+
+#[derive(serde::Deserialize)]
+struct PageUrls {
+    page: String,
+}
+
+#[serenity::async_trait]
+impl Plugin for Wiki {
+    fn name(&self) -> &'static str {
+        "wiki"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}{} <query> - summarize a Wikipedia article",
+            prefix,
+            self.name()
+        ))
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return match event {
+                Event::Message(msg) => handle_choice(ctx, msg).await,
+                _ => Ok(EventHandled::No),
+            };
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let query = args.trim();
+        if query.is_empty() {
+            return Err(PluginError::UserError("Usage: `wiki <query>`".to_string()));
+        }
+
+        let pages = search(query).await?;
+        let Some(first) = pages.first() else {
+            return Err(PluginError::UserError(format!(
+                "No Wikipedia articles match `{}`.",
+                query
+            )));
+        };
+        let summary = summary(&first.title).await?;
+        if summary.kind != "disambiguation" {
+            post_summary(ctx, msg, &summary).await?;
+            return Ok(EventHandled::Yes);
+        }
+
+        // Offer the other matches, which are more specific
+        let choices: Vec<&SearchPage> = pages
+            .iter()
+            .filter(|page| page.title != first.title)
+            .take(MAX_CHOICES)
+            .collect();
+        if choices.is_empty() {
+            post_summary(ctx, msg, &summary).await?;
+            return Ok(EventHandled::Yes);
+        }
+        let mut response = format!(
+            "`{}` could mean several things.  Reply with a number:\n",
+            query
+        );
+        for (i, page) in choices.iter().enumerate() {
+            response.push_str(&format!("{}. {}", i + 1, page.title));
+            if let Some(description) = &page.description {
+                response.push_str(&format!(" - {}", description));
+            }
+            response.push('\n');
+        }
+        ctx.vstate.write().await.wiki_choices.offer(
+            msg.channel_id,
+            msg.author.id,
+            choices.iter().map(|page| page.title.clone()).collect(),
+        );
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS.union(Permissions::EMBED_LINKS)
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// Summarize the page chosen by a numbered reply to an ambiguous query
+async fn handle_choice(ctx: &Context<'_>, msg: &Message) -> Result<EventHandled> {
+    let Ok(choice) = msg.content.trim().parse::<usize>() else {
+        return Ok(EventHandled::No);
+    };
+    let title = ctx
+        .vstate
+        .write()
+        .await
+        .wiki_choices
+        .take(msg.channel_id, msg.author.id, choice);
+    let Some(title) = title else {
+        return Ok(EventHandled::No);
+    };
+    post_summary(ctx, msg, &summary(&title).await?).await?;
+    Ok(EventHandled::Yes)
+}
+
+async fn search(query: &str) -> Result<Vec<SearchPage>> {
+    Ok(reqwest::Client::new()
+        .get(SEARCH_URL)
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .query(&[("q", query), ("limit", &(MAX_CHOICES + 1).to_string())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<SearchResponse>()
+        .await?
+        .pages)
+}
+
+async fn summary(title: &str) -> Result<Summary> {
+    let mut url = reqwest::Url::parse(SUMMARY_URL).map_err(anyhow::Error::from)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid Wikipedia URL"))?
+        .pop_if_empty()
+        .push(&title.replace(' ', "_"));
+    Ok(reqwest::Client::new()
+        .get(url)
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn post_summary(ctx: &Context<'_>, msg: &Message, summary: &Summary) -> Result<()> {
+    let mut embed = CreateEmbed::new()
+        .title(&summary.title)
+        .url(&summary.content_urls.desktop.page)
+        .description(&summary.extract);
+    if let Some(thumbnail) = &summary.thumbnail {
+        embed = embed.thumbnail(&thumbnail.source);
+    }
+    msg.channel_id
+        .send_message(
+            ctx.cache_http,
+            CreateMessage::new().embed(embed).reference_message(msg),
+        )
+        .await?;
+    Ok(())
+}
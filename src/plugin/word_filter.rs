@@ -0,0 +1,160 @@
+//! Removes messages containing words from `[word_filter]`, in the guilds it lists.  Depending on
+//! the guild's mode, the message is either deleted and reported to the moderation log, or
+//! reposted through a webhook under the author's name and avatar with the filtered words masked,
+//! so the conversation still reads naturally.
+
+use crate::error::Result;
+use crate::helper::{post_mod_log, UserHelper};
+use crate::{config::FilterMode, event::*, plugin::*};
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateWebhook, ExecuteWebhook, Message, Permissions, Webhook,
+};
+
+/// Name of the webhook reposts are sent through, created in each channel as needed
+const WEBHOOK_NAME: &str = "digmbot word filter";
+
+pub struct WordFilter;
+
+#[serenity::async_trait]
+impl Plugin for WordFilter {
+    fn name(&self) -> &'static str {
+        "word_filter"
+    }
+
+    async fn usage(&self, _ctx: &Context) -> Option<String> {
+        None
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Event::Message(msg) = event else {
+            return Ok(EventHandled::No);
+        };
+        let Some(guild_id) = msg.guild_id else {
+            return Ok(EventHandled::No);
+        };
+        let (mode, censored) = match &ctx.cfg.read().await.word_filter {
+            Some(filter) => match filter.guilds.get(&guild_id) {
+                Some(&mode) => (mode, censor(&msg.content, &filter.words)),
+                None => return Ok(EventHandled::No),
+            },
+            None => return Ok(EventHandled::No),
+        };
+        let Some(censored) = censored else {
+            return Ok(EventHandled::No);
+        };
+
+        msg.delete(ctx.cache_http).await?;
+        match mode {
+            FilterMode::Delete => {
+                post_mod_log(
+                    ctx,
+                    guild_id,
+                    &format!(
+                        "Deleted a message from <@{}> in <#{}> containing filtered words: {}",
+                        msg.author.id, msg.channel_id, censored
+                    ),
+                )
+                .await?;
+            }
+            FilterMode::Repost => repost(ctx, msg, censored).await?,
+        }
+        // Nothing else should respond to a message which is gone
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        Permissions::MANAGE_MESSAGES.union(Permissions::MANAGE_WEBHOOKS)
+    }
+
+    fn in_quiet_channels(&self) -> QuietMode {
+        QuietMode::Run
+    }
+
+    fn runs_when_ignored(&self) -> bool {
+        true
+    }
+}
+
+/// `content` with each filtered word masked but for its first letter, or None if it has none.
+/// Words are matched whole, ignoring case.
+fn censor(content: &str, words: &[String]) -> Option<String> {
+    let mut censored = String::with_capacity(content.len());
+    let mut found = false;
+    let mut word = String::new();
+    // A trailing separator flushes the last word
+    for c in content.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            if words
+                .iter()
+                .any(|filtered| filtered.to_lowercase() == word.to_lowercase())
+            {
+                found = true;
+                let mut chars = word.chars();
+                censored.extend(chars.next());
+                // Escaped, so the asterisks aren't taken as formatting
+                censored.extend(chars.map(|_| "\\*"));
+            } else {
+                censored.push_str(&word);
+            }
+            word.clear();
+        }
+        censored.push(c);
+    }
+    censored.pop();
+    found.then_some(censored)
+}
+
+/// Post `content` in place of `msg`, as if from its author
+async fn repost(ctx: &Context<'_>, msg: &Message, content: String) -> Result<()> {
+    // Webhooks belong to channels; messages in threads go through the parent's
+    let channel = msg.channel_id.to_channel(ctx.cache_http).await?.guild();
+    let (channel_id, thread_id) = match channel {
+        Some(channel) if channel.thread_metadata.is_some() => match channel.parent_id {
+            Some(parent_id) => (parent_id, Some(channel.id)),
+            None => (channel.id, None),
+        },
+        _ => (msg.channel_id, None),
+    };
+    let webhook = webhook(ctx, channel_id).await?;
+
+    // Attachments can't be reposted as they were, but can still be linked
+    let mut content = content;
+    for attachment in &msg.attachments {
+        content.push('\n');
+        content.push_str(&attachment.url);
+    }
+    let mut builder = ExecuteWebhook::new()
+        .content(content)
+        .username(msg.author.nick_in_guild(ctx, msg.guild_id).await)
+        .avatar_url(msg.author.face())
+        .allowed_mentions(CreateAllowedMentions::new());
+    if let Some(thread_id) = thread_id {
+        builder = builder.in_thread(thread_id);
+    }
+    webhook.execute(ctx.cache_http, false, builder).await?;
+    Ok(())
+}
+
+/// The bot's webhook in `channel_id`, created if it doesn't exist yet
+async fn webhook(ctx: &Context<'_>, channel_id: ChannelId) -> Result<Webhook> {
+    let me = ctx.cache.current_user().id;
+    let existing = channel_id
+        .webhooks(ctx.cache_http)
+        .await?
+        .into_iter()
+        .find(|webhook| {
+            webhook.token.is_some()
+                && webhook.user.as_ref().is_some_and(|user| user.id == me)
+                && webhook.name.as_deref() == Some(WEBHOOK_NAME)
+        });
+    match existing {
+        Some(webhook) => Ok(webhook),
+        None => Ok(channel_id
+            .create_webhook(ctx.cache_http, CreateWebhook::new(WEBHOOK_NAME))
+            .await?),
+    }
+}
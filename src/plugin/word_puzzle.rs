@@ -0,0 +1,217 @@
+//! Commands for the daily word puzzle posted by `word_puzzle.rs`
+
+use crate::error::{PluginError, Result};
+use crate::persistent_state::PuzzleSolve;
+use crate::word_puzzle::{self, MAX_GUESSES, WEEK_SECS};
+use crate::{acl, event::*, plugin::*};
+use serenity::all::{GuildId, Message, Permissions, Timestamp};
+
+pub struct WordPuzzle;
+
+#[serenity::async_trait]
+impl Plugin for WordPuzzle {
+    fn name(&self) -> &'static str {
+        "puzzle"
+    }
+
+    fn command(&self) -> Option<&'static str> {
+        Some(self.name())
+    }
+
+    async fn usage(&self, ctx: &Context) -> Option<String> {
+        let prefix = &ctx.cfg.read().await.general.command_prefix;
+        Some(format!(
+            "{}puzzle <subcommand> -- daily word puzzle\n\
+             | Subcommands:\n\
+             | guess <word> [server id] - guess today's word; wrap it in ||spoilers|| to hide it.\n\
+             |     In a DM, give the server if you're in more than one with a puzzle\n\
+             | today - today's leaderboard\n\
+             | week - this week's leaderboard",
+            prefix
+        ))
+    }
+
+    fn category(&self) -> Category {
+        Category::Games
+    }
+
+    async fn handle(&self, ctx: &Context, event: &Event) -> Result<EventHandled> {
+        let Some((msg, args)) = event.is_bot_cmd(ctx, self.name()).await else {
+            return Ok(EventHandled::No);
+        };
+        acl::check(ctx, msg, self.name(), true).await?;
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let response = match args.as_slice() {
+            ["guess", word, server @ ..] if server.len() <= 1 => {
+                let guild_id = puzzle_guild(ctx, msg, server.first().copied()).await?;
+                guess(ctx, msg, guild_id, word).await?
+            }
+            [board @ ("today" | "week")] => {
+                let Some(guild_id) = msg.guild_id else {
+                    return Err(PluginError::UserError(
+                        "Leaderboards only work within a server.".to_string(),
+                    ));
+                };
+                leaderboard(ctx, guild_id, *board == "week").await
+            }
+            _ => {
+                return Err(PluginError::UserError(
+                    "Invalid command.  See help for usage.".to_string(),
+                ))
+            }
+        };
+
+        msg.reply(ctx.cache_http, response).await?;
+        Ok(EventHandled::Yes)
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        REPLY_PERMISSIONS
+    }
+
+    fn dm_policy(&self) -> DmPolicy {
+        DmPolicy::Allow
+    }
+}
+
+/// The guild whose puzzle a guess is for: the message's own, or in a DM, the one given or the only
+/// one the author shares with the bot that has a puzzle
+async fn puzzle_guild(ctx: &Context<'_>, msg: &Message, server: Option<&str>) -> Result<GuildId> {
+    if let Some(guild_id) = msg.guild_id {
+        return Ok(guild_id);
+    }
+    if let Some(server) = server {
+        return server
+            .parse()
+            .map_err(|_| PluginError::UserError(format!("`{}` is not a server ID.", server)));
+    }
+
+    let guilds: Vec<GuildId> = ctx
+        .pstate
+        .read()
+        .await
+        .word_puzzles
+        .guilds
+        .keys()
+        .copied()
+        .collect();
+    let guilds: Vec<GuildId> = guilds
+        .into_iter()
+        .filter(|guild_id| {
+            ctx.cache
+                .guild(*guild_id)
+                .is_some_and(|guild| guild.members.contains_key(&msg.author.id))
+        })
+        .collect();
+    match guilds.as_slice() {
+        [guild_id] => Ok(*guild_id),
+        [] => Err(PluginError::UserError(
+            "None of your servers have a word puzzle.".to_string(),
+        )),
+        _ => Err(PluginError::UserError(
+            "You're in several servers with a word puzzle.  Add the server ID after your guess."
+                .to_string(),
+        )),
+    }
+}
+
+async fn guess(ctx: &Context<'_>, msg: &Message, guild_id: GuildId, word: &str) -> Result<String> {
+    let word = word.trim_matches('|').to_lowercase();
+    if !word_puzzle::is_word(&word) {
+        return Err(PluginError::UserError(format!(
+            "Guesses must be {} letters.",
+            word_puzzle::WORD_LEN
+        )));
+    }
+
+    let mut pstate = ctx.pstate.write().await;
+    pstate.ensure_writable()?;
+    let Some(puzzle) = pstate.word_puzzles.guilds.get_mut(&guild_id) else {
+        return Err(PluginError::UserError(
+            "There's no word puzzle in that server yet.".to_string(),
+        ));
+    };
+    let guesses = puzzle.guesses.entry(msg.author.id).or_default();
+    let solved = guesses.last() == Some(&puzzle.word);
+    if solved || guesses.len() >= MAX_GUESSES {
+        return Err(PluginError::UserError(format!(
+            "You've finished puzzle #{}.  Come back for the next one!",
+            puzzle.number
+        )));
+    }
+    guesses.push(word.clone());
+
+    let grid: Vec<String> = guesses
+        .iter()
+        .map(|guess| {
+            format!(
+                "{} {}",
+                word_puzzle::mark(&puzzle.word, guess),
+                guess.to_uppercase()
+            )
+        })
+        .collect();
+    let count = guesses.len();
+    let status = if word == puzzle.word {
+        puzzle.solves.push(PuzzleSolve {
+            number: puzzle.number,
+            posted: puzzle.posted,
+            user_id: msg.author.id,
+            guesses: count,
+        });
+        format!(
+            "Solved puzzle #{} in {}/{}!",
+            puzzle.number, count, MAX_GUESSES
+        )
+    } else if count == MAX_GUESSES {
+        format!(
+            "Out of guesses.  Puzzle #{} was {}.",
+            puzzle.number,
+            puzzle.word.to_uppercase()
+        )
+    } else {
+        format!("{}/{} guesses", count, MAX_GUESSES)
+    };
+    pstate.save().await?;
+
+    // Keep the grid from others in the channel
+    let grid = grid.join("\n");
+    Ok(match msg.guild_id {
+        Some(_) => format!("||{}||\n{}", grid, status),
+        None => format!("{}\n{}", grid, status),
+    })
+}
+
+async fn leaderboard(ctx: &Context<'_>, guild_id: GuildId, week: bool) -> String {
+    let (number, solves): (u64, Vec<PuzzleSolve>) = {
+        let pstate = ctx.pstate.read().await;
+        let Some(puzzle) = pstate.word_puzzles.guilds.get(&guild_id) else {
+            return "There's no word puzzle in this server yet.".to_string();
+        };
+        let since = Timestamp::now().unix_timestamp() - WEEK_SECS;
+        let solves = puzzle
+            .solves
+            .iter()
+            .filter(|solve| match week {
+                true => solve.posted > since,
+                false => solve.number == puzzle.number,
+            })
+            .cloned()
+            .collect();
+        (puzzle.number, solves)
+    };
+    if solves.is_empty() {
+        return "Nobody has solved it yet.".to_string();
+    }
+
+    let title = match week {
+        true => "This week's word puzzle leaderboard:".to_string(),
+        false => format!("Puzzle #{} leaderboard:", number),
+    };
+    format!(
+        "{}\n{}",
+        title,
+        word_puzzle::leaderboard(ctx, guild_id, &solves).await
+    )
+}
@@ -0,0 +1,141 @@
+//! Audit log of LLM requests, for debugging persona behavior
+//!
+//! When `[llm_audit]` is configured, every chat request, after redaction, is appended with its
+//! response or error to `~/.config/digmbot/llm_audit.jsonl`, one JSON object per line.  Once the
+//! file reaches `max_bytes` it's rotated to `llm_audit.jsonl.1`, and older files shift up to
+//! `llm_audit.jsonl.<keep_files>`.  The most recent request is also kept in memory for
+//! `;llm lastprompt`.  Files last written before `[retention] history_days` are deleted.
+
+use crate::{config::LlmAudit, context::Context, log_internal};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+const AUDIT_PATH_REL_HOME: &str = ".config/digmbot/llm_audit.jsonl";
+
+/// Keeps concurrent requests from interleaving their lines or rotating at once
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(serde::Serialize)]
+struct Entry<'a, T: serde::Serialize> {
+    /// RFC 3339, UTC
+    timestamp: String,
+    request: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn audit_path() -> Result<PathBuf> {
+    dirs::home_dir()
+        .map(|p| p.join(AUDIT_PATH_REL_HOME))
+        .ok_or(anyhow!("Could not find home directory"))
+}
+
+/// Path of the `n`th rotated file, or the current one for 0
+fn rotated_path(path: &std::path::Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    rotated.into()
+}
+
+/// Record `request` and its outcome.  Failures are logged rather than returned so a full disk
+/// can't stop the bot from replying.
+pub async fn record<T: serde::Serialize>(
+    ctx: &Context<'_>,
+    cfg: &LlmAudit,
+    request: &T,
+    outcome: std::result::Result<String, String>,
+) {
+    let (response, error) = match outcome {
+        Ok(response) => (Some(response), None),
+        Err(error) => (None, Some(error)),
+    };
+    let entry = Entry {
+        timestamp: Utc::now().to_rfc3339(),
+        request,
+        response,
+        error,
+    };
+
+    match serde_json::to_string_pretty(&entry) {
+        Ok(pretty) => ctx.vstate.write().await.last_prompt = Some(pretty),
+        Err(err) => log_internal!("Error serializing LLM audit entry: {}", err),
+    }
+    if let Err(err) = append(cfg, &entry).await {
+        log_internal!("Error writing LLM audit log: {}", err);
+    }
+}
+
+/// Delete the log files, current or rotated, last written before `cutoff`.  Returns the number
+/// deleted.
+pub async fn remove_older_than(cutoff: SystemTime) -> Result<usize> {
+    let path = audit_path()?;
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(0);
+    };
+    if !tokio::fs::try_exists(dir).await? {
+        return Ok(0);
+    }
+
+    let _lock = WRITE_LOCK.lock().await;
+    let mut removed = 0;
+    let mut files = tokio::fs::read_dir(dir).await?;
+    while let Some(file) = files.next_entry().await? {
+        if !file
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&*name.to_string_lossy())
+        {
+            continue;
+        }
+        if file.metadata().await?.modified()? < cutoff {
+            tokio::fs::remove_file(file.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+async fn append<T: serde::Serialize>(cfg: &LlmAudit, entry: &Entry<'_, T>) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let path = audit_path()?;
+    let _lock = WRITE_LOCK.lock().await;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let len = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if len > 0 && len + line.len() as u64 > cfg.max_bytes {
+        // The oldest is overwritten by the one before it
+        for n in (0..cfg.keep_files).rev() {
+            let from = rotated_path(&path, n);
+            if tokio::fs::try_exists(&from).await? {
+                tokio::fs::rename(&from, rotated_path(&path, n + 1)).await?;
+            }
+        }
+        if cfg.keep_files == 0 {
+            tokio::fs::remove_file(&path).await?;
+        }
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
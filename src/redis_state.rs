@@ -0,0 +1,452 @@
+//! Redis backing for parts of the volatile state
+//!
+//! Only built with the `redis` feature.  When `[state_backend]` selects Redis, channel history
+//! and command cooldowns are written through to it, and voice channel queues are kept in it, so
+//! they survive restarts and are shared by instances using the same database.  Redis errors are
+//! logged, and the bot carries on with the in-memory state.  Nothing here is awaited while
+//! holding the state locks, and after a failed connection no other is attempted for a while.
+//!
+//! Keys:
+//! - `digmbot:history:<channel id>`: hash of message ID to JSON `HistoryEntry`
+//! - `digmbot:cooldown:<plugin>:<user id>`: set while the user's cooldown runs
+//! - `digmbot:queue:<voice channel id>`: list of queued user IDs, first in line first
+//! - `digmbot:queue:<voice channel id>:channel`: text channel in which to ping the next in line
+
+use crate::{
+    config::StateBackend,
+    context::{Context, OwnedContext},
+    log_internal,
+    volatile_state::HistoryEntry,
+};
+use anyhow::Result;
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    AsyncCommands,
+};
+use serenity::all::{ChannelId, UserId};
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, Mutex};
+
+const KEY_PREFIX: &str = "digmbot";
+
+/// Stored history beyond a channel's maximum is only trimmed once it exceeds it by this many, as
+/// finding the oldest entries means listing them all
+const HISTORY_TRIM_SLACK: usize = 32;
+
+/// History entries waiting to be written before new ones are dropped
+const HISTORY_BACKLOG: usize = 1000;
+
+/// How long to wait after a failed connection before trying again
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait for a connection or a reply
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Append `ARGV[1]` to the queue `KEYS[1]` unless already in it.  Its position, or 0 if it was
+/// already queued.
+const JOIN_SCRIPT: &str = r"
+if redis.call('LPOS', KEYS[1], ARGV[1]) then
+    return 0
+end
+return redis.call('RPUSH', KEYS[1], ARGV[1])
+";
+
+enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected(Box<ConnectionManager>),
+    Failed(Instant),
+}
+
+static CONNECTION: Mutex<ConnectionState> = Mutex::const_new(ConnectionState::Disconnected);
+
+/// A connection, if Redis is the configured backend and reachable.  Connects on first use, so a
+/// changed `url` takes effect on restart.  While connecting, or for a while after failing to,
+/// other callers get None rather than waiting.
+async fn connection(ctx: &Context<'_>) -> Option<ConnectionManager> {
+    let url = match &ctx.cfg.read().await.state_backend {
+        StateBackend::Redis { url } => url.clone(),
+        StateBackend::Memory => return None,
+    };
+    {
+        let mut state = CONNECTION.lock().await;
+        match &*state {
+            ConnectionState::Connected(connection) => return Some((**connection).clone()),
+            ConnectionState::Connecting => return None,
+            ConnectionState::Failed(at) if at.elapsed() < RECONNECT_DELAY => return None,
+            ConnectionState::Disconnected | ConnectionState::Failed(_) => {}
+        }
+        *state = ConnectionState::Connecting;
+    }
+
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(TIMEOUT)
+        .set_response_timeout(TIMEOUT)
+        .set_number_of_retries(1);
+    let connected = match redis::Client::open(url.as_str()) {
+        Ok(client) => client.get_connection_manager_with_config(config).await,
+        Err(err) => Err(err),
+    };
+    let mut state = CONNECTION.lock().await;
+    match connected {
+        Ok(connection) => {
+            *state = ConnectionState::Connected(Box::new(connection.clone()));
+            Some(connection)
+        }
+        Err(err) => {
+            log_internal!(
+                "Could not connect to Redis, retrying in {}s: {}",
+                RECONNECT_DELAY.as_secs(),
+                err
+            );
+            *state = ConnectionState::Failed(Instant::now());
+            None
+        }
+    }
+}
+
+fn history_key(channel_id: ChannelId) -> String {
+    format!("{}:history:{}", KEY_PREFIX, channel_id)
+}
+
+fn cooldown_key(plugin: &str, user_id: UserId) -> String {
+    format!("{}:cooldown:{}:{}", KEY_PREFIX, plugin, user_id)
+}
+
+fn queue_key(vc_id: ChannelId) -> String {
+    format!("{}:queue:{}", KEY_PREFIX, vc_id)
+}
+
+fn queue_channel_key(vc_id: ChannelId) -> String {
+    format!("{}:queue:{}:channel", KEY_PREFIX, vc_id)
+}
+
+/// A channel's stored history, oldest first.  None if Redis isn't in use or can't be reached.
+pub async fn load_history(ctx: &Context<'_>, channel_id: ChannelId) -> Option<Vec<HistoryEntry>> {
+    let mut connection = connection(ctx).await?;
+    let stored: HashMap<u64, String> = match connection.hgetall(history_key(channel_id)).await {
+        Ok(stored) => stored,
+        Err(err) => {
+            log_internal!(
+                "Could not load history of {} from Redis: {}",
+                channel_id,
+                err
+            );
+            return None;
+        }
+    };
+    let mut entries: Vec<HistoryEntry> = stored
+        .values()
+        .filter_map(|json| serde_json::from_str(json).ok())
+        .collect();
+    entries.sort_unstable_by_key(|entry| entry.message_id);
+    Some(entries)
+}
+
+/// History entry waiting to be written by `write_history`
+struct HistoryWrite {
+    channel_id: ChannelId,
+    message_id: u64,
+    json: String,
+    max: usize,
+}
+
+static HISTORY_WRITES: OnceLock<mpsc::Sender<HistoryWrite>> = OnceLock::new();
+
+/// Queue `entry` to be stored in its channel's history, which keeps about `max` entries.  Doesn't
+/// wait for it to be written.
+pub async fn push_history(
+    ctx: &Context<'_>,
+    channel_id: ChannelId,
+    entry: &HistoryEntry,
+    max: usize,
+) {
+    if matches!(ctx.cfg.read().await.state_backend, StateBackend::Memory) {
+        return;
+    }
+    let json = match serde_json::to_string(entry) {
+        Ok(json) => json,
+        Err(err) => {
+            log_internal!("Could not serialize history entry: {}", err);
+            return;
+        }
+    };
+    let writes = HISTORY_WRITES.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel(HISTORY_BACKLOG);
+        tokio::spawn(write_history(ctx.owned(), receiver));
+        sender
+    });
+    let write = HistoryWrite {
+        channel_id,
+        message_id: entry.message_id.get(),
+        json,
+        max,
+    };
+    if writes.try_send(write).is_err() {
+        log_internal!(
+            "Redis history backlog is full, not storing a message in {}",
+            channel_id
+        );
+    }
+}
+
+/// Write queued history entries, in order, forever
+async fn write_history(owned: OwnedContext, mut receiver: mpsc::Receiver<HistoryWrite>) {
+    while let Some(write) = receiver.recv().await {
+        // Entries arriving while Redis is unreachable are dropped; backfill covers the gap
+        let Some(mut connection) = connection(&owned.ctx()).await else {
+            continue;
+        };
+        let result: Result<()> = async {
+            let key = history_key(write.channel_id);
+            let () = connection.hset(&key, write.message_id, write.json).await?;
+            let len: usize = connection.hlen(&key).await?;
+            if len > write.max + HISTORY_TRIM_SLACK {
+                let ids: Vec<u64> = connection.hkeys(&key).await?;
+                let excess = excess_history(ids, write.max);
+                if !excess.is_empty() {
+                    let () = connection.hdel(&key, excess).await?;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(err) = result {
+            log_internal!(
+                "Could not store history of {} in Redis: {}",
+                write.channel_id,
+                err
+            );
+        }
+    }
+}
+
+/// The oldest of a channel's stored message IDs, beyond the newest `max`
+fn excess_history(mut ids: Vec<u64>, max: usize) -> Vec<u64> {
+    ids.sort_unstable();
+    // Another instance may have trimmed since the length was checked
+    ids.truncate(ids.len().saturating_sub(max));
+    ids
+}
+
+/// Delete stored history older than `cutoff` (unix seconds).  Returns the number of entries
+/// deleted.
+pub async fn remove_history_older_than(ctx: &Context<'_>, cutoff: i64) -> Result<usize> {
+    let Some(mut connection) = connection(ctx).await else {
+        return Ok(0);
+    };
+    let keys: Vec<String> = {
+        let pattern = format!("{}:history:*", KEY_PREFIX);
+        let mut iter = connection.scan_match::<_, String>(pattern).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        keys
+    };
+
+    let mut removed = 0;
+    for key in keys {
+        let stored: HashMap<u64, String> = connection.hgetall(&key).await?;
+        let old: Vec<u64> = stored
+            .iter()
+            .filter(|(_, json)| {
+                serde_json::from_str::<HistoryEntry>(json)
+                    .map_or(true, |entry| entry.timestamp < cutoff)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        if !old.is_empty() {
+            let () = connection.hdel(&key, &old).await?;
+            removed += old.len();
+        }
+    }
+    Ok(removed)
+}
+
+/// Start `user_id`'s cooldown for `plugin` if it has elapsed, across instances.  Otherwise the
+/// time remaining.  None if Redis isn't in use or can't be reached, to fall back on the in-memory
+/// cooldowns.
+pub async fn start_cooldown(
+    ctx: &Context<'_>,
+    plugin: &str,
+    user_id: UserId,
+    cooldown: Duration,
+) -> Option<std::result::Result<(), Duration>> {
+    let mut connection = connection(ctx).await?;
+    let key = cooldown_key(plugin, user_id);
+    let result: redis::RedisResult<_> = async {
+        // Only set if absent, i.e. if the cooldown has elapsed and the key expired
+        let started: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("PX")
+            .arg(cooldown.as_millis() as u64)
+            .arg("NX")
+            .query_async(&mut connection)
+            .await?;
+        if started.is_some() {
+            return Ok(Ok(()));
+        }
+        let remaining: i64 = connection.pttl(&key).await?;
+        Ok(Err(Duration::from_millis(remaining.max(0) as u64)))
+    }
+    .await;
+    match result {
+        Ok(result) => Some(result),
+        Err(err) => {
+            log_internal!("Could not check cooldown in Redis: {}", err);
+            None
+        }
+    }
+}
+
+/// Atomic pipeline on a voice channel's queue, first recording `text_channel` as where to ping
+/// the next in line
+fn queue_pipe(vc_id: ChannelId, text_channel: ChannelId) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .set(queue_channel_key(vc_id), text_channel.get())
+        .ignore();
+    pipe
+}
+
+/// Run `pipe`, logging any error.  None if Redis isn't in use or can't be reached.
+async fn query_queue<T: redis::FromRedisValue>(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    pipe: &redis::Pipeline,
+) -> Option<T> {
+    let mut connection = connection(ctx).await?;
+    match pipe.query_async(&mut connection).await {
+        Ok(result) => Some(result),
+        Err(err) => {
+            log_internal!("Could not update queue for {} in Redis: {}", vc_id, err);
+            None
+        }
+    }
+}
+
+/// Append `user_id` to a voice channel's queue.  Their position, or None if already queued.
+pub async fn queue_join(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: ChannelId,
+    user_id: UserId,
+) -> Option<Option<usize>> {
+    let mut pipe = queue_pipe(vc_id, text_channel);
+    pipe.cmd("EVAL")
+        .arg(JOIN_SCRIPT)
+        .arg(1)
+        .arg(queue_key(vc_id))
+        .arg(user_id.get());
+    let (position,): (usize,) = query_queue(ctx, vc_id, &pipe).await?;
+    Some(Some(position).filter(|position| *position > 0))
+}
+
+/// Remove `user_id` from a voice channel's queue.  Whether they were in it.
+pub async fn queue_leave(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: ChannelId,
+    user_id: UserId,
+) -> Option<bool> {
+    let mut pipe = queue_pipe(vc_id, text_channel);
+    pipe.lrem(queue_key(vc_id), 0, user_id.get());
+    let (removed,): (usize,) = query_queue(ctx, vc_id, &pipe).await?;
+    Some(removed > 0)
+}
+
+/// Everyone in a voice channel's queue, first in line first
+pub async fn queue_members(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: ChannelId,
+) -> Option<Vec<UserId>> {
+    let mut pipe = queue_pipe(vc_id, text_channel);
+    pipe.lrange(queue_key(vc_id), 0, -1);
+    let (members,): (Vec<u64>,) = query_queue(ctx, vc_id, &pipe).await?;
+    Some(members.into_iter().map(UserId::new).collect())
+}
+
+/// Remove `left` from a voice channel's queue, if given, then take the next in line along with
+/// the text channel in which to ping them
+pub async fn queue_pop(
+    ctx: &Context<'_>,
+    vc_id: ChannelId,
+    text_channel: Option<ChannelId>,
+    left: Option<UserId>,
+) -> Option<Option<(UserId, ChannelId)>> {
+    let mut pipe = match text_channel {
+        Some(text_channel) => queue_pipe(vc_id, text_channel),
+        None => {
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            pipe
+        }
+    };
+    if let Some(left) = left {
+        pipe.lrem(queue_key(vc_id), 0, left.get()).ignore();
+    }
+    pipe.lpop(queue_key(vc_id), None)
+        .get(queue_channel_key(vc_id));
+    let (next, text_channel): (Option<u64>, Option<u64>) = query_queue(ctx, vc_id, &pipe).await?;
+    Some(
+        next.zip(text_channel)
+            .map(|(next, text_channel)| (UserId::new(next), ChannelId::new(text_channel))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::all::MessageId;
+
+    #[test]
+    fn key_format() {
+        let channel_id = ChannelId::new(123);
+        assert_eq!(history_key(channel_id), "digmbot:history:123");
+        assert_eq!(queue_key(channel_id), "digmbot:queue:123");
+        assert_eq!(queue_channel_key(channel_id), "digmbot:queue:123:channel");
+        assert_eq!(
+            cooldown_key("history_search", UserId::new(456)),
+            "digmbot:cooldown:history_search:456"
+        );
+    }
+
+    #[test]
+    fn excess_history_is_oldest() {
+        assert_eq!(excess_history(vec![5, 1, 4, 2, 3], 2), vec![1, 2, 3]);
+        assert_eq!(excess_history(vec![2, 1], 2), Vec::<u64>::new());
+        // Trimmed by another instance in the meantime
+        assert_eq!(excess_history(vec![1], 2), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn history_entry_round_trip() {
+        let entry = HistoryEntry {
+            message_id: MessageId::new(1),
+            timestamp: 1_700_000_000,
+            author_id: UserId::new(2),
+            author_name: "someone".to_string(),
+            human_format_content: "hello @everyone".to_string(),
+            image_urls: vec!["https://example.com/a.png".to_string()],
+            archived: HashMap::from([(
+                "https://example.com/a.png".to_string(),
+                "/tmp/a.png".into(),
+            )]),
+            attachments: vec!["a.png".to_string()],
+            embeds: vec!["title: description".to_string()],
+            reply_to: None,
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        let parsed: HistoryEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        assert_eq!(parsed.message_id, entry.message_id);
+        assert_eq!(parsed.archived, entry.archived);
+    }
+}
@@ -0,0 +1,452 @@
+//! Cron-like scheduling of bot actions
+//!
+//! Schedules are stored in `PersistentState` and evaluated once per minute by a background task
+//! started on `Ready`.  Jobs scheduled by configuration, such as `[backup]`, the `[reactions]`
+//! highlight, `[[channel_schedules]]`, `[[photo_contests]]`, the `[word_puzzle]`, and
+//! `[llm_keep_alive]` pings, run here as well.  Schedule expressions use the standard five cron fields:
+//!
+//! ```text
+//! minute hour day-of-month month day-of-week
+//! ```
+//!
+//! Each field may be `*`, a number, a range (`1-5`), a list (`1,15`), or a step (`*/15`, `0-30/10`).
+//! Months and days of the week may also be given by three-letter name, e.g. `JAN` or `MON`.  As in
+//! standard cron, if both day-of-month and day-of-week are restricted, either matching suffices; a
+//! field starting with `*`, such as `*/2`, does not count as restricted.
+
+use crate::{
+    backup, channel_schedule,
+    context::{Context, OwnedContext},
+    helper::{format_number, UserIdHelper},
+    llm, log_internal,
+    persistent_state::{ScheduleEntry, ScheduledAction},
+    photo_contest, word_puzzle,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const DAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+/// Longest each month can be, counting February 29th
+const MONTH_LENGTHS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// Parsed cron expression.  Each field is a bitmask of matching values.
+pub struct Cron {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields.as_slice() else {
+            return Err(anyhow!(
+                "Expected five fields (minute hour day-of-month month day-of-week), found {}",
+                fields.len()
+            ));
+        };
+
+        // Sunday may be 0 or 7
+        let days_of_week = parse_field(day_of_week, 0, 7, &DAY_NAMES, 0)?;
+        let days_of_week = (days_of_week | (days_of_week >> 7)) & 0x7f;
+
+        let cron = Self {
+            minutes: parse_field(minute, 0, 59, &[], 0)?,
+            hours: parse_field(hour, 0, 23, &[], 0)? as u32,
+            days_of_month: parse_field(day_of_month, 1, 31, &[], 0)? as u32,
+            months: parse_field(month, 1, 12, &MONTH_NAMES, 1)? as u16,
+            days_of_week: days_of_week as u8,
+            day_of_month_restricted: !day_of_month.starts_with('*'),
+            day_of_week_restricted: !day_of_week.starts_with('*'),
+        };
+
+        // With the day of the week unrestricted, e.g. `0 0 31 2 *` could never run
+        let day_possible = (1..=12)
+            .filter(|&month| cron.months & (1 << month) != 0)
+            .any(|month| {
+                let length = MONTH_LENGTHS[month as usize - 1];
+                cron.days_of_month as u64 & ((2 << length) - 1) != 0
+            });
+        if !day_possible && !cron.day_of_week_restricted {
+            return Err(anyhow!("Cron expression `{}` never matches", expression));
+        }
+        Ok(cron)
+    }
+
+    pub fn matches<T: TimeZone>(&self, time: &DateTime<T>) -> bool {
+        self.matches_date(time.date_naive())
+            && self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let day = match (self.day_of_month_restricted, self.day_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+        day && self.months & (1 << date.month()) != 0
+    }
+
+    /// Next matching minute strictly after `time`, if any within the next few decades.
+    pub fn next_after<T: TimeZone>(&self, time: &DateTime<T>) -> Option<DateTime<T>> {
+        let timezone = time.timezone();
+        let mut date = time.date_naive();
+        // Dates fall on the same days of the week every 28 years, barring skipped leap years
+        for _ in 0..(28 * 366) {
+            if self.matches_date(date) {
+                let times = (0..24)
+                    .filter(|hour| self.hours & (1 << hour) != 0)
+                    .flat_map(|hour| (0..60).map(move |minute| (hour, minute)))
+                    .filter(|(_, minute)| self.minutes & (1 << minute) != 0);
+                for (hour, minute) in times {
+                    // Local times skipped by a DST change never match
+                    let Some(candidate) = timezone
+                        .from_local_datetime(&date.and_hms_opt(hour, minute, 0)?)
+                        .earliest()
+                    else {
+                        continue;
+                    };
+                    if candidate > *time {
+                        return Some(candidate);
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+/// Parse one cron field into a bitmask over `min..=max`.  `names[i]` is an alias for `i + offset`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        if let Some(i) = names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            return Ok(i as u32 + offset);
+        }
+        let v: u32 = s
+            .parse()
+            .map_err(|_| anyhow!("Invalid cron value `{}`", s))?;
+        if v < min || v > max {
+            return Err(anyhow!("Cron value `{}` out of range {}-{}", v, min, max));
+        }
+        Ok(v)
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid cron step `{}`", step))?;
+                if step == 0 {
+                    return Err(anyhow!("Cron step must be non-zero"));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // A bare value with a step, e.g. `5/15`, runs through the maximum
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(anyhow!("Invalid cron range `{}`", range));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse()
+        .map_err(|_| anyhow!("Unknown timezone `{}`, e.g. `America/New_York`", name))
+}
+
+/// Run scheduled actions at the top of every minute, forever.
+pub async fn run(owned: OwnedContext) {
+    loop {
+        // Sleep until just after the next minute boundary
+        let now = Utc::now();
+        let into_minute = now.second() as u64 * 1000 + now.timestamp_subsec_millis() as u64;
+        tokio::time::sleep(std::time::Duration::from_millis(60_000 - into_minute + 50)).await;
+
+        let Some(minute) = Utc::now().with_second(0).and_then(|t| t.with_nanosecond(0)) else {
+            continue;
+        };
+        run_due(&owned.ctx(), minute).await;
+    }
+}
+
+async fn run_due(ctx: &Context<'_>, minute: DateTime<Utc>) {
+    run_configured(ctx, minute).await;
+
+    let entries = ctx.pstate.read().await.schedules.entries.clone();
+    for entry in entries {
+        match is_cron_due(&entry.cron, &entry.timezone, minute) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                log_internal!("Invalid schedule #{}: {}", entry.id, err);
+                continue;
+            }
+        }
+        if let Err(err) = execute(ctx, &entry.action).await {
+            log_internal!("Error running schedule #{}: {}", entry.id, err);
+        }
+    }
+}
+
+/// Jobs scheduled by configuration rather than by `schedule` commands
+async fn run_configured(ctx: &Context<'_>, minute: DateTime<Utc>) {
+    let (backup_due, highlight_due, puzzle_due, keep_alive_due, channels_due, contests_due) = {
+        let cfg = ctx.cfg.read().await;
+        let timezone = cfg
+            .scheduler
+            .as_ref()
+            .map(|s| s.default_timezone.as_str())
+            .unwrap_or("UTC");
+        let backup_due = match &cfg.backup {
+            Some(backup) => is_cron_due(&backup.schedule, timezone, minute),
+            None => Ok(false),
+        };
+        let highlight_due = match &cfg.reactions {
+            Some(reactions) => is_cron_due(&reactions.highlight_schedule, timezone, minute),
+            None => Ok(false),
+        };
+        let puzzle_due = match &cfg.word_puzzle {
+            Some(word_puzzle) => is_cron_due(&word_puzzle.schedule, timezone, minute),
+            None => Ok(false),
+        };
+        let keep_alive_due = match &cfg.llm_keep_alive {
+            Some(keep_alive) => is_cron_due(&keep_alive.schedule, timezone, minute),
+            None => Ok(false),
+        };
+        let mut channels_due = Vec::new();
+        for schedule in &cfg.channel_schedules {
+            let timezone = schedule.timezone.as_deref().unwrap_or(timezone);
+            for (open, cron) in [(true, &schedule.open), (false, &schedule.close)] {
+                match is_cron_due(cron, timezone, minute) {
+                    Ok(true) => channels_due.push((schedule.channel, schedule.mode, open)),
+                    Ok(false) => {}
+                    Err(err) => {
+                        log_internal!("Invalid channel schedule for {}: {}", schedule.channel, err)
+                    }
+                }
+            }
+        }
+        let mut contests_due = Vec::new();
+        for contest in &cfg.photo_contests {
+            let timezone = contest.timezone.as_deref().unwrap_or(timezone);
+            // Close before opening, in case one contest ends as the next begins
+            let steps = [
+                (photo_contest::Step::Close, &contest.close),
+                (photo_contest::Step::Open, &contest.open),
+                (photo_contest::Step::Vote, &contest.vote),
+            ];
+            for (step, cron) in steps {
+                match is_cron_due(cron, timezone, minute) {
+                    Ok(true) => contests_due.push((contest.channel, step)),
+                    Ok(false) => {}
+                    Err(err) => {
+                        log_internal!(
+                            "Invalid photo contest schedule for {}: {}",
+                            contest.channel,
+                            err
+                        )
+                    }
+                }
+            }
+        }
+        (
+            backup_due,
+            highlight_due,
+            puzzle_due,
+            keep_alive_due,
+            channels_due,
+            contests_due,
+        )
+    };
+
+    match backup_due {
+        Ok(true) => {
+            if let Err(err) = backup::run(ctx).await {
+                log_internal!("{}", err);
+            }
+        }
+        Ok(false) => {}
+        Err(err) => log_internal!("Invalid backup schedule: {}", err),
+    }
+
+    match highlight_due {
+        Ok(true) => {
+            if let Err(err) = post_reaction_highlight(ctx).await {
+                log_internal!("Error posting reaction highlight: {}", err);
+            }
+        }
+        Ok(false) => {}
+        Err(err) => log_internal!("Invalid reaction highlight schedule: {}", err),
+    }
+
+    match puzzle_due {
+        Ok(true) => {
+            if let Err(err) = word_puzzle::post_daily(ctx).await {
+                log_internal!("Error posting word puzzle: {}", err);
+            }
+        }
+        Ok(false) => {}
+        Err(err) => log_internal!("Invalid word puzzle schedule: {}", err),
+    }
+
+    match keep_alive_due {
+        Ok(true) => {
+            if let Err(err) = llm::keep_alive(ctx).await {
+                log_internal!("Error keeping the LLM loaded: {}", err);
+            }
+        }
+        Ok(false) => {}
+        Err(err) => log_internal!("Invalid LLM keep-alive schedule: {}", err),
+    }
+
+    for (channel_id, step) in contests_due {
+        if let Err(err) = photo_contest::run_step(ctx, channel_id, step).await {
+            log_internal!("Error running photo contest in {}: {}", channel_id, err);
+        }
+    }
+
+    for (channel_id, mode, open) in channels_due {
+        if let Err(err) = channel_schedule::set_open(ctx, channel_id, mode, open, "scheduled").await
+        {
+            log_internal!("Error updating scheduled channel {}: {}", channel_id, err);
+        }
+    }
+}
+
+/// Post the week's most reacted messages in the highlight channel's guild
+async fn post_reaction_highlight(ctx: &Context<'_>) -> Result<()> {
+    let (channel_id, count) = match &ctx.cfg.read().await.reactions {
+        Some(reactions) => (reactions.highlight_channel, reactions.highlight_count),
+        None => return Ok(()),
+    };
+    let guild_id = channel_id
+        .to_channel(ctx.cache_http)
+        .await?
+        .guild()
+        .ok_or(anyhow!("Reaction highlight channel is not in a server"))?
+        .guild_id;
+
+    let top = ctx
+        .pstate
+        .read()
+        .await
+        .reactions
+        .top_messages(guild_id, count);
+    if top.is_empty() {
+        return Ok(());
+    }
+    let mut content = String::from("Most reacted messages this week:");
+    for (rank, (message_id, message)) in top.iter().enumerate() {
+        let author = message.author_id.nick_in_guild(ctx, Some(guild_id)).await;
+        content.push_str(&format!(
+            "\n{}. {} ({} reactions): {}",
+            rank + 1,
+            author,
+            format_number(message.count),
+            message_id.link(message.channel_id, Some(guild_id)),
+        ));
+    }
+    channel_id.say(ctx.cache_http, content).await?;
+    Ok(())
+}
+
+fn is_cron_due(cron: &str, timezone: &str, minute: DateTime<Utc>) -> Result<bool> {
+    let cron = Cron::parse(cron)?;
+    let tz = parse_timezone(timezone)?;
+    Ok(cron.matches(&minute.with_timezone(&tz)))
+}
+
+async fn execute(ctx: &Context<'_>, action: &ScheduledAction) -> Result<()> {
+    match action {
+        ScheduledAction::Post {
+            channel_id,
+            message,
+        } => {
+            let message = ctx.cfg.read().await.redact(message).into_owned();
+            channel_id.say(ctx.cache_http, message).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Next time a schedule entry will run, as unix seconds
+pub fn next_run(entry: &ScheduleEntry) -> Result<Option<i64>> {
+    let cron = Cron::parse(&entry.cron)?;
+    let tz = parse_timezone(&entry.timezone)?;
+    let now = Utc::now().with_timezone(&tz);
+    Ok(cron.next_after(&now).map(|t| t.timestamp()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn rejects_impossible_day() {
+        assert!(Cron::parse("0 0 31 2 *").is_err());
+        assert!(Cron::parse("0 0 30,31 FEB *").is_err());
+        assert!(Cron::parse("0 0 29 2 *").is_ok());
+        // Either field matching suffices when both are restricted
+        assert!(Cron::parse("0 0 31 2 MON").is_ok());
+    }
+
+    #[test]
+    fn finds_leap_day() {
+        let cron = Cron::parse("30 12 29 2 *").unwrap();
+        let next = cron.next_after(&utc("2025-03-01T00:00:00Z"));
+        assert_eq!(next, Some(utc("2028-02-29T12:30:00Z")));
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        let cron = Cron::parse("*/15 * * * *").unwrap();
+        let next = cron.next_after(&utc("2025-01-01T10:15:00Z"));
+        assert_eq!(next, Some(utc("2025-01-01T10:30:00Z")));
+    }
+
+    #[test]
+    fn starred_step_is_unrestricted() {
+        // Odd days which are also Mondays, not odd days or Mondays
+        let cron = Cron::parse("0 0 */2 * MON").unwrap();
+        let next = cron.next_after(&utc("2025-01-01T00:00:00Z"));
+        assert_eq!(next, Some(utc("2025-01-13T00:00:00Z")));
+    }
+
+    #[test]
+    fn skips_nonexistent_local_time() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let cron = Cron::parse("30 2 * * *").unwrap();
+        let next = cron.next_after(&utc("2025-03-09T05:00:00Z").with_timezone(&tz));
+        assert_eq!(next, Some(utc("2025-03-10T06:30:00Z").with_timezone(&tz)));
+    }
+}
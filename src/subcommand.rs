@@ -0,0 +1,187 @@
+//! Dispatch of plugins' subcommands
+//!
+//! A plugin with subcommands describes each with a `SubcommandRouter`, which then parses the
+//! command's arguments, checks permissions and calls the matching handler.  Its usage, and the
+//! replies for unknown subcommands and missing arguments, come from the same descriptions.
+
+use crate::error::{PluginError, Result};
+use crate::{acl, context::Context, event::EventHandled, helper::Args};
+use futures::future::BoxFuture;
+use serenity::all::{Message, Permissions};
+
+/// Subcommand handler, given the arguments after the subcommand's name
+pub type Handler =
+    for<'a> fn(&'a Context<'a>, &'a Message, &'a Args) -> BoxFuture<'a, Result<EventHandled>>;
+
+/// Who may use a subcommand.  Restricted subcommands are checked against the capability named
+/// after the plugin and the subcommand's first word, e.g. `typingpace.on`, so server admins can
+/// adjust them with `;perm` and bot owners can delegate them with `;grant`.
+#[derive(Clone, Copy)]
+pub enum Access {
+    /// Anyone who may use the plugin
+    Everyone,
+    /// By default, those with these Discord permissions
+    Permissions(Permissions),
+    /// By default, bot owners only
+    Owner,
+}
+
+struct Subcommand {
+    /// One or more words, e.g. `season start`
+    name: &'static str,
+    /// The arguments, e.g. `<player1> beat <player2>`.  Words which aren't in `[brackets]` are
+    /// required.
+    args: &'static str,
+    description: &'static str,
+    access: Access,
+    handler: Handler,
+}
+
+pub struct SubcommandRouter {
+    plugin: &'static str,
+    description: &'static str,
+    subcommands: Vec<Subcommand>,
+    notes: Vec<&'static str>,
+}
+
+impl SubcommandRouter {
+    pub fn new(plugin: &'static str, description: &'static str) -> Self {
+        Self {
+            plugin,
+            description,
+            subcommands: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Add a subcommand.  `syntax` is its name followed by its arguments, e.g.
+    /// `report <player1> beat <player2>`; the name is the words before the first `<argument>` or
+    /// `[argument]`.
+    pub fn subcommand(
+        mut self,
+        syntax: &'static str,
+        description: &'static str,
+        access: Access,
+        handler: Handler,
+    ) -> Self {
+        let split = [syntax.find(" <"), syntax.find(" [")]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(syntax.len());
+        self.subcommands.push(Subcommand {
+            name: &syntax[..split],
+            args: syntax[split..].trim(),
+            description,
+            access,
+            handler,
+        });
+        self
+    }
+
+    /// Add a line to the end of the usage, e.g. about argument formats
+    pub fn note(mut self, note: &'static str) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Usage for the help message, listing each subcommand
+    pub fn usage(&self, prefix: &str) -> String {
+        let mut usage = format!(
+            "{}{} <subcommand> -- {}\n| Subcommands:",
+            prefix, self.plugin, self.description
+        );
+        for subcommand in &self.subcommands {
+            usage.push_str(&format!("\n| {}", subcommand.syntax()));
+            usage.push_str(&format!(" - {}", subcommand.description));
+            match subcommand.access {
+                Access::Everyone => {}
+                Access::Permissions(permissions) => usage.push_str(&format!(
+                    " (requires {})",
+                    permissions.get_permission_names().join(", ")
+                )),
+                Access::Owner => usage.push_str(" (bot owner only)"),
+            }
+        }
+        for note in &self.notes {
+            usage.push_str(&format!("\n| {}", note));
+        }
+        usage
+    }
+
+    /// Run the subcommand named at the start of `args`
+    pub async fn dispatch(
+        &self,
+        ctx: &Context<'_>,
+        msg: &Message,
+        args: &str,
+    ) -> Result<EventHandled> {
+        let args = Args::parse(args);
+        let given = args.positional();
+        // The longest name which matches, so `season start` wins over `season`
+        let Some(subcommand) = self
+            .subcommands
+            .iter()
+            .filter(|subcommand| {
+                let words: Vec<&str> = subcommand.name.split(' ').collect();
+                given.len() >= words.len()
+                    && words
+                        .iter()
+                        .zip(&given)
+                        .all(|(word, arg)| word.eq_ignore_ascii_case(arg))
+            })
+            .max_by_key(|subcommand| subcommand.name.len())
+        else {
+            let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+            let problem = match given.first() {
+                Some(arg) => format!("Unknown subcommand `{}`.", arg),
+                None => "Please provide a subcommand.".to_string(),
+            };
+            return Err(PluginError::UserError(format!(
+                "{}\n```\n{}\n```",
+                problem,
+                self.usage(&prefix)
+            )));
+        };
+
+        let capability = format!(
+            "{}.{}",
+            self.plugin,
+            subcommand.name.split(' ').next().unwrap_or_default()
+        );
+        match subcommand.access {
+            Access::Everyone => {}
+            Access::Permissions(permissions) => {
+                let permitted = msg
+                    .author_permissions(ctx.cache)
+                    .is_some_and(|p| p.contains(permissions));
+                acl::check(ctx, msg, &capability, permitted).await?;
+            }
+            Access::Owner => acl::check(ctx, msg, &capability, false).await?,
+        }
+
+        let args = args.skip(subcommand.name.split(' ').count());
+        let required = subcommand
+            .args
+            .split_whitespace()
+            .filter(|arg| !arg.starts_with('['))
+            .count();
+        if args.positional().len() < required {
+            return Err(PluginError::UserError(format!(
+                "Usage: `{}`",
+                subcommand.syntax()
+            )));
+        }
+        (subcommand.handler)(ctx, msg, &args).await
+    }
+}
+
+impl Subcommand {
+    fn syntax(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
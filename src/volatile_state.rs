@@ -1,38 +1,361 @@
 use crate::{
     context::Context,
-    helper::{MessageHelper, UserHelper},
+    error::Service,
+    helper::{truncate, MessageHelper, UserHelper},
     log_internal,
     logging::AsyncPrintColor,
 };
 use anyhow::Result;
-use serenity::all::{ChannelId, GetMessages, Message, UserId};
-use std::{collections::HashMap, time::Duration};
-use tokio::time::Instant;
+use serenity::all::{ChannelId, GetMessages, GuildId, Message, MessageId, UserId};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::Semaphore, task::JoinHandle, time::Instant};
 
 /// State which is lost across sessions
 pub struct VolatileState {
     pub history: History,
     pub notify_timestamp: NotifyTimestamp,
+    pub summaries: Summaries,
+    pub stream_notify_timestamp: NotifyTimestamp,
+    pub vc_queues: VcQueues,
+    pub digests: Digests,
+    /// When activity stats, including reactions, were last written to disk
+    pub stats_saved: Option<Instant>,
+    pub degraded: Degraded,
+    pub handlers: Handlers,
+    /// When the bot started, for uptime
+    pub started: Instant,
+    pub error_reports: ErrorReports,
+    pub in_flight: InFlight,
+    pub triggers: Triggers,
+    pub cooldowns: Cooldowns,
+    pub confirmations: Confirmations,
+    pub topic_activity: TopicActivity,
+    pub conversations: Conversations,
+    pub response_budget: ResponseBudget,
+    pub wiki_choices: WikiChoices,
+    pub trivia: TriviaSessions,
+    pub command_slots: CommandSlots,
+    pub metrics: Metrics,
+    /// Read-only maintenance mode, if on.  See `plugin/maintenance.rs`.
+    pub maintenance: Option<Maintenance>,
+    /// Plugins' background tasks, or None until they've been started.  See `Plugin::start`.
+    pub plugin_tasks: Option<Vec<JoinHandle<()>>>,
+    /// The most recent LLM request and response, as JSON, if `[llm_audit]` is configured.  See
+    /// `prompt_audit.rs`.
+    pub last_prompt: Option<String>,
+    pub llm_cache: LlmCache,
+    /// Loaded from disk on startup.  See `plugin/memory.rs`.
+    pub memory: LongTermMemory,
 }
 
 pub struct History(HashMap<ChannelId, Vec<HistoryEntry>>);
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct HistoryEntry {
+    pub message_id: MessageId,
+    /// Unix seconds
+    pub timestamp: i64,
     pub author_id: UserId,
     pub author_name: String,
     /// Translate Discord markup such as `<@123>` to human (and LLM) understandable formats such as
     /// usernames.
     pub human_format_content: String,
+    /// URLs of image attachments, for vision-capable models
+    pub image_urls: Vec<String>,
+    /// Local copies of attachments, keyed by URL.  See `archive.rs`.
+    pub archived: HashMap<String, PathBuf>,
+    /// Attachment filenames
+    pub attachments: Vec<String>,
+    /// Rendered as `title: description`
+    pub embeds: Vec<String>,
+    /// The message this one replies to, if any
+    pub reply_to: Option<ReplyContext>,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ReplyContext {
+    pub author_name: String,
+    /// Truncated content of the replied-to message
+    pub snippet: String,
+}
+
+/// Longest replied-to message or embed text kept in history, in bytes
+const SNIPPET_LEN: usize = 100;
+
 pub struct NotifyTimestamp(HashMap<UserId, Instant>);
 
+/// Rolling summaries of channel history which has fallen out of the LLM context window.
+pub struct Summaries(HashMap<ChannelId, Summary>);
+
+pub struct Summary {
+    pub content: String,
+    /// Most recent message incorporated into the summary
+    pub through: MessageId,
+}
+
+/// Per voice channel waiting lists
+pub struct VcQueues(HashMap<ChannelId, VcQueue>);
+
+pub struct VcQueue {
+    pub members: VecDeque<UserId>,
+    /// Text channel in which to ping the next person in line
+    pub text_channel: ChannelId,
+}
+
+/// Notifications queued for users' next digest DM
+pub struct Digests(HashMap<UserId, PendingDigest>);
+
+pub struct PendingDigest {
+    pub items: Vec<String>,
+    /// When the oldest pending item was queued
+    pub since: Instant,
+}
+
+/// Backend services which have recently failed
+pub struct Degraded(HashMap<Service, Degradation>);
+
+pub struct Degradation {
+    /// When the service started failing, as unix seconds
+    pub since: i64,
+    pub last_error: String,
+    last_failure: Instant,
+}
+
+/// Which plugin handled recent messages, to attribute the bot's replies in the sent log
+pub struct Handlers(VecDeque<(MessageId, &'static str)>);
+
+const HANDLERS_CAPACITY: usize = 256;
+
+/// Recently reported errors, keyed by plugin and error, to avoid repeatedly reporting the same one
+pub struct ErrorReports(HashMap<(&'static str, String), ReportedError>);
+
+struct ReportedError {
+    reported: Instant,
+    /// Repeats since `reported`
+    suppressed: usize,
+}
+
+/// Plugins currently handling events, for the watchdog
+pub struct InFlight {
+    next_id: u64,
+    operations: HashMap<u64, Operation>,
+}
+
+/// Recent messages which triggered the bot, keyed by content hash, to detect crossposts
+pub struct Triggers(HashMap<u64, Trigger>);
+
+pub struct Trigger {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    /// The bot's reply, once sent
+    pub response: Option<MessageId>,
+    at: Instant,
+}
+
+/// Recent LLM responses, by a hash of their request, for `[llm_cache]`
+pub struct LlmCache(HashMap<u64, (Instant, String)>);
+
+/// Embedded past messages, per channel, oldest first
+pub struct LongTermMemory(HashMap<ChannelId, VecDeque<MemoryEntry>>);
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MemoryEntry {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    /// Unix seconds
+    pub timestamp: i64,
+    pub author_id: UserId,
+    /// `author: content`, as the LLM sees it
+    pub text: String,
+    /// `text`'s embedding, from the `[llm_memory]` model at the time it was indexed
+    pub embedding: Vec<f32>,
+}
+
+/// When users last used each rate limited plugin, by plugin name
+pub struct Cooldowns(HashMap<(&'static str, UserId), Instant>);
+
+/// Messages per channel since its topic summary was last updated
+pub struct TopicActivity(HashMap<ChannelId, usize>);
+
+/// When the bot last replied to each user, per channel, for `[conversation]` follow-ups
+pub struct Conversations(HashMap<(ChannelId, UserId), Instant>);
+
+/// The bot's recent messages per channel, for `[response_budget]`
+pub struct ResponseBudget {
+    sent: HashMap<ChannelId, VecDeque<Instant>>,
+    /// Channels currently over budget, so throttling is only logged once
+    throttled: HashSet<ChannelId>,
+}
+
+/// Pages offered by `wiki` for an ambiguous query, per channel and user, awaiting a numbered
+/// choice
+pub struct WikiChoices(HashMap<(ChannelId, UserId), (Instant, Vec<String>)>);
+
+/// Limits how many events each channel may have running through ordered plugins at once
+pub struct CommandSlots(HashMap<ChannelId, Arc<Semaphore>>);
+
+/// Activity counters for the stats snapshot.  See `plugin/stats_snapshot.rs`.
+pub struct Metrics {
+    /// Events received since startup
+    pub events: u64,
+    /// Events each plugin handled or failed on, by plugin name
+    pub handled: HashMap<&'static str, u64>,
+    /// Round trips of the most recent LLM chat requests
+    llm_latencies: VecDeque<Duration>,
+    /// Replies which were configured fallbacks, as the LLM couldn't be reached
+    pub llm_fallbacks: u64,
+    /// LLM requests answered from `[llm_cache]`
+    pub llm_cache_hits: u64,
+}
+
+/// LLM chat requests whose latencies are kept for percentiles
+const LLM_LATENCY_SAMPLES: usize = 100;
+
+/// Trivia games in progress, by channel.  See `plugin/trivia.rs`.
+pub struct TriviaSessions(HashMap<ChannelId, TriviaSession>);
+
+#[derive(Default)]
+pub struct TriviaSession {
+    /// Points this session
+    pub scores: HashMap<UserId, u32>,
+    /// The question currently accepting answers, if any
+    pub question: Option<TriviaQuestion>,
+    /// Set by `trivia stop` to end the game after the current question
+    pub stopping: bool,
+}
+
+pub struct TriviaQuestion {
+    pub choices: Vec<String>,
+    /// Index of the correct choice
+    pub correct: usize,
+    /// Each player gets one guess
+    pub guessed: HashSet<UserId>,
+    pub winner: Option<UserId>,
+}
+
+pub struct Maintenance {
+    pub since: Instant,
+    pub by: UserId,
+}
+
+/// Destructive operations awaiting confirmation, by token.  See `confirm.rs`.
+pub struct Confirmations(HashMap<String, PendingConfirmation>);
+
+pub struct PendingConfirmation {
+    /// Only the requester may confirm
+    pub user_id: UserId,
+    pub expires: Instant,
+    pub action: crate::confirm::Action,
+}
+
+pub struct Operation {
+    pub plugin: &'static str,
+    /// Message being handled, if any, as channel and message
+    pub message: Option<(ChannelId, MessageId)>,
+    pub started: Instant,
+    /// Cancels the operation.  Taken once the watchdog has fired.
+    pub cancel: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+/// Consider a service recovered if it hasn't failed for this long, even if nothing has confirmed
+/// it works again.
+const DEGRADED_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
 impl VolatileState {
     pub async fn new() -> Self {
         Self {
             history: History::new(),
             notify_timestamp: NotifyTimestamp::new(),
+            summaries: Summaries::new(),
+            stream_notify_timestamp: NotifyTimestamp::new(),
+            vc_queues: VcQueues::new(),
+            digests: Digests::new(),
+            stats_saved: None,
+            degraded: Degraded::new(),
+            handlers: Handlers::new(),
+            started: Instant::now(),
+            error_reports: ErrorReports::new(),
+            in_flight: InFlight::new(),
+            triggers: Triggers::new(),
+            cooldowns: Cooldowns::new(),
+            confirmations: Confirmations::new(),
+            topic_activity: TopicActivity::new(),
+            conversations: Conversations::new(),
+            response_budget: ResponseBudget::new(),
+            wiki_choices: WikiChoices::new(),
+            trivia: TriviaSessions::new(),
+            command_slots: CommandSlots::new(),
+            metrics: Metrics::new(),
+            maintenance: None,
+            plugin_tasks: None,
+            last_prompt: None,
+            llm_cache: LlmCache::new(),
+            memory: LongTermMemory::new(),
+        }
+    }
+}
+
+impl HistoryEntry {
+    pub async fn from_message(ctx: &Context<'_>, msg: &Message) -> Result<Self> {
+        Ok(Self {
+            message_id: msg.id,
+            timestamp: msg.timestamp.unix_timestamp(),
+            author_id: msg.author.id,
+            author_name: msg.author.nick_in_guild(ctx, msg.guild_id).await,
+            human_format_content: msg.human_format_content(ctx).await?,
+            image_urls: msg.image_urls(),
+            archived: HashMap::new(),
+            attachments: msg
+                .attachments
+                .iter()
+                .map(|attachment| attachment.filename.clone())
+                .collect(),
+            embeds: msg
+                .embeds
+                .iter()
+                .filter_map(|embed| {
+                    let text = match (&embed.title, &embed.description) {
+                        (Some(title), Some(description)) => format!("{}: {}", title, description),
+                        (Some(text), None) | (None, Some(text)) => text.clone(),
+                        (None, None) => return None,
+                    };
+                    Some(truncate(&text, SNIPPET_LEN))
+                })
+                .collect(),
+            reply_to: match msg.referenced_message.as_deref() {
+                Some(referenced) => Some(ReplyContext {
+                    author_name: referenced.author.nick_in_guild(ctx, msg.guild_id).await,
+                    snippet: truncate(&referenced.human_format_content(ctx).await?, SNIPPET_LEN),
+                }),
+                None => None,
+            },
+        })
+    }
+
+    /// Content with attachments, embeds, and reply context appended, so the LLM can follow
+    /// references to them, e.g. `look at this [attached: cat.png]`.
+    pub fn llm_content(&self) -> String {
+        let mut content = String::new();
+        if let Some(reply_to) = &self.reply_to {
+            content.push_str(&format!(
+                "(replying to {}: \"{}\") ",
+                reply_to.author_name, reply_to.snippet
+            ));
+        }
+        content.push_str(&self.human_format_content);
+        for attachment in &self.attachments {
+            content.push_str(&format!(" [attached: {}]", attachment));
+        }
+        for embed in &self.embeds {
+            content.push_str(&format!(" [embed: {}]", embed));
         }
+        content
     }
 }
 
@@ -41,17 +364,8 @@ impl<'a> History {
         Self(HashMap::new())
     }
 
-    pub async fn backfill(
-        &'a mut self,
-        ctx: &Context<'_>,
-        channel_id: ChannelId,
-    ) -> Result<&'a mut Vec<HistoryEntry>> {
-        use std::collections::hash_map::Entry::*;
-        let vacant_entry = match self.0.entry(channel_id) {
-            Occupied(occupied_entry) => return Ok(occupied_entry.into_mut()),
-            Vacant(vacant_entry) => vacant_entry,
-        };
-
+    /// Fetch a channel's recent messages from Discord.
+    async fn fetch_recent(ctx: &Context<'_>, channel_id: ChannelId) -> Result<Vec<HistoryEntry>> {
         let backfill_limit = ctx.cfg.read().await.history.channel_backfill_message_count;
 
         log_internal!(
@@ -69,27 +383,70 @@ impl<'a> History {
         // Messages are provided newest to oldest.  Iterate in reverse order so the messages are in chronological order.
         let mut messages = Vec::new();
         for msg in backfill_messages.iter().rev() {
-            let author_id = msg.author.id;
-            let author_name = msg.author.nick_in_guild(ctx, msg.guild_id).await;
-            let human_format_content = msg.human_format_content(ctx).await?;
-
-            let entry = HistoryEntry {
-                author_id,
-                author_name,
-                human_format_content,
-            };
-            messages.push(entry);
+            messages.push(HistoryEntry::from_message(ctx, msg).await?);
         }
 
-        let channel_history = vacant_entry.insert(messages);
-
         log_internal!(
             "Backfilling the last {} messages in \"{}\"... done",
             backfill_limit,
             channel_id.color(ctx.http).await,
         );
 
-        Ok(channel_history)
+        // Stored history reaches back before a restart, and keeps archived attachments
+        #[cfg(feature = "redis")]
+        if let Some(stored) = crate::redis_state::load_history(ctx, channel_id).await {
+            let fetched: HashSet<MessageId> = messages.iter().map(|e| e.message_id).collect();
+            messages.extend(
+                stored
+                    .into_iter()
+                    .filter(|entry| !fetched.contains(&entry.message_id)),
+            );
+            messages.sort_unstable_by_key(|entry| entry.message_id);
+            let history_max = ctx.cfg.read().await.history.channel_max_message_count;
+            let excess = messages.len().saturating_sub(history_max);
+            messages.drain(..excess);
+        }
+
+        Ok(messages)
+    }
+
+    /// Backfill a channel's history if it hasn't been already, without holding the state lock
+    /// while waiting on Discord.  Call before locking the state to access the channel's history.
+    pub async fn ensure_backfilled(ctx: &Context<'_>, channel_id: ChannelId) -> Result<()> {
+        if ctx.vstate.read().await.history.0.contains_key(&channel_id) {
+            return Ok(());
+        }
+        let messages = Self::fetch_recent(ctx, channel_id).await?;
+        // Another event may have backfilled the channel in the meantime.
+        ctx.vstate
+            .write()
+            .await
+            .history
+            .0
+            .entry(channel_id)
+            .or_insert(messages);
+        Ok(())
+    }
+
+    /// A channel's history, if it has been recorded, without backfilling
+    pub fn recorded(&self, channel_id: ChannelId) -> Option<&Vec<HistoryEntry>> {
+        self.0.get(&channel_id)
+    }
+
+    /// Get a channel's history, backfilling it while holding the state lock if necessary.  Prefer
+    /// calling `ensure_backfilled()` first.
+    pub async fn backfill(
+        &'a mut self,
+        ctx: &Context<'_>,
+        channel_id: ChannelId,
+    ) -> Result<&'a mut Vec<HistoryEntry>> {
+        use std::collections::hash_map::Entry::*;
+        match self.0.entry(channel_id) {
+            Occupied(occupied_entry) => Ok(occupied_entry.into_mut()),
+            Vacant(vacant_entry) => {
+                Ok(vacant_entry.insert(Self::fetch_recent(ctx, channel_id).await?))
+            }
+        }
     }
 
     pub async fn get_mut(
@@ -111,21 +468,19 @@ impl<'a> History {
             .map(|history| &*history)
     }
 
-    pub async fn push(&mut self, ctx: &Context<'_>, msg: &Message) -> Result<()> {
-        let channel_id = msg.channel_id;
-        let author_id = msg.author.id;
-        let author_name = msg.author.nick_in_guild(ctx, msg.guild_id).await;
-        let human_format_content = msg.human_format_content(ctx).await?;
-        let entry = HistoryEntry {
-            author_id,
-            author_name,
-            human_format_content,
-        };
-
+    /// Append `entry`, keeping the newest `history_max` entries
+    pub async fn push(
+        &mut self,
+        ctx: &Context<'_>,
+        channel_id: ChannelId,
+        entry: HistoryEntry,
+        history_max: usize,
+    ) -> Result<()> {
         let history = self.get_mut(ctx, channel_id).await?;
-        history.push(entry);
-
-        let history_max = ctx.cfg.read().await.history.channel_max_message_count;
+        // A backfill triggered by this very message may already include it.
+        if !history.iter().any(|e| e.message_id == entry.message_id) {
+            history.push(entry);
+        }
 
         while history.len() > history_max {
             history.remove(0);
@@ -133,6 +488,17 @@ impl<'a> History {
 
         Ok(())
     }
+
+    /// Drop entries older than `cutoff` (unix seconds).  Returns the number of entries removed.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let mut removed = 0;
+        for history in self.0.values_mut() {
+            let len = history.len();
+            history.retain(|entry| entry.timestamp >= cutoff);
+            removed += len - history.len();
+        }
+        removed
+    }
 }
 
 impl NotifyTimestamp {
@@ -157,3 +523,549 @@ impl NotifyTimestamp {
         self.0.insert(id, now);
     }
 }
+
+impl Summaries {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn get(&self, channel_id: ChannelId) -> Option<&Summary> {
+        self.0.get(&channel_id)
+    }
+
+    pub fn insert(&mut self, channel_id: ChannelId, summary: Summary) {
+        self.0.insert(channel_id, summary);
+    }
+}
+
+impl VcQueues {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Get the queue for a voice channel, creating it if necessary.  Updates the text channel in
+    /// which the queue is announced.
+    pub fn get_mut(&mut self, vc_id: ChannelId, text_channel: ChannelId) -> &mut VcQueue {
+        let queue = self.0.entry(vc_id).or_insert_with(|| VcQueue {
+            members: VecDeque::new(),
+            text_channel,
+        });
+        queue.text_channel = text_channel;
+        queue
+    }
+
+    pub fn get_existing_mut(&mut self, vc_id: ChannelId) -> Option<&mut VcQueue> {
+        self.0.get_mut(&vc_id)
+    }
+}
+
+impl Digests {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn push(&mut self, user_id: UserId, item: String) {
+        let now = tokio::time::Instant::now();
+        self.0
+            .entry(user_id)
+            .or_insert_with(|| PendingDigest {
+                items: Vec::new(),
+                since: now,
+            })
+            .items
+            .push(item);
+    }
+
+    /// Digests which have been pending for at least the user's interval.  They stay queued until
+    /// `remove_delivered()`.
+    pub fn due(&self, interval: impl Fn(UserId) -> Duration) -> Vec<(UserId, Vec<String>)> {
+        let now = tokio::time::Instant::now();
+        self.0
+            .iter()
+            .filter(|(user_id, pending)| {
+                !pending.items.is_empty()
+                    && now.duration_since(pending.since) >= interval(**user_id)
+            })
+            .map(|(user_id, pending)| (*user_id, pending.items.clone()))
+            .collect()
+    }
+
+    /// Remove the first `count` items of the user's digest once they're delivered.  Any queued
+    /// since start the next digest.
+    pub fn remove_delivered(&mut self, user_id: UserId, count: usize) {
+        let Some(pending) = self.0.get_mut(&user_id) else {
+            return;
+        };
+        pending.items.drain(..count.min(pending.items.len()));
+        if pending.items.is_empty() {
+            self.0.remove(&user_id);
+        } else {
+            pending.since = tokio::time::Instant::now();
+        }
+    }
+}
+
+impl Degraded {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Note a failure of `service`
+    pub fn mark(&mut self, service: Service, err: &impl std::fmt::Display) {
+        let now = Instant::now();
+        let degradation = self
+            .0
+            .entry(service)
+            .and_modify(|d| {
+                if now.duration_since(d.last_failure) > DEGRADED_EXPIRY {
+                    d.since = serenity::all::Timestamp::now().unix_timestamp();
+                }
+            })
+            .or_insert_with(|| Degradation {
+                since: serenity::all::Timestamp::now().unix_timestamp(),
+                last_error: String::new(),
+                last_failure: now,
+            });
+        degradation.last_error = err.to_string();
+        degradation.last_failure = now;
+    }
+
+    /// Note a success of `service`
+    pub fn clear(&mut self, service: Service) {
+        self.0.remove(&service);
+    }
+
+    /// How `service` is failing, if it is
+    pub fn get(&self, service: Service) -> Option<&Degradation> {
+        self.0
+            .get(&service)
+            .filter(|d| d.last_failure.elapsed() <= DEGRADED_EXPIRY)
+    }
+}
+
+impl Handlers {
+    pub fn new() -> Self {
+        Self(VecDeque::new())
+    }
+
+    pub fn insert(&mut self, message_id: MessageId, plugin: &'static str) {
+        self.0.push_back((message_id, plugin));
+        if self.0.len() > HANDLERS_CAPACITY {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn get(&self, message_id: MessageId) -> Option<&'static str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == message_id)
+            .map(|(_, plugin)| *plugin)
+    }
+}
+
+impl ErrorReports {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Whether to report an error, given it was last reported `window` or longer ago.  If so,
+    /// returns the number of repeats suppressed since the last report.
+    pub fn should_report(
+        &mut self,
+        plugin: &'static str,
+        err: &str,
+        window: Duration,
+    ) -> Option<usize> {
+        let now = Instant::now();
+        // Forget errors which haven't recurred; there's nothing left to tell about them.
+        self.0
+            .retain(|_, e| e.suppressed > 0 || now.duration_since(e.reported) < window);
+
+        use std::collections::hash_map::Entry::*;
+        let reported = match self.0.entry((plugin, err.to_string())) {
+            Occupied(occupied) => occupied.into_mut(),
+            Vacant(vacant) => {
+                vacant.insert(ReportedError {
+                    reported: now,
+                    suppressed: 0,
+                });
+                return Some(0);
+            }
+        };
+        if now.duration_since(reported.reported) < window {
+            reported.suppressed += 1;
+            return None;
+        }
+        let suppressed = reported.suppressed;
+        reported.reported = now;
+        reported.suppressed = 0;
+        Some(suppressed)
+    }
+}
+
+impl InFlight {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Track an operation until `finish()`ed.  Returns its ID.
+    pub fn start(&mut self, operation: Operation) -> u64 {
+        self.next_id += 1;
+        self.operations.insert(self.next_id, operation);
+        self.next_id
+    }
+
+    pub fn finish(&mut self, id: u64) {
+        self.operations.remove(&id);
+    }
+
+    /// Operations running longer than `timeout` which haven't yet been cancelled
+    pub fn stuck(&mut self, timeout: Duration) -> impl Iterator<Item = &mut Operation> {
+        self.operations
+            .values_mut()
+            .filter(move |op| op.cancel.is_some() && op.started.elapsed() > timeout)
+    }
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Record `msg` as a trigger with content hash `hash`.  If the same content already triggered
+    /// the bot in another channel within `window`, returns that trigger instead.
+    pub fn check(&mut self, hash: u64, msg: &Message, window: Duration) -> Option<&Trigger> {
+        self.0.retain(|_, trigger| trigger.at.elapsed() < window);
+        if self
+            .0
+            .get(&hash)
+            .is_some_and(|trigger| trigger.channel_id != msg.channel_id)
+        {
+            return self.0.get(&hash);
+        }
+        self.0.insert(
+            hash,
+            Trigger {
+                guild_id: msg.guild_id,
+                channel_id: msg.channel_id,
+                message_id: msg.id,
+                response: None,
+                at: Instant::now(),
+            },
+        );
+        None
+    }
+
+    /// Note the bot's reply to a recorded trigger, so later crossposts can link to it
+    pub fn responded(&mut self, trigger: MessageId, response: MessageId) {
+        if let Some(trigger) = self.0.values_mut().find(|t| t.message_id == trigger) {
+            trigger.response.get_or_insert(response);
+        }
+    }
+}
+
+impl LlmCache {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// The response to the request with `hash`, if one was cached within `ttl`
+    pub fn get(&mut self, hash: u64, ttl: Duration) -> Option<String> {
+        self.0.retain(|_, (cached, _)| cached.elapsed() < ttl);
+        self.0.get(&hash).map(|(_, response)| response.clone())
+    }
+
+    pub fn insert(&mut self, hash: u64, response: String, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if !self.0.contains_key(&hash) && self.0.len() >= capacity {
+            let oldest = self
+                .0
+                .iter()
+                .min_by_key(|(_, (cached, _))| *cached)
+                .map(|(hash, _)| *hash);
+            if let Some(oldest) = oldest {
+                self.0.remove(&oldest);
+            }
+        }
+        self.0.insert(hash, (Instant::now(), response));
+    }
+}
+
+impl LongTermMemory {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Remember `entry`, forgetting the channel's oldest beyond `capacity`.  Returns the number
+    /// forgotten.
+    pub fn push(&mut self, entry: MemoryEntry, capacity: usize) -> usize {
+        let entries = self.0.entry(entry.channel_id).or_default();
+        entries.push_back(entry);
+        let excess = entries.len().saturating_sub(capacity);
+        entries.drain(..excess);
+        excess
+    }
+
+    /// Forget entries older than `cutoff` (unix seconds).  Returns the number forgotten.
+    pub fn remove_older_than(&mut self, cutoff: i64) -> usize {
+        let mut removed = 0;
+        for entries in self.0.values_mut() {
+            let before = entries.len();
+            entries.retain(|entry| entry.timestamp >= cutoff);
+            removed += before - entries.len();
+        }
+        self.0.retain(|_, entries| !entries.is_empty());
+        removed
+    }
+
+    pub fn channel(&self, channel_id: ChannelId) -> impl Iterator<Item = &MemoryEntry> {
+        self.0.get(&channel_id).into_iter().flatten()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &MemoryEntry> {
+        self.0.values().flatten()
+    }
+}
+
+impl Cooldowns {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Start a user's cooldown for `plugin` if it has elapsed.  Otherwise returns the time
+    /// remaining.
+    pub fn start(
+        &mut self,
+        plugin: &'static str,
+        id: UserId,
+        cooldown: Duration,
+    ) -> std::result::Result<(), Duration> {
+        let now = Instant::now();
+        if let Some(last) = self.0.get(&(plugin, id)) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < cooldown {
+                return Err(cooldown - elapsed);
+            }
+        }
+        self.0.insert((plugin, id), now);
+        Ok(())
+    }
+}
+
+impl TopicActivity {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn record(&mut self, channel_id: ChannelId) {
+        *self.0.entry(channel_id).or_default() += 1;
+    }
+
+    /// Take the channels which have seen at least `threshold` messages, resetting their counts.
+    pub fn take_due(&mut self, threshold: usize) -> Vec<ChannelId> {
+        let due: Vec<ChannelId> = self
+            .0
+            .iter()
+            .filter(|(_, &count)| count >= threshold)
+            .map(|(&channel_id, _)| channel_id)
+            .collect();
+        for channel_id in &due {
+            self.0.remove(channel_id);
+        }
+        due
+    }
+}
+
+impl Conversations {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Record that the bot replied to `user_id` in `channel_id`, forgetting expired conversations.
+    pub fn record(&mut self, channel_id: ChannelId, user_id: UserId, window: Duration) {
+        let now = Instant::now();
+        self.0.retain(|_, last| now.duration_since(*last) < window);
+        self.0.insert((channel_id, user_id), now);
+    }
+
+    /// Whether the bot replied to `user_id` in `channel_id` within `window`
+    pub fn is_active(&self, channel_id: ChannelId, user_id: UserId, window: Duration) -> bool {
+        self.0
+            .get(&(channel_id, user_id))
+            .is_some_and(|last| last.elapsed() < window)
+    }
+}
+
+impl ResponseBudget {
+    /// How far back sent messages count against the budget
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        Self {
+            sent: HashMap::new(),
+            throttled: HashSet::new(),
+        }
+    }
+
+    pub fn record(&mut self, channel_id: ChannelId) {
+        self.sent
+            .entry(channel_id)
+            .or_default()
+            .push_back(Instant::now());
+    }
+
+    /// Whether the bot has sent `limit` messages in `channel_id` within the last minute.  The
+    /// second value is whether the channel has just become throttled.
+    pub fn check(&mut self, channel_id: ChannelId, limit: usize) -> (bool, bool) {
+        let now = Instant::now();
+        self.sent.retain(|_, sent| {
+            while sent
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= Self::WINDOW)
+            {
+                sent.pop_front();
+            }
+            !sent.is_empty()
+        });
+        let exhausted = self
+            .sent
+            .get(&channel_id)
+            .is_some_and(|sent| sent.len() >= limit);
+        if exhausted {
+            (true, self.throttled.insert(channel_id))
+        } else {
+            self.throttled.remove(&channel_id);
+            (false, false)
+        }
+    }
+}
+
+impl WikiChoices {
+    /// How long a choice may be made
+    const EXPIRY: Duration = Duration::from_secs(120);
+
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn offer(&mut self, channel_id: ChannelId, user_id: UserId, titles: Vec<String>) {
+        let now = Instant::now();
+        self.0
+            .retain(|_, (offered, _)| now.duration_since(*offered) < Self::EXPIRY);
+        self.0.insert((channel_id, user_id), (now, titles));
+    }
+
+    /// Take the `choice`th (from 1) page offered to `user_id` in `channel_id`, if still pending
+    pub fn take(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        choice: usize,
+    ) -> Option<String> {
+        let (offered, titles) = self.0.get(&(channel_id, user_id))?;
+        if offered.elapsed() >= Self::EXPIRY {
+            self.0.remove(&(channel_id, user_id));
+            return None;
+        }
+        let title = titles.get(choice.checked_sub(1)?)?.clone();
+        self.0.remove(&(channel_id, user_id));
+        Some(title)
+    }
+}
+
+impl CommandSlots {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// The channel's slots, each of which admits one event at a time
+    pub fn get(&mut self, channel_id: ChannelId, slots: usize) -> Arc<Semaphore> {
+        Arc::clone(
+            self.0
+                .entry(channel_id)
+                .or_insert_with(|| Arc::new(Semaphore::new(slots))),
+        )
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            events: 0,
+            handled: HashMap::new(),
+            llm_latencies: VecDeque::new(),
+            llm_fallbacks: 0,
+            llm_cache_hits: 0,
+        }
+    }
+
+    pub fn record_llm_latency(&mut self, latency: Duration) {
+        if self.llm_latencies.len() == LLM_LATENCY_SAMPLES {
+            self.llm_latencies.pop_front();
+        }
+        self.llm_latencies.push_back(latency);
+    }
+
+    /// The given percentile, from 0 to 100, of recent LLM chat latencies, if there have been any
+    pub fn llm_latency_percentile(&self, percentile: usize) -> Option<Duration> {
+        let mut latencies: Vec<_> = self.llm_latencies.iter().copied().collect();
+        latencies.sort_unstable();
+        let last = latencies.len().checked_sub(1)?;
+        latencies.get(last * percentile.min(100) / 100).copied()
+    }
+}
+
+impl TriviaSessions {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Start a game in `channel_id`, unless one is already running there
+    pub fn start(&mut self, channel_id: ChannelId) -> bool {
+        if self.0.contains_key(&channel_id) {
+            return false;
+        }
+        self.0.insert(channel_id, TriviaSession::default());
+        true
+    }
+
+    pub fn get(&self, channel_id: ChannelId) -> Option<&TriviaSession> {
+        self.0.get(&channel_id)
+    }
+
+    pub fn get_mut(&mut self, channel_id: ChannelId) -> Option<&mut TriviaSession> {
+        self.0.get_mut(&channel_id)
+    }
+
+    pub fn finish(&mut self, channel_id: ChannelId) -> Option<TriviaSession> {
+        self.0.remove(&channel_id)
+    }
+}
+
+impl Confirmations {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn insert(&mut self, token: String, pending: PendingConfirmation) {
+        let now = Instant::now();
+        self.0.retain(|_, pending| pending.expires > now);
+        self.0.insert(token, pending);
+    }
+
+    /// Claim a pending confirmation on behalf of `user_id`.  Expired confirmations, and those
+    /// requested by someone else, are not returned.
+    pub fn take(&mut self, token: &str, user_id: UserId) -> Option<PendingConfirmation> {
+        let pending = self.0.get(token)?;
+        if pending.user_id != user_id || pending.expires <= Instant::now() {
+            return None;
+        }
+        self.0.remove(token)
+    }
+}
@@ -0,0 +1,357 @@
+//! HTTP listener for incoming webhooks
+//!
+//! Only built with the `webhooks` feature.  Currently accepts GitHub push, pull request, and issue
+//! events at `/github` and forwards them into the Discord channel mapped to the repository.  Also
+//! serves `/healthz` for container orchestration.
+
+use crate::{context::OwnedContext, health::Health, log_internal};
+use anyhow::{anyhow, Result};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use serenity::all::{CreateAllowedMentions, CreateMessage};
+use sha2::Sha256;
+
+/// Commits listed in a push notification; the rest are summarized by count
+const MAX_LISTED_COMMITS: usize = 5;
+
+/// Listen for webhooks until the listener fails.
+pub async fn serve(owned: OwnedContext) -> Result<()> {
+    let listen = owned
+        .ctx()
+        .cfg
+        .read()
+        .await
+        .webhooks
+        .as_ref()
+        .map(|webhooks| webhooks.listen.clone())
+        .ok_or_else(|| anyhow!("No [webhooks] configuration"))?;
+
+    let app = Router::new()
+        .route("/github", post(github))
+        .route("/healthz", get(healthz))
+        .with_state(owned);
+
+    log_internal!("Listening for webhooks on {}", listen);
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Health as JSON, with a 503 status if the bot isn't connected to Discord
+async fn healthz(State(owned): State<OwnedContext>) -> (StatusCode, Json<Health>) {
+    let health = Health::check(&owned.ctx()).await;
+    let status = match health.is_healthy() {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(health))
+}
+
+async fn github(State(owned): State<OwnedContext>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let ctx = owned.ctx();
+
+    let (channel_id, content) = {
+        let cfg = ctx.cfg.read().await;
+        let Some(github) = cfg.webhooks.as_ref().and_then(|w| w.github.as_ref()) else {
+            return StatusCode::NOT_FOUND;
+        };
+
+        let signature = headers
+            .get("X-Hub-Signature-256")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !verify_signature(&github.secret, &body, signature) {
+            log_internal!("Rejected GitHub webhook with invalid signature");
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+            return StatusCode::BAD_REQUEST;
+        };
+        let Some(repo) = payload["repository"]["full_name"].as_str() else {
+            return StatusCode::NO_CONTENT;
+        };
+        let Some(channel_id) = github.channels.get(repo) else {
+            return StatusCode::NO_CONTENT;
+        };
+
+        let event = headers
+            .get("X-GitHub-Event")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let Some(content) = format_event(event, &payload) else {
+            return StatusCode::NO_CONTENT;
+        };
+        (*channel_id, cfg.redact(&content).into_owned())
+    };
+
+    match channel_id
+        .send_message(ctx.cache_http, announcement(content))
+        .await
+    {
+        Ok(_) => StatusCode::OK,
+        Err(err) => {
+            log_internal!("Error forwarding GitHub webhook: {}", err);
+            StatusCode::BAD_GATEWAY
+        }
+    }
+}
+
+/// Message for an event.  Titles and commit messages are written by whoever opened the issue or
+/// pushed, so mentions such as `@everyone` in them must not ping anyone.
+fn announcement(content: String) -> CreateMessage {
+    CreateMessage::new()
+        .content(content)
+        .allowed_mentions(CreateAllowedMentions::new())
+}
+
+/// Check GitHub's `sha256=<hex hmac>` signature of the body
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(signature) = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex| hex::decode(hex).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Render a GitHub event as a Discord message.  None for events which aren't announced.
+fn format_event(event: &str, payload: &Value) -> Option<String> {
+    let repo = payload["repository"]["full_name"].as_str()?;
+    let sender = payload["sender"]["login"].as_str().unwrap_or("someone");
+
+    match event {
+        "push" => {
+            let commits = payload["commits"].as_array()?;
+            if commits.is_empty() {
+                return None;
+            }
+            let branch = payload["ref"]
+                .as_str()
+                .map(|r| r.trim_start_matches("refs/heads/"))
+                .unwrap_or("?");
+            let mut content = format!(
+                "[{}] {} pushed {} commit(s) to `{}`\n",
+                repo,
+                sender,
+                commits.len(),
+                branch
+            );
+            for commit in commits.iter().take(MAX_LISTED_COMMITS) {
+                let id = commit["id"].as_str().unwrap_or_default();
+                let message = commit["message"]
+                    .as_str()
+                    .and_then(|m| m.lines().next())
+                    .unwrap_or_default();
+                content.push_str(&format!("• `{}` {}\n", id.get(..7).unwrap_or(id), message));
+            }
+            if commits.len() > MAX_LISTED_COMMITS {
+                content.push_str(&format!(
+                    "• ...and {} more\n",
+                    commits.len() - MAX_LISTED_COMMITS
+                ));
+            }
+            if let Some(compare) = payload["compare"].as_str() {
+                content.push_str(&format!("<{}>", compare));
+            }
+            Some(content)
+        }
+        "pull_request" => {
+            let pr = &payload["pull_request"];
+            let action = match payload["action"].as_str()? {
+                "closed" if pr["merged"].as_bool() == Some(true) => "merged",
+                action @ ("opened" | "closed" | "reopened") => action,
+                _ => return None,
+            };
+            Some(format!(
+                "[{}] {} {} pull request #{}: {}\n<{}>",
+                repo,
+                sender,
+                action,
+                pr["number"],
+                pr["title"].as_str().unwrap_or_default(),
+                pr["html_url"].as_str().unwrap_or_default(),
+            ))
+        }
+        "issues" => {
+            let issue = &payload["issue"];
+            let action = match payload["action"].as_str()? {
+                action @ ("opened" | "closed" | "reopened") => action,
+                _ => return None,
+            };
+            Some(format!(
+                "[{}] {} {} issue #{}: {}\n<{}>",
+                repo,
+                sender,
+                action,
+                issue["number"],
+                issue["title"].as_str().unwrap_or_default(),
+                issue["html_url"].as_str().unwrap_or_default(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// From GitHub's documentation on validating webhook deliveries
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    const SIGNATURE: &str =
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    #[test]
+    fn accepts_valid_signature() {
+        assert!(verify_signature(SECRET, BODY, SIGNATURE));
+    }
+
+    #[test]
+    fn rejects_invalid_signatures() {
+        assert!(!verify_signature("wrong secret", BODY, SIGNATURE));
+        assert!(!verify_signature(SECRET, b"Hello, World?", SIGNATURE));
+        assert!(!verify_signature(
+            SECRET,
+            BODY,
+            &SIGNATURE.replace("sha256=", "sha1=")
+        ));
+        assert!(!verify_signature(SECRET, BODY, "sha256=not-hex"));
+        assert!(!verify_signature(SECRET, BODY, "sha256="));
+        assert!(!verify_signature(SECRET, BODY, ""));
+    }
+
+    #[test]
+    fn announcement_pings_nobody() {
+        let message =
+            serde_json::to_value(announcement("@everyone <@&123> <@456>".to_string())).unwrap();
+        assert_eq!(message["content"], "@everyone <@&123> <@456>");
+        assert_eq!(message["allowed_mentions"]["parse"], json!([]));
+        assert_eq!(message["allowed_mentions"]["users"], json!([]));
+        assert_eq!(message["allowed_mentions"]["roles"], json!([]));
+    }
+
+    fn commit(id: &str, message: &str) -> Value {
+        json!({ "id": id, "message": message })
+    }
+
+    #[test]
+    fn formats_push() {
+        let payload = json!({
+            "repository": { "full_name": "paradigm/digmbot-rs" },
+            "sender": { "login": "octocat" },
+            "ref": "refs/heads/main",
+            "compare": "https://github.com/paradigm/digmbot-rs/compare/a...b",
+            "commits": [
+                commit("0123456789abcdef", "Fix things\n\nLonger description"),
+                commit("fedcba9876543210", "Add things"),
+            ],
+        });
+        assert_eq!(
+            format_event("push", &payload).unwrap(),
+            "[paradigm/digmbot-rs] octocat pushed 2 commit(s) to `main`\n\
+             • `0123456` Fix things\n\
+             • `fedcba9` Add things\n\
+             <https://github.com/paradigm/digmbot-rs/compare/a...b>"
+        );
+    }
+
+    #[test]
+    fn summarizes_long_pushes() {
+        let commits: Vec<Value> = (0..MAX_LISTED_COMMITS + 3)
+            .map(|i| commit(&format!("{:040}", i), "Commit"))
+            .collect();
+        let payload = json!({
+            "repository": { "full_name": "a/b" },
+            "ref": "refs/heads/dev",
+            "commits": commits,
+        });
+        let content = format_event("push", &payload).unwrap();
+        assert!(content.starts_with("[a/b] someone pushed 8 commit(s) to `dev`\n"));
+        assert_eq!(content.matches("` Commit").count(), MAX_LISTED_COMMITS);
+        assert!(content.ends_with("• ...and 3 more\n"));
+    }
+
+    #[test]
+    fn skips_empty_pushes() {
+        let payload = json!({
+            "repository": { "full_name": "a/b" },
+            "ref": "refs/heads/main",
+            "commits": [],
+        });
+        assert_eq!(format_event("push", &payload), None);
+    }
+
+    #[test]
+    fn formats_pull_requests() {
+        let payload = |action: &str, merged: bool| {
+            json!({
+                "action": action,
+                "repository": { "full_name": "a/b" },
+                "sender": { "login": "octocat" },
+                "pull_request": {
+                    "number": 42,
+                    "title": "Add feature",
+                    "html_url": "https://github.com/a/b/pull/42",
+                    "merged": merged,
+                },
+            })
+        };
+        assert_eq!(
+            format_event("pull_request", &payload("opened", false)).unwrap(),
+            "[a/b] octocat opened pull request #42: Add feature\n<https://github.com/a/b/pull/42>"
+        );
+        assert!(format_event("pull_request", &payload("closed", true))
+            .unwrap()
+            .contains("octocat merged pull request #42"));
+        assert!(format_event("pull_request", &payload("closed", false))
+            .unwrap()
+            .contains("octocat closed pull request #42"));
+        assert_eq!(
+            format_event("pull_request", &payload("labeled", false)),
+            None
+        );
+    }
+
+    #[test]
+    fn formats_issues() {
+        let payload = |action: &str| {
+            json!({
+                "action": action,
+                "repository": { "full_name": "a/b" },
+                "sender": { "login": "octocat" },
+                "issue": {
+                    "number": 7,
+                    "title": "Bug",
+                    "html_url": "https://github.com/a/b/issues/7",
+                },
+            })
+        };
+        assert_eq!(
+            format_event("issues", &payload("reopened")).unwrap(),
+            "[a/b] octocat reopened issue #7: Bug\n<https://github.com/a/b/issues/7>"
+        );
+        assert_eq!(format_event("issues", &payload("assigned")), None);
+    }
+
+    #[test]
+    fn skips_unknown_events_and_payloads_without_repository() {
+        let payload = json!({ "repository": { "full_name": "a/b" } });
+        assert_eq!(format_event("star", &payload), None);
+        assert_eq!(format_event("push", &json!({ "commits": [] })), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Daily five-letter word puzzle
+//!
+//! `scheduler.rs` posts a new puzzle in each `[word_puzzle]` guild on its schedule.  Members have
+//! `MAX_GUESSES` tries, made with the `puzzle` command in the channel or in a DM.  Each guess is
+//! marked letter by letter: green for the right letter in the right place, yellow for a letter
+//! elsewhere in the word, and black for one not in it (or not there as many times).  Any five
+//! letters make a valid guess; there's no dictionary to check against.
+
+use crate::{
+    context::Context,
+    helper::UserIdHelper,
+    persistent_state::{GuildPuzzle, PuzzleSolve},
+};
+use anyhow::Result;
+use serenity::all::{ChannelId, GuildId, Timestamp};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+pub const WORD_LEN: usize = 5;
+pub const MAX_GUESSES: usize = 6;
+/// How long solves count toward the weekly leaderboard
+pub const WEEK_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Answers used when `[word_puzzle] words` is empty
+const WORDS: &[&str] = &[
+    "apple", "beach", "brain", "bread", "brick", "cabin", "candy", "chair", "chess", "cloud",
+    "crane", "crown", "dance", "dream", "eagle", "earth", "flame", "fruit", "ghost", "grape",
+    "green", "heart", "honey", "horse", "house", "juice", "knife", "lemon", "light", "magic",
+    "maple", "money", "mouse", "music", "night", "ocean", "olive", "paint", "party", "piano",
+    "pilot", "plant", "quest", "queen", "radio", "river", "robot", "salad", "sheep", "shirt",
+    "slate", "smile", "snake", "spice", "storm", "sugar", "table", "tiger", "toast", "tower",
+    "train", "truck", "water", "whale", "world", "zebra",
+];
+
+/// Whether `word` could be an answer or guess
+pub fn is_word(word: &str) -> bool {
+    word.len() == WORD_LEN && word.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Colored squares marking each letter of `guess` against `answer`
+pub fn mark(answer: &str, guess: &str) -> String {
+    let answer: Vec<char> = answer.chars().collect();
+    let guess: Vec<char> = guess.chars().collect();
+    let mut marks = ['⬛'; WORD_LEN];
+    // Letters of the answer not matched exactly, each available to one yellow mark
+    let mut unmatched: HashMap<char, usize> = HashMap::new();
+    for i in 0..WORD_LEN {
+        if guess[i] == answer[i] {
+            marks[i] = '🟩';
+        } else {
+            *unmatched.entry(answer[i]).or_default() += 1;
+        }
+    }
+    for i in 0..WORD_LEN {
+        if marks[i] == '🟩' {
+            continue;
+        }
+        if let Some(count) = unmatched.get_mut(&guess[i]).filter(|count| **count > 0) {
+            *count -= 1;
+            marks[i] = '🟨';
+        }
+    }
+    marks.iter().collect()
+}
+
+/// Weekly leaderboard points for solving in `guesses` tries
+pub fn points(guesses: usize) -> usize {
+    MAX_GUESSES + 1 - guesses.min(MAX_GUESSES)
+}
+
+/// Start a new puzzle in every configured guild, announcing the previous day's answer
+pub async fn post_daily(ctx: &Context<'_>) -> Result<()> {
+    let (channels, words): (HashMap<GuildId, ChannelId>, Vec<String>) =
+        match &ctx.cfg.read().await.word_puzzle {
+            Some(cfg) => (cfg.channels.clone(), cfg.words.clone()),
+            None => return Ok(()),
+        };
+    let words: Vec<String> = if words.is_empty() {
+        WORDS.iter().map(|word| word.to_string()).collect()
+    } else {
+        words.iter().map(|word| word.to_lowercase()).collect()
+    };
+
+    let now = Timestamp::now().unix_timestamp();
+    let random = std::collections::hash_map::RandomState::new();
+    for (guild_id, channel_id) in channels {
+        let (number, previous) = {
+            let mut pstate = ctx.pstate.write().await;
+            pstate.ensure_writable()?;
+            let puzzles = &mut pstate.word_puzzles.guilds;
+            let previous = puzzles
+                .get(&guild_id)
+                .map(|puzzle| (puzzle.number, puzzle.word.clone(), solvers(puzzle)));
+            let number = previous.as_ref().map_or(1, |(number, _, _)| number + 1);
+            // Don't repeat yesterday's word
+            let mut word = &words[random.hash_one(number) as usize % words.len()];
+            if words.len() > 1 && previous.as_ref().is_some_and(|(_, last, _)| last == word) {
+                word = &words[(random.hash_one(number) as usize + 1) % words.len()];
+            }
+
+            let puzzle = puzzles.entry(guild_id).or_insert_with(|| GuildPuzzle {
+                number: 0,
+                word: String::new(),
+                posted: now,
+                guesses: HashMap::new(),
+                solves: Vec::new(),
+            });
+            puzzle.number = number;
+            puzzle.word = word.clone();
+            puzzle.posted = now;
+            puzzle.guesses.clear();
+            puzzle.solves.retain(|solve| solve.posted > now - WEEK_SECS);
+            pstate.save().await?;
+            (number, previous)
+        };
+
+        let prefix = ctx.cfg.read().await.general.command_prefix.clone();
+        let mut content = String::new();
+        if let Some((last_number, word, solvers)) = previous {
+            content.push_str(&format!(
+                "Puzzle #{} was **{}**, solved by {}.\n\n",
+                last_number,
+                word.to_uppercase(),
+                solvers
+            ));
+        }
+        content.push_str(&format!(
+            "**Daily word puzzle #{}**: guess the {}-letter word in {} tries with \
+             `{}puzzle guess ||word||`, here or in a DM.",
+            number, WORD_LEN, MAX_GUESSES, prefix
+        ));
+        channel_id.say(ctx.cache_http, content).await?;
+    }
+    Ok(())
+}
+
+/// How many solved the current puzzle, e.g. `3 players`
+fn solvers(puzzle: &GuildPuzzle) -> String {
+    let count = puzzle
+        .solves
+        .iter()
+        .filter(|solve| solve.number == puzzle.number)
+        .count();
+    match count {
+        1 => "1 player".to_string(),
+        count => format!("{} players", count),
+    }
+}
+
+/// Leaderboard of `solves` by total points, so fewer guesses and more puzzles solved rank higher
+pub async fn leaderboard(ctx: &Context<'_>, guild_id: GuildId, solves: &[PuzzleSolve]) -> String {
+    let mut totals: HashMap<_, (usize, usize)> = HashMap::new();
+    for solve in solves {
+        let (points_total, solved) = totals.entry(solve.user_id).or_default();
+        *points_total += points(solve.guesses);
+        *solved += 1;
+    }
+    let mut totals: Vec<_> = totals.into_iter().collect();
+    totals.sort_unstable_by_key(|&(_, (points, _))| std::cmp::Reverse(points));
+
+    let mut lines = Vec::new();
+    for (rank, (user_id, (points, solved))) in totals.into_iter().enumerate() {
+        lines.push(format!(
+            "{}. {}: {} points ({} solved)",
+            rank + 1,
+            user_id.nick_in_guild(ctx, Some(guild_id)).await,
+            points,
+            solved
+        ));
+    }
+    lines.join("\n")
+}